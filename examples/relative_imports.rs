@@ -1,13 +1,14 @@
 use ruwren::{VMConfig, ModuleScriptLoader};
+use std::borrow::Cow;
 
 struct TestLoader;
 
 impl ModuleScriptLoader for TestLoader {
-	fn load_script(&mut self, module: String) -> Option<String> {
+	fn load_script(&mut self, module: String) -> Option<Cow<'static, str>> {
 		if module == "test/a" {
-			Some(include_str!("relative_imports/a.wren").into())
+			Some(concat!(include_str!("relative_imports/a.wren"), "\0").into())
 		} else if module == "test" {
-			Some(include_str!("relative_imports/test.wren").into())
+			Some(concat!(include_str!("relative_imports/test.wren"), "\0").into())
 		} else {
 			None
 		}
@@ -0,0 +1,65 @@
+/* C interface to ruwren (built with the "capi" feature) */
+#ifndef RUWREN_H
+#define RUWREN_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ModuleLibrary RuwrenLibrary;
+typedef struct RuwrenVM RuwrenVM;
+typedef struct WrenValue RuwrenValue;
+
+typedef enum {
+    RUWREN_SUCCESS = 0,
+    RUWREN_COMPILE_ERROR = 1,
+    RUWREN_RUNTIME_ERROR = 2,
+    RUWREN_INVALID_ARGUMENT = 3,
+    RUWREN_PANIC = 4,
+} RuwrenResult;
+
+typedef enum {
+    RUWREN_TYPE_NULL = 0,
+    RUWREN_TYPE_BOOL = 1,
+    RUWREN_TYPE_NUM = 2,
+    RUWREN_TYPE_STRING = 3,
+    RUWREN_TYPE_LIST = 4,
+    RUWREN_TYPE_MAP = 5,
+} RuwrenValueType;
+
+RuwrenLibrary *ruwren_library_new(void);
+void ruwren_library_free(RuwrenLibrary *lib);
+bool ruwren_library_add_stdlib(RuwrenLibrary *lib, const char *name);
+
+RuwrenVM *ruwren_vm_new(const RuwrenLibrary *lib);
+void ruwren_vm_free(RuwrenVM *vm);
+const char *ruwren_vm_error(const RuwrenVM *vm);
+RuwrenResult ruwren_vm_interpret(RuwrenVM *vm, const char *module, const char *source);
+RuwrenResult ruwren_vm_eval(RuwrenVM *vm, const char *expr, RuwrenValue **out);
+RuwrenResult ruwren_vm_call(RuwrenVM *vm, const char *module, const char *class_name, const char *method,
+                            const RuwrenValue *const *args, size_t argc, RuwrenValue **out);
+
+RuwrenValue *ruwren_value_null(void);
+RuwrenValue *ruwren_value_bool(bool b);
+RuwrenValue *ruwren_value_num(double n);
+RuwrenValue *ruwren_value_string(const char *s);
+RuwrenValue *ruwren_value_list(void);
+bool ruwren_value_list_push(RuwrenValue *list, RuwrenValue *item);
+void ruwren_value_free(RuwrenValue *value);
+RuwrenValueType ruwren_value_type(const RuwrenValue *value);
+bool ruwren_value_get_bool(const RuwrenValue *value);
+double ruwren_value_get_num(const RuwrenValue *value);
+char *ruwren_value_get_string(const RuwrenValue *value);
+size_t ruwren_value_list_count(const RuwrenValue *value);
+const RuwrenValue *ruwren_value_list_get(const RuwrenValue *value, size_t index);
+char *ruwren_value_to_string(const RuwrenValue *value);
+void ruwren_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,531 @@
+//! Derive and attribute macros for ruwren (use them through ruwren's `derive` feature)
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, FnArg, ImplItem, ItemImpl, ItemStruct, LitStr, Pat, ReturnType, Signature, Type};
+
+/// Implements `ruwren::WrenRecord` for a struct with named fields, each of which has to be
+/// `Clone + Into<WrenValue> + FromWrenValue`
+///
+/// A field can be given a different key with `#[wren(rename = "key")]`.
+#[proc_macro_derive(WrenRecord, attributes(wren))]
+pub fn derive_wren_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match record(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn record(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(input, "WrenRecord needs a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "WrenRecord can only be derived for structs")),
+    };
+
+    let mut idents = vec![];
+    let mut keys = vec![];
+    for field in fields {
+        let ident = field.ident.clone().unwrap();
+        let mut key = ident.to_string();
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("wren")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    key = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown wren attribute"))
+                }
+            })?;
+        }
+        idents.push(ident);
+        keys.push(key);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::ruwren::WrenRecord for #name #ty_generics #where_clause {
+            fn to_wren_map(&self) -> ::ruwren::WrenMap {
+                let mut map = ::ruwren::WrenMap::new();
+                #( map.insert(#keys, ::ruwren::WrenValue::from(::std::clone::Clone::clone(&self.#idents))); )*
+                map
+            }
+
+            fn from_wren_map(map: &::ruwren::WrenMap) -> ::std::option::Option<Self> {
+                ::std::option::Option::Some(#name {
+                    #( #idents: match map.get(#keys) {
+                        ::std::option::Option::Some(value) => ::ruwren::FromWrenValue::from_wren_value(value)?,
+                        ::std::option::Option::None => ::ruwren::FromWrenValue::from_missing()?,
+                    }, )*
+                })
+            }
+        }
+
+        impl #impl_generics ::std::convert::From<#name #ty_generics> for ::ruwren::WrenValue #where_clause {
+            fn from(record: #name #ty_generics) -> Self {
+                ::ruwren::WrenValue::Map(::ruwren::WrenRecord::to_wren_map(&record))
+            }
+        }
+
+        impl #impl_generics ::ruwren::FromWrenValue for #name #ty_generics #where_clause {
+            fn from_wren_value(value: &::ruwren::WrenValue) -> ::std::option::Option<Self> {
+                match value {
+                    ::ruwren::WrenValue::Map(map) => ::ruwren::WrenRecord::from_wren_map(map),
+                    _ => ::std::option::Option::None,
+                }
+            }
+        }
+    })
+}
+
+/// Implements `ruwren::WrenEnum` for a fieldless enum, which has to be `Copy + PartialEq`
+///
+/// Variants are passed as their names, unless renamed with `#[wren(rename = "name")]`, or as
+/// their discriminants if the enum has `#[wren(numbers)]` (either is read back).
+#[proc_macro_derive(WrenEnum, attributes(wren))]
+pub fn derive_wren_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match wren_enum(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn wren_enum(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => return Err(syn::Error::new_spanned(input, "WrenEnum can only be derived for enums")),
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&input.generics, "WrenEnum can't be derived for generic enums"));
+    }
+
+    let mut numbered = false;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("wren")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("numbers") {
+                numbered = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown wren attribute"))
+            }
+        })?;
+    }
+
+    let mut idents = vec![];
+    let mut names = vec![];
+    for variant in variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(variant, "WrenEnum variants can't have fields"));
+        }
+        let mut name = variant.ident.to_string();
+        for attr in variant.attrs.iter().filter(|attr| attr.path().is_ident("wren")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown wren attribute"))
+                }
+            })?;
+        }
+        idents.push(&variant.ident);
+        names.push(name);
+    }
+
+    let name = &input.ident;
+    let enum_name = name.to_string();
+    Ok(quote! {
+        impl ::ruwren::WrenEnum for #name {
+            const NAME: &'static str = #enum_name;
+            const VARIANTS: &'static [(&'static str, f64, Self)] = &[
+                #( (#names, #name::#idents as isize as f64, #name::#idents), )*
+            ];
+            const NUMBERED: bool = #numbered;
+        }
+
+        impl ::ruwren::FromWren for #name {
+            fn from_wren(vm: &::ruwren::VM, slot: ::ruwren::SlotId) -> ::std::result::Result<Self, ::ruwren::SlotError> {
+                <Self as ::ruwren::WrenEnum>::from_slot(vm, slot)
+            }
+        }
+
+        impl ::ruwren::ToWren for #name {
+            fn to_wren(self, vm: &::ruwren::VM, slot: ::ruwren::SlotId) -> ::std::result::Result<(), ::ruwren::ForeignSendError> {
+                vm.set_slot_enum(slot, self);
+                ::std::result::Result::Ok(())
+            }
+        }
+
+        impl ::std::convert::From<#name> for ::ruwren::WrenValue {
+            fn from(variant: #name) -> Self {
+                ::ruwren::WrenEnum::to_value(variant)
+            }
+        }
+
+        impl ::ruwren::FromWrenValue for #name {
+            fn from_wren_value(value: &::ruwren::WrenValue) -> ::std::option::Option<Self> {
+                <Self as ::ruwren::WrenEnum>::from_value(value)
+            }
+        }
+    })
+}
+
+/// Gives a struct a Wren class name, for `#[wren_methods]` on its impl block
+///
+/// The name is the struct's own unless given with `name = "..."`. `module = "..."` only labels
+/// stack frames in errors (the class can still be registered under any module).
+#[proc_macro_attribute]
+pub fn wren_class(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as ItemStruct);
+    let mut name = item.ident.to_string();
+    let mut module = String::new();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = meta.value()?.parse::<LitStr>()?.value();
+            Ok(())
+        } else if meta.path.is_ident("module") {
+            module = meta.value()?.parse::<LitStr>()?.value();
+            Ok(())
+        } else {
+            Err(meta.error("unknown wren_class argument"))
+        }
+    });
+    parse_macro_input!(args with parser);
+    if !item.generics.params.is_empty() {
+        return syn::Error::new_spanned(&item.generics, "wren_class can't be generic").to_compile_error().into();
+    }
+
+    let ident = &item.ident;
+    quote! {
+        #item
+
+        impl #ident {
+            #[doc(hidden)]
+            pub const __WREN_CLASS: &'static str = #name;
+            #[doc(hidden)]
+            pub const __WREN_MODULE: &'static str = #module;
+        }
+    }.into()
+}
+
+/// Turns an impl block into the foreign methods of a `#[wren_class]` struct, implementing
+/// `ruwren::WrenClass` so it can be registered with `Module::wren_class`
+///
+/// Each method's arguments are read from their slots with `FromWren`, and its return value is
+/// put in slot 0 with `ToWren`. A `&VM` argument gets the VM, and isn't a Wren parameter.
+/// Methods taking `self` are instance methods, and the rest are static. Wren names are the
+/// Rust names in camelCase, and what kind of method each one is comes from its signature:
+///
+/// - no Wren parameters and a return value makes a getter (`fn health(&self) -> f64` is `health`)
+/// - `set_` with one parameter and no return value makes a setter (`set_health` is `health=(_)`)
+/// - anything else is a method (`fn take_damage(&mut self, amount: f64)` is `takeDamage(_)`)
+///
+/// `#[wren(getter)]`, `#[wren(setter)]` and `#[wren(method)]` override that, `#[wren(name = "...")]`
+/// renames a method, and `#[wren(skip)]` leaves a method out. One static method can be marked
+/// `#[wren(constructor)]` to become the Wren constructor. Without one, the class can only be
+/// created from Rust.
+#[proc_macro_attribute]
+pub fn wren_methods(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut item = parse_macro_input!(input as ItemImpl);
+    if !args.is_empty() {
+        return syn::Error::new(proc_macro2::Span::call_site(), "wren_methods takes no arguments").to_compile_error().into();
+    }
+    match methods(&mut item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MethodKind {
+    Method,
+    Getter,
+    Setter,
+    Constructor,
+}
+
+// What #[wren(...)] says about a method
+#[derive(Default)]
+struct MethodAttrs {
+    kind: Option<MethodKind>,
+    name: Option<String>,
+    skip: bool,
+}
+
+fn method_attrs(attrs: &mut Vec<Attribute>) -> syn::Result<MethodAttrs> {
+    let mut parsed = MethodAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("wren")) {
+        attr.parse_nested_meta(|meta| {
+            let kind = if meta.path.is_ident("getter") {
+                MethodKind::Getter
+            } else if meta.path.is_ident("setter") {
+                MethodKind::Setter
+            } else if meta.path.is_ident("method") {
+                MethodKind::Method
+            } else if meta.path.is_ident("constructor") {
+                MethodKind::Constructor
+            } else if meta.path.is_ident("skip") {
+                parsed.skip = true;
+                return Ok(());
+            } else if meta.path.is_ident("name") {
+                parsed.name = Some(meta.value()?.parse::<LitStr>()?.value());
+                return Ok(());
+            } else {
+                return Err(meta.error("unknown wren attribute"));
+            };
+            parsed.kind = Some(kind);
+            Ok(())
+        })?;
+    }
+    attrs.retain(|attr| !attr.path().is_ident("wren"));
+    Ok(parsed)
+}
+
+fn camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    let mut upper = false;
+    for (i, c) in name.chars().enumerate() {
+        if c == '_' && i > 0 && !camel.ends_with('_') {
+            upper = true;
+        } else if upper {
+            camel.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+// Whether [ty] is a reference to the VM, which methods get instead of a slot
+fn is_vm(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => match &*reference.elem {
+            Type::Path(path) => path.path.segments.last().is_some_and(|segment| segment.ident == "VM"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// The statements reading a method's arguments, and the expressions to pass it
+fn arguments(sig: &Signature) -> (Vec<proc_macro2::TokenStream>, Vec<proc_macro2::TokenStream>, Vec<String>) {
+    let (mut reads, mut passed, mut names) = (vec![], vec![], vec![]);
+    for input in &sig.inputs {
+        let input = match input {
+            FnArg::Receiver(_) => continue,
+            FnArg::Typed(input) => input,
+        };
+        if is_vm(&input.ty) {
+            passed.push(quote!(vm));
+            continue;
+        }
+        let slot = names.len() + 1;
+        let var = format_ident!("__arg{}", slot);
+        let ty = &input.ty;
+        reads.push(quote! {
+            let #var: #ty = ::ruwren::FromWren::from_wren(vm, #slot).unwrap_or_else(|err| panic!("{}", err));
+        });
+        passed.push(quote!(#var));
+        names.push(match &*input.pat {
+            Pat::Ident(pat) => camel_case(pat.ident.to_string().trim_start_matches('_')),
+            _ => format!("arg{}", slot - 1),
+        });
+    }
+    (reads, passed, names)
+}
+
+fn methods(item: &mut ItemImpl) -> syn::Result<proc_macro2::TokenStream> {
+    if !item.generics.params.is_empty() || item.trait_.is_some() {
+        return Err(syn::Error::new_spanned(&item.self_ty, "wren_methods needs an inherent impl of a non-generic type"));
+    }
+    let self_ty = item.self_ty.clone();
+    let type_name = match &*self_ty {
+        Type::Path(path) => path.path.segments.last().unwrap().ident.to_string(),
+        _ => return Err(syn::Error::new_spanned(&self_ty, "wren_methods needs an impl of a named type")),
+    };
+
+    let mut wrappers = vec![];
+    let mut bindings = vec![];
+    let mut constructor = None;
+    for impl_item in &mut item.items {
+        let method = match impl_item {
+            ImplItem::Fn(method) => method,
+            _ => continue,
+        };
+        let attrs = method_attrs(&mut method.attrs)?;
+        if attrs.skip {
+            continue;
+        }
+        let sig = &method.sig;
+        let ident = &sig.ident;
+        let receiver = sig.receiver();
+        if receiver.is_some_and(|receiver| receiver.reference.is_none()) {
+            return Err(syn::Error::new_spanned(sig, "foreign methods can't take self by value"));
+        }
+        let (reads, passed, names) = arguments(sig);
+        let returns = !matches!(sig.output, ReturnType::Default);
+        let kind = attrs.kind.unwrap_or(if names.is_empty() && returns {
+            MethodKind::Getter
+        } else if ident.to_string().starts_with("set_") && names.len() == 1 && !returns {
+            MethodKind::Setter
+        } else {
+            MethodKind::Method
+        });
+
+        if kind == MethodKind::Constructor {
+            if receiver.is_some() || constructor.is_some() {
+                return Err(syn::Error::new_spanned(sig, "a class has one constructor, which can't take self"));
+            }
+            let name = attrs.name.unwrap_or_else(|| camel_case(&ident.to_string()));
+            let declaration = format!("construct {}({}) {{}}", name, names.join(", "));
+            constructor = Some((declaration, quote! {
+                #(#reads)*
+                <#self_ty>::#ident(#(#passed),*)
+            }));
+            continue;
+        }
+
+        let name = attrs.name.unwrap_or_else(|| {
+            let rust_name = ident.to_string();
+            match kind {
+                MethodKind::Setter => camel_case(rust_name.strip_prefix("set_").unwrap_or(&rust_name)),
+                _ => camel_case(&rust_name),
+            }
+        });
+        let label = match kind {
+            MethodKind::Getter if names.is_empty() => quote!(getter #name),
+            MethodKind::Setter if names.len() == 1 => quote!(setter #name),
+            MethodKind::Method => {
+                let arity = names.len();
+                quote!(fn #name, #arity)
+            },
+            _ => return Err(syn::Error::new_spanned(sig, "getters take no Wren parameters, and setters take one")),
+        };
+
+        let wrapper = format_ident!("__wren_{}", ident);
+        let (static_label, receiver_arg, call) = match receiver {
+            Some(_) => (quote!(instance), quote!(&mut self,), quote!(self.#ident(#(#passed),*))),
+            None => (quote!(static), quote!(), quote!(<#self_ty>::#ident(#(#passed),*))),
+        };
+        wrappers.push(quote! {
+            #[doc(hidden)]
+            fn #wrapper(#receiver_arg vm: &::ruwren::VM) {
+                #(#reads)*
+                let ret = #call;
+                ::ruwren::ToWren::to_wren(ret, vm, 0).unwrap_or_else(|err| panic!("{}", err));
+            }
+        });
+        bindings.push(quote!(#static_label(#label) #wrapper));
+    }
+
+    let (declaration, initialize) = match constructor {
+        Some((declaration, body)) => (declaration, body),
+        None => (String::new(), quote! {
+            panic!("{} can only be created from Rust", <#self_ty>::__WREN_CLASS)
+        }),
+    };
+    let glue = format_ident!("__wren_{}", type_name.to_lowercase());
+    Ok(quote! {
+        #item
+
+        impl #self_ty {
+            #(#wrappers)*
+        }
+
+        impl ::ruwren::Class for #self_ty {
+            fn initialize(vm: &::ruwren::VM) -> Self {
+                #initialize
+            }
+        }
+
+        ::ruwren::create_module!(@class <#self_ty>::__WREN_CLASS, <#self_ty>::__WREN_MODULE, #self_ty => #glue {
+            #(#bindings),*
+        });
+
+        impl ::ruwren::WrenClass for #self_ty {
+            const NAME: &'static str = <#self_ty>::__WREN_CLASS;
+
+            fn source() -> ::std::string::String {
+                ::ruwren::class_source::<Self>(Self::NAME, #declaration)
+            }
+
+            fn is_send() -> bool {
+                #[allow(unused_imports)]
+                use ::ruwren::{SendProbeSend, SendProbeAny};
+                (&::ruwren::SendProbe::<Self>(::std::marker::PhantomData)).is_send()
+            }
+        }
+    })
+}
+
+/// Compiles every Wren file matching a pattern into the binary, as a `ruwren::StaticLoader`
+///
+/// The pattern is relative to the crate's manifest, and is a directory followed by `*.ext`, or
+/// `**/*.ext` to include subdirectories. Modules are named after their paths in that directory,
+/// without the extension, so with `embed_modules!("scripts/**/*.wren")`,
+/// `scripts/enemies/slime.wren` is the module `enemies/slime`.
+#[proc_macro]
+pub fn embed_modules(input: TokenStream) -> TokenStream {
+    let pattern = parse_macro_input!(input as LitStr);
+    match embed(&pattern) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn embed(pattern: &LitStr) -> syn::Result<proc_macro2::TokenStream> {
+    use std::path::{Path, PathBuf};
+
+    let value = pattern.value();
+    let (dir, file) = value.rsplit_once('/').unwrap_or((".", &value));
+    let (dir, recursive) = match dir.strip_suffix("**") {
+        Some(dir) => (dir.trim_end_matches('/'), true),
+        None => (dir, false),
+    };
+    let extension = file.strip_prefix("*.")
+        .filter(|ext| !ext.is_empty() && !ext.contains('*') && !dir.contains('*'))
+        .ok_or_else(|| syn::Error::new_spanned(pattern, "expected a pattern like \"scripts/*.wren\" or \"scripts/**/*.wren\""))?;
+
+    let root = std::env::var("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default()
+        .join(if dir.is_empty() { "." } else { dir });
+
+    fn walk(dir: &Path, recursive: bool, extension: &str, found: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if recursive {
+                    walk(&path, recursive, extension, found)?;
+                }
+            } else if path.extension().is_some_and(|ext| ext == extension) {
+                found.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut found = vec![];
+    walk(&root, recursive, extension, &mut found)
+        .map_err(|err| syn::Error::new_spanned(pattern, format!("can't read {}: {}", root.display(), err)))?;
+    let mut modules = found.iter()
+        .map(|path| {
+            let name = path.strip_prefix(&root).unwrap_or(path).with_extension("");
+            let name = name.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            (name, path.to_string_lossy().into_owned())
+        })
+        .collect::<Vec<_>>();
+    modules.sort();
+
+    let names = modules.iter().map(|(name, _)| name);
+    // include_str! rather than reading them here, so changing a script rebuilds the crate
+    let paths = modules.iter().map(|(_, path)| path);
+    Ok(quote! {
+        ::ruwren::StaticLoader::new(&[
+            #( (#names, ::std::concat!(::std::include_str!(#paths), "\0")), )*
+        ])
+    })
+}
@@ -0,0 +1,25 @@
+//! Warnings for conversions that lose information, with the `audit` feature
+//!
+//! Each is logged at warn level with a target of `ruwren::audit`, so they can be turned on just
+//! for tracking down a wrong value and filtered out of everything else. Without the feature the
+//! checks compile away.
+
+// Logs a conversion that lost information
+#[cfg(feature = "audit")]
+macro_rules! audit {
+    ($($arg:tt)+) => {
+        log::warn!(target: "ruwren::audit", $($arg)+)
+    };
+}
+
+#[cfg(not(feature = "audit"))]
+macro_rules! audit {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+// The largest integer a Wren number holds exactly
+pub(crate) const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
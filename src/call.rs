@@ -0,0 +1,96 @@
+//! Calling into Wren with typed arguments and typed return values
+//!
+//! ```ignore
+//! let scaled: f64 = vm.call_on(&player, "transform(_,_)").arg(1.5).arg("name").invoke()?;
+//! let score: u32 = vm.interpret_returning("main", "Game.score")?;
+//! ```
+use crate::{VM, VMWrapper, VMError, Handle, FunctionSignature, SignatureError, ForeignSendError, FromWren, ToWren, SlotId, Error, ModuleName};
+
+// Declared in each module VMWrapper::interpret_returning is used on
+const RETURN_VARIABLE: &str = "ruwrenReturn";
+
+type Argument<'a> = Box<dyn FnOnce(&VM, SlotId) -> Result<(), ForeignSendError> + 'a>;
+
+/// A method call being put together, from [VMWrapper::call_on]
+#[must_use = "nothing is called until invoke"]
+pub struct CallBuilder<'a> {
+    vm: &'a VMWrapper,
+    receiver: &'a Handle<'a>,
+    signature: &'a str,
+    args: Vec<Argument<'a>>,
+}
+
+impl<'a> CallBuilder<'a> {
+    /// Adds the next argument, converted when the call is made
+    pub fn arg<T: ToWren + 'a>(mut self, value: T) -> Self {
+        self.args.push(Box::new(move |vm, slot| value.to_wren(vm, slot)));
+        self
+    }
+
+    /// Makes the call, and converts what it returned
+    ///
+    /// Fails without calling anything if the signature doesn't parse, or takes a different number
+    /// of arguments than were given.
+    pub fn invoke<R: FromWren>(self) -> Result<R, Error> {
+        let signature = FunctionSignature::parse(self.signature)?;
+        if signature.arity() != self.args.len() {
+            return Err(SignatureError::ArgumentCount {
+                signature: self.signature.to_string(),
+                expected: signature.arity(),
+                found: self.args.len(),
+            }.into());
+        }
+        let receiver = self.receiver;
+        let args = self.args;
+        let call = self.vm.execute(|vm| -> Result<_, Error> {
+            let call = vm.cached_call_handle(signature.clone());
+            vm.ensure_slots(args.len() + 1);
+            unsafe { wren_sys::wrenSetSlotHandle(vm.vm, 0, receiver.handle) };
+            for (slot, arg) in args.into_iter().enumerate() {
+                arg(vm, slot + 1)?;
+            }
+            Ok(call)
+        })?;
+        self.vm.call_raw(call, &signature.as_wren_string())?;
+        Ok(self.vm.execute(|vm| R::from_wren(vm, 0))?)
+    }
+}
+
+impl VMWrapper {
+    /// Starts a call of [signature] (like `"transform(_,_)"`) on [receiver]
+    ///
+    /// Arguments are added with [CallBuilder::arg], and [CallBuilder::invoke] runs the call and
+    /// converts slot 0 into the return type (`()` to ignore it).
+    pub fn call_on<'a>(&'a self, receiver: &'a Handle<'a>, signature: &'a str) -> CallBuilder<'a> {
+        CallBuilder { vm: self, receiver, signature, args: vec![] }
+    }
+}
+
+impl VMWrapper {
+    /// Like [VMWrapper::call], but converts what the method returned (`()` to ignore it)
+    pub fn call_returning<T: FromWren>(&self, signature: FunctionSignature) -> Result<T, VMError> {
+        self.call(signature)?;
+        self.execute(|vm| T::from_wren(vm, 0).map_err(|error| vm.rewrite_error(VMError::InvalidReturn { error })))
+    }
+
+    /// Evaluates the Wren expression [expr] in [module], where it can see the module's variables,
+    /// and converts its value
+    ///
+    /// Unlike [VMWrapper::eval], this declares a variable of its own in [module] the first time.
+    pub fn interpret_returning<T: FromWren, M: ModuleName, E: AsRef<str>>(&self, module: M, expr: E) -> Result<T, VMError> {
+        let module = module.module_name();
+        if !self.execute(|vm| vm.user_data().return_declared.borrow().contains(module)) {
+            self.interpret(module, format!("var {} = null", RETURN_VARIABLE))?;
+            self.execute(|vm| vm.user_data().return_declared.borrow_mut().insert(module.to_string()));
+        }
+        self.interpret(module, format!("{} = ({})", RETURN_VARIABLE, expr.as_ref()))?;
+        let value = self.execute(|vm| {
+            vm.ensure_slots(1);
+            vm.get_variable(module, RETURN_VARIABLE, 0);
+            T::from_wren(vm, 0).map_err(|error| vm.rewrite_error(VMError::InvalidReturn { error }))
+        });
+        // Don't keep the result alive for the garbage collector
+        self.interpret(module, format!("{} = null", RETURN_VARIABLE))?;
+        value
+    }
+}
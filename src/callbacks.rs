@@ -0,0 +1,213 @@
+//! Callbacks that scripts register by key, for Rust to call later, or schedule to run from [VMWrapper::tick]
+//!
+//! ```wren
+//! import "ruwren/callbacks" for Callbacks
+//! Callbacks.register("onHit", Fn.new {|damage| System.print("ouch: %(damage)") })
+//! Callbacks.schedule(0.5, Fn.new { System.print("half a second later") })
+//! ```
+use crate::{VMWrapper, VMError, UserData, WrenValue, FunctionSignature};
+use wren_sys::{WrenVM, WrenHandle};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::{ffi, mem, os::raw};
+
+pub(crate) const MODULE: &str = "ruwren/callbacks";
+
+pub(crate) const SOURCE: &str = "class Callbacks {
+    foreign static register(key, fn)
+    foreign static unregister(key)
+    foreign static schedule(delay, fn)
+}
+";
+
+/// Every callback registered on a VM, each kept alive by a handle until it's unregistered or the VM is dropped
+#[derive(Debug, Default)]
+pub(crate) struct CallbackTable {
+    entries: HashMap<String, *mut WrenHandle>,
+    // Scheduled functions, kept sorted by when they're due (ties in the order they were scheduled)
+    scheduled: Vec<(Instant, *mut WrenHandle)>,
+    // How far the last tick ran over its budget, taken out of the next one
+    overrun: Duration,
+}
+
+/// What a call to [VMWrapper::tick] did
+#[derive(Debug, Default)]
+pub struct TickReport {
+    /// Scheduled functions that ran
+    pub ran: usize,
+    /// Functions that were due, but left for the next tick because the budget ran out
+    pub deferred: usize,
+    /// Errors from functions that aborted
+    pub errors: Vec<VMError>,
+    pub elapsed: Duration,
+}
+
+impl CallbackTable {
+    /// Releases every handle, before the VM is freed
+    pub(crate) fn release(&mut self, vm: *mut WrenVM) {
+        let scheduled = self.scheduled.drain(..).map(|(_, h)| h);
+        for handle in self.entries.drain().map(|(_, h)| h).chain(scheduled) {
+            unsafe { wren_sys::wrenReleaseHandle(vm, handle) };
+        }
+    }
+
+    fn schedule(&mut self, due: Instant, handle: *mut WrenHandle) {
+        let index = self.scheduled.partition_point(|(other, _)| *other <= due);
+        self.scheduled.insert(index, (due, handle));
+    }
+
+    fn pop_due(&mut self, now: Instant) -> Option<*mut WrenHandle> {
+        match self.scheduled.first() {
+            Some((due, _)) if *due <= now => Some(self.scheduled.remove(0).1),
+            _ => None,
+        }
+    }
+}
+
+// Reads the key in slot 1, aborting the fiber if it isn't a string
+unsafe fn key(vm: *mut WrenVM) -> Option<String> {
+    if wren_sys::wrenGetSlotType(vm, 1) == wren_sys::WrenType_WREN_TYPE_STRING {
+        Some(ffi::CStr::from_ptr(wren_sys::wrenGetSlotString(vm, 1)).to_string_lossy().to_string())
+    } else {
+        let message = ffi::CString::new("Callback key must be a string").unwrap();
+        wren_sys::wrenSetSlotString(vm, 0, message.as_ptr());
+        wren_sys::wrenAbortFiber(vm, 0);
+        None
+    }
+}
+
+pub(crate) extern "C" fn register(vm: *mut WrenVM) {
+    unsafe {
+        let conf = &*(wren_sys::wrenGetUserData(vm) as *const UserData);
+        if let Some(key) = key(vm) {
+            let handle = wren_sys::wrenGetSlotHandle(vm, 2 as raw::c_int);
+            if let Some(old) = conf.callbacks.borrow_mut().entries.insert(key, handle) {
+                wren_sys::wrenReleaseHandle(vm, old);
+            }
+        }
+    }
+}
+
+pub(crate) extern "C" fn unregister(vm: *mut WrenVM) {
+    unsafe {
+        let conf = &*(wren_sys::wrenGetUserData(vm) as *const UserData);
+        if let Some(key) = key(vm) {
+            if let Some(old) = conf.callbacks.borrow_mut().entries.remove(&key) {
+                wren_sys::wrenReleaseHandle(vm, old);
+            }
+        }
+    }
+}
+
+pub(crate) extern "C" fn schedule(vm: *mut WrenVM) {
+    unsafe {
+        let conf = &*(wren_sys::wrenGetUserData(vm) as *const UserData);
+        let delay = if wren_sys::wrenGetSlotType(vm, 1) == wren_sys::WrenType_WREN_TYPE_NUM {
+            wren_sys::wrenGetSlotDouble(vm, 1)
+        } else {
+            f64::NAN
+        };
+        if !(delay >= 0.0 && delay.is_finite()) {
+            let message = ffi::CString::new("Delay must be a non-negative number of seconds").unwrap();
+            wren_sys::wrenSetSlotString(vm, 0, message.as_ptr());
+            wren_sys::wrenAbortFiber(vm, 0);
+            return;
+        }
+        // Finite delays can still be too far off for an Instant
+        let due = match Duration::try_from_secs_f64(delay).ok().and_then(|delay| Instant::now().checked_add(delay)) {
+            Some(due) => due,
+            None => {
+                let message = ffi::CString::new("Delay is too long").unwrap();
+                wren_sys::wrenSetSlotString(vm, 0, message.as_ptr());
+                wren_sys::wrenAbortFiber(vm, 0);
+                return;
+            },
+        };
+        let handle = wren_sys::wrenGetSlotHandle(vm, 2 as raw::c_int);
+        conf.callbacks.borrow_mut().schedule(due, handle);
+    }
+}
+
+impl VMWrapper {
+    /// Calls the callback a script registered as [key] with [args], leaving its result in slot 0
+    ///
+    /// Returns None if nothing is registered as [key].
+    pub fn invoke_callback<K: AsRef<str>>(&self, key: K, args: &[WrenValue]) -> Option<Result<(), VMError>> {
+        let signature = FunctionSignature::new_function("call", args.len());
+        let call = self.execute(|vm| {
+            let callback = *vm.user_data().callbacks.borrow().entries.get(key.as_ref())?;
+            let call = vm.cached_call_handle(signature.clone());
+
+            vm.ensure_slots(args.len() + 1);
+            unsafe { wren_sys::wrenSetSlotHandle(vm.vm, 0, callback) };
+            for (slot, arg) in args.iter().enumerate() {
+                vm.set_slot_value(slot + 1, arg);
+            }
+            Some(call)
+        })?;
+        Some(self.call_raw(call, &signature.as_wren_string()))
+    }
+
+    /// Whether a script has registered a callback as [key]
+    pub fn has_callback<K: AsRef<str>>(&self, key: K) -> bool {
+        self.execute(|vm| vm.user_data().callbacks.borrow().entries.contains_key(key.as_ref()))
+    }
+
+    /// The keys of every registered callback, sorted
+    pub fn callback_keys(&self) -> Vec<String> {
+        let mut keys: Vec<_> = self.execute(|vm| vm.user_data().callbacks.borrow().entries.keys().cloned().collect());
+        keys.sort();
+        keys
+    }
+
+    /// Releases the callback registered as [key], returning whether there was one
+    pub fn unregister_callback<K: AsRef<str>>(&self, key: K) -> bool {
+        self.execute(|vm| {
+            let handle = vm.user_data().callbacks.borrow_mut().entries.remove(key.as_ref());
+            if let Some(handle) = handle {
+                unsafe { wren_sys::wrenReleaseHandle(vm.vm, handle) };
+            }
+            handle.is_some()
+        })
+    }
+
+    /// Runs scheduled functions that are due, in the order they're due, until [budget] is spent
+    ///
+    /// At least one due function runs per tick, so nothing waits forever. Anything due that doesn't
+    /// fit is left for the next tick, and if a function overruns the budget, the overrun is taken
+    /// out of the next tick's budget. Functions scheduled while ticking wait for the next tick.
+    pub fn tick(&self, budget: Duration) -> TickReport {
+        let start = Instant::now();
+        let budget = budget.saturating_sub(self.execute(|vm| mem::take(&mut vm.user_data().callbacks.borrow_mut().overrun)));
+        let mut report = TickReport::default();
+        loop {
+            if report.ran > 0 && start.elapsed() >= budget {
+                break;
+            }
+            let next = self.execute(|vm| {
+                let handle = vm.user_data().callbacks.borrow_mut().pop_due(start)?;
+                Some((handle, vm.cached_call_handle(FunctionSignature::new_function("call", 0))))
+            });
+            let (handle, call) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            self.execute(|vm| unsafe {
+                vm.ensure_slots(1);
+                wren_sys::wrenSetSlotHandle(vm.vm, 0, handle);
+                wren_sys::wrenReleaseHandle(vm.vm, handle);
+            });
+            if let Err(err) = self.call_raw(call, "call()") {
+                report.errors.push(err);
+            }
+            report.ran += 1;
+        }
+        report.elapsed = start.elapsed();
+        self.execute(|vm| {
+            let mut callbacks = vm.user_data().callbacks.borrow_mut();
+            report.deferred = callbacks.scheduled.iter().take_while(|(due, _)| *due <= start).count();
+            callbacks.overrun = report.elapsed.saturating_sub(budget);
+        });
+        report
+    }
+}
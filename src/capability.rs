@@ -0,0 +1,119 @@
+//! Capability policy for the optional stdlib modules
+//!
+//! Modules that reach outside of the VM (spawning processes and the like) check the
+//! policy of the VM they are running in before doing anything, and abort the calling fiber
+//! if the capability was never granted.
+//!
+//! Capabilities can be granted to the whole VM, or only to the modules whose names match a pattern,
+//! so trusted scripts and untrusted mods can share a VM. Importing a module that needs a capability
+//! from a module without it aborts the import.
+use std::collections::HashSet;
+
+/// Something a script may be allowed to do outside of the VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Spawning host processes (the `process` module)
+    Process,
+}
+
+impl Capability {
+    /// Every capability there is
+    pub const ALL: &'static [Capability] = &[Capability::Process];
+
+    /// The stdlib module that needs this capability
+    pub fn module(self) -> &'static str {
+        match self {
+            Capability::Process => "process",
+        }
+    }
+
+    /// The capability needed to import [module], if any
+    pub fn for_module<M: AsRef<str>>(module: M) -> Option<Capability> {
+        Capability::ALL.iter().copied().find(|cap| cap.module() == module.as_ref())
+    }
+}
+
+/// The set of capabilities granted to a VM
+///
+/// By default, nothing is granted.
+#[derive(Debug, Clone, Default)]
+pub struct CapabilityPolicy {
+    granted: HashSet<Capability>,
+    // Capabilities granted only to modules matching a pattern
+    module_grants: Vec<(String, Capability)>,
+    allowed_commands: Option<HashSet<String>>,
+}
+
+impl CapabilityPolicy {
+    pub fn new() -> CapabilityPolicy {
+        CapabilityPolicy::default()
+    }
+
+    /// Grants every capability, with no command allowlist
+    pub fn allow_all() -> CapabilityPolicy {
+        CapabilityPolicy::new().grant(Capability::Process)
+    }
+
+    pub fn grant(mut self, cap: Capability) -> Self {
+        self.granted.insert(cap);
+        self
+    }
+
+    /// Grants [cap] only to modules whose name matches [pattern]
+    ///
+    /// A pattern is either a module name, or a prefix followed by `*` (so `"mods/*"` matches every module
+    /// under `mods/`, and `"*"` matches everything). Grants decide which modules can import the capability's
+    /// module. A module without the grant can't import one that imported it (however indirectly), and a module
+    /// with the grant loaded by an import from one without it doesn't get it either, so nothing is passed on.
+    pub fn grant_to<P: Into<String>>(mut self, pattern: P, cap: Capability) -> Self {
+        self.module_grants.push((pattern.into(), cap));
+        self
+    }
+
+    /// Revokes [cap] from the whole VM, including any grants to modules
+    pub fn revoke(mut self, cap: Capability) -> Self {
+        self.granted.remove(&cap);
+        self.module_grants.retain(|(_, granted)| *granted != cap);
+        self
+    }
+
+    /// Adds a program to the command allowlist
+    ///
+    /// Once any command is allowed, only allowlisted programs can be spawned.
+    pub fn allow_command<S: Into<String>>(mut self, command: S) -> Self {
+        self.allowed_commands.get_or_insert_with(HashSet::new).insert(command.into());
+        self
+    }
+
+    /// Whether [cap] is granted to the whole VM
+    pub fn is_granted(&self, cap: Capability) -> bool {
+        self.granted.contains(&cap)
+    }
+
+    /// Whether [module] may import the module behind [cap]
+    pub fn is_granted_to<M: AsRef<str>>(&self, module: M, cap: Capability) -> bool {
+        self.is_granted(cap) || self.module_grants.iter().any(|(pattern, granted)| {
+            *granted == cap && matches(pattern, module.as_ref())
+        })
+    }
+
+    /// Whether [cap] is granted to the VM or to any module in it
+    pub fn is_granted_anywhere(&self, cap: Capability) -> bool {
+        self.is_granted(cap) || self.module_grants.iter().any(|(_, granted)| *granted == cap)
+    }
+
+    /// Checks that processes may be spawned, and that [command] passes the allowlist (if any)
+    pub fn is_command_allowed<S: AsRef<str>>(&self, command: S) -> bool {
+        self.is_granted_anywhere(Capability::Process) && match self.allowed_commands {
+            Some(ref allowed) => allowed.contains(command.as_ref()),
+            None => true,
+        }
+    }
+}
+
+fn matches(pattern: &str, module: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => module.starts_with(prefix),
+        None => pattern == module,
+    }
+}
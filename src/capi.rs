@@ -0,0 +1,386 @@
+//! A C interface to ruwren, for engines that aren't written in Rust
+//!
+//! Build it with `cargo rustc --release --features capi --crate-type staticlib` (or `cdylib`),
+//! and include `include/ruwren.h`.
+//!
+//! Every pointer returned by a `_new` function (or documented as owned) must be given back to
+//! the matching `_free` function. Strings going in must be NUL-terminated UTF-8.
+use crate::{VMConfig, VMWrapper, VMError, ModuleLibrary, WrenValue, WrenList, FunctionSignature};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// A VM, along with the last error it reported
+pub struct RuwrenVM {
+    vm: VMWrapper,
+    error: Option<CString>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuwrenResult {
+    Success = 0,
+    CompileError = 1,
+    RuntimeError = 2,
+    /// A pointer was null, a string wasn't UTF-8, or an argument can't be given to Wren
+    InvalidArgument = 3,
+    /// Rust code panicked
+    Panic = 4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuwrenValueType {
+    Null = 0,
+    Bool = 1,
+    Num = 2,
+    String = 3,
+    List = 4,
+    Map = 5,
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+fn owned_string(s: String) -> *mut c_char {
+    // Wren strings can hold NULs, which C can't see past anyway
+    let s = s.split('\0').next().unwrap_or_default();
+    CString::new(s).unwrap().into_raw()
+}
+
+fn boxed(value: WrenValue) -> *mut WrenValue {
+    Box::into_raw(Box::new(value))
+}
+
+impl RuwrenVM {
+    fn finish<T>(&mut self, result: std::thread::Result<Result<T, VMError>>, out: impl FnOnce(T)) -> RuwrenResult {
+        match result {
+            Ok(Ok(value)) => {
+                self.error = None;
+                out(value);
+                RuwrenResult::Success
+            },
+            Ok(Err(err)) => {
+                let code = match err {
+                    VMError::Compile { .. } => RuwrenResult::CompileError,
+                    _ => RuwrenResult::RuntimeError,
+                };
+                self.error = CString::new(err.to_string().replace('\0', "")).ok();
+                code
+            },
+            Err(_) => {
+                self.error = CString::new("ruwren panicked").ok();
+                RuwrenResult::Panic
+            },
+        }
+    }
+}
+
+/// Returns an owned, empty library
+#[no_mangle]
+pub extern "C" fn ruwren_library_new() -> *mut ModuleLibrary {
+    Box::into_raw(Box::new(ModuleLibrary::new()))
+}
+
+/// # Safety
+/// [lib] must have come from [ruwren_library_new], and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_library_free(lib: *mut ModuleLibrary) {
+    if !lib.is_null() {
+        drop(Box::from_raw(lib));
+    }
+}
+
+/// Registers one of ruwren's [stdlib](crate::stdlib) modules ("path", "encoding", and, if ruwren
+/// was built with them, "process", "hash" and "log"), returning whether [name] was one of them
+///
+/// # Safety
+/// [lib] must be a live library, and [name] a C string
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_library_add_stdlib(lib: *mut ModuleLibrary, name: *const c_char) -> bool {
+    let lib = match lib.as_mut() {
+        Some(lib) => lib,
+        None => return false,
+    };
+    match str_arg(name) {
+        Some("path") => crate::stdlib::path::publish_module(lib),
+        Some("encoding") => crate::stdlib::encoding::publish_module(lib),
+        #[cfg(feature = "process")]
+        Some("process") => crate::stdlib::process::publish_module(lib),
+        #[cfg(feature = "hash")]
+        Some("hash") => crate::stdlib::hash::publish_module(lib),
+        #[cfg(feature = "log")]
+        Some("log") => crate::stdlib::log::publish_module(lib),
+        _ => return false,
+    }
+    true
+}
+
+/// Returns an owned VM using the classes in [lib] (which may be null), or null if it couldn't be built
+///
+/// The VM keeps its own copy of [lib], which can be freed afterwards.
+///
+/// # Safety
+/// [lib] must be null or a live library
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_vm_new(lib: *const ModuleLibrary) -> *mut RuwrenVM {
+    let lib = lib.as_ref();
+    // Closure methods in the library can't be unwind safe, but a VM that failed to build isn't kept
+    catch_unwind(AssertUnwindSafe(|| {
+        let mut config = VMConfig::new();
+        if let Some(lib) = lib {
+            config = config.library(lib);
+        }
+        config.build()
+    })).map(|vm| Box::into_raw(Box::new(RuwrenVM { vm, error: None }))).unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+/// [vm] must have come from [ruwren_vm_new], and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_vm_free(vm: *mut RuwrenVM) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// The message of the last error [vm] reported, or null if the last call succeeded
+///
+/// The string belongs to [vm], and only lasts until the next call that uses it.
+///
+/// # Safety
+/// [vm] must be a live VM
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_vm_error(vm: *const RuwrenVM) -> *const c_char {
+    match vm.as_ref().and_then(|vm| vm.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Runs [source] in [module]
+///
+/// # Safety
+/// [vm] must be a live VM, and [module] and [source] C strings
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_vm_interpret(vm: *mut RuwrenVM, module: *const c_char, source: *const c_char) -> RuwrenResult {
+    let (vm, module, source) = match (vm.as_mut(), str_arg(module), str_arg(source)) {
+        (Some(vm), Some(module), Some(source)) => (vm, module, source),
+        _ => return RuwrenResult::InvalidArgument,
+    };
+    let result = catch_unwind(AssertUnwindSafe(|| vm.vm.interpret(module, source)));
+    vm.finish(result, |_| {})
+}
+
+/// Evaluates the expression [expr] (see [VMWrapper::eval]), storing its owned value in [out] if that isn't null
+///
+/// # Safety
+/// [vm] must be a live VM, [expr] a C string, and [out] null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_vm_eval(vm: *mut RuwrenVM, expr: *const c_char, out: *mut *mut WrenValue) -> RuwrenResult {
+    let (vm, expr) = match (vm.as_mut(), str_arg(expr)) {
+        (Some(vm), Some(expr)) => (vm, expr),
+        _ => return RuwrenResult::InvalidArgument,
+    };
+    let result = catch_unwind(AssertUnwindSafe(|| vm.vm.eval(expr)));
+    vm.finish(result, |value| if !out.is_null() {
+        *out = boxed(value);
+    })
+}
+
+/// Calls static method [method] of [class] in [module] with the [argc] values in [args], storing
+/// the owned result in [out] if that isn't null
+///
+/// Results that can't be copied out of Wren (like foreign objects) come back as null values.
+///
+/// # Safety
+/// [vm] must be a live VM, [module], [class] and [method] C strings, [args] [argc] live values
+/// (or null if [argc] is 0), and [out] null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_vm_call(vm: *mut RuwrenVM, module: *const c_char, class: *const c_char, method: *const c_char, args: *const *const WrenValue, argc: usize, out: *mut *mut WrenValue) -> RuwrenResult {
+    let (vm, module, class, method) = match (vm.as_mut(), str_arg(module), str_arg(class), str_arg(method)) {
+        (Some(vm), Some(module), Some(class), Some(method)) => (vm, module, class, method),
+        _ => return RuwrenResult::InvalidArgument,
+    };
+    let args: Vec<&WrenValue> = if argc == 0 {
+        vec![]
+    } else if args.is_null() {
+        return RuwrenResult::InvalidArgument;
+    } else {
+        match std::slice::from_raw_parts(args, argc).iter().map(|arg| arg.as_ref()).collect() {
+            Some(args) => args,
+            None => return RuwrenResult::InvalidArgument,
+        }
+    };
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        vm.vm.execute(|vm| {
+            vm.ensure_slots(args.len() + 1);
+            for (slot, arg) in args.iter().enumerate() {
+                vm.set_slot_value(slot + 1, arg);
+            }
+        });
+        vm.vm.call_static(module, class, FunctionSignature::new_function(method, args.len()))?;
+        Ok(vm.vm.execute(|vm| vm.get_slot_value(0).unwrap_or(WrenValue::Null)))
+    }));
+    vm.finish(result, |value| if !out.is_null() {
+        *out = boxed(value);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn ruwren_value_null() -> *mut WrenValue {
+    boxed(WrenValue::Null)
+}
+
+#[no_mangle]
+pub extern "C" fn ruwren_value_bool(b: bool) -> *mut WrenValue {
+    boxed(WrenValue::Bool(b))
+}
+
+#[no_mangle]
+pub extern "C" fn ruwren_value_num(n: f64) -> *mut WrenValue {
+    boxed(WrenValue::Num(n))
+}
+
+/// Returns null if [s] isn't UTF-8
+///
+/// # Safety
+/// [s] must be a C string
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_string(s: *const c_char) -> *mut WrenValue {
+    str_arg(s).map(|s| boxed(WrenValue::String(s.to_string()))).unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+pub extern "C" fn ruwren_value_list() -> *mut WrenValue {
+    boxed(WrenValue::List(WrenList::new()))
+}
+
+/// Appends [item] to [list], taking ownership of [item] (even if [list] isn't a list)
+///
+/// # Safety
+/// [list] and [item] must be live values
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_list_push(list: *mut WrenValue, item: *mut WrenValue) -> bool {
+    if item.is_null() {
+        return false;
+    }
+    let item = *Box::from_raw(item);
+    match list.as_mut() {
+        Some(WrenValue::List(list)) => {
+            list.push(item);
+            true
+        },
+        _ => false,
+    }
+}
+
+/// # Safety
+/// [value] must have been returned owned by ruwren, and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_free(value: *mut WrenValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// # Safety
+/// [value] must be a live value
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_type(value: *const WrenValue) -> RuwrenValueType {
+    match value.as_ref() {
+        Some(WrenValue::Bool(_)) => RuwrenValueType::Bool,
+        Some(WrenValue::Num(_)) => RuwrenValueType::Num,
+        Some(WrenValue::String(_)) => RuwrenValueType::String,
+        Some(WrenValue::List(_)) => RuwrenValueType::List,
+        Some(WrenValue::Map(_)) => RuwrenValueType::Map,
+        Some(WrenValue::Null) | None => RuwrenValueType::Null,
+    }
+}
+
+/// false for anything but a bool
+///
+/// # Safety
+/// [value] must be a live value
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_get_bool(value: *const WrenValue) -> bool {
+    matches!(value.as_ref(), Some(WrenValue::Bool(true)))
+}
+
+/// 0 for anything but a number
+///
+/// # Safety
+/// [value] must be a live value
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_get_num(value: *const WrenValue) -> f64 {
+    match value.as_ref() {
+        Some(WrenValue::Num(n)) => *n,
+        _ => 0.0,
+    }
+}
+
+/// An owned copy of a string value (free it with [ruwren_string_free]), or null for anything else
+///
+/// # Safety
+/// [value] must be a live value
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_get_string(value: *const WrenValue) -> *mut c_char {
+    match value.as_ref() {
+        Some(WrenValue::String(s)) => owned_string(s.clone()),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// The number of elements in a list value, or 0 for anything else
+///
+/// # Safety
+/// [value] must be a live value
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_list_count(value: *const WrenValue) -> usize {
+    match value.as_ref() {
+        Some(WrenValue::List(list)) => list.len(),
+        _ => 0,
+    }
+}
+
+/// Element [index] of a list value, or null if there isn't one
+///
+/// The element still belongs to the list, so it mustn't be freed.
+///
+/// # Safety
+/// [value] must be a live value
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_list_get(value: *const WrenValue, index: usize) -> *const WrenValue {
+    match value.as_ref() {
+        Some(WrenValue::List(list)) => list.get(index).map(|item| item as *const _).unwrap_or(ptr::null()),
+        _ => ptr::null(),
+    }
+}
+
+/// An owned string of [value] as Wren would print it (free it with [ruwren_string_free])
+///
+/// # Safety
+/// [value] must be a live value
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_value_to_string(value: *const WrenValue) -> *mut c_char {
+    match value.as_ref() {
+        Some(value) => owned_string(value.to_string()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// [s] must be a string returned owned by ruwren, and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn ruwren_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
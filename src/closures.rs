@@ -0,0 +1,279 @@
+//! Foreign methods made from closures registered on a [Module] at runtime, so bindings can hold on
+//! to host state (asset managers, channels) without a static to find it in
+//!
+//! Wren calls a foreign method with nothing but the VM, so each closure a VM binds is given one of a
+//! fixed table of trampolines, which knows its index into that VM's bound closures.
+//!
+//! Libraries can be shared between threads building VMs, so the closures have to be Send and
+//! Sync; state they share with the host goes in an `Arc<Mutex<_>>` or a channel.
+use crate::{VM, Module, UserData, Constructor, ForeignObject, ForeignBorrowError, FunctionSignature, SignatureError, MethodFn, RuntimeClass, Allocator};
+use crate::current::RunningMethod;
+use wren_sys::WrenVM;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::{any, ffi, fmt, mem, ptr};
+
+/// The most closure methods one VM can bind
+pub const MAX_CLOSURE_METHODS: usize = 256;
+
+pub(crate) struct ClosureMethod {
+    pub(crate) class: String,
+    // None for a constructor
+    pub(crate) signature: Option<FunctionSignature>,
+    call: Box<dyn Fn(&VM) + Send + Sync>,
+}
+
+impl fmt::Debug for ClosureMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClosureMethod")
+            .field("class", &self.class)
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
+
+impl Module {
+    /// Binds the foreign instance method [signature] of [class] to [method], which is handed the
+    /// foreign T the method was called on
+    ///
+    /// A VM can bind at most [MAX_CLOSURE_METHODS] of them; past that, Wren reports the method as
+    /// missing when the class declaring it is compiled.
+    pub fn method<T, C, F>(&mut self, class: C, signature: FunctionSignature, method: F) -> &mut Self
+        where T: 'static, C: Into<String>, F: 'static + Fn(&VM, &mut T) + Send + Sync
+    {
+        self.closure(class.into(), false, signature, Box::new(instance_call(method)))
+    }
+
+    /// Binds the foreign static method [signature] of [class] to [method]
+    ///
+    /// [class] doesn't have to be foreign, as Wren lets any class declare foreign methods.
+    pub fn static_method<C, F>(&mut self, class: C, signature: FunctionSignature, method: F) -> &mut Self
+        where C: Into<String>, F: 'static + Fn(&VM) + Send + Sync
+    {
+        self.closure(class.into(), true, signature, Box::new(method))
+    }
+
+    fn closure(&mut self, class: String, is_static: bool, signature: FunctionSignature, call: Box<dyn Fn(&VM) + Send + Sync>) -> &mut Self {
+        let key = (class.clone(), is_static, signature.as_wren_string());
+        self.closures.insert(key, Arc::new(ClosureMethod { class, signature: Some(signature), call }));
+        self
+    }
+
+    /// Starts registering the foreign class [name] without [crate::create_module!], holding a T
+    ///
+    /// For classes only known at runtime, like ones listed by plugins or in a data file:
+    ///
+    /// ```ignore
+    /// module.class_builder::<Vec2, _>("Vec2")
+    ///     .constructor(|c| Vec2 { x: c.arg(0).unwrap(), y: c.arg(1).unwrap() })
+    ///     .method("dot(_)", |vm, v| {
+    ///         let other: Vec2 = vm.try_borrow_foreign(1, Vec2::clone).unwrap();
+    ///         vm.set_slot_double(0, v.x * other.x + v.y * other.y);
+    ///     })?
+    ///     .static_method("zero", |vm| vm.set_slot_foreign(0, Vec2 { x: 0.0, y: 0.0 }).unwrap())?;
+    /// ```
+    ///
+    /// The class is registered straight away, and each call adds to it. Unless the module is given
+    /// a [Module::source], it's declared by the module's stub (see
+    /// [crate::ModuleLibrary::generate_stub]), so constructors go in [ClassBuilder::wren].
+    pub fn class_builder<T: 'static, N: Into<String>>(&mut self, name: N) -> ClassBuilder<'_, T> {
+        let name = name.into();
+        let construct = Arc::new(ClosureMethod {
+            class: name.clone(),
+            signature: None,
+            call: Box::new(allocate::<T, _>(|_: &Constructor| -> T { panic!("can only be created from Rust") })),
+        });
+        self.classes.insert(name.clone(), RuntimeClass {
+            construct: Allocator::Closure(construct),
+            destruct: finalize::<T>,
+            method_index: [HashMap::new(), HashMap::new()],
+            wren: String::new(),
+            type_id: any::TypeId::of::<T>(),
+            is_send: false,
+        });
+        ClassBuilder { module: self, name, marker: PhantomData }
+    }
+
+    pub(crate) fn get_closure(&self, class: &str, is_static: bool, signature: &str) -> Option<&Arc<ClosureMethod>> {
+        self.closures.get(&(class.to_string(), is_static, signature.to_string()))
+    }
+}
+
+// Runs an instance method's closure on the foreign object it was called on
+fn instance_call<T, F>(method: F) -> impl Fn(&VM) + Send + Sync where T: 'static, F: Fn(&VM, &mut T) + Send + Sync {
+    move |vm: &VM| {
+        vm.ensure_slots(1);
+        match vm.try_borrow_foreign_mut::<T, _, _>(0, |inst| method(vm, inst)) {
+            Ok(()) => {},
+            Err(ForeignBorrowError::AlreadyBorrowed) => panic!("Tried to call a method of {} while it is already in use", std::any::type_name::<T>()),
+            Err(_) => panic!("Tried to call a method of {0} on non-{0} type", std::any::type_name::<T>()),
+        }
+    }
+}
+
+/// Adds to a foreign class registered with [Module::class_builder]
+pub struct ClassBuilder<'a, T> {
+    module: &'a mut Module,
+    name: String,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: 'static> ClassBuilder<'a, T> {
+    /// Makes the T for each of the class's constructors (see [crate::Constructor::dispatch] for
+    /// telling several apart)
+    ///
+    /// Without one, the class can only be created from Rust.
+    pub fn constructor<F: 'static + Fn(&Constructor) -> T + Send + Sync>(self, constructor: F) -> Self {
+        let construct = Arc::new(ClosureMethod { class: self.name.clone(), signature: None, call: Box::new(allocate(constructor)) });
+        if let Some(rc) = self.module.classes.get_mut(&self.name) {
+            rc.construct = Allocator::Closure(construct);
+        }
+        self
+    }
+
+    /// Binds the instance method [signature] (like `dot(_)`) to [method] (see [Module::method])
+    pub fn method<F>(self, signature: &str, method: F) -> Result<Self, SignatureError>
+        where F: 'static + Fn(&VM, &mut T) + Send + Sync
+    {
+        let signature = FunctionSignature::parse(signature)?;
+        self.module.method(self.name.clone(), signature, method);
+        Ok(self)
+    }
+
+    /// Binds the static method [signature] (like `zero`) to [method] (see [Module::static_method])
+    pub fn static_method<F>(self, signature: &str, method: F) -> Result<Self, SignatureError>
+        where F: 'static + Fn(&VM) + Send + Sync
+    {
+        let signature = FunctionSignature::parse(signature)?;
+        self.module.static_method(self.name.clone(), signature, method);
+        Ok(self)
+    }
+
+    /// Pastes [wren] (like `construct new(x, y) {}`) into the start of the class's body in its stub
+    pub fn wren<S: Into<String>>(self, wren: S) -> Self {
+        self.module.class_wren(&self.name, wren);
+        self
+    }
+
+    /// Marks the class as Send (see [crate::VMConfig::require_send])
+    pub fn send(self) -> Self where T: Send {
+        if let Some(rc) = self.module.classes.get_mut(&self.name) {
+            rc.is_send = true;
+        }
+        self
+    }
+}
+
+// Does what create_module!'s constructor glue does, with [constructor] making the object
+fn allocate<T: 'static, F: Fn(&Constructor) -> T + Send + Sync>(constructor: F) -> impl Fn(&VM) + Send + Sync {
+    move |vm: &VM| unsafe {
+        let context = Constructor::new(vm);
+        let data = wren_sys::wrenSetSlotNewForeign(vm.vm, 0, 0, mem::size_of::<ForeignObject<T>>() as wren_sys::size_t) as *mut ForeignObject<T>;
+        // Left empty if the constructor panics, so the finalizer knows to skip it
+        ptr::write(data, ForeignObject::new(ptr::null_mut()));
+        let object = constructor(&context);
+        (*data).object = Box::into_raw(Box::new(object));
+    }
+}
+
+extern "C" fn finalize<T>(data: *mut ffi::c_void) {
+    unsafe {
+        let fo = &mut *(data as *mut ForeignObject<T>);
+        if !fo.object.is_null() {
+            drop(Box::from_raw(fo.object));
+            fo.object = ptr::null_mut();
+        }
+    }
+}
+
+impl UserData {
+    // The closure registered for a foreign method, given a trampoline
+    pub(crate) fn bind_closure_method(&self, module: &str, class: &str, is_static: bool, signature: &str) -> Option<MethodFn> {
+        let method = self.overlay.borrow().modules.get(module)
+            .and_then(|md| md.get_closure(class, is_static, signature).cloned())
+            .or_else(|| self.library.as_ref()?.modules.get(module)?.get_closure(class, is_static, signature).cloned())?;
+        self.bind_closure(module, method)
+    }
+
+    // Gives [method] a trampoline, if there's one free (reusing its own if it's been bound before,
+    // so running a module again doesn't use more up)
+    pub(crate) fn bind_closure(&self, module: &str, method: Arc<ClosureMethod>) -> Option<MethodFn> {
+        let mut bound = self.closure_methods.borrow_mut();
+        let existing = bound.iter().position(|(bound_module, bound_method)| {
+            Arc::ptr_eq(bound_method, &method) && bound_module == module
+        });
+        if let Some(index) = existing {
+            return Some(TRAMPOLINES[index / 16][index % 16]);
+        }
+        let index = bound.len();
+        if index >= MAX_CLOSURE_METHODS {
+            return None;
+        }
+        bound.push((module.to_string(), method));
+        Some(TRAMPOLINES[index / 16][index % 16])
+    }
+}
+
+unsafe extern "C" fn trampoline<const HI: usize, const LO: usize>(vm: *mut WrenVM) {
+    run_closure(vm, HI * 16 + LO)
+}
+
+macro_rules! trampoline_row {
+    ($hi:literal) => {
+        [
+            trampoline::<$hi, 0>, trampoline::<$hi, 1>, trampoline::<$hi, 2>, trampoline::<$hi, 3>,
+            trampoline::<$hi, 4>, trampoline::<$hi, 5>, trampoline::<$hi, 6>, trampoline::<$hi, 7>,
+            trampoline::<$hi, 8>, trampoline::<$hi, 9>, trampoline::<$hi, 10>, trampoline::<$hi, 11>,
+            trampoline::<$hi, 12>, trampoline::<$hi, 13>, trampoline::<$hi, 14>, trampoline::<$hi, 15>,
+        ]
+    };
+}
+
+static TRAMPOLINES: [[MethodFn; 16]; MAX_CLOSURE_METHODS / 16] = [
+    trampoline_row!(0), trampoline_row!(1), trampoline_row!(2), trampoline_row!(3),
+    trampoline_row!(4), trampoline_row!(5), trampoline_row!(6), trampoline_row!(7),
+    trampoline_row!(8), trampoline_row!(9), trampoline_row!(10), trampoline_row!(11),
+    trampoline_row!(12), trampoline_row!(13), trampoline_row!(14), trampoline_row!(15),
+];
+
+// Does what create_module!'s glue does for a static method or constructor, for the closure bound at [index]
+unsafe fn run_closure(vm: *mut WrenVM, index: usize) {
+    use std::panic::{take_hook, set_hook, catch_unwind, AssertUnwindSafe};
+
+    let conf = &*(wren_sys::wrenGetUserData(vm) as *const UserData);
+    let (module, method) = conf.closure_methods.borrow()[index].clone();
+    let vm = std::rc::Weak::upgrade(&conf.vm).unwrap_or_else(|| panic!("Failed to access VM at {:p}", &conf.vm));
+    set_hook(Box::new(|_| {}));
+    let vm_borrow = AssertUnwindSafe(vm.borrow());
+    // Constructors aren't profiled, like create_module!'s
+    let profile = match method.signature {
+        Some(_) => vm_borrow.profile_foreign_start(),
+        None => None,
+    };
+    let aborted = match catch_unwind(AssertUnwindSafe(|| {
+        vm_borrow.check_heap_limit();
+        let _scope = vm_borrow.enter_method(RunningMethod::Closure(Arc::as_ptr(&method)));
+        (method.call)(&vm_borrow)
+    })) {
+        Ok(_) => false,
+        Err(err) => {
+            let err_string = if let Some(strg) = err.downcast_ref::<String>() {
+                strg.clone()
+            } else if let Some(strg) = err.downcast_ref::<&str>() {
+                strg.to_string()
+            } else {
+                "Non-string panic message".into()
+            };
+
+            let err_string = vm_borrow.record_foreign_abort(&err_string, &module, &method.class, method.signature.clone());
+            vm_borrow.set_slot_string(0, err_string);
+            vm_borrow.abort_fiber(0);
+            true
+        }
+    };
+    if let Some(signature) = &method.signature {
+        vm_borrow.profile_foreign_end(profile, aborted, || (method.class.as_str(), signature.clone()));
+    }
+    drop(take_hook());
+}
@@ -0,0 +1,414 @@
+//! Line coverage for scripts, by instrumenting module sources before Wren compiles them
+//!
+//! Lines that start a statement get a `RuwrenCoverage.hit(module, line)` call inserted before them,
+//! and line numbers in errors are mapped back to the original source. The instrumentation is
+//! conservative: lines it can't be sure are the start of a statement are left alone (and not reported).
+use crate::{VM, VMWrapper, UserData};
+use wren_sys::WrenVM;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::os::raw;
+
+/// The module the instrumentation imports its hit counter from
+pub(crate) const MODULE: &str = "ruwren_coverage";
+
+pub(crate) const SOURCE: &str = "class RuwrenCoverage {\n    foreign static hit(module, line)\n}\n";
+
+/// Which lines of a module were instrumented, and how often they ran
+#[derive(Debug, Clone, Default)]
+pub struct ModuleCoverage {
+    /// Lines that could be tracked
+    pub lines: BTreeSet<usize>,
+    /// Hit counts for each tracked line that ran at least once
+    pub hits: BTreeMap<usize, usize>,
+}
+
+impl ModuleCoverage {
+    /// Tracked lines that never ran
+    pub fn missed(&self) -> Vec<usize> {
+        self.lines.iter().filter(|line| !self.hits.contains_key(line)).copied().collect()
+    }
+
+    /// The fraction of tracked lines that ran
+    pub fn ratio(&self) -> f64 {
+        if self.lines.is_empty() {
+            1.0
+        } else {
+            self.hits.len() as f64 / self.lines.len() as f64
+        }
+    }
+}
+
+/// Coverage for every module interpreted or loaded since coverage was last reset
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    pub modules: BTreeMap<String, ModuleCoverage>,
+}
+
+#[derive(Debug, Default)]
+struct ModuleData {
+    name: String,
+    coverage: ModuleCoverage,
+    // Instrumented line (0-based) to original line (1-based), for the last source compiled
+    line_map: Vec<usize>,
+    // Top-level variables, and the line each is declared on
+    variables: Vec<(usize, String)>,
+    imported: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Coverage {
+    modules: Vec<ModuleData>,
+    ids: HashMap<String, usize>,
+}
+
+impl Coverage {
+    fn module_id(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.modules.len();
+        self.modules.push(ModuleData { name: name.to_string(), ..ModuleData::default() });
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub(crate) fn instrument(&mut self, module: &str, source: &str) -> String {
+        let id = self.module_id(module);
+        let data = &mut self.modules[id];
+        let mut output = String::with_capacity(source.len() * 2);
+        data.line_map.clear();
+        let lines = scan(source);
+        // Anything declared by earlier sources is already defined
+        for (declared, _) in &mut data.variables {
+            *declared = 0;
+        }
+        for (number, line) in lines.iter().enumerate() {
+            if let Some(name) = &line.declares {
+                data.variables.retain(|(_, known)| known != name);
+                data.variables.push((number + 1, name.clone()));
+            }
+        }
+
+        // Imports can't be repeated within a module
+        if !data.imported {
+            output.push_str(&format!("import \"{}\" for RuwrenCoverage\n", MODULE));
+            data.line_map.push(1);
+            data.imported = true;
+        }
+
+        for (number, (text, track)) in source.split('\n').zip(trackable(&lines)).enumerate() {
+            let number = number + 1;
+            if track {
+                output.push_str(&format!("RuwrenCoverage.hit({}, {})\n", id, number));
+                data.line_map.push(number);
+                data.coverage.lines.insert(number);
+            }
+            output.push_str(text);
+            output.push('\n');
+            data.line_map.push(number);
+        }
+        output
+    }
+
+    /// Maps a line Wren reported in [module] back to the original source
+    pub(crate) fn original_line(&self, module: &str, line: i32) -> i32 {
+        self.ids.get(module)
+            .and_then(|&id| self.modules[id].line_map.get((line as usize).wrapping_sub(1)))
+            .map(|&line| line as i32)
+            .unwrap_or(line)
+    }
+
+    fn hit(&mut self, id: usize, line: usize) {
+        if let Some(data) = self.modules.get_mut(id) {
+            *data.coverage.hits.entry(line).or_insert(0) += 1;
+        }
+    }
+
+    /// The name of module [id], and the top-level variables declared before [line]
+    #[cfg(feature = "debugger")]
+    pub(crate) fn scope(&self, id: usize, line: usize) -> Option<(String, Vec<String>)> {
+        self.modules.get(id).map(|data| {
+            let variables = data.variables.iter().filter(|(declared, _)| *declared < line).map(|(_, name)| name.clone()).collect();
+            (data.name.clone(), variables)
+        })
+    }
+
+    fn report(&self) -> CoverageReport {
+        CoverageReport {
+            modules: self.modules.iter().map(|data| (data.name.clone(), data.coverage.clone())).collect(),
+        }
+    }
+
+    fn reset(&mut self) {
+        for data in &mut self.modules {
+            data.coverage.hits.clear();
+        }
+    }
+}
+
+pub(crate) extern "C" fn hit(vm: *mut WrenVM) {
+    let conf = unsafe { &*(wren_sys::wrenGetUserData(vm) as *const UserData) };
+    let (id, line) = unsafe {
+        (wren_sys::wrenGetSlotDouble(vm, 1 as raw::c_int), wren_sys::wrenGetSlotDouble(vm, 2 as raw::c_int))
+    };
+    if let Some(coverage) = conf.coverage.as_ref() {
+        coverage.borrow_mut().hit(id as usize, line as usize);
+        #[cfg(feature = "debugger")]
+        {
+            // Don't hold the coverage data while the pause handler runs
+            let scope = coverage.borrow().scope(id as usize, line as usize);
+            if let Some((module, variables)) = scope {
+                crate::debugger::on_line(conf, &module, line as usize, &variables);
+            }
+        }
+    }
+}
+
+impl VM {
+    pub(crate) fn coverage_instrument<'a>(&self, module: &str, source: &'a str) -> Cow<'a, str> {
+        match self.user_data().coverage.as_ref() {
+            Some(coverage) if module != MODULE => Cow::Owned(coverage.borrow_mut().instrument(module, source)),
+            _ => Cow::Borrowed(source),
+        }
+    }
+}
+
+impl VMWrapper {
+    /// Coverage collected so far, or None if coverage wasn't enabled on the VM's config
+    pub fn coverage_report(&self) -> Option<CoverageReport> {
+        self.execute(|vm| vm.user_data().coverage.as_ref().map(|coverage| coverage.borrow().report()))
+    }
+
+    /// Clears all hit counts
+    pub fn reset_coverage(&self) {
+        self.execute(|vm| if let Some(coverage) = vm.user_data().coverage.as_ref() {
+            coverage.borrow_mut().reset()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Brace {
+    Class,
+    Block,
+    Map,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Line {
+    // Starts outside of any string or comment
+    in_code: bool,
+    // Starts inside parentheses or brackets
+    nested: bool,
+    // The innermost brace the line starts in
+    context: Option<Brace>,
+    first: Option<char>,
+    first_word: String,
+    last: Option<char>,
+    last_word: String,
+    // The top-level variable this line declares, if any
+    declares: Option<String>,
+}
+
+/// Works out which lines start a statement that a call can safely be inserted before
+fn trackable(lines: &[Line]) -> Vec<bool> {
+    let mut previous: Option<&Line> = None;
+    lines.iter().map(|line| {
+        let track = starts_statement(line, previous);
+        if line.first.is_some() {
+            previous = Some(line);
+        }
+        track
+    }).collect()
+}
+
+fn starts_statement(line: &Line, previous: Option<&Line>) -> bool {
+    if !line.in_code || line.nested {
+        return false;
+    }
+    if let Some(Brace::Class) | Some(Brace::Map) = line.context {
+        return false;
+    }
+    match line.first {
+        Some(c) if c.is_alphanumeric() || c == '_' || c == '"' => {},
+        _ => return false,
+    }
+    if line.first_word == "else" {
+        return false;
+    }
+    match previous {
+        None => true,
+        Some(prev) => {
+            let last = match prev.last {
+                Some(last) => last,
+                None => return true,
+            };
+            // Attributes have to stay attached to whatever they annotate
+            let attribute = prev.first == Some('#');
+            let header = matches!(prev.first_word.as_str(), "if" | "while" | "for") && last == ')';
+            let dangling = matches!(prev.last_word.as_str(), "else" | "return" | "is" | "in");
+            !attribute && !header && !dangling && (last.is_alphanumeric() || matches!(last, '_' | ')' | ']' | '}' | '"' | '{'))
+        }
+    }
+}
+
+fn scan(source: &str) -> Vec<Line> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut lines = vec![];
+    let mut line = Line { in_code: true, ..Line::default() };
+    let mut comment_depth = 0usize;
+    let (mut in_string, mut in_raw_string) = (false, false);
+    let mut nesting = 0usize;
+    let mut interpolations: Vec<usize> = vec![];
+    let mut braces: Vec<Brace> = vec![];
+    let mut last: Option<char> = None;
+    let mut last_word = String::new();
+    let mut line_has_class = false;
+    let mut declaring = false;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c == '\n' {
+            lines.push(std::mem::take(&mut line));
+            line.in_code = comment_depth == 0 && !in_string && !in_raw_string;
+            line.nested = nesting > 0;
+            line.context = braces.last().copied();
+            line_has_class = false;
+            i += 1;
+            continue;
+        }
+
+        if comment_depth > 0 {
+            if c == '/' && next == Some('*') {
+                comment_depth += 1;
+                i += 2;
+            } else if c == '*' && next == Some('/') {
+                comment_depth -= 1;
+                i += 2;
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+
+        if in_raw_string {
+            if c == '"' && next == Some('"') && chars.get(i + 2) == Some(&'"') {
+                in_raw_string = false;
+                i += 3;
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+
+        if in_string {
+            match c {
+                '\\' if next != Some('\n') => i += 2,
+                '"' => {
+                    in_string = false;
+                    line.last = Some(c);
+                    last = Some(c);
+                    i += 1;
+                },
+                '%' if next == Some('(') => {
+                    in_string = false;
+                    interpolations.push(nesting);
+                    nesting += 1;
+                    i += 2;
+                },
+                _ => i += 1,
+            }
+            continue;
+        }
+
+        if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && next == Some('*') {
+            comment_depth += 1;
+            i += 2;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if line.first.is_none() {
+                line.first = Some(c);
+                line.first_word = word.clone();
+            }
+            if word == "class" {
+                line_has_class = true;
+            }
+            if declaring {
+                line.declares = Some(word.clone());
+                declaring = false;
+            } else if word == "var" && braces.is_empty() && nesting == 0 {
+                declaring = true;
+            }
+            line.last = Some(chars[i - 1]);
+            line.last_word = word.clone();
+            last = Some(chars[i - 1]);
+            last_word = word;
+            continue;
+        }
+
+        match c {
+            '"' => {
+                if next == Some('"') && chars.get(i + 2) == Some(&'"') {
+                    in_raw_string = true;
+                    i += 2;
+                } else {
+                    in_string = true;
+                }
+            },
+            '(' | '[' => nesting += 1,
+            ')' | ']' => {
+                nesting = nesting.saturating_sub(1);
+                if c == ')' && interpolations.last() == Some(&nesting) {
+                    // The end of an interpolation, so we're back in the string
+                    interpolations.pop();
+                    in_string = true;
+                    i += 1;
+                    continue;
+                }
+            },
+            '{' => braces.push(if line_has_class {
+                Brace::Class
+            } else {
+                match last {
+                    None | Some(')') | Some('}') | Some('{') | Some('|') => Brace::Block,
+                    Some(l) if l.is_alphanumeric() || l == '_' => if last_word == "return" { Brace::Map } else { Brace::Block },
+                    _ => Brace::Map,
+                }
+            }),
+            '}' => { braces.pop(); },
+            _ => {},
+        }
+
+        if line.first.is_none() {
+            line.first = Some(c);
+        }
+        line.last = Some(c);
+        line.last_word.clear();
+        last = Some(c);
+        last_word.clear();
+        i += 1;
+    }
+    lines.push(line);
+    lines
+}
@@ -0,0 +1,99 @@
+//! The VM and foreign method running on this thread, for host code too far down to be handed them
+//!
+//! ```ignore
+//! let vm = VMConfig::new().expose_current_vm(true).build();
+//!
+//! fn deep_in_game_logic() {
+//!     ruwren::current_vm(|vm| println!("called from {:?}", vm.name()));
+//! }
+//! ```
+use crate::{VM, FunctionSignature};
+use crate::closures::ClosureMethod;
+use std::cell::Cell;
+
+thread_local! {
+    static CURRENT: Cell<Option<*const VM>> = const { Cell::new(None) };
+}
+
+/// Runs [f] on the VM whose foreign method is running on this thread
+///
+/// Returns None outside of a foreign method, or if that VM wasn't built with
+/// [crate::VMConfig::expose_current_vm]. When foreign methods nest (through calls back into
+/// Wren, or across VMs), this is the innermost one.
+pub fn current_vm<R, F: FnOnce(&VM) -> R>(f: F) -> Option<R> {
+    let vm = CURRENT.with(|current| current.get())?;
+    // Only set by a ForeignScope, which outlives the borrow of the VM it was made from
+    Some(f(unsafe { &*vm }))
+}
+
+// A running foreign method
+#[derive(Clone, Copy)]
+pub(crate) enum RunningMethod {
+    // From create_module!: the class, and the signature (which constructors don't have). The
+    // signature is only built if it's asked for, since most foreign methods never need it.
+    Glue(&'static str, Option<fn() -> FunctionSignature>),
+    // Kept alive by the VM's bound closures
+    Closure(*const ClosureMethod),
+}
+
+impl RunningMethod {
+    // The method's class, and its signature (None for a constructor)
+    fn names(self) -> (String, Option<String>) {
+        match self {
+            RunningMethod::Glue(class, signature) => (class.to_string(), signature.map(|signature| signature().as_wren_string())),
+            RunningMethod::Closure(method) => {
+                let method = unsafe { &*method };
+                (method.class.clone(), method.signature.as_ref().map(FunctionSignature::as_wren_string))
+            },
+        }
+    }
+}
+
+/// Records a foreign method as running until it's dropped, which puts back whatever was running before
+#[doc(hidden)]
+pub struct ForeignScope<'a> {
+    vm: &'a VM,
+    previous_method: Option<RunningMethod>,
+    // Only Some if the VM was made the current_vm
+    previous_vm: Option<Option<*const VM>>,
+    _span: crate::trace::FfiSpan,
+}
+
+impl Drop for ForeignScope<'_> {
+    fn drop(&mut self) {
+        // Runs while unwinding too, so a panicking foreign method can't leave a dangling VM behind
+        self.vm.user_data().foreign_method.set(self.previous_method);
+        if let Some(previous) = self.previous_vm {
+            CURRENT.with(|current| current.set(previous));
+        }
+    }
+}
+
+impl VM {
+    /// Records the foreign method [signature] of [class] as running, and makes this VM the
+    /// [current_vm] if it was built to expose itself, for as long as the scope is held
+    ///
+    /// Called by [crate::create_module!] around each foreign method.
+    #[doc(hidden)]
+    pub fn enter_foreign(&self, class: &'static str, signature: Option<fn() -> FunctionSignature>) -> ForeignScope<'_> {
+        self.enter_method(RunningMethod::Glue(class, signature))
+    }
+
+    pub(crate) fn enter_method(&self, method: RunningMethod) -> ForeignScope<'_> {
+        let previous_method = self.user_data().foreign_method.replace(Some(method));
+        let previous_vm = match self.user_data().expose_current {
+            true => Some(CURRENT.with(|current| current.replace(Some(self as *const VM)))),
+            false => None,
+        };
+        let _span = ffi_span!(TRACE, "foreign", class = %method.names().0, signature = method.names().1.as_deref());
+        ForeignScope { vm: self, previous_method, previous_vm, _span }
+    }
+
+    /// The foreign method running right now, like `Player.heal(_)` (or just `Player` for a constructor)
+    pub fn foreign_method(&self) -> Option<String> {
+        self.user_data().foreign_method.get().map(|method| match method.names() {
+            (class, Some(signature)) => format!("{}.{}", class, signature),
+            (class, None) => class,
+        })
+    }
+}
@@ -0,0 +1,148 @@
+//! An experimental debugger, built on the same source instrumentation as coverage
+//!
+//! The pause handler runs on the script's own thread, in the middle of the script, so "pausing"
+//! is just the handler not returning until the debugger front-end says to carry on.
+//!
+//! Wren's embedding API can't see local variables, so a pause only snapshots the module's top-level variables.
+use crate::{VM, VMWrapper, UserData, SlotType};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+
+/// Why the script paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// The line has a breakpoint on it
+    Breakpoint,
+    /// The handler asked to step on the last pause
+    Step,
+    /// [VMWrapper::request_pause] was called
+    Requested,
+}
+
+/// What the script should do after a pause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run until the next breakpoint
+    Continue,
+    /// Pause again on the next line that can be tracked
+    Step,
+}
+
+/// A snapshot of a variable's value at a pause
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugValue {
+    Null,
+    Bool(bool),
+    Num(f64),
+    String(String),
+    /// Anything that can't be copied out of the VM, like a list or an instance
+    Other(SlotType),
+}
+
+/// Where the script is paused, and what its module looked like at the time
+#[derive(Debug, Clone)]
+pub struct PauseEvent {
+    pub module: String,
+    pub line: usize,
+    pub reason: PauseReason,
+    /// The module's top-level variables that have been declared by this line
+    pub variables: Vec<(String, DebugValue)>,
+}
+
+type PauseHandler = Box<dyn Fn(&PauseEvent, &VM) -> DebugAction>;
+
+pub(crate) struct Debugger {
+    handler: PauseHandler,
+    breakpoints: RefCell<HashSet<(String, usize)>>,
+    stepping: Cell<bool>,
+    pause_requested: Cell<bool>,
+    // Set while the handler runs, so anything it interprets doesn't pause too
+    paused: Cell<bool>,
+}
+
+impl Debugger {
+    pub(crate) fn new<F: 'static + Fn(&PauseEvent, &VM) -> DebugAction>(handler: F) -> Debugger {
+        Debugger {
+            handler: Box::new(handler),
+            breakpoints: RefCell::new(HashSet::new()),
+            stepping: Cell::new(false),
+            pause_requested: Cell::new(false),
+            paused: Cell::new(false),
+        }
+    }
+}
+
+pub(crate) fn on_line(conf: &UserData, module: &str, line: usize, variables: &[String]) {
+    let debugger = match conf.debugger.as_ref() {
+        Some(debugger) if !debugger.paused.get() => debugger,
+        _ => return,
+    };
+
+    let reason = if debugger.pause_requested.take() {
+        PauseReason::Requested
+    } else if debugger.stepping.get() {
+        PauseReason::Step
+    } else if debugger.breakpoints.borrow().contains(&(module.to_string(), line)) {
+        PauseReason::Breakpoint
+    } else {
+        return;
+    };
+
+    let vm = conf.vm.upgrade().expect("VM was dropped while it was running");
+    let vm = vm.borrow();
+    let event = PauseEvent {
+        module: module.to_string(),
+        line,
+        reason,
+        variables: variables.iter().map(|name| (name.clone(), snapshot(&vm, module, name))).collect(),
+    };
+
+    debugger.paused.set(true);
+    let action = crate::guard_callback(&conf.callback_panic, "pause handler", || (debugger.handler)(&event, &vm))
+        .unwrap_or(DebugAction::Continue);
+    debugger.paused.set(false);
+    debugger.stepping.set(action == DebugAction::Step);
+}
+
+fn snapshot(vm: &VM, module: &str, name: &str) -> DebugValue {
+    // Slots 0 to 2 hold the arguments to the instrumentation call
+    let slot = vm.get_slot_count();
+    vm.ensure_slots(slot + 1);
+    vm.get_variable(module, name, slot);
+    match vm.get_slot_type(slot) {
+        SlotType::Null => DebugValue::Null,
+        SlotType::Bool => DebugValue::Bool(vm.get_slot_bool(slot).unwrap()),
+        SlotType::Num => DebugValue::Num(vm.get_slot_double(slot).unwrap()),
+        SlotType::String => vm.get_slot_string(slot).map(DebugValue::String).unwrap_or(DebugValue::Other(SlotType::String)),
+        other => DebugValue::Other(other),
+    }
+}
+
+impl VMWrapper {
+    fn with_debugger<T, F: FnOnce(&Debugger) -> T>(&self, f: F) -> T {
+        self.execute(|vm| f(vm.user_data().debugger.as_ref().expect("no pause handler was set on the VM's config (see VMConfig::on_pause)")))
+    }
+
+    /// Pauses whenever [line] of [module] is about to run
+    ///
+    /// Only lines that show up in the module's [crate::ModuleCoverage::lines] can be broken on.
+    ///
+    /// # Panics
+    /// If the VM wasn't configured with [crate::VMConfig::on_pause]
+    pub fn set_breakpoint<M: Into<String>>(&self, module: M, line: usize) {
+        self.with_debugger(|debugger| debugger.breakpoints.borrow_mut().insert((module.into(), line)));
+    }
+
+    pub fn clear_breakpoint<M: Into<String>>(&self, module: M, line: usize) {
+        self.with_debugger(|debugger| debugger.breakpoints.borrow_mut().remove(&(module.into(), line)));
+    }
+
+    pub fn clear_breakpoints(&self) {
+        self.with_debugger(|debugger| debugger.breakpoints.borrow_mut().clear());
+    }
+
+    /// Pauses on the next line that can be tracked, wherever it is
+    pub fn request_pause(&self) {
+        self.with_debugger(|debugger| debugger.pause_requested.set(true));
+    }
+}
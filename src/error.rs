@@ -0,0 +1,507 @@
+//! Every error ruwren can return
+//!
+//! All of the error enums are non-exhaustive, so new failure modes can be added without breaking
+//! downstream matches. To handle errors without depending on their exact shape, match on [ErrorKind]
+//! (from each error's `kind()`), or convert them into the catch-all [Error].
+use crate::SlotType;
+use thiserror::Error;
+
+/// A coarse, stable classification shared by every ruwren error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Wren couldn't compile some source
+    Compile,
+    /// A fiber aborted while running
+    Runtime,
+    /// Wren produced a string that isn't valid UTF-8
+    InvalidUtf8,
+    /// A slot or foreign object didn't hold the expected type
+    TypeMismatch,
+    /// A foreign object was already borrowed in a conflicting way
+    AlreadyBorrowed,
+    /// A class or module needed for an operation isn't registered or declared
+    MissingClass,
+    /// Wren couldn't allocate memory, or went over its heap limit
+    OutOfMemory,
+    /// Something that has to be Send isn't
+    NotSend,
+    /// A [crate::VMConfig] setting couldn't be applied
+    InvalidConfig,
+    /// Calls between Rust and Wren nested too deeply
+    CallDepthExceeded,
+    /// A method signature isn't one Wren could bind
+    InvalidSignature,
+    /// A callback given to the VM (like its printer or script loader) panicked
+    CallbackPanic,
+}
+
+/// Errors as Wren reports them, before they're collected into a [VMError]
+#[derive(Debug, Clone, Error)]
+#[non_exhaustive]
+pub enum WrenError {
+    /// (module, line, message)
+    #[error("compile error ({0}:{1}): {2}")]
+    Compile(String, i32, String),
+    #[error("runtime error: {0}")]
+    Runtime(String),
+    /// (module, line, function)
+    #[error("in {0}:{1}: {2}")]
+    StackTrace(String, i32, String),
+}
+
+impl WrenError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            WrenError::Compile(..) => ErrorKind::Compile,
+            WrenError::Runtime(..) | WrenError::StackTrace(..) => ErrorKind::Runtime,
+        }
+    }
+}
+
+/// Errors from running Wren code
+#[derive(Debug, Clone, Error)]
+#[non_exhaustive]
+pub enum VMError {
+    #[error("Compile Error ({module}:{line}): {error}")]
+    Compile {
+        module: String,
+        line: i32,
+        error: String
+    },
+    #[error("Runtime Error: {error}\n{}", DisplayFrames(frames))]
+    Runtime {
+        error: String,
+        frames: Vec<VMStackFrameError>
+    },
+    /// Wren produced invalid UTF-8 while running under [crate::StringPolicy::Strict]
+    #[error("Invalid UTF-8 in {context}")]
+    InvalidUtf8 {
+        context: String
+    },
+    /// Too many interprets or calls were nested (see [crate::VMConfig::max_call_depth])
+    #[error("Call depth exceeded the limit of {limit}")]
+    CallDepthExceeded {
+        limit: usize
+    },
+    /// A callback given to the VM, like its [crate::Printer] or [crate::ModuleScriptLoader], panicked while Wren was running
+    ///
+    /// The panic is caught before it reaches Wren: a printer's output is dropped, and a script
+    /// loader that panicked is treated as not finding the module.
+    #[error("{callback} panicked: {message}")]
+    CallbackPanic {
+        callback: String,
+        message: String,
+    },
+    /// Wren allocated more than [crate::VMConfig::max_heap_size] while running
+    #[error("Heap limit of {limit} bytes exceeded")]
+    HeapLimitExceeded {
+        limit: usize
+    },
+    /// Scripts declared foreign classes or methods that nothing was registered for, and the VM
+    /// was built with [crate::VMConfig::strict_library]
+    #[error("Unbound foreign declarations: {}", DisplayList(unbound))]
+    UnboundForeign {
+        unbound: Vec<UnboundForeign>
+    },
+    /// The code ran, but what it returned couldn't be converted (see [crate::VMWrapper::call_returning])
+    #[error("Unexpected return value: {error}")]
+    InvalidReturn {
+        error: SlotError
+    },
+}
+
+impl VMError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            VMError::Compile { .. } => ErrorKind::Compile,
+            VMError::Runtime { .. } => ErrorKind::Runtime,
+            VMError::InvalidUtf8 { .. } => ErrorKind::InvalidUtf8,
+            VMError::CallDepthExceeded { .. } => ErrorKind::CallDepthExceeded,
+            VMError::CallbackPanic { .. } => ErrorKind::CallbackPanic,
+            VMError::HeapLimitExceeded { .. } => ErrorKind::OutOfMemory,
+            VMError::UnboundForeign { .. } => ErrorKind::MissingClass,
+            VMError::InvalidReturn { error } => error.kind(),
+        }
+    }
+}
+
+/// A foreign class or method a script declared, which nothing was registered for (see
+/// [crate::VMWrapper::validate_library])
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum UnboundForeign {
+    /// Wren only finds out when the class is constructed, as it not having an allocator
+    #[error("foreign class {class} in module {module}")]
+    Class {
+        module: String,
+        class: String,
+    },
+    /// Wren aborts the fiber declaring the class
+    #[error("foreign {}method {class}.{signature} in module {module}", if *is_static { "static " } else { "" })]
+    Method {
+        module: String,
+        class: String,
+        is_static: bool,
+        signature: String,
+    },
+}
+
+impl UnboundForeign {
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::MissingClass
+    }
+}
+
+struct DisplayFrames<'a>(&'a [VMStackFrameError]);
+
+impl std::fmt::Display for DisplayFrames<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for frame in self.0 {
+            writeln!(fmt, "\t{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+struct DisplayList<'a, T>(&'a [T]);
+
+impl<T: std::fmt::Display> std::fmt::Display for DisplayList<'_, T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                fmt.write_str(", ")?;
+            }
+            write!(fmt, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VMStackFrameError {
+    pub module: String,
+    /// 0 for foreign frames, which have no line
+    pub line: i32,
+    /// The name Wren gives the function, or Class.signature for foreign frames
+    pub function: String,
+    pub kind: FrameKind,
+    /// The signature of the method, without any decoration (empty for module code)
+    pub signature: String,
+}
+
+/// What kind of code a stack frame is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameKind {
+    /// Top-level code of a module
+    Module,
+    /// A method or function defined in Wren
+    Method,
+    /// A constructor, or the allocation that runs before it
+    Constructor,
+    /// A foreign method or constructor that aborted the fiber
+    Foreign,
+}
+
+impl VMStackFrameError {
+    pub(crate) fn script(module: String, line: i32, function: String) -> VMStackFrameError {
+        let (kind, signature) = if function == "(script)" {
+            (FrameKind::Module, String::new())
+        } else if function.is_empty() {
+            (FrameKind::Constructor, String::new())
+        } else if let Some(signature) = function.strip_prefix("init ") {
+            (FrameKind::Constructor, signature.to_string())
+        } else {
+            (FrameKind::Method, function.clone())
+        };
+        VMStackFrameError { module, line, function, kind, signature }
+    }
+
+    pub fn is_foreign(&self) -> bool {
+        self.kind == FrameKind::Foreign
+    }
+}
+
+impl std::fmt::Display for VMStackFrameError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_foreign() {
+            write!(fmt, "in {}: {} <foreign>", self.module, self.function)
+        } else if self.function.is_empty() {
+            write!(fmt, "in {}:{}: <constructor>", self.module, self.line)
+        } else {
+            write!(fmt, "in {}:{}: {}", self.module, self.line, self.function)
+        }
+    }
+}
+
+/// Errors from building a VM
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// A foreign class isn't known to be Send, but the config requires it (see [crate::VMConfig::require_send])
+    #[error("foreign class {class} in module {module} is not Send")]
+    NonSendClass {
+        module: String,
+        class: String,
+    },
+    /// A callback isn't known to be Send, but the VM is meant to be shared (see [crate::SyncVM])
+    #[error("{callback} is not Send")]
+    NonSendCallback {
+        callback: String,
+    },
+    /// A variable given to [crate::VMConfig::define] couldn't be defined
+    #[error("could not define {name} in module {module}: {error}")]
+    InvalidDefinition {
+        module: String,
+        name: String,
+        error: String,
+    },
+}
+
+impl BuildError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            BuildError::NonSendClass { .. } | BuildError::NonSendCallback { .. } => ErrorKind::NotSend,
+            BuildError::InvalidDefinition { .. } => ErrorKind::InvalidConfig,
+        }
+    }
+}
+
+/// Errors from sending a Rust object to Wren as a foreign object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ForeignSendError {
+    /// The type isn't registered with the VM's library
+    #[error("no foreign class")]
+    NoForeignClass,
+    /// The class hasn't been declared in Wren
+    #[error("no Wren class")]
+    NoWrenClass,
+    #[error("unable to allocate memory")]
+    NoMemory,
+    /// The Wren class is bound to a different Rust type
+    #[error("class mismatch")]
+    ClassMismatch,
+}
+
+impl ForeignSendError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ForeignSendError::NoForeignClass | ForeignSendError::NoWrenClass => ErrorKind::MissingClass,
+            ForeignSendError::NoMemory => ErrorKind::OutOfMemory,
+            ForeignSendError::ClassMismatch => ErrorKind::TypeMismatch,
+        }
+    }
+}
+
+/// Errors from borrowing a foreign object out of a slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ForeignBorrowError {
+    #[error("slot is not a foreign object")]
+    NotForeign,
+    #[error("foreign object is of a different type")]
+    TypeMismatch,
+    #[error("foreign object is already borrowed")]
+    AlreadyBorrowed,
+}
+
+impl ForeignBorrowError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ForeignBorrowError::NotForeign | ForeignBorrowError::TypeMismatch => ErrorKind::TypeMismatch,
+            ForeignBorrowError::AlreadyBorrowed => ErrorKind::AlreadyBorrowed,
+        }
+    }
+}
+
+/// Errors from reading a slot
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum SlotError {
+    #[error("slot {slot} is a <{found:?}>, not a <{expected:?}>")]
+    TypeMismatch {
+        slot: usize,
+        expected: SlotType,
+        found: SlotType,
+    },
+    #[error("slot {slot} is not valid UTF-8")]
+    InvalidUtf8 {
+        slot: usize,
+    },
+    /// The slot holds a number with a fractional part (or NaN, or an infinity), where an integer
+    /// was asked for (see [crate::VM::get_slot_i32])
+    #[error("slot {slot} holds {value}, which is not an integer")]
+    NotAnInteger {
+        slot: usize,
+        value: String,
+    },
+    /// The slot holds an integer that doesn't fit in [ty]
+    #[error("slot {slot} holds {value}, which is out of range for {ty}")]
+    OutOfRange {
+        slot: usize,
+        value: String,
+        ty: &'static str,
+    },
+    /// The slot holds something other than the name or number of one of [ty]'s variants (see
+    /// [crate::VM::get_slot_enum])
+    #[error("slot {slot} holds {value}, which is not a {ty} (expected one of {expected})")]
+    NotAVariant {
+        slot: usize,
+        value: String,
+        ty: &'static str,
+        expected: String,
+    },
+    /// The slot doesn't hold the foreign object that was asked for, or it can't be borrowed
+    #[error("slot {slot}: {error}")]
+    Foreign {
+        slot: usize,
+        error: ForeignBorrowError,
+    },
+    /// A constructor was asked for an argument it wasn't called with (see [crate::Constructor::arg])
+    #[error("argument {index} is past the {arity} the constructor was called with")]
+    MissingArgument {
+        index: usize,
+        arity: usize,
+    },
+    /// Any of the others, read by the foreign method [method] (like `Player.heal(_)`)
+    #[error("{method}: {error}")]
+    InMethod {
+        method: String,
+        error: Box<SlotError>,
+    },
+}
+
+impl SlotError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            SlotError::TypeMismatch { .. } => ErrorKind::TypeMismatch,
+            SlotError::InvalidUtf8 { .. } => ErrorKind::InvalidUtf8,
+            SlotError::NotAnInteger { .. } | SlotError::OutOfRange { .. } => ErrorKind::TypeMismatch,
+            SlotError::NotAVariant { .. } => ErrorKind::TypeMismatch,
+            SlotError::Foreign { error, .. } => error.kind(),
+            SlotError::MissingArgument { .. } => ErrorKind::TypeMismatch,
+            SlotError::InMethod { error, .. } => error.kind(),
+        }
+    }
+}
+
+/// Errors from parsing or validating a [crate::FunctionSignature]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum SignatureError {
+    #[error("signature {0:?} is malformed")]
+    Malformed(String),
+    #[error("{0:?} is not a valid method name")]
+    InvalidName(String),
+    /// The method takes more than the 16 parameters Wren allows
+    #[error("{name} takes {arity} parameters, more than Wren's limit of 16")]
+    TooManyParameters {
+        name: String,
+        arity: usize,
+    },
+    /// An operator with the wrong number of operands, or an operator setter
+    #[error("operator {name} can't be declared as {signature}")]
+    InvalidOperator {
+        name: String,
+        signature: String,
+    },
+    /// A call was given a different number of arguments than its signature takes
+    #[error("{signature} takes {expected} arguments, but was given {found}")]
+    ArgumentCount {
+        signature: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl SignatureError {
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::InvalidSignature
+    }
+}
+
+/// Errors from converting a [crate::WrenValue] into another data format
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ConversionError {
+    /// The format has no way to write the value (like null in TOML)
+    #[error("{format} has no equivalent of {value}")]
+    Unrepresentable {
+        format: &'static str,
+        value: String,
+    },
+    #[error("{format} map keys must be strings, not {key}")]
+    NonStringKey {
+        format: &'static str,
+        key: String,
+    },
+    /// A value's own Serialize impl failed (see [crate::serde])
+    #[cfg(feature = "serde")]
+    #[error("Failed to serialize: {message}")]
+    Serialize {
+        message: String,
+    },
+    /// A slot's value didn't fit the type it was read into, at [path] (like `players[1].name`)
+    /// in the value, which is empty for the value itself (see [crate::VM::get_slot_deserialize])
+    #[cfg(feature = "serde")]
+    #[error("Failed to deserialize{}: {message}", DisplayPath(path))]
+    Deserialize {
+        path: String,
+        message: String,
+    },
+}
+
+#[cfg(feature = "serde")]
+struct DisplayPath<'a>(&'a str);
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for DisplayPath<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            "" => Ok(()),
+            path => write!(fmt, " {}", path),
+        }
+    }
+}
+
+impl ConversionError {
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::TypeMismatch
+    }
+}
+
+/// Any ruwren error, for code that just wants to pass them along
+#[derive(Debug, Clone, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    VM(#[from] VMError),
+    #[error(transparent)]
+    Build(#[from] BuildError),
+    #[error(transparent)]
+    ForeignSend(#[from] ForeignSendError),
+    #[error(transparent)]
+    ForeignBorrow(#[from] ForeignBorrowError),
+    #[error(transparent)]
+    Slot(#[from] SlotError),
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+    #[error(transparent)]
+    Conversion(#[from] ConversionError),
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::VM(err) => err.kind(),
+            Error::Build(err) => err.kind(),
+            Error::ForeignSend(err) => err.kind(),
+            Error::ForeignBorrow(err) => err.kind(),
+            Error::Slot(err) => err.kind(),
+            Error::Signature(err) => err.kind(),
+            Error::Conversion(err) => err.kind(),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Events that scripts emit for Rust to pick up, as typed values
+//!
+//! ```wren
+//! import "ruwren/host" for Host
+//! Host.emit("spawn", {"kind": "goblin", "x": 3, "y": 4})
+//! ```
+use crate::{VMWrapper, UserData, WrenValue, WrenMap, FromWrenValue};
+use wren_sys::WrenVM;
+use std::collections::VecDeque;
+use std::ffi;
+
+pub(crate) const MODULE: &str = "ruwren/host";
+
+// Wren can't list a map's keys from Rust, so payloads are flattened before they're sent: every
+// list becomes [1, items...] and every map [0, key, value, ...], so the two can be told apart
+pub(crate) const SOURCE: &str = "class Host {
+    foreign static emit_(name, payload)
+    static emit(name) { emit_(name, null) }
+    static emit(name, payload) { emit_(name, flatten_(payload, [])) }
+    // [path] holds the lists and maps [value] is inside of, so one that contains itself is caught
+    static flatten_(value, path) {
+        if (!(value is List) && !(value is Map)) return value
+        if (path.any {|outer| Object.same(outer, value) }) Fiber.abort(\"Event payload can't contain itself\")
+        path.add(value)
+        var flat = [value is List ? 1 : 0]
+        if (value is List) {
+            for (item in value) flat.add(flatten_(item, path))
+        } else {
+            for (key in value.keys) {
+                flat.add(flatten_(key, path))
+                flat.add(flatten_(value[key], path))
+            }
+        }
+        path.removeAt(-1)
+        return flat
+    }
+}
+";
+
+// Every event emitted so far, as (name, payload)
+pub(crate) type EventQueue = VecDeque<(String, WrenValue)>;
+
+/// A Rust type scripts can emit as an event (see [VMWrapper::events])
+///
+/// Usually a [crate::WrenRecord], so the payload is a map of its fields:
+///
+/// ```ignore
+/// #[derive(WrenRecord)]
+/// struct SpawnEvent { kind: String, x: f64, y: f64 }
+///
+/// impl WrenEvent for SpawnEvent {
+///     const NAME: &'static str = "spawn";
+/// }
+/// ```
+pub trait WrenEvent: FromWrenValue {
+    /// The name scripts emit it under
+    const NAME: &'static str;
+}
+
+// Rebuilds a payload flattened by Host.flatten_
+fn unflatten(value: WrenValue) -> WrenValue {
+    let items = match value {
+        WrenValue::List(list) => list.into_vec(),
+        value => return value,
+    };
+    let mut items = items.into_iter().map(unflatten);
+    match items.next() {
+        Some(WrenValue::Num(0.0)) => {
+            let mut map = WrenMap::new();
+            while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                map.insert(key, value);
+            }
+            WrenValue::Map(map)
+        },
+        _ => items.collect::<Vec<_>>().into(),
+    }
+}
+
+unsafe fn abort(vm: *mut WrenVM, message: &str) {
+    let message = ffi::CString::new(message).unwrap();
+    wren_sys::wrenSetSlotString(vm, 0, message.as_ptr());
+    wren_sys::wrenAbortFiber(vm, 0);
+}
+
+pub(crate) extern "C" fn emit(vm: *mut WrenVM) {
+    unsafe {
+        let conf = &*(wren_sys::wrenGetUserData(vm) as *const UserData);
+        if wren_sys::wrenGetSlotType(vm, 1) != wren_sys::WrenType_WREN_TYPE_STRING {
+            return abort(vm, "Event name must be a string");
+        }
+        let name = ffi::CStr::from_ptr(wren_sys::wrenGetSlotString(vm, 1)).to_string_lossy().to_string();
+        let wvm = conf.vm.upgrade().expect("VM was dropped while it was running");
+        let payload = wvm.borrow().get_slot_value(2);
+        match payload {
+            Some(payload) => conf.events.borrow_mut().push_back((name, unflatten(payload))),
+            None => abort(vm, "Event payload must be null, a bool, number, string, list or map"),
+        }
+    }
+}
+
+impl VMWrapper {
+    /// Takes every event scripts have emitted as [E::NAME](WrenEvent::NAME), in the order they were emitted
+    ///
+    /// Events whose payload doesn't convert to E are left queued, for [VMWrapper::drain_events].
+    pub fn events<E: WrenEvent>(&self) -> Vec<E> {
+        self.execute(|vm| {
+            let mut queue = vm.user_data().events.borrow_mut();
+            let mut events = vec![];
+            queue.retain(|(name, payload)| {
+                if name != E::NAME {
+                    return true;
+                }
+                match E::from_wren_value(payload) {
+                    Some(event) => {
+                        events.push(event);
+                        false
+                    },
+                    None => true,
+                }
+            });
+            events
+        })
+    }
+
+    /// Takes every event still queued, as (name, payload)
+    pub fn drain_events(&self) -> Vec<(String, WrenValue)> {
+        self.execute(|vm| vm.user_data().events.borrow_mut().drain(..).collect())
+    }
+
+    /// How many events are waiting to be taken
+    pub fn pending_events(&self) -> usize {
+        self.execute(|vm| vm.user_data().events.borrow().len())
+    }
+}
@@ -0,0 +1,122 @@
+//! Driving Wren fibers from Rust, for coroutines like entity behavior scripts
+//!
+//! ```ignore
+//! vm.interpret("ai", "var Patrol = Fiber.new {|steps| while (true) steps = Fiber.yield(steps * 2) }")?;
+//! vm.execute(|vm| {
+//!     vm.ensure_slots(1);
+//!     vm.get_variable("ai", "Patrol", 0);
+//! });
+//! let patrol = vm.get_slot_fiber(0).unwrap();
+//! // every frame, until it's done
+//! let doubled: f64 = patrol.resume_with(3.0)?;
+//! ```
+use crate::{VMWrapper, VMError, Error, OwnedHandle, Handle, FunctionSignature, ToWren, FromWren, SlotId};
+
+pub(crate) const MODULE: &str = "ruwren/fibers";
+
+// Calls into fibers from Wren, since a fiber can only be called or transferred to from another fiber
+const SOURCE: &str = r##"
+class Fibers {
+    static isFiber(value) { value is Fiber }
+    static new(fn) { Fiber.new(fn) }
+    static isDone(fiber) { fiber.isDone }
+    static error(fiber) { fiber.error }
+    static call(fiber) { fiber.call() }
+    static call(fiber, value) { fiber.call(value) }
+    static transfer(fiber, value) { fiber.transfer(value) }
+}
+"##;
+
+/// A fiber that Rust can resume, which keeps its VM alive (see [VMWrapper::get_slot_fiber])
+#[derive(Debug)]
+pub struct FiberHandle {
+    handle: OwnedHandle,
+}
+
+impl FiberHandle {
+    /// The handle, to pass to anything that takes a [Handle]
+    pub fn as_handle(&self) -> &Handle<'_> {
+        self.handle.as_handle()
+    }
+
+    /// Whether the fiber has finished, or aborted
+    pub fn is_done(&self) -> bool {
+        self.call::<(), _>("isDone", None).unwrap_or(true)
+    }
+
+    /// The error the fiber aborted with, if it did
+    pub fn error(&self) -> Option<String> {
+        self.call::<(), Option<String>>("error", None).ok().flatten()
+    }
+
+    /// Runs the fiber until it yields or finishes (as `fiber.call()`), returning the value it
+    /// yielded or returned
+    ///
+    /// Errors if the fiber aborts, or was already done.
+    pub fn resume<R: FromWren>(&self) -> Result<R, Error> {
+        self.call::<(), R>("call", None)
+    }
+
+    /// [FiberHandle::resume], passing [value] to the fiber (as `fiber.call(value)`), which is what
+    /// its `Fiber.yield()` returns (or its function's argument, when it's first run)
+    pub fn resume_with<A: ToWren, R: FromWren>(&self, value: A) -> Result<R, Error> {
+        self.call("call", Some(value))
+    }
+
+    /// Switches to the fiber with [value] (as `fiber.transfer(value)`)
+    ///
+    /// Unlike resuming, the fiber doesn't return to Rust when it yields; the call returns once
+    /// the fiber finishes, suspends, or transfers somewhere else, and what it returns is lost.
+    pub fn transfer_with<A: ToWren>(&self, value: A) -> Result<(), Error> {
+        self.call("transfer", Some(value))
+    }
+
+    // Calls the Fibers method [name] with the fiber, and [value] if there is one
+    fn call<A: ToWren, R: FromWren>(&self, name: &str, value: Option<A>) -> Result<R, Error> {
+        let vm = self.handle.vm();
+        let signature = FunctionSignature::new_function(name, 1 + value.is_some() as usize);
+        let call = vm.execute(|vm| -> Result<_, Error> {
+            vm.ensure_slots(3);
+            vm.get_variable(MODULE, "Fibers", 0);
+            unsafe { wren_sys::wrenSetSlotHandle(vm.vm, 1, self.as_handle().handle) };
+            if let Some(value) = value {
+                value.to_wren(vm, 2)?;
+            }
+            Ok(vm.cached_call_handle(signature.clone()))
+        })?;
+        vm.call_raw(call, &signature.as_wren_string())?;
+        Ok(vm.execute(|vm| R::from_wren(vm, 0))?)
+    }
+}
+
+impl VMWrapper {
+    /// Gets the fiber in [slot], or None if the slot doesn't hold one
+    ///
+    /// Checking calls into Wren, so the other slots are overwritten.
+    pub fn get_slot_fiber(&self, slot: SlotId) -> Option<FiberHandle> {
+        let fiber = FiberHandle { handle: self.get_slot_owned_handle(slot) };
+        self.declare_fibers().ok()?;
+        fiber.call::<(), bool>("isFiber", None).ok()?.then_some(fiber)
+    }
+
+    /// Makes a new fiber that runs the function [function] when it's first resumed
+    pub fn new_fiber(&self, function: &Handle) -> Result<FiberHandle, VMError> {
+        self.declare_fibers()?;
+        let call = self.execute(|vm| {
+            vm.ensure_slots(2);
+            vm.get_variable(MODULE, "Fibers", 0);
+            unsafe { wren_sys::wrenSetSlotHandle(vm.vm, 1, function.handle) };
+            vm.cached_call_handle(FunctionSignature::new_function("new", 1))
+        });
+        self.call_raw(call, "new(_)")?;
+        Ok(FiberHandle { handle: self.get_slot_owned_handle(0) })
+    }
+
+    fn declare_fibers(&self) -> Result<(), VMError> {
+        if !self.execute(|vm| vm.user_data().fibers_declared.get()) {
+            self.interpret(MODULE, SOURCE)?;
+            self.execute(|vm| vm.user_data().fibers_declared.set(true));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+//! Foreign methods that return Rust futures, which scripts wait on without blocking the VM
+//!
+//! A foreign method hands back a future with [VM::set_slot_future], and the script calls `await`
+//! on what it returns, which suspends its fiber. [VMWrapper::poll_async] polls the futures, and
+//! resumes each waiting fiber with its future's result once it's ready.
+//!
+//! ```wren
+//! import "ruwren/async" for Future
+//! class Assets {
+//!     foreign static loadAsync_(path)
+//!     static load(path) { loadAsync_(path).await }
+//! }
+//! var text = Assets.load("intro.txt") // the interpret returns here, until the file is read
+//! ```
+use crate::{VM, VMWrapper, VMError, UserData, WrenValue, FunctionSignature, SlotId, ForeignSendError, ForeignObject};
+use wren_sys::{WrenVM, WrenHandle};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use std::{ffi, fmt, mem, os::raw};
+
+pub(crate) const MODULE: &str = "ruwren/async";
+
+pub(crate) const SOURCE: &str = "foreign class Future {
+    // Suspends the current fiber until the future is ready, then returns its value (or aborts with its error)
+    await {
+        if (!isDone) {
+            wait_(Fiber.current)
+            Fiber.suspend()
+        }
+        return result_
+    }
+
+    foreign isDone
+    foreign wait_(fiber)
+    foreign result_
+
+    static resume_(fiber) { fiber.transfer() }
+}
+";
+
+type BoxedFuture = Pin<Box<dyn Future<Output = Result<WrenValue, String>>>>;
+
+pub(crate) enum State {
+    Pending(BoxedFuture),
+    // Driven somewhere else, which completes it (see VM::spawn_future)
+    #[cfg(feature = "tokio")]
+    Spawned(crate::tokio_bridge::Spawned),
+    Done(Result<WrenValue, String>),
+}
+
+// Set when a future's waker is woken, so only futures that can make progress are polled
+struct Woken(AtomicBool);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+// A future, shared by its Wren object and the VM's table until it's ready
+pub(crate) struct Task {
+    state: RefCell<State>,
+    woken: Arc<Woken>,
+    // Fibers waiting on the future
+    waiting: RefCell<Vec<*mut WrenHandle>>,
+}
+
+impl Task {
+    fn is_done(&self) -> bool {
+        matches!(*self.state.borrow(), State::Done(_))
+    }
+
+    // Polls the future if it's been woken since it was last polled, returning whether it's ready
+    fn poll(&self) -> bool {
+        if !self.woken.0.swap(false, Ordering::AcqRel) {
+            return self.is_done();
+        }
+        let mut state = self.state.borrow_mut();
+        let result = match &mut *state {
+            State::Pending(future) => {
+                let waker = Waker::from(self.woken.clone());
+                match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return false,
+                }
+            },
+            #[cfg(feature = "tokio")]
+            State::Spawned(_) => return false,
+            State::Done(_) => return true,
+        };
+        *state = State::Done(result);
+        true
+    }
+}
+
+/// Every future handed to a VM that isn't ready yet (or is ready, but has fibers to resume)
+#[derive(Default)]
+pub(crate) struct FutureTable {
+    tasks: Vec<Rc<Task>>,
+}
+
+impl fmt::Debug for FutureTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FutureTable").field("tasks", &self.tasks.len()).finish()
+    }
+}
+
+impl FutureTable {
+    // Completes the spawned future [id], if it's still wanted
+    #[cfg(feature = "tokio")]
+    pub(crate) fn complete(&self, id: u64, result: Result<WrenValue, String>) {
+        let task = self.tasks.iter().find(|task| matches!(&*task.state.borrow(), State::Spawned(spawned) if spawned.id == id));
+        if let Some(task) = task {
+            *task.state.borrow_mut() = State::Done(result);
+        }
+    }
+
+    /// Releases every waiting fiber's handle, before the VM is freed
+    pub(crate) fn release(&mut self, vm: *mut WrenVM) {
+        for task in self.tasks.drain(..) {
+            for handle in task.waiting.borrow_mut().drain(..) {
+                unsafe { wren_sys::wrenReleaseHandle(vm, handle) };
+            }
+        }
+    }
+}
+
+/// What a call to [VMWrapper::poll_async] did
+#[derive(Debug, Default)]
+pub struct AsyncReport {
+    /// Futures that became ready
+    pub completed: usize,
+    /// Fibers resumed with their future's result
+    pub resumed: usize,
+    /// Futures still waiting to be ready
+    pub pending: usize,
+    /// Errors from resumed fibers that aborted
+    pub errors: Vec<VMError>,
+}
+
+// The task behind the Future in slot 0
+unsafe fn receiver<'a>(vm: *mut WrenVM) -> &'a Rc<Task> {
+    &*(*(wren_sys::wrenGetSlotForeign(vm, 0) as *const ForeignObject<Rc<Task>>)).object
+}
+
+unsafe fn abort(vm: *mut WrenVM, message: &str) {
+    let message = ffi::CString::new(message).unwrap();
+    wren_sys::wrenSetSlotString(vm, 0, message.as_ptr());
+    wren_sys::wrenAbortFiber(vm, 0);
+}
+
+pub(crate) extern "C" fn finalize(data: *mut ffi::c_void) {
+    unsafe {
+        let object = (*(data as *mut ForeignObject<Rc<Task>>)).object;
+        if !object.is_null() {
+            drop(Box::from_raw(object));
+        }
+    }
+}
+
+pub(crate) extern "C" fn is_done(vm: *mut WrenVM) {
+    unsafe {
+        let done = receiver(vm).is_done();
+        wren_sys::wrenSetSlotBool(vm, 0, done);
+    }
+}
+
+pub(crate) extern "C" fn wait(vm: *mut WrenVM) {
+    unsafe {
+        let handle = wren_sys::wrenGetSlotHandle(vm, 1 as raw::c_int);
+        receiver(vm).waiting.borrow_mut().push(handle);
+    }
+}
+
+pub(crate) extern "C" fn result(vm: *mut WrenVM) {
+    unsafe {
+        let conf = &*(wren_sys::wrenGetUserData(vm) as *const UserData);
+        let state = receiver(vm).state.borrow();
+        match &*state {
+            State::Done(Ok(value)) => {
+                let wvm = conf.vm.upgrade().expect("VM was dropped while it was running");
+                wvm.borrow().set_slot_value(0, value);
+            },
+            State::Done(Err(message)) => abort(vm, message),
+            _ => abort(vm, "Future is not ready yet"),
+        }
+    }
+}
+
+impl VM {
+    /// Puts a `Future` (from the "ruwren/async" module) for [future] in [slot], for an async foreign
+    /// method to return
+    ///
+    /// Scripts `await` it, and [VMWrapper::poll_async] resumes them with its value once it's ready
+    /// (or aborts their fibers with its error). The module has to have been imported already,
+    /// usually by the module declaring the method. Uses one slot past the current slot count.
+    pub fn set_slot_future<F, T, E>(&self, slot: SlotId, future: F) -> Result<(), ForeignSendError>
+    where
+        F: 'static + Future<Output = Result<T, E>>,
+        T: Into<WrenValue>,
+        E: fmt::Display,
+    {
+        let future = async move { future.await.map(Into::into).map_err(|e| e.to_string()) };
+        self.set_slot_task(slot, State::Pending(Box::pin(future)))
+    }
+
+    // Puts a new Future for [state] in [slot], and adds it to the table
+    pub(crate) fn set_slot_task(&self, slot: SlotId, state: State) -> Result<(), ForeignSendError> {
+        if !self.has_module(MODULE) {
+            return Err(ForeignSendError::NoWrenClass);
+        }
+        let scratch = self.get_slot_count().max(slot + 1);
+        self.ensure_slots(scratch + 1);
+        self.get_variable(MODULE, "Future", scratch);
+
+        let task = Rc::new(Task {
+            state: RefCell::new(state),
+            woken: Arc::new(Woken(AtomicBool::new(true))),
+            waiting: RefCell::new(vec![]),
+        });
+        unsafe { self.new_foreign_object(scratch, slot, task.clone())? };
+        self.user_data().futures.borrow_mut().tasks.push(task);
+        Ok(())
+    }
+}
+
+impl VMWrapper {
+    /// Polls every future async foreign methods have returned, and resumes the fibers waiting on
+    /// the ones that are ready
+    ///
+    /// Futures are only polled again once their waker has been woken. Futures whose Wren object was
+    /// collected with no fiber waiting on them are dropped.
+    pub fn poll_async(&self) -> AsyncReport {
+        let mut report = AsyncReport::default();
+        // Polled without the table borrowed, in case a future calls back into the VM
+        let tasks = self.execute(|vm| vm.user_data().futures.borrow().tasks.clone());
+        let ready: Vec<Rc<Task>> = tasks.into_iter().filter(|task| task.poll()).collect();
+        report.completed = ready.len();
+        self.execute(|vm| {
+            let mut futures = vm.user_data().futures.borrow_mut();
+            futures.tasks.retain(|task| !task.is_done() && Rc::strong_count(task) > 1);
+            report.pending = futures.tasks.len();
+        });
+
+        for task in ready {
+            let waiting = mem::take(&mut *task.waiting.borrow_mut());
+            for fiber in waiting {
+                let resume = self.execute(|vm| unsafe {
+                    vm.ensure_slots(2);
+                    vm.get_variable(MODULE, "Future", 0);
+                    wren_sys::wrenSetSlotHandle(vm.vm, 1, fiber);
+                    wren_sys::wrenReleaseHandle(vm.vm, fiber);
+                    vm.cached_call_handle(FunctionSignature::new_function("resume_", 1))
+                });
+                if let Err(err) = self.call_raw(resume, "resume_(_)") {
+                    report.errors.push(err);
+                }
+                report.resumed += 1;
+            }
+        }
+        report
+    }
+
+    /// How many futures weren't ready as of the last poll
+    pub fn pending_futures(&self) -> usize {
+        self.execute(|vm| vm.user_data().futures.borrow().tasks.len())
+    }
+}
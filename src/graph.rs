@@ -0,0 +1,130 @@
+//! Copies what a script object contains out of the VM, for editors and debuggers to show
+use crate::{VMWrapper, Handle, WrenValue, WrenMap, WrenList, FunctionSignature};
+
+pub(crate) const MODULE: &str = "ruwren/graph";
+
+// Walks the graph on the Wren side, since Wren's slot API can't list a map's keys. Each node is a list
+// tagged with its kind, which export_object_graph turns into maps.
+const SOURCE: &str = r##"
+class Graph {
+    static export(roots, maxDepth) {
+        var seen = []
+        return roots.map {|root| node_(root, seen, maxDepth) }.toList
+    }
+
+    static node_(value, seen, depth) {
+        if (value is Null || value is Bool || value is Num || value is String) return ["value", value]
+        for (id in 0...seen.count) {
+            if (Object.same(seen[id], value)) return ["ref", id]
+        }
+        if (depth <= 0) return ["truncated", value.type.name]
+
+        var id = seen.count
+        seen.add(value)
+        if (value is List) return ["list", id, value.map {|item| node_(item, seen, depth - 1) }.toList]
+        if (value is Map) {
+            var entries = []
+            for (key in value.keys) entries.add([node_(key, seen, depth - 1), node_(value[key], seen, depth - 1)])
+            return ["map", id, entries]
+        }
+        var fiber = Fiber.new { value.toString }
+        var string = fiber.try()
+        return ["object", id, value.type.name, fiber.error == null && string is String ? string : null]
+    }
+}
+"##;
+
+/// How deep [VMWrapper::export_object_graph] goes
+pub const DEFAULT_GRAPH_DEPTH: usize = 32;
+
+impl VMWrapper {
+    /// Copies everything reachable from [roots] into a tree, one per root
+    ///
+    /// Strings, numbers, bools and null are copied as they are. Everything else becomes a map with
+    /// the object's `"type"` name and an `"id"` unique within the export: lists get their `"items"`,
+    /// maps their `"entries"` (as `[key, value]` lists), and other objects (foreign ones included)
+    /// their `"string"` from `toString`, since Wren can't see inside them. An object that was already
+    /// exported becomes `{"ref": id}`, and one more than [DEFAULT_GRAPH_DEPTH] levels down becomes
+    /// `{"type": name, "truncated": true}`.
+    pub fn export_object_graph(&self, roots: &[Handle]) -> WrenValue {
+        self.export_object_graph_to_depth(roots, DEFAULT_GRAPH_DEPTH)
+    }
+
+    /// [VMWrapper::export_object_graph], going at most [max_depth] levels down
+    pub fn export_object_graph_to_depth(&self, roots: &[Handle], max_depth: usize) -> WrenValue {
+        if !self.execute(|vm| vm.user_data().graph_declared.get()) {
+            if self.interpret(MODULE, SOURCE).is_err() {
+                return WrenValue::Null;
+            }
+            self.execute(|vm| vm.user_data().graph_declared.set(true));
+        }
+        let call = self.execute(|vm| {
+            vm.ensure_slots(4);
+            vm.get_variable(MODULE, "Graph", 0);
+            vm.set_slot_new_list(1);
+            for root in roots {
+                unsafe { wren_sys::wrenSetSlotHandle(vm.vm, 3, root.handle) };
+                vm.insert_in_list(1, -1, 3);
+            }
+            vm.set_slot_double(2, max_depth as f64);
+            vm.cached_call_handle(FunctionSignature::new_function("export", 2))
+        });
+        if self.call_raw(call, "export(_,_)").is_err() {
+            return WrenValue::Null;
+        }
+        match self.execute(|vm| vm.copy_slot_list(0)) {
+            Some(trees) => WrenValue::List(trees.iter().map(decode).collect()),
+            None => WrenValue::Null,
+        }
+    }
+}
+
+// Turns a tagged node from Graph.export into its tree
+fn decode(node: &WrenValue) -> WrenValue {
+    let node = match node {
+        WrenValue::List(node) => node,
+        _ => return WrenValue::Null,
+    };
+    let field = |index| node.get(index).cloned().unwrap_or(WrenValue::Null);
+    let mut tree = WrenMap::new();
+    match node.get(0) {
+        Some(WrenValue::String(kind)) => match kind.as_str() {
+            "value" => return field(1),
+            "ref" => {
+                tree.insert("ref", field(1));
+            },
+            "truncated" => {
+                tree.insert("type", field(1));
+                tree.insert("truncated", true);
+            },
+            "list" => {
+                tree.insert("type", "List");
+                tree.insert("id", field(1));
+                tree.insert("items", decode_all(&field(2)));
+            },
+            "map" => {
+                tree.insert("type", "Map");
+                tree.insert("id", field(1));
+                let entries = match field(2) {
+                    WrenValue::List(entries) => entries.iter().map(decode_all).collect(),
+                    _ => WrenList::new(),
+                };
+                tree.insert("entries", entries);
+            },
+            _ => {
+                tree.insert("type", field(2));
+                tree.insert("id", field(1));
+                tree.insert("string", field(3));
+            },
+        },
+        _ => return WrenValue::Null,
+    }
+    WrenValue::Map(tree)
+}
+
+fn decode_all(nodes: &WrenValue) -> WrenValue {
+    match nodes {
+        WrenValue::List(nodes) => WrenValue::List(nodes.iter().map(decode).collect()),
+        _ => WrenValue::List(WrenList::new()),
+    }
+}
@@ -0,0 +1,229 @@
+//! Reloading modules into a running VM, for editing scripts while the game is running
+//!
+//! Wren can't unload a module or define a module variable twice, so each reload interprets the
+//! new source into a module of its own (like `game~reload2`), and then points the module's
+//! variables at what it defined. Code that goes through the module (its own methods, new
+//! imports, and [HotReload::track]ed handles) sees the new definitions; modules that already
+//! imported them keep the old ones, and so do objects that were already made.
+//!
+//! ```ignore
+//! let mut hot = HotReload::new();
+//! hot.watch_file("game", "scripts/game.wren");
+//! let game = hot.track(&vm, "game", "Game");
+//! loop {
+//!     for event in hot.poll(&vm) {
+//!         if let Err(e) = event.result { eprintln!("{}", e); }
+//!     }
+//!     vm.call_on(hot.handle(game).unwrap().as_handle(), "update()").invoke::<()>()?;
+//! }
+//! ```
+use crate::{VMWrapper, VMError, OwnedHandle};
+use crate::lexer::{Token, Tokens};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+const SUFFIX: &str = "~reload";
+
+// The module a reload's module was made for, if [module] is one
+pub(crate) fn reloaded_module(module: &str) -> Option<&str> {
+    let (original, generation) = module.rsplit_once(SUFFIX)?;
+    (!generation.is_empty() && generation.bytes().all(|b| b.is_ascii_digit())).then_some(original)
+}
+
+/// What happened when a module was reloaded (see [HotReload::poll])
+#[derive(Debug, Clone)]
+pub struct ReloadEvent {
+    pub module: String,
+    /// How many times the module has been reloaded, this one included
+    pub generation: usize,
+    /// The error the new source failed with, in which case the module is left as it was
+    pub result: Result<(), VMError>,
+}
+
+/// A module variable whose handle is fetched again whenever its module is reloaded (see [HotReload::track])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrackedVariable(usize);
+
+type ChangedSource = Box<dyn FnMut() -> Option<String>>;
+type ReloadCallback = Box<dyn FnMut(&ReloadEvent)>;
+
+struct Tracked {
+    module: String,
+    variable: String,
+    handle: Option<OwnedHandle>,
+}
+
+/// Watches module sources, and reloads the ones that change into a VM
+#[derive(Default)]
+pub struct HotReload {
+    watches: Vec<(String, ChangedSource)>,
+    // Changes reported with HotReload::changed, waiting for the next poll
+    pending: Vec<(String, String)>,
+    generations: HashMap<String, usize>,
+    tracked: Vec<Tracked>,
+    on_reload: Option<ReloadCallback>,
+}
+
+impl HotReload {
+    pub fn new() -> HotReload {
+        HotReload::default()
+    }
+
+    /// Watches [module] with [changed], which is asked on every poll, and returns the module's
+    /// new source if it has changed since it was last asked
+    pub fn watch<M: Into<String>, F: 'static + FnMut() -> Option<String>>(&mut self, module: M, changed: F) -> &mut Self {
+        self.watches.push((module.into(), Box::new(changed)));
+        self
+    }
+
+    /// Watches the file at [path] as the source of [module], reloading it whenever its
+    /// modification time changes
+    pub fn watch_file<M: Into<String>, P: Into<PathBuf>>(&mut self, module: M, path: P) -> &mut Self {
+        let path = path.into();
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        let mut last: Option<SystemTime> = modified(&path);
+        self.watch(module, move || {
+            let now = modified(&path)?;
+            if last == Some(now) {
+                return None;
+            }
+            // Read first, so a file caught halfway through being saved is tried again
+            let source = std::fs::read_to_string(&path).ok()?;
+            last = Some(now);
+            Some(source)
+        })
+    }
+
+    /// Queues [source] as the new source of [module], for the next poll
+    ///
+    /// For hosts that already watch files some other way (like with the `notify` crate).
+    pub fn changed<M: Into<String>, S: Into<String>>(&mut self, module: M, source: S) -> &mut Self {
+        self.pending.push((module.into(), source.into()));
+        self
+    }
+
+    /// Calls [callback] with each reload, as well as returning them from [HotReload::poll]
+    pub fn on_reload<F: 'static + FnMut(&ReloadEvent)>(&mut self, callback: F) -> &mut Self {
+        self.on_reload = Some(Box::new(callback));
+        self
+    }
+
+    /// Keeps a handle to the variable [variable] of [module] (like a class the host calls into),
+    /// which is fetched again after every reload of the module
+    ///
+    /// The handle is None while the variable isn't defined. Call handles made with
+    /// [VMWrapper::make_call_handle] only name a signature, so they never need refreshing.
+    pub fn track<M: Into<String>, V: Into<String>>(&mut self, vm: &VMWrapper, module: M, variable: V) -> TrackedVariable {
+        let mut tracked = Tracked { module: module.into(), variable: variable.into(), handle: None };
+        tracked.refresh(vm);
+        self.tracked.push(tracked);
+        TrackedVariable(self.tracked.len() - 1)
+    }
+
+    /// The current handle to a tracked variable
+    pub fn handle(&self, variable: TrackedVariable) -> Option<&OwnedHandle> {
+        self.tracked.get(variable.0)?.handle.as_ref()
+    }
+
+    /// Reloads every module that has changed into [vm]
+    pub fn poll(&mut self, vm: &VMWrapper) -> Vec<ReloadEvent> {
+        let mut changes = std::mem::take(&mut self.pending);
+        for (module, changed) in &mut self.watches {
+            if let Some(source) = changed() {
+                changes.push((module.clone(), source));
+            }
+        }
+        changes.into_iter().map(|(module, source)| self.reload(vm, module, source)).collect()
+    }
+
+    /// Reloads [source] into [module] straight away
+    ///
+    /// A module that hasn't been loaded yet is just interpreted.
+    pub fn reload<M: Into<String>, S: AsRef<str>>(&mut self, vm: &VMWrapper, module: M, source: S) -> ReloadEvent {
+        let module = module.into();
+        let generation = self.generations.entry(module.clone()).or_insert(0);
+        *generation += 1;
+        let generation = *generation;
+
+        let result = match vm.execute(|vm| vm.has_module(&module)) {
+            true => replace(vm, &module, generation, source.as_ref()),
+            false => vm.interpret(&module, source.as_ref()),
+        };
+        if result.is_ok() {
+            for tracked in self.tracked.iter_mut().filter(|tracked| tracked.module == module) {
+                tracked.refresh(vm);
+            }
+        }
+
+        let event = ReloadEvent { module, generation, result };
+        if let Some(on_reload) = &mut self.on_reload {
+            on_reload(&event);
+        }
+        event
+    }
+}
+
+impl Tracked {
+    fn refresh(&mut self, vm: &VMWrapper) {
+        let defined = vm.execute(|vm| {
+            let defined = vm.has_variable(&self.module, &self.variable);
+            if defined {
+                vm.ensure_slots(1);
+                vm.get_variable(&self.module, &self.variable, 0);
+            }
+            defined
+        });
+        self.handle = defined.then(|| vm.get_slot_owned_handle(0));
+    }
+}
+
+// Interprets [source] into a module of its own, then points [module]'s variables at what it defined
+fn replace(vm: &VMWrapper, module: &str, generation: usize, source: &str) -> Result<(), VMError> {
+    let reloaded = format!("{}{}{}", module, SUFFIX, generation);
+    vm.interpret(&reloaded, source)?;
+
+    let names: Vec<String> = vm.execute(|vm| top_level_names(source).into_iter()
+        .filter(|name| vm.has_variable(&reloaded, name))
+        .collect());
+    if names.is_empty() {
+        return Ok(());
+    }
+    let mut update = String::new();
+    // Only the module can define its variables, and only once
+    for name in names.iter().filter(|name| !vm.execute(|vm| vm.has_variable(module, name))) {
+        update.push_str(&format!("var {}\n", name));
+    }
+    // Imported inside a function, so the new values are locals that can be copied over
+    let imports: Vec<String> = names.iter().enumerate().map(|(i, name)| format!("{} as Reloaded{}_", name, i)).collect();
+    update.push_str(&format!("Fn.new {{\n    import \"{}\" for {}\n", reloaded, imports.join(", ")));
+    for (i, name) in names.iter().enumerate() {
+        update.push_str(&format!("    {} = Reloaded{}_\n", name, i));
+    }
+    update.push_str("}.call()\n");
+    vm.interpret(module, update)
+}
+
+// The classes and variables [source] defines at the top level, as best as can be told without
+// compiling it (names it doesn't actually define are skipped by the caller)
+pub(crate) fn top_level_names(source: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut depth = 0usize;
+    let mut defining = false;
+    for token in Tokens::new(source) {
+        match token {
+            Token::Open('{') => depth += 1,
+            Token::Close('}') => depth = depth.saturating_sub(1),
+            Token::Word(word) if depth == 0 => {
+                if defining {
+                    names.push(word.to_string());
+                }
+                defining = word == "class" || word == "var";
+            },
+            Token::Word(_) => {},
+            _ => defining = false,
+        }
+    }
+    names.dedup();
+    names
+}
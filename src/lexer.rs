@@ -0,0 +1,130 @@
+//! Just enough of a Wren lexer to find brackets and words in source that might not compile
+//!
+//! Used by the REPL to tell whether a line is finished, and by hot reloading to find a module's
+//! top-level names. Comments and strings are skipped whole, including block comments inside
+//! block comments and the expressions inside `%(...)` interpolations.
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// `{`, `(` or `[`
+    Open(char),
+    /// `}`, `)` or `]`
+    Close(char),
+    /// A name or keyword
+    Word(&'a str),
+    /// Anything else that isn't whitespace, a comment or a string
+    Other(char),
+}
+
+pub(crate) struct Tokens<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    unterminated: bool,
+}
+
+impl<'a> Tokens<'a> {
+    pub(crate) fn new(source: &'a str) -> Self {
+        Tokens { source, chars: source.char_indices().peekable(), unterminated: false }
+    }
+
+    /// Whether the source ended inside a comment or string
+    pub(crate) fn unterminated(&self) -> bool {
+        self.unterminated
+    }
+
+    fn peek_is(&mut self, c: char) -> bool {
+        self.chars.peek().map(|&(_, next)| next) == Some(c)
+    }
+
+    // Skips the rest of a block comment, whose opening /* has been read
+    fn block_comment(&mut self) {
+        let mut nesting = 1;
+        while nesting > 0 {
+            match self.chars.next() {
+                Some((_, '*')) if self.peek_is('/') => { self.chars.next(); nesting -= 1; },
+                Some((_, '/')) if self.peek_is('*') => { self.chars.next(); nesting += 1; },
+                Some(_) => {},
+                None => { self.unterminated = true; return; },
+            }
+        }
+    }
+
+    // Skips the rest of a raw string, whose opening """ has been read
+    fn raw_string(&mut self) {
+        let mut quotes = 0;
+        for (_, c) in self.chars.by_ref() {
+            quotes = if c == '"' { quotes + 1 } else { 0 };
+            if quotes == 3 {
+                return;
+            }
+        }
+        self.unterminated = true;
+    }
+
+    // Skips the rest of a string, whose opening " has been read
+    fn string(&mut self) {
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '\\' => { self.chars.next(); },
+                '"' => return,
+                '%' if self.peek_is('(') => {
+                    self.chars.next();
+                    self.interpolation();
+                },
+                _ => {},
+            }
+        }
+        self.unterminated = true;
+    }
+
+    // Skips the rest of an interpolated expression, whose opening %( has been read
+    fn interpolation(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Token::Open('(')) => depth += 1,
+                Some(Token::Close(')')) => depth -= 1,
+                Some(_) => {},
+                None => { self.unterminated = true; return; },
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        while let Some((start, c)) = self.chars.next() {
+            match c {
+                '/' if self.peek_is('/') => {
+                    self.chars.by_ref().find(|&(_, c)| c == '\n');
+                },
+                '/' if self.peek_is('*') => {
+                    self.chars.next();
+                    self.block_comment();
+                },
+                '"' if self.source[start + 1..].starts_with("\"\"") => {
+                    self.chars.nth(1);
+                    self.raw_string();
+                },
+                '"' => self.string(),
+                '{' | '(' | '[' => return Some(Token::Open(c)),
+                '}' | ')' | ']' => return Some(Token::Close(c)),
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut end = start + 1;
+                    while let Some(&(index, c)) = self.chars.peek().filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                        end = index + c.len_utf8();
+                        self.chars.next();
+                    }
+                    return Some(Token::Word(&self.source[start..end]));
+                },
+                c if c.is_whitespace() => {},
+                c => return Some(Token::Other(c)),
+            }
+        }
+        None
+    }
+}
@@ -1,69 +1,231 @@
 //! We need to expose the Wren API in a Rust-y way
 use wren_sys::{WrenVM, WrenHandle, WrenConfiguration, WrenErrorType, WrenForeignClassMethods};
 use std::sync::mpsc::{channel, Sender, Receiver};
-use std::collections::HashMap;
+use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use indexmap::IndexMap;
 use std::rc::{Rc, Weak};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
 
 pub use wren_sys;
 
+// Lets derived code name ::ruwren from inside this crate too
+extern crate self as ruwren;
+
 use std::{mem, ffi, os::raw, any, marker};
+use std::borrow::Cow;
 
+#[macro_use]
+mod audit;
+#[macro_use]
+mod trace;
 #[cfg(test)]
 mod tests;
 
-#[derive(Debug)]
-pub enum WrenError {
-    Compile(String, i32, String),
-    Runtime(String),
-    StackTrace(String, i32, String),
+mod capability;
+mod profile;
+mod sync;
+mod value;
+mod error;
+mod module_id;
+mod signature;
+mod callbacks;
+mod events;
+mod futures;
+mod fibers;
+mod slot_dump;
+mod write_printer;
+mod graph;
+mod script_cache;
+mod static_loader;
+mod test_runner;
+mod scratch;
+mod call;
+mod sandbox;
+mod current;
+mod closures;
+mod hot_reload;
+mod repl;
+mod lexer;
+#[cfg(feature = "coverage")]
+mod coverage;
+#[cfg(feature = "scheduler")]
+mod scheduler;
+#[cfg(feature = "debugger")]
+mod debugger;
+#[cfg(feature = "tokio")]
+mod tokio_bridge;
+#[cfg(feature = "log")]
+mod log_output;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod stdlib;
+
+pub use capability::{Capability, CapabilityPolicy};
+pub use profile::{ProfilingSession, ProfileReport, ForeignTiming, CallInfo, CallKind};
+pub use sync::{SyncVM, SyncHandle, SyncFunctionHandle};
+pub use module_id::{ModuleId, ModuleName};
+pub use signature::FunctionSignature;
+pub use callbacks::TickReport;
+pub use events::WrenEvent;
+pub use futures::AsyncReport;
+pub use fibers::FiberHandle;
+pub use slot_dump::SlotDump;
+pub use write_printer::{WritePrinter, FlushPolicy};
+pub use graph::DEFAULT_GRAPH_DEPTH;
+pub use script_cache::ScriptCache;
+pub use static_loader::StaticLoader;
+pub use closures::{ClassBuilder, MAX_CLOSURE_METHODS};
+pub use hot_reload::{HotReload, ReloadEvent, TrackedVariable};
+pub use repl::{Repl, ReplOutput};
+pub use scratch::ScratchStats;
+pub use call::CallBuilder;
+pub use sandbox::SandboxReport;
+pub use current::current_vm;
+pub use test_runner::{TestRunner, TestReport, FileReport, CaseResult};
+pub use value::{WrenValue, WrenList, WrenMap, FromWrenValue, FromWren, ToWren, WrenRecord, WrenEnum, NumberFormat};
+#[cfg(feature = "derive")]
+pub use ruwren_derive::{WrenRecord, WrenEnum, wren_class, wren_methods, embed_modules};
+pub use error::{Error, ErrorKind, WrenError, VMError, VMStackFrameError, FrameKind, BuildError, ForeignSendError, ForeignBorrowError, SlotError, SignatureError, ConversionError, UnboundForeign};
+#[cfg(feature = "coverage")]
+pub use coverage::{CoverageReport, ModuleCoverage};
+#[cfg(feature = "scheduler")]
+pub use scheduler::SchedulerPump;
+#[cfg(feature = "log")]
+pub use log_output::{LogPrinter, LogErrorHandler};
+#[cfg(feature = "debugger")]
+pub use debugger::{PauseEvent, PauseReason, DebugAction, DebugValue};
+
+// Wren doesn't say how big a block is when it resizes or frees it, so each one starts with its size
+const ALLOC_HEADER: usize = 16;
+
+fn alloc_layout(size: usize) -> std::alloc::Layout {
+    std::alloc::Layout::from_size_align(size + ALLOC_HEADER, ALLOC_HEADER).unwrap()
 }
 
 // Force Wren to use Rust's allocator to allocate memory
 // Done because sometimes Wren forces us to allocate memory and give *it* ownership
 // Rust might not use the standard allocator, so we move Wren to use *our* allocator
-extern "C" fn wren_realloc(memory: *mut ffi::c_void, new_size: wren_sys::size_t) -> *mut ffi::c_void {
+extern "C" fn wren_realloc(memory: *mut ffi::c_void, new_size: wren_sys::size_t, user_data: *mut ffi::c_void) -> *mut ffi::c_void {
+    let new_size = new_size as usize;
     unsafe {
-        if memory.is_null() { // If memory == NULL
-            // allocate new memory
-            std::alloc::alloc_zeroed(std::alloc::Layout::from_size_align(new_size as usize, 8).unwrap()) as *mut _
+        let (block, old_size) = if memory.is_null() {
+            (std::ptr::null_mut(), 0)
         } else {
-            // Memory is an actual pointer to a location.
-            if new_size == 0 {
-                std::alloc::dealloc(memory as *mut _, std::alloc::Layout::from_size_align(0, 8).unwrap());
-                std::ptr::null_mut()
-            } else {
-                std::alloc::realloc(memory as *mut _, std::alloc::Layout::from_size_align(new_size as usize, 8).unwrap(), new_size as usize) as *mut _
+            let block = (memory as *mut u8).sub(ALLOC_HEADER);
+            (block, *(block as *const usize))
+        };
+        let block = if new_size == 0 {
+            if !block.is_null() {
+                std::alloc::dealloc(block, alloc_layout(old_size));
             }
+            std::ptr::null_mut()
+        } else if block.is_null() {
+            std::alloc::alloc_zeroed(alloc_layout(new_size))
+        } else {
+            std::alloc::realloc(block, alloc_layout(old_size), new_size + ALLOC_HEADER)
+        };
+        if !user_data.is_null() && (new_size == 0 || !block.is_null()) {
+            (*(user_data as *const UserData)).account_heap(old_size, new_size);
+        }
+        if block.is_null() {
+            return std::ptr::null_mut();
+        }
+        *(block as *mut usize) = new_size;
+        block.add(ALLOC_HEADER) as *mut _
+    }
+}
+
+// Copies [string] into memory Wren can free
+fn wren_string(vm: *mut WrenVM, string: ffi::CString) -> *const raw::c_char {
+    let bytes = string.as_bytes_with_nul();
+    unsafe {
+        let memory = wren_realloc(std::ptr::null_mut(), bytes.len() as wren_sys::size_t, wren_sys::wrenGetUserData(vm)) as *mut u8;
+        if memory.is_null() {
+            std::alloc::handle_alloc_error(alloc_layout(bytes.len()));
         }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), memory, bytes.len());
+        memory as *const _
     }
 }
 
 extern "C" fn wren_error(vm: *mut WrenVM, typ: WrenErrorType, module: *const raw::c_char, line: raw::c_int, message: *const raw::c_char) {
     let conf = unsafe { &mut *(wren_sys::wrenGetUserData(vm) as *mut UserData) };
+    // Instrumented sources have extra lines, so report where the error is in the original
+    #[cfg(feature = "coverage")]
+    let line = match conf.coverage.as_ref() {
+        Some(coverage) if !module.is_null() => coverage.borrow().original_line(&unsafe { ffi::CStr::from_ptr(module) }.to_string_lossy(), line),
+        _ => line,
+    };
     match typ {
         wren_sys::WrenErrorType_WREN_ERROR_COMPILE => {
             let module_str = unsafe { ffi::CStr::from_ptr(module) };
             let message_str = unsafe { ffi::CStr::from_ptr(message) };
-            conf.error_channel.send(WrenError::Compile(module_str.to_string_lossy().to_string(), line as i32, message_str.to_string_lossy().to_string())).unwrap();
+            conf.report_error(WrenError::Compile(module_str.to_string_lossy().to_string(), line, message_str.to_string_lossy().to_string()));
         },
         wren_sys::WrenErrorType_WREN_ERROR_RUNTIME => {
             let message_str = unsafe { ffi::CStr::from_ptr(message) };
-            conf.error_channel.send(WrenError::Runtime(message_str.to_string_lossy().to_string())).unwrap();
+            conf.report_error(WrenError::Runtime(message_str.to_string_lossy().to_string()));
         },
         wren_sys::WrenErrorType_WREN_ERROR_STACK_TRACE => {
             let module_str = unsafe { ffi::CStr::from_ptr(module) };
             let message_str = unsafe { ffi::CStr::from_ptr(message) };
-            conf.error_channel.send(WrenError::StackTrace(module_str.to_string_lossy().to_string(), line as i32, message_str.to_string_lossy().to_string())).unwrap();
+            conf.report_error(WrenError::StackTrace(module_str.to_string_lossy().to_string(), line, message_str.to_string_lossy().to_string()));
         },
         _ => unreachable!()
     }
 }
 
+// Runs a callback the embedder gave us, so that a panic in it doesn't unwind into Wren.
+// The panic is kept to fail the running interpret or call with, and None returned instead.
+fn guard_callback<R, F: FnOnce() -> R>(panicked: &RefCell<Option<(&'static str, String)>>, callback: &'static str, f: F) -> Option<R> {
+    use std::panic::{take_hook, set_hook, catch_unwind, AssertUnwindSafe};
+
+    let hook = take_hook();
+    set_hook(Box::new(|_| {}));
+    let result = catch_unwind(AssertUnwindSafe(f));
+    set_hook(hook);
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            let message = if let Some(strg) = err.downcast_ref::<String>() {
+                strg.clone()
+            } else if let Some(strg) = err.downcast_ref::<&str>() {
+                strg.to_string()
+            } else {
+                "Non-string panic message".into()
+            };
+            // The first panic is the one worth reporting
+            panicked.borrow_mut().get_or_insert((callback, message));
+            None
+        }
+    }
+}
+
 extern "C" fn wren_print(vm: *mut WrenVM, message: *const raw::c_char) {
     let conf = unsafe { &mut *(wren_sys::wrenGetUserData(vm) as *mut UserData) };
     let message_str = unsafe { ffi::CStr::from_ptr(message) };
-    conf.printer.print(message_str.to_string_lossy().to_string());
+    let printer = &mut conf.printer;
+    match conf.string_policy {
+        StringPolicy::Lossy => {
+            if message_str.to_str().is_err() {
+                audit!("Printed string isn't valid UTF-8, so invalid bytes were replaced with U+FFFD");
+            }
+            guard_callback(&conf.callback_panic, "printer", || printer.print(message_str.to_string_lossy().to_string()));
+        },
+        StringPolicy::Strict => match message_str.to_str() {
+            Ok(message) => {
+                guard_callback(&conf.callback_panic, "printer", || printer.print(message.to_string()));
+            },
+            Err(_) => conf.utf8_violation.set(Some("print")),
+        },
+        StringPolicy::Bytes => {
+            guard_callback(&conf.callback_panic, "printer", || printer.print_bytes(message_str.to_bytes()));
+        },
+    }
 }
 
 extern "C" fn wren_bind_foreign_method(vm: *mut WrenVM, mdl: *const raw::c_char, class: *const raw::c_char, is_static: bool, sgn: *const raw::c_char) -> Option<unsafe extern "C" fn(*mut WrenVM)> {
@@ -72,17 +234,62 @@ extern "C" fn wren_bind_foreign_method(vm: *mut WrenVM, mdl: *const raw::c_char,
     let class = unsafe { ffi::CStr::from_ptr(class) };
     let signature = unsafe { ffi::CStr::from_ptr(sgn) };
 
-    if let Some(ref library) = conf.library {
-        if let Some(rc) = library.get_foreign_class(module.to_string_lossy(), class.to_string_lossy()) {
-            rc.methods.function_pointers.iter().find(|mp| {
-                mp.signature.as_wren_string() == signature.to_string_lossy() && mp.is_static == is_static
-            }).map(|mp| mp.pointer)
-        } else {
-            None
+    if module.to_string_lossy() == callbacks::MODULE && is_static {
+        match signature.to_string_lossy().as_ref() {
+            "register(_,_)" => return Some(callbacks::register),
+            "unregister(_)" => return Some(callbacks::unregister),
+            "schedule(_,_)" => return Some(callbacks::schedule),
+            _ => {},
+        }
+    }
+
+    if module.to_string_lossy() == events::MODULE && is_static && signature.to_string_lossy() == "emit_(_,_)" {
+        return Some(events::emit);
+    }
+
+    if module.to_string_lossy() == futures::MODULE && !is_static {
+        match signature.to_string_lossy().as_ref() {
+            "isDone" => return Some(futures::is_done),
+            "wait_(_)" => return Some(futures::wait),
+            "result_" => return Some(futures::result),
+            _ => {},
         }
-    } else {
-        None
     }
+
+    #[cfg(feature = "coverage")]
+    {
+        if conf.coverage.is_some() && module.to_string_lossy() == coverage::MODULE && is_static && signature.to_string_lossy() == "hit(_,_)" {
+            return Some(coverage::hit);
+        }
+    }
+
+    #[cfg(feature = "scheduler")]
+    {
+        if !conf.pure && module.to_string_lossy() == scheduler::TIMER_MODULE && is_static && signature.to_string_lossy() == "startTimer_(_,_)" {
+            return Some(scheduler::start_timer);
+        }
+    }
+
+    // Reloaded modules bind what the module they replace did
+    let module = module.to_string_lossy();
+    let module = hot_reload::reloaded_module(&module).unwrap_or(&module);
+    conf.foreign_class(module, &class.to_string_lossy(), |rc| {
+        rc.method_index[is_static as usize].get(signature.to_string_lossy().as_ref()).copied()
+    }).flatten()
+        .or_else(|| conf.bind_closure_method(module, &class.to_string_lossy(), is_static, &signature.to_string_lossy()))
+        .or_else(|| {
+            // Wren binds its own optional modules after asking
+            if matches!(module, "random" | "meta") {
+                return None;
+            }
+            conf.unbound.borrow_mut().push(UnboundForeign::Method {
+                module: module.to_string(),
+                class: class.to_string_lossy().into_owned(),
+                is_static,
+                signature: signature.to_string_lossy().into_owned(),
+            });
+            None
+        })
 }
 
 extern "C" fn wren_bind_foreign_class(vm: *mut WrenVM, mdl: *const raw::c_char, class: *const raw::c_char) -> WrenForeignClassMethods {
@@ -95,86 +302,271 @@ extern "C" fn wren_bind_foreign_class(vm: *mut WrenVM, mdl: *const raw::c_char,
     let module = unsafe { ffi::CStr::from_ptr(mdl) };
     let class = unsafe { ffi::CStr::from_ptr(class) };
 
-    if let Some(ref library) = conf.library {
-        let rc = library.get_foreign_class(module.to_string_lossy(), class.to_string_lossy());
-        if let Some(rc) = rc {
-            fcm.allocate = Some(rc.construct);
-            fcm.finalize = Some(rc.destruct);
-        }
+    let module = module.to_string_lossy();
+    // Futures are only made by VM::set_slot_future
+    if module == futures::MODULE {
+        fcm.finalize = Some(futures::finalize);
+        return fcm;
+    }
+    let module = hot_reload::reloaded_module(&module).unwrap_or(&module);
+    conf.foreign_class(module, &class.to_string_lossy(), |rc| {
+        fcm.allocate = match &rc.construct {
+            Allocator::Glue(construct) => Some(*construct),
+            Allocator::Closure(construct) => conf.bind_closure(module, construct.clone()),
+        };
+        fcm.finalize = Some(rc.destruct);
+    });
+    // Wren binds its own optional modules after asking
+    if fcm.allocate.is_none() && !matches!(module, "random" | "meta") {
+        conf.unbound.borrow_mut().push(UnboundForeign::Class { module: module.to_string(), class: class.to_string_lossy().into_owned() });
     }
     fcm
 }
 
-extern "C" fn wren_load_module(vm: *mut WrenVM, name: *const raw::c_char) -> *mut raw::c_char {
-    // The whoooole reason we wrote wren_realloc - to force Wren into Rust's allocation space
+// Prefers the script loader, falling back to source registered with the library
+fn load_source(conf: &mut UserData, name: &str) -> Option<Cow<'static, str>> {
+    let loader = &mut conf.loader;
+    guard_callback(&conf.callback_panic, "script loader", || loader.load_script(name.to_string())).flatten()
+        .or_else(|| conf.overlay.borrow().get_source(name))
+        .or_else(|| conf.library.as_ref().and_then(|lib| lib.get_source(name)))
+}
+
+extern "C" fn wren_load_module(vm: *mut WrenVM, name: *const raw::c_char) -> wren_sys::WrenLoadModuleResult {
+    let mut result = wren_sys::WrenLoadModuleResult {
+        source: std::ptr::null(),
+        onComplete: None,
+        userData: std::ptr::null_mut(),
+    };
+    let _span = ffi_span!(DEBUG, "load_module", module = %unsafe { ffi::CStr::from_ptr(name) }.to_string_lossy());
+    match load_module(vm, name) {
+        Some(ModuleSource::Static(source)) => result.source = source.as_ptr(),
+        Some(source) => {
+            // Kept alive in userData until Wren has compiled it
+            let source = Box::new(source);
+            result.source = source.as_c_str().as_ptr();
+            result.onComplete = Some(wren_load_module_complete);
+            result.userData = Box::into_raw(source) as *mut ffi::c_void;
+        },
+        None => {},
+    }
+    result
+}
+
+// Wren is done with a source load_module gave it
+extern "C" fn wren_load_module_complete(_: *mut WrenVM, _: *const raw::c_char, result: wren_sys::WrenLoadModuleResult) {
+    if !result.userData.is_null() {
+        drop(unsafe { Box::from_raw(result.userData as *mut ModuleSource) });
+    }
+}
+
+// A module's source, and whatever owns it while Wren compiles it
+enum ModuleSource {
+    // Already nul-terminated, so Wren can read it in place
+    Static(&'static ffi::CStr),
+    Owned(ffi::CString),
+    Cached(Arc<ffi::CString>),
+}
+
+impl ModuleSource {
+    fn new(source: Cow<'static, str>) -> Result<ModuleSource, ffi::NulError> {
+        match source {
+            Cow::Borrowed(source) => match ffi::CStr::from_bytes_with_nul(source.as_bytes()) {
+                Ok(source) => Ok(ModuleSource::Static(source)),
+                Err(_) => ffi::CString::new(source).map(ModuleSource::Owned),
+            },
+            Cow::Owned(source) => ffi::CString::new(source).map(ModuleSource::Owned),
+        }
+    }
+
+    fn owned<S: Into<Vec<u8>>>(source: S) -> ModuleSource {
+        ModuleSource::Owned(ffi::CString::new(source).unwrap())
+    }
+
+    fn as_c_str(&self) -> &ffi::CStr {
+        match self {
+            ModuleSource::Static(source) => source,
+            ModuleSource::Owned(source) => source,
+            ModuleSource::Cached(source) => source,
+        }
+    }
+}
+
+fn load_module(vm: *mut WrenVM, name: *const raw::c_char) -> Option<ModuleSource> {
     let conf = unsafe { &mut *(wren_sys::wrenGetUserData(vm) as *mut UserData) };
     let module_name = unsafe { ffi::CStr::from_ptr(name) };
-    match conf.loader.load_script(module_name.to_string_lossy().to_string()) {
-        Some(string) => {
-            ffi::CString::new(string).unwrap_or_else(|_| panic!("Failed to convert source to C string for {}", module_name.to_string_lossy())).into_raw()
-        },
-        None => std::ptr::null_mut()
+
+    if !conf.pure && module_name.to_string_lossy() == callbacks::MODULE {
+        return Some(ModuleSource::owned(callbacks::SOURCE));
+    }
+
+    if !conf.pure && module_name.to_string_lossy() == events::MODULE {
+        return Some(ModuleSource::owned(events::SOURCE));
+    }
+
+    if !conf.pure && module_name.to_string_lossy() == futures::MODULE {
+        return Some(ModuleSource::owned(futures::SOURCE));
+    }
+
+    if let Some(denied) = module_name.to_string_lossy().strip_prefix(DENIED_PREFIX) {
+        // Denied modules can have an @ in them, but importers don't
+        let (module, importer) = denied.rsplit_once('@').unwrap_or((denied, ""));
+        let message = format!("Module {:?} may not import {:?}", importer, module);
+        return Some(ModuleSource::owned(format!("Fiber.abort({})", WrenValue::from(message).to_wren_source())));
+    }
+
+    if module_name.to_string_lossy() == test_runner::MODULE {
+        return Some(ModuleSource::owned(test_runner::SOURCE));
+    }
+
+    #[cfg(feature = "log")]
+    {
+        if let Some(importer) = module_name.to_string_lossy().strip_prefix(stdlib::log::PREFIX) {
+            return Some(ModuleSource::owned(stdlib::log::logger_source(importer)));
+        }
+    }
+
+    #[cfg(feature = "process")]
+    {
+        if let Some(importer) = module_name.to_string_lossy().strip_prefix(stdlib::process::PREFIX) {
+            return Some(ModuleSource::owned(stdlib::process::importer_source(importer)));
+        }
+    }
+
+    #[cfg(feature = "coverage")]
+    {
+        if conf.coverage.is_some() && module_name.to_string_lossy() == coverage::MODULE {
+            return Some(ModuleSource::owned(coverage::SOURCE));
+        }
+    }
+
+    #[cfg(feature = "scheduler")]
+    {
+        if !conf.pure {
+            match module_name.to_string_lossy().as_ref() {
+                scheduler::SCHEDULER_MODULE => return Some(ModuleSource::owned(scheduler::SCHEDULER_SOURCE)),
+                scheduler::TIMER_MODULE => return Some(ModuleSource::owned(scheduler::TIMER_SOURCE)),
+                _ => {},
+            }
+        }
     }
+
+    let name = module_name.to_string_lossy();
+    let source = match conf.script_cache.as_ref().and_then(|cache| cache.get(&name)) {
+        Some(source) => ModuleSource::Cached(source),
+        None => {
+            let source = ModuleSource::new(load_source(conf, &name)?)
+                .unwrap_or_else(|_| panic!("Failed to convert source to C string for {}", name));
+            match conf.script_cache.as_ref() {
+                Some(cache) => ModuleSource::Cached(cache.insert(&name, source.as_c_str().to_owned())),
+                None => source,
+            }
+        }
+    };
+    #[cfg(feature = "coverage")]
+    let source = match conf.coverage.as_ref() {
+        Some(coverage) => ModuleSource::owned(coverage.borrow_mut().instrument(&name, &source.as_c_str().to_string_lossy())),
+        None => source,
+    };
+    Some(source)
 }
 
-extern "C" fn wren_canonicalize(_: *mut WrenVM, importer: *const raw::c_char, name: *const raw::c_char) -> *const raw::c_char {
+extern "C" fn wren_canonicalize(vm: *mut WrenVM, importer: *const raw::c_char, name: *const raw::c_char) -> *const raw::c_char {
+    let conf = unsafe { &*(wren_sys::wrenGetUserData(vm) as *const UserData) };
     let _importer = unsafe { ffi::CStr::from_ptr(importer) };
     let _name = unsafe { ffi::CStr::from_ptr(name) };
     let _importer = _importer.to_string_lossy();
     let _name = _name.to_string_lossy();
 
-    if let Some('@') = _name.chars().next() {
-        let real_name: String = _name.chars().skip(1).collect();
-        ffi::CString::new(format!("{}/{}", _importer, real_name))
-            .unwrap_or_else(|_| panic!("Failed to convert name {}/{} to C string", _importer, real_name))
-            .into_raw() as *const _
-    } else {
-        name
+    // Every module gets its own copy of Log, so messages know where they came from
+    #[cfg(feature = "log")]
+    {
+        let has_log = conf.foreign_class(stdlib::log::MODULE, "LogCore", |_| ()).is_some();
+        if has_log && _name == stdlib::log::MODULE && !_importer.starts_with(stdlib::log::PREFIX) {
+            return wren_string(vm, ffi::CString::new(format!("{}{}", stdlib::log::PREFIX, _importer))
+                .unwrap_or_else(|_| panic!("Failed to convert name {}{} to C string", stdlib::log::PREFIX, _importer))
+                );
+        }
     }
-}
 
-#[derive(Debug, Clone)]
-pub enum VMError {
-    Compile {
-        module: String,
-        line: i32,
-        error: String
-    },
-    Runtime {
-        error: String,
-        frames: Vec<VMStackFrameError>
+    // Modules mounted under a prefix still import their siblings by their old names
+    let resolved = match conf.library.as_ref().and_then(|lib| lib.resolve_mounted(&_importer, &_name)) {
+        Some(resolved) => Some(resolved),
+        None => match (conf.relative_import, _name.chars().next()) {
+            (true, Some('@')) => Some(format!("{}/{}", _importer, _name.chars().skip(1).collect::<String>())),
+            _ => None,
+        },
+    };
+
+    // Once the process stdlib is registered, importers without the capability get a module that
+    // aborts the import instead, and those with it their own copy, so its calls know who's making them
+    #[cfg(feature = "process")]
+    {
+        use stdlib::process::{MODULE, PREFIX};
+        let has_process = conf.foreign_class(MODULE, "Process", |_| ()).is_some();
+        let target = resolved.as_deref().unwrap_or(&_name);
+        if has_process && !_importer.starts_with(PREFIX) {
+            let mut imports = conf.process_imports.borrow_mut();
+            let granted = imports.is_granted(&conf.capabilities, &_importer);
+            // Nothing imports another module's copy by name, or by importing a module that has one
+            let forged = target.starts_with(PREFIX);
+            let passed_on = !granted && imports.reaches_process(target);
+            if forged || passed_on || (target == MODULE && !granted) {
+                return wren_string(vm, ffi::CString::new(format!("{}{}@{}", DENIED_PREFIX, target, _importer))
+                    .unwrap_or_else(|_| panic!("Failed to convert name {}{}@{} to C string", DENIED_PREFIX, target, _importer))
+                    );
+            }
+            if target == MODULE {
+                let copy = format!("{}{}", PREFIX, _importer);
+                imports.record(&_importer, &copy, true);
+                return wren_string(vm, ffi::CString::new(copy.as_str())
+                    .unwrap_or_else(|_| panic!("Failed to convert name {} to C string", copy))
+                    );
+            }
+            let loaded = ffi::CString::new(target)
+                .is_ok_and(|module| unsafe { wren_sys::wrenHasModule(vm, module.as_ptr()) });
+            imports.record(&_importer, target, loaded);
+        }
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct VMStackFrameError {
-    pub module: String,
-    pub line: i32,
-    pub function: String
+    match resolved {
+        Some(resolved) => wren_string(vm, ffi::CString::new(resolved.as_str())
+            .unwrap_or_else(|_| panic!("Failed to convert name {} to C string", resolved))
+            ),
+        None => name,
+    }
 }
 
-
-impl std::fmt::Display for VMError {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            VMError::Compile { module, line, error } => write!(fmt, "Compile Error ({}:{}): {}", module, line, error),
-            VMError::Runtime { error, frames } => {
-                writeln!(fmt, "Runtime Error: {}", error)?;
-                for frame in frames {
-                    if frame.function == "" {
-                        writeln!(fmt, "\tin {}:{}: <constructor>", frame.module, frame.line)?;
-                    } else {
-                        writeln!(fmt, "\tin {}:{}: {}", frame.module, frame.line, frame.function)?;
-                    }
-                }
-                Ok(())
-            },
-        }
+// What a module is resolved to when its importer lacks the capability for it
+const DENIED_PREFIX: &str = "ruwren/denied/";
+
+// What to call a module ruwren generated in stack traces, and whether to hide it unless traces are verbose
+//
+// [_pure] is whether the VM is in pure mode, where ruwren doesn't provide the scheduler and timer modules.
+fn glue_label(module: &str, _pure: bool) -> Option<(String, bool)> {
+    match module {
+        value::EVAL_MODULE => Some(("<eval>".to_string(), false)),
+        callbacks::MODULE => Some(("<callbacks>".to_string(), true)),
+        events::MODULE => Some(("<host>".to_string(), true)),
+        futures::MODULE => Some(("<async>".to_string(), true)),
+        test_runner::MODULE => Some(("<test>".to_string(), true)),
+        graph::MODULE => Some(("<graph>".to_string(), true)),
+        fibers::MODULE => Some(("<fibers>".to_string(), true)),
+        module if module.starts_with(DENIED_PREFIX) => Some(("<denied>".to_string(), true)),
+        module if hot_reload::reloaded_module(module).is_some() => hot_reload::reloaded_module(module).map(|original| (original.to_string(), false)),
+        #[cfg(feature = "coverage")]
+        coverage::MODULE => Some(("<coverage>".to_string(), true)),
+        #[cfg(feature = "scheduler")]
+        scheduler::SCHEDULER_MODULE if !_pure => Some(("<scheduler>".to_string(), true)),
+        #[cfg(feature = "scheduler")]
+        scheduler::TIMER_MODULE if !_pure => Some(("<timer>".to_string(), true)),
+        #[cfg(feature = "log")]
+        module if module.starts_with(stdlib::log::PREFIX) => Some(("<log>".to_string(), true)),
+        #[cfg(feature = "process")]
+        module if module.starts_with(stdlib::process::PREFIX) => Some(("<process>".to_string(), true)),
+        _ => None,
     }
 }
 
-impl std::error::Error for VMError {}
-
 /// A handle to a Wren object
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Handle<'a> {
@@ -191,20 +583,229 @@ impl<'a> Drop for Handle<'a> {
     }
 }
 
+/// A handle that keeps its VM alive, so it can be stored without borrowing anything
+/// (see [VMWrapper::get_slot_owned_handle])
+///
+/// The VM isn't freed until every owned handle to it has been dropped.
+#[derive(Debug)]
+pub struct OwnedHandle {
+    // Declared first, so it's released before the VM can be freed
+    handle: Handle<'static>,
+    vm: Evm,
+}
+
+impl OwnedHandle {
+    /// The handle, to pass to anything that takes a [Handle]
+    pub fn as_handle(&self) -> &Handle<'_> {
+        &self.handle
+    }
+
+    /// The VM this handle belongs to
+    pub fn vm(&self) -> VMWrapper {
+        VMWrapper(self.vm.clone())
+    }
+}
+
+/// A rooted handle to a foreign object
+///
+/// Wren never moves objects, so while the pin is held, references into the foreign object
+/// stay valid, even across garbage collections.
+#[derive(Debug)]
+pub struct Pin<'a> {
+    handle: Handle<'a>,
+    object: *mut ffi::c_void,
+}
+
+impl<'a> Pin<'a> {
+    // The pinned object, if it is a T ruwren made
+    fn object<T: 'static>(&self) -> Option<*mut ForeignObject<T>> {
+        let ptr = self.object as *mut ForeignObject<T>;
+        unsafe {
+            match ForeignObject::type_of(self.object) {
+                Some(type_id) if type_id == any::TypeId::of::<T>() && !(*ptr).object.is_null() => Some(ptr),
+                _ => None,
+            }
+        }
+    }
+
+    /// Borrows the pinned object, if it is of type T
+    ///
+    /// Returns None if the object is already mutably borrowed.
+    pub fn get<T: 'static + ClassObject>(&self) -> Option<ForeignRef<'_, T>> {
+        let object = self.object::<T>()?;
+        unsafe {
+            if (*object).borrow < 0 {
+                return None;
+            }
+            (*object).borrow += 1;
+        }
+        Some(ForeignRef { object, _root: None })
+    }
+
+    /// Mutably borrows the pinned object, if it is of type T
+    ///
+    /// Returns None if the object is already borrowed.
+    pub fn get_mut<T: 'static + ClassObject>(&mut self) -> Option<ForeignRefMut<'_, T>> {
+        let object = self.object::<T>()?;
+        unsafe {
+            if (*object).borrow != 0 {
+                return None;
+            }
+            (*object).borrow = -1;
+        }
+        Some(ForeignRefMut { object, _root: None })
+    }
+
+    /// The handle rooting the object, so it can be placed back into a slot
+    pub fn handle(&self) -> &Handle<'a> {
+        &self.handle
+    }
+}
+
+/// A rooted foreign object of type T, like a [Pin] whose type is already known
+#[derive(Debug)]
+pub struct ObjectHandle<'a, T> {
+    handle: Handle<'a>,
+    object: *mut ForeignObject<T>,
+}
+
+impl<'a, T> ObjectHandle<'a, T> {
+    /// Runs [f] with a shared borrow of the object
+    pub fn with<R, F: FnOnce(&T) -> R>(&self, f: F) -> Result<R, ForeignBorrowError> {
+        unsafe {
+            if (*self.object).borrow < 0 {
+                return Err(ForeignBorrowError::AlreadyBorrowed);
+            }
+            (*self.object).borrow += 1;
+            let _release = BorrowRelease(&mut (*self.object).borrow);
+            Ok(f(&*(*self.object).object))
+        }
+    }
+
+    /// Runs [f] with a mutable borrow of the object
+    pub fn with_mut<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Result<R, ForeignBorrowError> {
+        unsafe {
+            if (*self.object).borrow != 0 {
+                return Err(ForeignBorrowError::AlreadyBorrowed);
+            }
+            (*self.object).borrow = -1;
+            let _release = BorrowRelease(&mut (*self.object).borrow);
+            Ok(f(&mut *(*self.object).object))
+        }
+    }
+
+    /// The handle rooting the object, so it can be placed back into a slot
+    pub fn handle(&self) -> &Handle<'a> {
+        &self.handle
+    }
+}
+
+/// A Wren instance of the foreign class bound to T, wherever it was created (see [VMWrapper::get_slot_typed_handle])
+///
+/// Unlike an [ObjectHandle], this doesn't keep the object's address: each borrow loads it back
+/// into slot 0 (putting back whatever was there) and checks its type again.
+pub struct TypedHandle<'a, T> {
+    handle: Handle<'a>,
+    vm: &'a VMWrapper,
+    object: marker::PhantomData<T>,
+}
+
+impl<'a, T: 'static + ClassObject> TypedHandle<'a, T> {
+    // Loads the object into slot 0, and returns it with a root for the borrow to hold
+    fn load(&self, vm: &VM) -> Result<(*mut ForeignObject<T>, Handle<'a>), ForeignBorrowError> {
+        // Borrowing in a loop shouldn't keep adding slots, so this lends slot 0 and puts it back after
+        let saved = match vm.get_slot_count() {
+            0 => {
+                vm.ensure_slots(1);
+                None
+            },
+            _ => Some(vm.root_slot(0)),
+        };
+        unsafe {
+            wren_sys::wrenSetSlotHandle(vm.vm, 0, self.handle.handle);
+            let loaded = vm.foreign_object_ptr::<T>(0).map(|ptr| (ptr, Handle {
+                handle: wren_sys::wrenGetSlotHandle(vm.vm, 0),
+                wvm: vm.vm,
+                vm: marker::PhantomData,
+            }));
+            if let Some(saved) = saved {
+                wren_sys::wrenSetSlotHandle(vm.vm, 0, saved.handle);
+            }
+            loaded
+        }
+    }
+
+    /// Borrows the object, failing if it's mutably borrowed
+    pub fn borrow(&self) -> Result<ForeignRef<'a, T>, ForeignBorrowError> {
+        self.vm.execute(|vm| {
+            let (object, root) = self.load(vm)?;
+            unsafe {
+                if (*object).borrow < 0 {
+                    return Err(ForeignBorrowError::AlreadyBorrowed);
+                }
+                (*object).borrow += 1;
+            }
+            Ok(ForeignRef { object, _root: Some(root) })
+        })
+    }
+
+    /// Mutably borrows the object, failing if it's borrowed at all
+    pub fn borrow_mut(&self) -> Result<ForeignRefMut<'a, T>, ForeignBorrowError> {
+        self.vm.execute(|vm| {
+            let (object, root) = self.load(vm)?;
+            unsafe {
+                if (*object).borrow != 0 {
+                    return Err(ForeignBorrowError::AlreadyBorrowed);
+                }
+                (*object).borrow = -1;
+            }
+            Ok(ForeignRefMut { object, _root: Some(root) })
+        })
+    }
+
+    /// The handle to the instance, so it can be placed back into a slot
+    pub fn handle(&self) -> &Handle<'a> {
+        &self.handle
+    }
+}
+
+/// The values of a VM's slots, kept alive until they're restored (see [VM::snapshot_slots])
+#[derive(Debug)]
+pub struct SlotSnapshot<'a> {
+    slots: Vec<Handle<'a>>,
+}
+
+impl SlotSnapshot<'_> {
+    /// How many slots were saved
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
 /// A handle to a Wren method call
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FunctionHandle<'a>(Handle<'a>);
+pub struct FunctionHandle<'a>(Handle<'a>, String);
 
 /// Simulates a module structure for foreign functions
+///
+/// Modules, and the classes in them, are kept in the order they were registered, so anything
+/// that goes through them all does the same thing on every run.
 #[derive(Debug, Clone, Default)]
 pub struct ModuleLibrary {
-    modules: HashMap<String, Module>,
+    modules: IndexMap<String, Module>,
+    // Prefixes other libraries have been mounted under
+    mounts: Vec<String>,
 }
 
 impl ModuleLibrary {
     pub fn new() -> ModuleLibrary {
         ModuleLibrary {
-            modules: HashMap::new()
+            modules: IndexMap::new(),
+            mounts: vec![],
         }
     }
 
@@ -212,24 +813,108 @@ impl ModuleLibrary {
         self.modules.insert(name.into(), modl);
     }
 
+    /// Adds every module of [library] under [prefix], so its module "x" becomes "[prefix]/x"
+    ///
+    /// Modules in [library] that import each other by their old names keep working.
+    pub fn mount<P: AsRef<str>>(&mut self, prefix: P, library: ModuleLibrary) {
+        let prefix = prefix.as_ref().trim_end_matches('/');
+        for (name, modl) in library.modules {
+            self.modules.insert(format!("{}/{}", prefix, name), modl);
+        }
+        self.mounts.extend(library.mounts.iter().map(|mount| format!("{}/{}", prefix, mount)));
+        self.mounts.push(prefix.to_string());
+        // Innermost mounts first
+        self.mounts.sort_by_key(|mount| std::cmp::Reverse(mount.len()));
+    }
+
+    // The mounted module [name] means when [importer] imports it, if any
+    fn resolve_mounted(&self, importer: &str, name: &str) -> Option<String> {
+        self.mounts.iter()
+            .filter(|mount| importer.strip_prefix(mount.as_str()).is_some_and(|rest| rest.starts_with('/')))
+            .map(|mount| format!("{}/{}", mount, name))
+            .find(|resolved| self.modules.contains_key(resolved))
+    }
+
     fn get_foreign_class<M: AsRef<str>, C: AsRef<str>>(&self, module: M, class: C) -> Option<&RuntimeClass> {
         self.modules.get(module.as_ref()).and_then(|md| md.classes.get(class.as_ref()))
     }
+
+    // The first (module, class) registered for the Rust type [type_id]
+    fn find_foreign_class(&self, type_id: any::TypeId) -> Option<(&str, &str)> {
+        self.modules.iter()
+            .flat_map(|(mname, md)| md.classes.iter()
+                .filter(|(_, rc)| rc.type_id == type_id)
+                .map(move |(cname, _)| (mname.as_str(), cname.as_str())))
+            .next()
+    }
+
+    // The module's registered source, or else its stub if it has anything foreign to declare
+    fn get_source<M: AsRef<str>>(&self, module: M) -> Option<Cow<'static, str>> {
+        let md = self.modules.get(module.as_ref())?;
+        match &md.source {
+            Some(source) => Some(source.clone().into()),
+            None if md.classes.is_empty() && md.closures.is_empty() => None,
+            None => Some(md.stub().into()),
+        }
+    }
+
+    /// Generates the Wren declarations of every class registered in [module], so they don't have
+    /// to be written out by hand
+    ///
+    /// Each foreign class gets its foreign methods, and any Wren given with [ClassBuilder::wren]
+    /// or create_module!'s `wren(...)` (which is where constructors go). Classes that only have
+    /// closure methods are declared without `foreign`. A module registered without
+    /// [Module::source] is loaded from its stub, so importing it is enough.
+    ///
+    /// Empty if there's no such module.
+    pub fn generate_stub<M: AsRef<str>>(&self, module: M) -> String {
+        self.modules.get(module.as_ref()).map(Module::stub).unwrap_or_default()
+    }
+
+    /// Lists (module, class) for every registered class that isn't known to be Send
+    pub fn non_send_classes(&self) -> Vec<(String, String)> {
+        let mut classes: Vec<_> = self.modules.iter()
+            .flat_map(|(mname, md)| md.classes.iter()
+                .filter(|(_, rc)| !rc.is_send)
+                .map(move |(cname, _)| (mname.clone(), cname.clone())))
+            .collect();
+        classes.sort();
+        classes
+    }
+}
+
+// A foreign method, as Wren binds it
+pub(crate) type MethodFn = unsafe extern "C" fn(*mut WrenVM);
+
+// What makes a foreign class's objects
+#[derive(Debug, Clone)]
+enum Allocator {
+    // From create_module!
+    Glue(extern "C" fn(*mut WrenVM)),
+    // From a ClassBuilder, given a trampoline when the class is bound
+    Closure(Arc<closures::ClosureMethod>),
 }
 
 #[derive(Debug, Clone)]
 struct RuntimeClass {
-    construct: extern "C" fn(*mut WrenVM),
+    construct: Allocator,
     destruct: extern "C" fn(*mut ffi::c_void),
-    methods: ClassObjectPointers,
+    // Instance methods, then static methods, by signature (worked out once, as binding happens a lot)
+    method_index: [HashMap<String, MethodFn>; 2],
+    // Pasted into the start of the class body by stubs
+    wren: String,
 
     // Use for "loading in" appropriate objects
     type_id: any::TypeId,
+    is_send: bool,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct Module {
-    classes: HashMap<String, RuntimeClass>,
+    classes: IndexMap<String, RuntimeClass>,
+    // By (class, is_static, signature)
+    closures: HashMap<(String, bool, String), Arc<closures::ClosureMethod>>,
+    source: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -244,48 +929,489 @@ pub struct MethodPointer {
     pub pointer: unsafe extern "C" fn(*mut WrenVM),
 }
 
-impl Module {
-    pub fn new() -> Module {
-        Module {
-            classes: HashMap::new()
-        }
+impl Module {
+    pub fn new() -> Module {
+        Module {
+            classes: IndexMap::new(),
+            closures: HashMap::new(),
+            source: None,
+        }
+    }
+
+    /// Wren source to load for this module, if the VM's script loader doesn't provide any
+    pub fn source<S: Into<String>>(&mut self, source: S) -> &mut Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    pub fn class<C: 'static + ClassObject, S: Into<String>>(&mut self, name: S) -> &mut Self {
+        self.class_with_send::<C, S>(name, false)
+    }
+
+    /// Registers a class that is known to be Send (see [VMConfig::require_send])
+    pub fn send_class<C: 'static + ClassObject + Send, S: Into<String>>(&mut self, name: S) -> &mut Self {
+        self.class_with_send::<C, S>(name, true)
+    }
+
+    /// Registers a class under its own name, and adds its declaration to the module's source
+    /// (see `#[wren_class]`, with the `derive` feature)
+    pub fn wren_class<C: 'static + WrenClass>(&mut self) -> &mut Self {
+        let source = self.source.take().unwrap_or_default() + &C::source();
+        self.source = Some(source);
+        self.class_with_send::<C, _>(C::NAME, C::is_send())
+    }
+
+    #[doc(hidden)]
+    pub fn class_with_send<C: 'static + ClassObject, S: Into<String>>(&mut self, name: S, is_send: bool) -> &mut Self {
+        let cp = C::generate_pointers();
+        let init = C::initialize_pointer();
+        let deinit = C::finalize_pointer();
+        let mut method_index = [HashMap::new(), HashMap::new()];
+        for mp in cp.function_pointers {
+            // Like a search from the start would, the first method with a signature wins
+            method_index[mp.is_static as usize].entry(mp.signature.as_wren_string()).or_insert(mp.pointer);
+        }
+        self.classes.insert(name.into(), RuntimeClass {
+            construct: Allocator::Glue(init),
+            destruct: deinit,
+            method_index,
+            wren: String::new(),
+            type_id: any::TypeId::of::<C>(),
+            is_send,
+        });
+        self
+    }
+
+    /// Sets the Wren pasted into the start of class [name]'s body in the module's stub
+    #[doc(hidden)]
+    pub fn class_wren<N: AsRef<str>, S: Into<String>>(&mut self, name: N, wren: S) -> &mut Self {
+        if let Some(rc) = self.classes.get_mut(name.as_ref()) {
+            rc.wren = wren.into();
+        }
+        self
+    }
+
+    // See ModuleLibrary::generate_stub
+    fn stub(&self) -> String {
+        let mut stub = String::new();
+        for (name, rc) in &self.classes {
+            let methods = (0..2)
+                .flat_map(|is_static| rc.method_index[is_static].keys().map(move |sig| (is_static == 1, sig.clone())))
+                .chain(self.closures.keys().filter(|(class, ..)| class == name).map(|(_, is_static, sig)| (*is_static, sig.clone())))
+                .collect();
+            let methods = parse_methods(methods);
+            stub.push_str(&declare_class(is_foreign(&rc.wren, &methods), name, &rc.wren, methods));
+        }
+        let mut plain: Vec<&str> = self.closures.keys()
+            .map(|(class, ..)| class.as_str())
+            .filter(|class| !self.classes.contains_key(*class))
+            .collect();
+        plain.sort_unstable();
+        plain.dedup();
+        for name in plain {
+            let methods = self.closures.keys()
+                .filter(|(class, ..)| class == name)
+                .map(|(_, is_static, sig)| (*is_static, sig.clone()))
+                .collect();
+            stub.push_str(&declare_class(false, name, "", parse_methods(methods)));
+        }
+        stub
+    }
+}
+
+// The Wren declaration of a class, with [wren] pasted in as the start of its body
+fn declare_class<I: IntoIterator<Item = (bool, FunctionSignature)>>(foreign: bool, name: &str, wren: &str, methods: I) -> String {
+    let mut source = format!("{}class {} {{\n", if foreign { "foreign " } else { "" }, name);
+    for line in wren.trim_matches('\n').lines() {
+        source.push_str(line);
+        source.push('\n');
+    }
+    for (is_static, signature) in methods {
+        source.push_str(&format!("    foreign {}{}\n", if is_static { "static " } else { "" }, signature.as_wren_declaration()));
+    }
+    source.push_str("}\n");
+    source
+}
+
+// Whether a class has to be declared foreign: it does if it has a constructor or instance methods,
+// but a class of only static methods has no instances, so it's declared as a plain class
+fn is_foreign(wren: &str, methods: &[(bool, FunctionSignature)]) -> bool {
+    wren.split(|c: char| !c.is_alphanumeric() && c != '_').any(|word| word == "construct")
+        || methods.iter().any(|(is_static, _)| !is_static)
+}
+
+// Methods are only ever indexed by signatures that were parsed or generated, so they parse back
+fn parse_methods(mut methods: Vec<(bool, String)>) -> Vec<(bool, FunctionSignature)> {
+    methods.sort();
+    methods.dedup();
+    methods.into_iter()
+        .filter_map(|(is_static, signature)| FunctionSignature::parse(&signature).ok().map(|signature| (is_static, signature)))
+        .collect()
+}
+
+/// A foreign class that knows its own Wren name and declaration, as `#[wren_methods]` generates
+pub trait WrenClass: ClassObject {
+    const NAME: &'static str;
+    /// The full Wren declaration of the class, constructors included
+    fn source() -> String where Self: Sized;
+    fn is_send() -> bool where Self: Sized;
+}
+
+/// Generates the Wren declaration of a foreign class, with [wren] pasted in as the start of its body
+///
+/// Classes with no constructor and only static methods are declared as plain classes.
+#[doc(hidden)]
+pub fn class_source<C: ClassObject>(name: &str, wren: &str) -> String {
+    let methods: Vec<_> = C::generate_pointers().function_pointers.into_iter().map(|mp| (mp.is_static, mp.signature)).collect();
+    declare_class(is_foreign(wren, &methods), name, wren, methods)
+}
+
+// Lets create_module! find out whether a class is Send without requiring it to be.
+// (&SendProbe::<T>(PhantomData)).is_send() resolves to SendProbeSend if T: Send, or SendProbeAny otherwise.
+#[doc(hidden)]
+pub struct SendProbe<T>(pub marker::PhantomData<T>);
+
+#[doc(hidden)]
+pub trait SendProbeSend {
+    fn is_send(&self) -> bool { true }
+}
+
+impl<T: Send> SendProbeSend for SendProbe<T> {}
+
+#[doc(hidden)]
+pub trait SendProbeAny {
+    fn is_send(&self) -> bool { false }
+}
+
+impl<T> SendProbeAny for &SendProbe<T> {}
+
+// Trait that all Wren "class" objects implement
+pub trait Class {
+    /// Makes the object for a Wren constructor, which left its arguments in slots 1 on
+    ///
+    /// Only called by the default [Class::construct], so classes that implement that instead can
+    /// leave this out.
+    fn initialize(_: &VM) -> Self where Self: Sized {
+        panic!("{} implements neither Class::initialize nor Class::construct", any::type_name::<Self>())
+    }
+
+    /// Makes the object for a Wren constructor, handed the arguments it was called with
+    fn construct(constructor: &Constructor) -> Self where Self: Sized {
+        Self::initialize(constructor.vm())
+    }
+}
+
+/// A foreign class's constructor call, like `Vec2.new(1, 2)`, handed to [Class::construct]
+///
+/// Slot 0 already holds the new object, so the arguments start at slot 1. It derefs to the [VM]
+/// for anything else.
+pub struct Constructor<'a> {
+    vm: &'a VM,
+    arity: usize,
+}
+
+impl<'a> Constructor<'a> {
+    #[doc(hidden)]
+    pub fn new(vm: &'a VM) -> Constructor<'a> {
+        Constructor { vm, arity: vm.get_slot_count().saturating_sub(1) }
+    }
+
+    pub fn vm(&self) -> &'a VM {
+        self.vm
+    }
+
+    /// How many arguments the constructor takes
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// The slot argument [index] (counting from 0) is in
+    pub fn arg_slot(&self, index: usize) -> SlotId {
+        index + 1
+    }
+
+    /// Reads argument [index] (counting from 0) as a T
+    ///
+    /// Errors name the class, like [VM::try_get_slot].
+    pub fn arg<T: FromWren>(&self, index: usize) -> Result<T, SlotError> {
+        if index >= self.arity {
+            return Err(self.vm.in_foreign_method(SlotError::MissingArgument { index, arity: self.arity }));
+        }
+        self.vm.try_get_slot(self.arg_slot(index))
+    }
+
+    /// The types of the arguments, in order
+    pub fn arg_types(&self) -> Vec<SlotType> {
+        (0..self.arity).map(|index| self.vm.get_slot_type(self.arg_slot(index))).collect()
+    }
+
+    /// Whether the arguments are exactly as many as [types], and of those types, with
+    /// [SlotType::Unknown] taking anything
+    pub fn matches(&self, types: &[SlotType]) -> bool {
+        types.len() == self.arity && types.iter().enumerate().all(|(index, expected)| {
+            *expected == SlotType::Unknown || *expected == self.vm.get_slot_type(self.arg_slot(index))
+        })
+    }
+
+    /// Runs the first of [constructors] whose argument types [match](Constructor::matches)
+    ///
+    /// Wren doesn't tell a foreign class which of its constructors was called, so a class with
+    /// several (like `new()`, `new(x)` and `fromJson(s)`) tells them apart by what they were passed:
+    ///
+    /// ```ignore
+    /// fn construct(constructor: &Constructor) -> Self {
+    ///     constructor.dispatch(&[
+    ///         (&[], |_| Foo::default()),
+    ///         (&[SlotType::Num], |c| Foo::sized(c.arg(0).unwrap())),
+    ///         (&[SlotType::String], Foo::from_json),
+    ///     ])
+    /// }
+    /// ```
+    ///
+    /// Panics (aborting the fiber) if none of them match.
+    pub fn dispatch<T>(&self, constructors: &[(&[SlotType], ConstructorFn<T>)]) -> T {
+        match constructors.iter().find(|(types, _)| self.matches(types)) {
+            Some((_, construct)) => construct(self),
+            None => panic!("no constructor takes ({})", self.arg_types().iter().map(|ty| format!("{:?}", ty)).collect::<Vec<_>>().join(", ")),
+        }
+    }
+}
+
+/// One of the constructors given to [Constructor::dispatch]
+pub type ConstructorFn<T> = fn(&Constructor) -> T;
+
+impl std::ops::Deref for Constructor<'_> {
+    type Target = VM;
+
+    fn deref(&self) -> &VM {
+        self.vm
+    }
+}
+
+pub trait ClassObject: Class {
+    fn initialize_pointer() -> extern "C" fn(*mut WrenVM) where Self: Sized;
+    fn finalize_pointer() -> extern "C" fn(*mut ffi::c_void) where Self: Sized;
+    fn generate_pointers() -> ClassObjectPointers where Self: Sized;
+}
+
+// Starts every ForeignObject, so objects ruwren didn't make (like Wren's own Random) can be told apart
+const FOREIGN_MAGIC: u64 = 0x7275_7772_656e_666f;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct ForeignObject<T> {
+    magic: u64,
+    pub object: *mut T,
+    pub type_id: any::TypeId,
+    // 0 when unborrowed, > 0 for shared borrows, -1 for a mutable borrow
+    borrow: isize,
+}
+
+impl<T: 'static> ForeignObject<T> {
+    pub fn new(object: *mut T) -> ForeignObject<T> {
+        ForeignObject {
+            magic: FOREIGN_MAGIC,
+            object,
+            type_id: any::TypeId::of::<T>(),
+            borrow: 0,
+        }
+    }
+}
+
+impl ForeignObject<()> {
+    /// The type of the object behind [ptr], a foreign object's data, if ruwren made it
+    ///
+    /// # Safety
+    /// [ptr] has to point to the data of a live foreign object.
+    pub(crate) unsafe fn type_of(ptr: *const ffi::c_void) -> Option<any::TypeId> {
+        // Wren's own foreign objects (Random's state) are all bigger than the magic, so reading it stays in bounds
+        let ptr = ptr as *const ForeignObject<()>;
+        match (*(ptr as *const u64)) == FOREIGN_MAGIC {
+            true => Some((*ptr).type_id),
+            false => None,
+        }
+    }
+}
+
+/// The bytes of a string in a slot, borrowed without copying (see [VM::get_slot_bytes_ref])
+///
+/// The string stays rooted for as long as this is held, so it's fine to overwrite the slot.
+pub struct SlotBytes<'a> {
+    bytes: &'a [u8],
+    _root: Handle<'a>,
+}
+
+impl<'a> std::ops::Deref for SlotBytes<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+impl<'a> AsRef<[u8]> for SlotBytes<'a> {
+    fn as_ref(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+impl<'a> std::fmt::Debug for SlotBytes<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.bytes, f)
+    }
+}
+
+/// The string in a slot, borrowed without copying if it's valid UTF-8 (see [VM::get_slot_str])
+///
+/// The string stays rooted for as long as this is held, so it's fine to overwrite the slot.
+pub struct SlotStr<'a> {
+    string: Cow<'a, str>,
+    // Lossy copies don't need rooting
+    _root: Option<Handle<'a>>,
+}
+
+impl<'a> SlotStr<'a> {
+    /// Whether the string had to be copied, because it wasn't valid UTF-8
+    pub fn is_copied(&self) -> bool {
+        matches!(self.string, Cow::Owned(_))
+    }
+}
+
+impl<'a> std::ops::Deref for SlotStr<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.string
+    }
+}
+
+impl<'a> AsRef<str> for SlotStr<'a> {
+    fn as_ref(&self) -> &str {
+        &self.string
+    }
+}
+
+impl<'a> std::fmt::Debug for SlotStr<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&*self.string, f)
+    }
+}
+
+impl<'a> std::fmt::Display for SlotStr<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Display::fmt(&*self.string, f)
+    }
+}
+
+/// A shared borrow of a foreign object, which keeps the object rooted while held
+pub struct ForeignRef<'a, T> {
+    object: *mut ForeignObject<T>,
+    // None when the guard borrows from something that roots the object already, like a Pin
+    _root: Option<Handle<'a>>,
+}
+
+impl<'a, T> std::ops::Deref for ForeignRef<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*(*self.object).object }
+    }
+}
+
+impl<'a, T> Drop for ForeignRef<'a, T> {
+    fn drop(&mut self) {
+        unsafe { (*self.object).borrow -= 1; }
+    }
+}
+
+/// A mutable borrow of a foreign object, which keeps the object rooted while held
+pub struct ForeignRefMut<'a, T> {
+    object: *mut ForeignObject<T>,
+    // None when the guard borrows from something that roots the object already, like a Pin
+    _root: Option<Handle<'a>>,
+}
+
+impl<'a, T> std::ops::Deref for ForeignRefMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*(*self.object).object }
+    }
+}
+
+impl<'a, T> std::ops::DerefMut for ForeignRefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *(*self.object).object }
     }
+}
 
-    pub fn class<C: 'static + ClassObject, S: Into<String>>(&mut self, name: S) -> &mut Self {
-        let cp = C::generate_pointers();
-        let init = C::initialize_pointer();
-        let deinit = C::finalize_pointer();
-        self.classes.insert(name.into(), RuntimeClass {
-            construct: init,
-            destruct: deinit,
-            methods: cp,
-            type_id: any::TypeId::of::<C>(),
-        });
-        self
+impl<'a, T> Drop for ForeignRefMut<'a, T> {
+    fn drop(&mut self) {
+        unsafe { (*self.object).borrow = 0; }
     }
 }
 
-// Trait that all Wren "class" objects implement
-pub trait Class {
-    fn initialize(_: &VM) -> Self where Self: Sized;
+// Puts back the sinks replaced by VMWrapper::with_sinks, even if by panicking
+struct SinkRestore {
+    ud: *mut UserData,
+    printer: Option<Box<dyn Printer>>,
+    error_sink: Option<ErrorSink>,
 }
 
-pub trait ClassObject: Class {
-    fn initialize_pointer() -> extern "C" fn(*mut WrenVM) where Self: Sized;
-    fn finalize_pointer() -> extern "C" fn(*mut ffi::c_void) where Self: Sized;
-    fn generate_pointers() -> ClassObjectPointers where Self: Sized;
+impl Drop for SinkRestore {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(printer) = self.printer.take() {
+                (*self.ud).printer = printer;
+            }
+            (*self.ud).error_sink = self.error_sink.take();
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct ForeignObject<T> {
-    pub object: *mut T,
-    pub type_id: any::TypeId,
+// Leaves a nested interpret or call, even if by panicking
+struct CallDepth<'a>(&'a UserData);
+
+impl<'a> Drop for CallDepth<'a> {
+    fn drop(&mut self) {
+        let depth = &self.0.call_depth;
+        depth.set(depth.get() - 1);
+        // Nothing can still be using the scratch space once the outermost call is done
+        if depth.get() == 0 {
+            self.0.scratch.reset();
+        }
+    }
+}
+
+// Releases a borrow flag when a borrow ends, even if by panicking
+struct BorrowRelease(*mut isize);
+
+impl Drop for BorrowRelease {
+    fn drop(&mut self) {
+        unsafe {
+            if *self.0 < 0 {
+                *self.0 = 0;
+            } else {
+                *self.0 -= 1;
+            }
+        }
+    }
 }
 
 /// Creates a function at $modl::publish_module, that takes a &mut ModuleLibrary
 /// and handles Module object creation and registration
 /// 
 /// Also internally creates all the necessary extern "C" functions for Wren's callbacks
+///
+/// `instance(property "name") method` declares both the getter and the setter for `name`, calling
+/// `method(&mut self, vm: &VM, value: Option<T>)` with None to get (leaving the value in slot 0), or with
+/// the new value (any [FromWren] type) to set.
+///
+/// `instance(operator "+") method` declares an infix operator, taking the right-hand side in slot 1, and
+/// `instance(prefix "-") method` a prefix one, so foreign classes can be used with Wren's arithmetic.
+///
+/// `instance(subscript 1) method` and `instance(subscript_setter 1) method` declare `[_]` and
+/// `[_]=(_)`, with the indices from slot 1 on (and the value to set after them).
 #[macro_export]
 macro_rules! create_module {
     (
@@ -294,109 +1420,161 @@ macro_rules! create_module {
                 $(
                     $si:ident($lbls:ident $($sgns:expr),+) $id:ident
                 ),*
-            }
+            } $(wren($wren:expr))?
         )+
 
         module => $modl:ident
     ) => {
         $(
-            mod $md {
-                use std::panic::{take_hook, set_hook, catch_unwind, AssertUnwindSafe};
-
-                pub(in super) extern "C" fn _constructor(vm: *mut $crate::wren_sys::WrenVM) {
-                    use $crate::Class;
-                    unsafe {
-                        let conf = &mut *($crate::wren_sys::wrenGetUserData(vm) as *mut $crate::UserData);
-                        let vm = std::rc::Weak::upgrade(&conf.vm).expect(&format!("Failed to access VM at {:p}", &conf.vm));
-                        let wptr = $crate::wren_sys::wrenSetSlotNewForeign(vm.borrow().vm, 0, 0, std::mem::size_of::<$crate::ForeignObject<$name>>() as $crate::wren_sys::size_t);
-                        // Allocate a new object, and move it onto the heap
-                        set_hook(Box::new(|_| {}));
-                        let vm_borrow = AssertUnwindSafe(vm.borrow());
-                        let object = match catch_unwind(|| <$name as Class>::initialize(&*vm_borrow)) {
-                            Ok(obj) => Some(obj),
-                            Err(err) => {
-                                let err_string = if let Some(strg) = err.downcast_ref::<String>() {
-                                    strg.clone()
-                                } else if let Some(strg) = err.downcast_ref::<&str>() {
-                                    strg.to_string()
-                                } else {
-                                    "Non-string panic message".into()
-                                };
-
-                                vm_borrow.set_slot_string(0, err_string);
-                                vm_borrow.abort_fiber(0);
-                                None
-                            }
-                        };
-                        drop(take_hook());
-                        // Copy the object pointer if we were successful
-                        if let Some(object) = object {
-                            std::ptr::write(wptr as *mut _, $crate::ForeignObject {
-                                object: Box::into_raw(Box::new(object)),
-                                type_id: std::any::TypeId::of::<$name>(),
-                            });
-                        }
-                    }
-                }
+            $crate::create_module!(@class $mname, stringify!($modl), $name => $md {
+                $( $si($lbls $($sgns),+) $id ),*
+            });
+        )+
 
-                pub(in super) extern "C" fn _destructor(data: *mut std::ffi::c_void) {
-                    unsafe {
-                        let mut fo: &mut $crate::ForeignObject<$name> = &mut *(data as *mut _);
-                        if !fo.object.is_null() { // If we haven't dropped an object, work on dropping it.
-                            drop(Box::from_raw(fo.object));
-                            fo.object = std::ptr::null_mut();
-                        }
-                    }
-                }
+        mod $modl {
+            #[allow(unused_imports)]
+            use super::*;
 
+            /// The Wren declarations of every class in this module, including any inline Wren
+            pub fn source() -> String {
+                let mut source = String::new();
                 $(
-                    $crate::create_module!(@fn $si $name => $id);
-                )*
-            }
-
-            impl $crate::ClassObject for $name {
-                fn initialize_pointer() -> extern "C" fn(*mut $crate::wren_sys::WrenVM) { $md::_constructor }
-                fn finalize_pointer() -> extern "C" fn(*mut std::ffi::c_void) { $md::_destructor }
-                fn generate_pointers() -> $crate::ClassObjectPointers {
-                    $crate::ClassObjectPointers {
-                        function_pointers: vec![
-                            $(
-                                $crate::create_module!(@md $si $id $lbls $md $($sgns),+)
-                            ),*
-                        ]
-                    }
-                }
+                    source.push_str(&$crate::class_source::<$name>($mname, $crate::create_module!(@wren $($wren)?)));
+                )+
+                source
             }
-        )+
 
-        mod $modl {
             pub fn publish_module(lib: &mut $crate::ModuleLibrary) {
                 let mut module = $crate::Module::new();
+                #[allow(unused_imports)]
+                use $crate::{SendProbeSend, SendProbeAny};
                 module
                 $(
-                    .class::<$name, _>($mname)
-                )+;
+                    .class_with_send::<$name, _>($mname, (&$crate::SendProbe::<$name>(std::marker::PhantomData)).is_send())
+                    .class_wren($mname, $crate::create_module!(@wren $($wren)?))
+                )+
+                    .source(source());
                 lib.module(stringify!($modl).replace("_", "/"), module);
             }
         }
     };
 
     (@md static $id:ident $lbls:ident $md:ident $($sgns: expr),+) => {
-        $crate::MethodPointer {
+        [$crate::MethodPointer {
             pointer: $md::$id,
             signature: $crate::create_module!(@sgn $lbls $($sgns),+),
             is_static: true,
-        }
+        }]
+    };
+
+    (@md instance $id:ident property $md:ident $prop:expr) => {
+        [$crate::MethodPointer {
+            pointer: $md::$id::get,
+            signature: $crate::FunctionSignature::new_getter($prop),
+            is_static: false,
+        }, $crate::MethodPointer {
+            pointer: $md::$id::set,
+            signature: $crate::FunctionSignature::new_setter($prop),
+            is_static: false,
+        }]
     };
 
     (@md instance $id:ident $lbls:ident $md:ident $($sgns: expr),+) => {
-        $crate::MethodPointer {
+        [$crate::MethodPointer {
             pointer: $md::$id,
             signature: $crate::create_module!(@sgn $lbls $($sgns),+),
             is_static: false,
+        }]
+    };
+
+    // One class's glue code, which #[wren_methods] also expands to
+    (@class $mname:expr, $modl:expr, $name:ty => $md:ident {
+        $(
+            $si:ident($lbls:ident $($sgns:expr),+) $id:ident
+        ),*
+    }) => {
+        mod $md {
+            #[allow(unused_imports)]
+            use super::*;
+            use std::panic::{take_hook, set_hook, catch_unwind, AssertUnwindSafe};
+
+            pub(in super) extern "C" fn _constructor(vm: *mut $crate::wren_sys::WrenVM) {
+                use $crate::Class;
+                unsafe {
+                    let conf = &mut *($crate::wren_sys::wrenGetUserData(vm) as *mut $crate::UserData);
+                    let vm = std::rc::Weak::upgrade(&conf.vm).expect(&format!("Failed to access VM at {:p}", &conf.vm));
+                    let wptr = $crate::wren_sys::wrenSetSlotNewForeign(vm.borrow().vm, 0, 0, std::mem::size_of::<$crate::ForeignObject<$name>>() as $crate::wren_sys::size_t);
+                    // Allocate a new object, and move it onto the heap
+                    set_hook(Box::new(|_| {}));
+                    let vm_borrow = AssertUnwindSafe(vm.borrow());
+                    // Counted before the class can ensure more slots
+                    let constructor = $crate::Constructor::new(&*vm_borrow);
+                    let object = match catch_unwind(AssertUnwindSafe(|| {
+                        vm_borrow.check_heap_limit();
+                        let _scope = vm_borrow.enter_foreign($mname, None);
+                        <$name as Class>::construct(&constructor)
+                    })) {
+                        Ok(obj) => Some(obj),
+                        Err(err) => {
+                            let err_string = if let Some(strg) = err.downcast_ref::<String>() {
+                                strg.clone()
+                            } else if let Some(strg) = err.downcast_ref::<&str>() {
+                                strg.to_string()
+                            } else {
+                                "Non-string panic message".into()
+                            };
+
+                            let err_string = vm_borrow.record_foreign_abort(&err_string, $modl, $mname, None);
+                            vm_borrow.set_slot_string(0, err_string);
+                            vm_borrow.abort_fiber(0);
+                            None
+                        }
+                    };
+                    drop(take_hook());
+                    // Copy the object pointer if we were successful, and otherwise leave one the
+                    // destructor knows to skip
+                    let object = object.map_or(std::ptr::null_mut(), |object| Box::into_raw(Box::new(object)));
+                    std::ptr::write(wptr as *mut _, $crate::ForeignObject::<$name>::new(object));
+                }
+            }
+
+            pub(in super) extern "C" fn _destructor(data: *mut std::ffi::c_void) {
+                unsafe {
+                    let fo: &mut $crate::ForeignObject<$name> = &mut *(data as *mut _);
+                    if !fo.object.is_null() { // If we haven't dropped an object, work on dropping it.
+                        drop(Box::from_raw(fo.object));
+                        fo.object = std::ptr::null_mut();
+                    }
+                }
+            }
+
+            $(
+                $crate::create_module!(@fn $si $name => $id [$mname, $modl, $lbls $($sgns),+]);
+            )*
+        }
+
+        impl $crate::ClassObject for $name {
+            fn initialize_pointer() -> extern "C" fn(*mut $crate::wren_sys::WrenVM) { $md::_constructor }
+            fn finalize_pointer() -> extern "C" fn(*mut std::ffi::c_void) { $md::_destructor }
+            fn generate_pointers() -> $crate::ClassObjectPointers {
+                $crate::ClassObjectPointers {
+                    function_pointers: {
+                        #[allow(unused_mut)]
+                        let mut pointers: Vec<$crate::MethodPointer> = vec![];
+                        $(
+                            pointers.extend($crate::create_module!(@md $si $id $lbls $md $($sgns),+));
+                        )*
+                        pointers
+                    }
+                }
+            }
         }
     };
 
+    (@wren) => { "" };
+
+    (@wren $wren:expr) => { $wren };
+
     (@sgn fn $nom:expr, $arity:expr) => {
         $crate::FunctionSignature::new_function($nom, $arity)
     };
@@ -409,7 +1587,23 @@ macro_rules! create_module {
         $crate::FunctionSignature::new_setter($name)
     };
 
-    (@fn static $name:ty => $s:ident) => {
+    (@sgn operator $op:expr) => {
+        $crate::FunctionSignature::new_operator($op)
+    };
+
+    (@sgn prefix $op:expr) => {
+        $crate::FunctionSignature::new_prefix_operator($op)
+    };
+
+    (@sgn subscript $arity:expr) => {
+        $crate::FunctionSignature::new_subscript($arity)
+    };
+
+    (@sgn subscript_setter $arity:expr) => {
+        $crate::FunctionSignature::new_subscript_setter($arity)
+    };
+
+    (@fn static $name:ty => $s:ident [$mname:expr, $modl:expr, $lbls:ident $($sgns:expr),+]) => {
         pub(in super) unsafe extern "C" fn $s(vm: *mut $crate::wren_sys::WrenVM) {
             use std::panic::{take_hook, set_hook, catch_unwind, AssertUnwindSafe};
 
@@ -417,8 +1611,13 @@ macro_rules! create_module {
             let vm = std::rc::Weak::upgrade(&conf.vm).expect(&format!("Failed to access VM at {:p}", &conf.vm));
             set_hook(Box::new(|_| {}));
             let vm_borrow = AssertUnwindSafe(vm.borrow());
-            match catch_unwind(|| <$name>::$s(&*vm_borrow)) {
-                Ok(_) => (),
+            let profile = vm_borrow.profile_foreign_start();
+            let aborted = match catch_unwind(|| {
+                vm_borrow.check_heap_limit();
+                let _scope = vm_borrow.enter_foreign($mname, Some(|| $crate::create_module!(@sgn $lbls $($sgns),+)));
+                <$name>::$s(&*vm_borrow)
+            }) {
+                Ok(_) => false,
                 Err(err) => {
                     let err_string = if let Some(strg) = err.downcast_ref::<String>() {
                         strg.clone()
@@ -428,29 +1627,67 @@ macro_rules! create_module {
                         "Non-string panic message".into()
                     };
 
+                    let err_string = vm_borrow.record_foreign_abort(&err_string, $modl, $mname, Some($crate::create_module!(@sgn $lbls $($sgns),+)));
                     vm_borrow.set_slot_string(0, err_string);
                     vm_borrow.abort_fiber(0);
+                    true
                 }
             };
+            vm_borrow.profile_foreign_end(profile, aborted, || ($mname, $crate::create_module!(@sgn $lbls $($sgns),+)));
             drop(take_hook());
         }
     };
 
-    (@fn instance $name:ty => $inf:ident) => {
+    (@fn instance $name:ty => $inf:ident [$mname:expr, $modl:expr, property $prop:expr]) => {
+        pub(in super) mod $inf {
+            #[allow(unused_imports)]
+            use super::*;
+
+            pub(in super::super) unsafe extern "C" fn get(vm: *mut $crate::wren_sys::WrenVM) {
+                $crate::create_module!(@instance vm, $name, $mname, $modl, $crate::FunctionSignature::new_getter($prop),
+                    stringify!($inf), |inst: &mut $name, vm: &$crate::VM| inst.$inf(vm, None))
+            }
+
+            pub(in super::super) unsafe extern "C" fn set(vm: *mut $crate::wren_sys::WrenVM) {
+                $crate::create_module!(@instance vm, $name, $mname, $modl, $crate::FunctionSignature::new_setter($prop),
+                    stringify!($inf), |inst: &mut $name, vm: &$crate::VM| {
+                        let value = match $crate::FromWren::from_wren(vm, 1) {
+                            Ok(value) => value,
+                            Err(err) => panic!("{}", err),
+                        };
+                        inst.$inf(vm, Some(value))
+                    })
+            }
+        }
+    };
+
+    (@fn instance $name:ty => $inf:ident [$mname:expr, $modl:expr, $lbls:ident $($sgns:expr),+]) => {
         pub(in super) unsafe extern "C" fn $inf(vm: *mut $crate::wren_sys::WrenVM) {
+            $crate::create_module!(@instance vm, $name, $mname, $modl, $crate::create_module!(@sgn $lbls $($sgns),+),
+                stringify!($inf), |inst: &mut $name, vm: &$crate::VM| inst.$inf(vm))
+        }
+    };
+
+    (@instance $vm:ident, $name:ty, $mname:expr, $modl:expr, $sgn:expr, $method:expr, $call:expr) => {
+        {
             use std::panic::{take_hook, set_hook, catch_unwind, AssertUnwindSafe};
             
-            let conf = &mut *($crate::wren_sys::wrenGetUserData(vm) as *mut $crate::UserData);
+            let conf = &mut *($crate::wren_sys::wrenGetUserData($vm) as *mut $crate::UserData);
             let vm = std::rc::Weak::upgrade(&conf.vm).expect(&format!("Failed to access VM at {:p}", &conf.vm));
             set_hook(Box::new(|_| {}));
             let vm_borrow = AssertUnwindSafe(vm.borrow());
-            match catch_unwind(|| {
+            let profile = vm_borrow.profile_foreign_start();
+            let aborted = match catch_unwind(|| {
+                vm_borrow.check_heap_limit();
+                let _scope = vm_borrow.enter_foreign($mname, Some(|| $sgn));
                 vm_borrow.ensure_slots(1);
-                let inst = vm_borrow.get_slot_foreign_mut::<$name>(0)
-                    .expect(&format!("Tried to call {0} of {1} on non-{1} type", stringify!($inf), std::any::type_name::<$name>()));
-                inst.$inf(&*vm_borrow)
+                match vm_borrow.try_borrow_foreign_mut::<$name, _, _>(0, |inst| ($call)(inst, &*vm_borrow)) {
+                    Ok(ret) => ret,
+                    Err($crate::ForeignBorrowError::AlreadyBorrowed) => panic!("Tried to call {0} of {1} while it is already in use", $method, std::any::type_name::<$name>()),
+                    Err(_) => panic!("Tried to call {0} of {1} on non-{1} type", $method, std::any::type_name::<$name>()),
+                }
             }) {
-                Ok(_) => (),
+                Ok(_) => false,
                 Err(err) => {
                     let err_string = if let Some(strg) = err.downcast_ref::<String>() {
                         strg.clone()
@@ -460,47 +1697,137 @@ macro_rules! create_module {
                         "Non-string panic message".into()
                     };
 
+                    let err_string = vm_borrow.record_foreign_abort(&err_string, $modl, $mname, Some($sgn));
                     vm_borrow.set_slot_string(0, err_string);
                     vm_borrow.abort_fiber(0);
+                    true
                 }
             };
+            vm_borrow.profile_foreign_end(profile, aborted, || ($mname, $sgn));
             drop(take_hook());
         }
     }
 }
 
+/// Creates a class [$ns] that exposes free functions as static methods, and a
+/// $modl::publish_module that registers it (like [create_module])
+///
+/// Each function takes a `&VM`, just like a static method would:
+///
+/// ```ignore
+/// fn clamp(vm: &VM) { ... }
+///
+/// create_namespace! {
+///     namespace("Math2") Math2 => math2 {
+///         (fn "clamp", 3) clamp
+///     }
+///
+///     module => utils
+/// }
+/// ```
+#[macro_export]
+macro_rules! create_namespace {
+    (
+        $(
+            namespace($mname:expr) $ns:ident => $md:ident {
+                $(
+                    ($lbls:ident $($sgns:expr),+) $f:ident
+                ),*
+            } $(wren($wren:expr))?
+        )+
+
+        module => $modl:ident
+    ) => {
+        $(
+            pub struct $ns;
+
+            impl $crate::Class for $ns {
+                fn initialize(_: &$crate::VM) -> Self {
+                    panic!("{} is a namespace, and can't be constructed", $mname)
+                }
+            }
+
+            impl $ns {
+                $(
+                    fn $f(vm: &$crate::VM) {
+                        $f(vm)
+                    }
+                )*
+            }
+        )+
+
+        $crate::create_module! {
+            $(
+                class($mname) $ns => $md {
+                    $(
+                        static($lbls $($sgns),+) $f
+                    ),*
+                } $(wren($wren))?
+            )+
+
+            module => $modl
+        }
+    };
+}
+
 /// Checks if the slot type is correct at the given slot.
 /// If not, will panic.
 /// If it is, will return the item at the given slot.
+///
+/// `num?`, `bool?` and `string?` also accept null, returning an Option, or the default after `=>` if one's given
+/// (like `get_slot_checked!(vm => num? 2 => 1.0)`).
 // We can do unwraps because we manually check the type beforehand, so we are *sure* it is there.
 #[macro_export]
 macro_rules! get_slot_checked {
-    ($vm:expr => num $slot:expr) => {
-        {
-            if $vm.get_slot_type($slot) != $crate::SlotType::Num { panic!("rust error [{}:{}]: Slot {} is not a <num>", file!(), line!(), $slot) }
-            $vm.get_slot_double($slot).unwrap()
+    ($vm:expr => num? $slot:expr => $default:expr) => {
+        $crate::get_slot_checked!(@option $vm, f64, $slot).unwrap_or($default)
+    };
+
+    ($vm:expr => num? $slot:expr) => {
+        $crate::get_slot_checked!(@option $vm, f64, $slot)
+    };
+
+    ($vm:expr => bool? $slot:expr => $default:expr) => {
+        $crate::get_slot_checked!(@option $vm, bool, $slot).unwrap_or($default)
+    };
+
+    ($vm:expr => bool? $slot:expr) => {
+        $crate::get_slot_checked!(@option $vm, bool, $slot)
+    };
+
+    ($vm:expr => string? $slot:expr => $default:expr) => {
+        $crate::get_slot_checked!(@option $vm, String, $slot).unwrap_or_else(|| ($default).into())
+    };
+
+    ($vm:expr => string? $slot:expr) => {
+        $crate::get_slot_checked!(@option $vm, String, $slot)
+    };
+
+    (@option $vm:expr, $t:ty, $slot:expr) => {
+        $crate::get_slot_checked!(@try $vm.try_get_slot_option::<$t>($slot))
+    };
+
+    (@try $read:expr) => {
+        match $read {
+            Ok(value) => value,
+            Err(err) => panic!("rust error [{}:{}]: {}", file!(), line!(), err)
         }
     };
 
+    ($vm:expr => num $slot:expr) => {
+        $crate::get_slot_checked!(@try $vm.try_get_slot_double($slot))
+    };
+
     ($vm:expr => bool $slot:expr) => {
-        {
-            if $vm.get_slot_type($slot) != $crate::SlotType::Bool { panic!("rust error [{}:{}]: Slot {} is not a <bool>", file!(), line!(), $slot) }
-            $vm.get_slot_bool($slot).unwrap()
-        }
+        $crate::get_slot_checked!(@try $vm.try_get_slot_bool($slot))
     };
 
     ($vm:expr => string $slot:expr) => {
-        {
-            if $vm.get_slot_type($slot) != $crate::SlotType::String { panic!("rust error [{}:{}]: Slot {} is not a <string>", file!(), line!(), $slot) }
-            $vm.get_slot_string($slot).unwrap()
-        }
+        $crate::get_slot_checked!(@try $vm.try_get_slot_string($slot))
     };
 
     ($vm:expr => bytes $slot:expr) => {
-        {
-            if $vm.get_slot_type($slot) != $crate::SlotType::String { panic!("rust error [{}:{}]: Slot {} is not a <string>", file!(), line!(), $slot) }
-            $vm.get_slot_bytes($slot).unwrap()
-        }
+        $crate::get_slot_checked!(@try $vm.try_get_slot_bytes($slot))
     };
 
     ($vm:expr => foreign $t:ty => $slot:expr) => {
@@ -543,20 +1870,35 @@ macro_rules! send_foreign {
 }
 
 /// Enables one to enable module loading for Wren
+///
+/// Sources embedded with `include_str!` can be returned borrowed, and aren't copied if they end
+/// in a nul byte (like `concat!(include_str!("game.wren"), "\0")`).
+///
+/// A loader that panics fails the import, and the interpret or call that made it, with
+/// [VMError::CallbackPanic].
 pub trait ModuleScriptLoader {
-    fn load_script(&mut self, name: String) -> Option<String>;
+    fn load_script(&mut self, name: String) -> Option<Cow<'static, str>>;
 }
 
-impl<T> ModuleScriptLoader for T where T: FnMut(String) -> Option<String> {
-    fn load_script(&mut self, name: String) -> Option<String> {
-        (*self)(name)
+impl<T, S> ModuleScriptLoader for T where T: FnMut(String) -> Option<S>, S: Into<Cow<'static, str>> {
+    fn load_script(&mut self, name: String) -> Option<Cow<'static, str>> {
+        (*self)(name).map(Into::into)
     }
 }
 
-type EVM = Rc<RefCell<VM>>;
+type Evm = Rc<RefCell<VM>>;
 
+/// Where `System.print` output goes
+///
+/// A printer that panics loses that output, and fails the running interpret or call with
+/// [VMError::CallbackPanic].
 pub trait Printer {
     fn print(&mut self, s: String);
+
+    /// Receives raw output when the VM uses [StringPolicy::Bytes]
+    fn print_bytes(&mut self, bytes: &[u8]) {
+        self.print(String::from_utf8_lossy(bytes).into_owned())
+    }
 }
 
 impl<T> Printer for T where T: FnMut(String) {
@@ -565,16 +1907,37 @@ impl<T> Printer for T where T: FnMut(String) {
     }
 }
 
+/// Receives each error as Wren reports it, like a compile error, a runtime error, or one frame
+/// of its stack trace
+///
+/// Errors still come back from interpret and call as usual; the handler sees them as they happen,
+/// for streaming them somewhere else. A handler that panics fails the running interpret or call
+/// with [VMError::CallbackPanic].
+pub trait ErrorHandler {
+    fn handle(&mut self, error: &WrenError);
+}
+
+impl<T> ErrorHandler for T where T: FnMut(&WrenError) {
+    fn handle(&mut self, error: &WrenError) {
+        (*self)(error)
+    }
+}
+
 struct PrintlnPrinter;
 impl Printer for PrintlnPrinter {
     fn print(&mut self, s: String) {
         print!("{}", s);
     }
+
+    fn print_bytes(&mut self, bytes: &[u8]) {
+        use std::io::Write;
+        let _ = std::io::stdout().write_all(bytes);
+    }
 }
 
 struct NullLoader;
 impl ModuleScriptLoader for NullLoader {
-    fn load_script(&mut self, _: String) -> Option<String> { None }
+    fn load_script(&mut self, _: String) -> Option<Cow<'static, str>> { None }
 }
 
 #[derive(Debug)]
@@ -586,10 +1949,147 @@ pub struct VM {
 /// A mostly internal class that is exposed so that some externally generated code can access it.
 pub struct UserData {
     error_channel: Sender<WrenError>,
+    // Sees every error as Wren reports it (see VMConfig::error_handler)
+    error_sink: Option<ErrorSink>,
     printer: Box<dyn Printer>,
     pub vm: Weak<RefCell<VM>>, // is used a *lot* by externally generated code.
-    library: Option<ModuleLibrary>,
+    // Shared with every other VM given the same library
+    library: Option<Arc<ModuleLibrary>>,
+    // Modules registered with VMWrapper::register_module, for this VM alone
+    overlay: RefCell<ModuleLibrary>,
     loader: Box<dyn ModuleScriptLoader>,
+    capabilities: CapabilityPolicy,
+    #[cfg(feature = "process")]
+    process_imports: RefCell<stdlib::process::Imports>,
+    string_policy: StringPolicy,
+    utf8_violation: Cell<Option<&'static str>>,
+    // The first callback (printer, script loader, ...) to panic during the current interpret or call
+    callback_panic: RefCell<Option<(&'static str, String)>>,
+    // The error message and frame of the last foreign method to abort its fiber
+    foreign_abort: RefCell<Option<(String, VMStackFrameError)>>,
+    stats: Cell<VMStats>,
+    // How many GcGuards are held, and whether a collection was deferred by them
+    gc_guards: Cell<usize>,
+    gc_pending: Cell<bool>,
+    modules: RefCell<HashMap<String, ModuleId>>,
+    callbacks: RefCell<callbacks::CallbackTable>,
+    #[cfg(feature = "scheduler")]
+    timers: RefCell<scheduler::Timers>,
+    // Events emitted by scripts, waiting for VMWrapper::events
+    events: RefCell<events::EventQueue>,
+    // Futures from async foreign methods, until they're ready and their fibers resumed
+    futures: RefCell<futures::FutureTable>,
+    // Call handles ruwren makes for itself, by signature
+    call_handles: RefCell<HashMap<String, *mut WrenHandle>>,
+    // Whether VMWrapper::eval has declared its variable yet
+    eval_declared: Cell<bool>,
+    // Modules VMWrapper::interpret_returning has declared its variable in
+    return_declared: RefCell<HashSet<String>>,
+    // Whether VMWrapper::export_object_graph has interpreted its module yet
+    graph_declared: Cell<bool>,
+    // Whether the module FiberHandle calls through has been interpreted yet
+    fibers_declared: Cell<bool>,
+    call_depth: Cell<usize>,
+    max_call_depth: usize,
+    // Bytes Wren has allocated through wren_realloc, and whether that went over max_heap_size
+    heap_used: Cell<usize>,
+    max_heap_size: Option<usize>,
+    heap_exceeded: Cell<bool>,
+    scratch: scratch::Scratch,
+    number_format: NumberFormat,
+    name: Option<String>,
+    relative_import: bool,
+    pure: bool,
+    script_cache: Option<ScriptCache>,
+    verbose_traces: bool,
+    // Every foreign declaration nothing was registered for, and how many of them have already
+    // failed an interpret or call (see VMConfig::strict_library)
+    unbound: RefCell<Vec<UnboundForeign>>,
+    strict_library: bool,
+    unbound_reported: Cell<usize>,
+    // Whether foreign methods make this VM the current_vm
+    expose_current: bool,
+    foreign_method: Cell<Option<current::RunningMethod>>,
+    // Closures given trampolines by bind_closure, with their modules
+    closure_methods: RefCell<Vec<(String, Arc<closures::ClosureMethod>)>>,
+    profile: RefCell<Option<profile::Profile>>,
+    created: Instant,
+    drop_hooks: Vec<DropHook>,
+    call_observer: Option<Box<dyn Fn(CallInfo)>>,
+    error_rewriter: Option<ErrorRewriter>,
+    #[cfg(feature = "coverage")]
+    coverage: Option<RefCell<coverage::Coverage>>,
+    #[cfg(feature = "debugger")]
+    debugger: Option<debugger::Debugger>,
+    #[cfg(feature = "tokio")]
+    tokio: tokio_bridge::TokioBridge,
+}
+
+impl UserData {
+    // Runs [f] on a class registered with this VM, preferring its own modules over the shared library
+    fn foreign_class<R, F: FnOnce(&RuntimeClass) -> R>(&self, module: &str, class: &str, f: F) -> Option<R> {
+        if let Some(rc) = self.overlay.borrow().get_foreign_class(module, class) {
+            return Some(f(rc));
+        }
+        self.library.as_ref()?.get_foreign_class(module, class).map(f)
+    }
+
+    fn account_heap(&self, old_size: usize, new_size: usize) {
+        let used = self.heap_used.get() - old_size + new_size;
+        self.heap_used.set(used);
+        if let Some(limit) = self.max_heap_size {
+            if new_size > old_size && used > limit {
+                self.heap_exceeded.set(true);
+            }
+        }
+    }
+
+    fn report_error(&mut self, error: WrenError) {
+        if let Some(sink) = self.error_sink.as_mut() {
+            guard_callback(&self.callback_panic, "error handler", || sink.handle(&error));
+        }
+        self.error_channel.send(error).unwrap();
+    }
+}
+
+type CreatedHook = Box<dyn Fn(&VMWrapper)>;
+type ErrorSink = Box<dyn ErrorHandler>;
+type DropHook = Box<dyn Fn(VMReport)>;
+type ErrorRewriter = Box<dyn Fn(VMError) -> VMError>;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct VMStats {
+    interprets: usize,
+    calls: usize,
+    compile_errors: usize,
+    runtime_errors: usize,
+}
+
+/// Statistics about a VM's lifetime, handed to [VMConfig::on_vm_dropped] hooks
+#[derive(Debug, Clone)]
+pub struct VMReport {
+    pub interpret_count: usize,
+    pub call_count: usize,
+    pub compile_errors: usize,
+    pub runtime_errors: usize,
+    pub lifetime: Duration,
+}
+
+/// How a VM treats Wren strings that aren't valid UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringPolicy {
+    /// Replace invalid sequences with U+FFFD
+    #[default]
+    Lossy,
+    /// [VM::get_slot_string] returns None for invalid strings, and invalid print output
+    /// fails the running interpret/call with [VMError::InvalidUtf8]
+    ///
+    /// Error messages are still converted lossily, as they are already reporting a failure.
+    Strict,
+    /// Print output is passed to [Printer::print_bytes] untouched
+    ///
+    /// Prefer [VM::get_slot_bytes] to read slots under this policy.
+    Bytes,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -597,6 +2097,7 @@ pub enum SlotType {
     Num,
     Bool,
     List,
+    Map,
     Null,
     String,
     Foreign,
@@ -606,51 +2107,38 @@ pub enum SlotType {
 pub type SlotId = usize;
 
 #[derive(Debug, Clone)]
-pub enum FunctionSignature {
-    Function {
-        name: String,
-        arity: usize
-    },
-    Getter(String),
-    Setter(String),
-}
+pub struct VMWrapper(Evm);
 
-impl FunctionSignature {
-    pub fn new_function<N: Into<String>>(name: N, arity: usize) -> FunctionSignature {
-        FunctionSignature::Function {
-            name: name.into(),
-            arity
-        }
-    }
-
-    pub fn new_getter<N: Into<String>>(name: N) -> FunctionSignature {
-        FunctionSignature::Getter(name.into())
-    }
-
-    pub fn new_setter<N: Into<String>>(name: N) -> FunctionSignature {
-        FunctionSignature::Setter(name.into())
-    }
+/// Holds off collections started by ruwren, until dropped (see [VMWrapper::gc_guard])
+pub struct GcGuard<'a> {
+    vm: &'a VMWrapper,
+    collect: bool,
+}
 
-    fn as_wren_string(&self) -> String {
-        match self {
-            FunctionSignature::Function { name, arity } => format!("{}({})", name, vec!["_".to_string(); *arity].join(",")),
-            FunctionSignature::Getter(name) => name.clone(),
-            FunctionSignature::Setter(name) => format!("{}=(_)", name),
-        }
+impl<'a> GcGuard<'a> {
+    /// Collects when the last guard is dropped, even if no collection was deferred
+    pub fn collect_on_drop(mut self) -> Self {
+        self.collect = true;
+        self
     }
+}
 
-    pub fn arity(&self) -> usize {
-        match self {
-            FunctionSignature::Function { arity, .. } => *arity,
-            FunctionSignature::Getter(_) => 0,
-            FunctionSignature::Setter(_) => 1,
+impl<'a> Drop for GcGuard<'a> {
+    fn drop(&mut self) {
+        let collect = self.vm.execute(|vm| {
+            let ud = vm.user_data();
+            ud.gc_guards.set(ud.gc_guards.get() - 1);
+            if self.collect {
+                ud.gc_pending.set(true);
+            }
+            ud.gc_guards.get() == 0 && ud.gc_pending.replace(false)
+        });
+        if collect {
+            self.vm.collect_garbage();
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct VMWrapper(EVM);
-
 impl VMWrapper {
     pub fn call(&self, signature: FunctionSignature) -> Result<(), VMError> {
         let handle = self.make_call_handle(signature);
@@ -658,36 +2146,48 @@ impl VMWrapper {
     }
 
     pub fn call_handle(&self, handle: &FunctionHandle) -> Result<(), VMError> {
+        self.call_raw(handle.0.handle, &handle.1)
+    }
+
+    // [signature] is only for the call observer
+    fn call_raw(&self, handle: *mut WrenHandle, signature: &str) -> Result<(), VMError> {
+        let _span = ffi_span!(DEBUG, "call", signature);
         let vm = self.0.borrow();
-        match unsafe { wren_sys::wrenCall(vm.vm, handle.0.handle) } {
+        let _depth = vm.enter_call()?;
+        vm.user_data().utf8_violation.set(None);
+        vm.user_data().callback_panic.replace(None);
+        vm.user_data().foreign_abort.replace(None);
+        vm.reset_heap_limit();
+        let start = vm.call_start();
+        let profile = vm.profile_vm_start();
+        let result = match unsafe { wren_sys::wrenCall(vm.vm, handle) } {
             wren_sys::WrenInterpretResult_WREN_RESULT_SUCCESS => Ok(()),
             wren_sys::WrenInterpretResult_WREN_RESULT_COMPILE_ERROR => unreachable!("wrenCall doesn't compile anything"),
-            wren_sys::WrenInterpretResult_WREN_RESULT_RUNTIME_ERROR => {
-                let mut error = "".to_string();
-                let mut frames = vec![];
-                while let Ok(err) = vm.error_recv.try_recv() {
-                    match err {
-                        WrenError::Runtime(msg) => {error = msg; },
-                        WrenError::StackTrace(module, line, msg) => {frames.push(VMStackFrameError {
-                            module, line, function: msg
-                        }); },
-                        _ => unreachable!()
-                    }
-                }
-                Err(VMError::Runtime{
-                    error,
-                    frames
-                })
-            },
+            wren_sys::WrenInterpretResult_WREN_RESULT_RUNTIME_ERROR => Err(vm.runtime_error()),
             _ => unreachable!()
-        }
+        };
+        vm.profile_vm_end(profile);
+        let result = vm.check_violations(result).map_err(|err| vm.rewrite_error(err));
+        vm.record_result(false, &result);
+        vm.observe_call(start, CallKind::Call, signature, &result);
+        result
     }
 
-    pub fn interpret<M: AsRef<str>, C: AsRef<str>>(&self, module: M, code: C) -> Result<(), VMError> {
-        let module = ffi::CString::new(module.as_ref()).expect("module name conversion failed");
-        let code = ffi::CString::new(code.as_ref()).expect("code conversion failed");
+    pub fn interpret<M: ModuleName, C: AsRef<str>>(&self, module: M, code: C) -> Result<(), VMError> {
+        let _span = ffi_span!(DEBUG, "interpret", module = module.module_name());
         let vm = self.0.borrow();
-        match unsafe { wren_sys::wrenInterpret(vm.vm, module.as_ptr() as *const i8, code.as_ptr() as *const i8) } {
+        let _depth = vm.enter_call()?;
+        #[cfg(feature = "coverage")]
+        let code = vm.coverage_instrument(module.module_name(), code.as_ref());
+        let module_cstr = vm.module_cstr(&module);
+        let code = vm.scratch_cstr(code.as_ref(), "code");
+        vm.user_data().utf8_violation.set(None);
+        vm.user_data().callback_panic.replace(None);
+        vm.user_data().foreign_abort.replace(None);
+        vm.reset_heap_limit();
+        let start = vm.call_start();
+        let profile = vm.profile_vm_start();
+        let result = match unsafe { wren_sys::wrenInterpret(vm.vm, module_cstr.as_ptr(), code.as_ptr()) } {
             wren_sys::WrenInterpretResult_WREN_RESULT_SUCCESS => Ok(()),
             wren_sys::WrenInterpretResult_WREN_RESULT_COMPILE_ERROR => match vm.error_recv.try_recv() {
                 Ok(WrenError::Compile(module, line, msg)) => {
@@ -695,24 +2195,45 @@ impl VMWrapper {
                 }
                 _ => unreachable!()
             },
-            wren_sys::WrenInterpretResult_WREN_RESULT_RUNTIME_ERROR => {
-                let mut error = "".to_string();
-                let mut frames = vec![];
-                while let Ok(err) = vm.error_recv.try_recv() {
-                    match err {
-                        WrenError::Runtime(msg) => {error = msg; },
-                        WrenError::StackTrace(module, line, msg) => {frames.push(VMStackFrameError {
-                            module, line, function: msg
-                        }); },
-                        _ => unreachable!()
-                    }
-                }
-                Err(VMError::Runtime{
-                    error,
-                    frames
-                })
-            },
+            wren_sys::WrenInterpretResult_WREN_RESULT_RUNTIME_ERROR => Err(vm.runtime_error()),
             _ => unreachable!()
+        };
+        vm.profile_vm_end(profile);
+        let result = vm.check_violations(result).map_err(|err| vm.rewrite_error(err));
+        vm.record_result(true, &result);
+        vm.observe_call(start, CallKind::Interpret, module.module_name(), &result);
+        result
+    }
+
+    /// Runs [f] with output going to [printer], and errors Wren reports passed to [error_handler],
+    /// putting the VM's own sinks back afterwards
+    ///
+    /// Errors still come back from interpret and call as usual; the handler sees them as they
+    /// happen, including runtime errors caught by `Fiber.try`.
+    pub fn with_sinks<P, E, R, F>(&self, printer: P, error_handler: E, f: F) -> R
+        where P: 'static + Printer, E: 'static + ErrorHandler, F: FnOnce(&VMWrapper) -> R
+    {
+        let ud = unsafe { wren_sys::wrenGetUserData(self.0.borrow().vm) as *mut UserData };
+        let _restore = unsafe {
+            SinkRestore {
+                ud,
+                printer: Some(mem::replace(&mut (*ud).printer, Box::new(printer))),
+                error_sink: (*ud).error_sink.replace(Box::new(error_handler)),
+            }
+        };
+        f(self)
+    }
+
+    /// Lists every foreign class and method scripts have declared so far that nothing was
+    /// registered for, by module, class and signature
+    ///
+    /// Wren only asks for a foreign class or method when the module declaring it runs, so this
+    /// checks what has been interpreted or imported, not the whole library.
+    pub fn validate_library(&self) -> Result<(), Vec<UnboundForeign>> {
+        let unbound = self.0.borrow().user_data().unbound.borrow().clone();
+        match unbound.is_empty() {
+            true => Ok(()),
+            false => Err(unbound),
         }
     }
 
@@ -720,7 +2241,7 @@ impl VMWrapper {
         f(&self.0.borrow())
     }
 
-    pub fn get_slot_handle(&self, slot: SlotId) -> Rc<Handle> {
+    pub fn get_slot_handle(&self, slot: SlotId) -> Rc<Handle<'_>> {
         Rc::new(Handle {
             handle: unsafe {
                 wren_sys::wrenGetSlotHandle(self.0.borrow().vm, slot as raw::c_int)
@@ -730,33 +2251,193 @@ impl VMWrapper {
         })
     }
 
+    /// Pins the foreign object in [slot], or returns None if the slot doesn't hold a foreign
+    pub fn pin(&self, slot: SlotId) -> Option<Pin<'_>> {
+        let vm = self.0.borrow();
+        if vm.get_slot_type(slot) != SlotType::Foreign {
+            return None;
+        }
+        unsafe {
+            Some(Pin {
+                object: wren_sys::wrenGetSlotForeign(vm.vm, slot as raw::c_int),
+                handle: Handle {
+                    handle: wren_sys::wrenGetSlotHandle(vm.vm, slot as raw::c_int),
+                    wvm: vm.vm,
+                    vm: marker::PhantomData
+                },
+            })
+        }
+    }
+
+    /// Gets a handle to the foreign object in [slot], which has to be a T
+    pub fn get_slot_typed_handle<T: 'static + ClassObject>(&self, slot: SlotId) -> Result<TypedHandle<'_, T>, ForeignBorrowError> {
+        let vm = self.0.borrow();
+        vm.foreign_object_ptr::<T>(slot)?;
+        Ok(TypedHandle {
+            handle: Handle {
+                handle: unsafe { wren_sys::wrenGetSlotHandle(vm.vm, slot as raw::c_int) },
+                wvm: vm.vm,
+                vm: marker::PhantomData
+            },
+            vm: self,
+            object: marker::PhantomData,
+        })
+    }
+
+    /// Sends every object in [objects] to Wren as an instance of [class] in [module]
+    ///
+    /// Looks the class up once for the whole batch, so this is much faster than
+    /// calling [VM::set_slot_new_foreign] for each object. Overwrites slots 0 and 1.
+    pub fn spawn_foreigns<T, M, C>(&self, module: M, class: C, objects: Vec<T>) -> Result<Vec<ObjectHandle<'_, T>>, ForeignSendError>
+        where T: 'static + ClassObject, M: AsRef<str>, C: AsRef<str>
+    {
+        let vm = self.0.borrow();
+        vm.ensure_slots(2);
+        vm.load_foreign_class::<T, _, _>(module, class, 0)?;
+        objects.into_iter().map(|object| unsafe {
+            let object = vm.new_foreign_object(0, 1, object)?;
+            Ok(ObjectHandle {
+                handle: Handle {
+                    handle: wren_sys::wrenGetSlotHandle(vm.vm, 1),
+                    wvm: vm.vm,
+                    vm: marker::PhantomData
+                },
+                object,
+            })
+        }).collect()
+    }
+
+    /// Pins every element of the list in [list_slot]
+    ///
+    /// Returns None if the slot isn't a list, or if any element isn't a foreign.
+    /// Uses one slot past the current slot count as scratch space.
+    pub fn pin_all(&self, list_slot: SlotId) -> Option<Vec<Pin<'_>>> {
+        let (count, scratch) = {
+            let vm = self.0.borrow();
+            if vm.get_slot_type(list_slot) != SlotType::List {
+                return None;
+            }
+            let scratch = vm.get_slot_count();
+            vm.ensure_slots(scratch + 1);
+            (vm.get_list_count(list_slot), scratch)
+        };
+
+        (0..count).map(|i| {
+            self.execute(|vm| vm.get_list_element(list_slot, i as i32, scratch));
+            self.pin(scratch)
+        }).collect()
+    }
+
+    /// Like [VMWrapper::get_slot_handle], but the handle keeps the VM alive instead of borrowing it
+    pub fn get_slot_owned_handle(&self, slot: SlotId) -> OwnedHandle {
+        let vm = self.0.borrow();
+        OwnedHandle {
+            handle: Handle {
+                handle: unsafe { wren_sys::wrenGetSlotHandle(vm.vm, slot as raw::c_int) },
+                wvm: vm.vm,
+                vm: marker::PhantomData,
+            },
+            vm: self.0.clone(),
+        }
+    }
+
     pub fn set_slot_handle(&self, slot: SlotId, handle: &Handle) {
         unsafe {
             wren_sys::wrenSetSlotHandle(self.0.borrow().vm, slot as raw::c_int, handle.handle)
         }
     }
 
-    pub fn make_call_handle(&self, signature: FunctionSignature) -> Rc<FunctionHandle> {
+    pub fn make_call_handle(&self, signature: FunctionSignature) -> Rc<FunctionHandle<'_>> {
         VM::make_call_handle(self.0.borrow().vm, signature)
     }
 
     /// Instruct Wren to start a garbage collection cycle
+    ///
+    /// While a [GcGuard] is held, the collection waits until the last guard is dropped.
     pub fn collect_garbage(&self) {
+        let vm = self.0.borrow();
+        if vm.user_data().gc_guards.get() > 0 {
+            vm.user_data().gc_pending.set(true);
+            return;
+        }
+        let start = Instant::now();
+        let _span = ffi_span!(DEBUG, "gc");
         unsafe {
-            wren_sys::wrenCollectGarbage(self.0.borrow().vm)
+            wren_sys::wrenCollectGarbage(vm.vm)
         }
+        vm.profile_gc(start.elapsed());
+    }
+
+    /// Defers collections started with [VMWrapper::collect_garbage] until the guard is dropped
+    ///
+    /// Only collections ruwren starts are deferred: Wren still collects on its own when it allocates,
+    /// since Wren can't be told not to. So don't allocate Wren objects (or run Wren code) while relying
+    /// on a guard. Guards nest; when the last one is dropped, any collection that was deferred runs.
+    pub fn gc_guard(&self) -> GcGuard<'_> {
+        self.execute(|vm| vm.user_data().gc_guards.set(vm.user_data().gc_guards.get() + 1));
+        GcGuard { vm: self, collect: false }
+    }
+
+    /// How many bytes Wren currently has allocated, garbage included (see [VMConfig::max_heap_size])
+    pub fn heap_size(&self) -> usize {
+        self.execute(|vm| vm.user_data().heap_used.get())
+    }
+
+    /// Whether a collection is waiting for [GcGuard]s to be dropped
+    pub fn gc_pending(&self) -> bool {
+        self.execute(|vm| vm.user_data().gc_pending.get())
+    }
+
+    /// Starts recording where this VM spends its time, until the session is dropped
+    pub fn profiling_session(&self) -> ProfilingSession<'_> {
+        ProfilingSession::start(self)
+    }
+
+    /// Adds [modl] to this VM alone, leaving the library it was built with (and shares) untouched
+    ///
+    /// Its classes take precedence over the library's. Like any module, it has to be registered
+    /// before scripts import it.
+    pub fn register_module<N: Into<String>>(&self, name: N, modl: Module) {
+        self.execute(|vm| vm.user_data().overlay.borrow_mut().module(name, modl));
     }
 }
 
 pub struct VMConfig {
+    // The first callback given that isn't known to be Send, if any
+    thread_bound: Option<&'static str>,
     printer: Box<dyn Printer>,
     script_loader: Box<dyn ModuleScriptLoader>,
-    library: Option<ModuleLibrary>,
+    error_handler: Option<ErrorSink>,
+    library: Option<Arc<ModuleLibrary>>,
+    capabilities: CapabilityPolicy,
+    string_policy: StringPolicy,
+    require_send: bool,
+    created_hooks: Vec<CreatedHook>,
+    drop_hooks: Vec<DropHook>,
+    call_observer: Option<Box<dyn Fn(CallInfo)>>,
+    error_rewriter: Option<ErrorRewriter>,
+    definitions: Vec<(String, String, WrenValue)>,
+    #[cfg(feature = "coverage")]
+    coverage: bool,
+    #[cfg(feature = "debugger")]
+    debugger: Option<debugger::Debugger>,
+    #[cfg(feature = "tokio")]
+    tokio_runtime: Option<tokio::runtime::Handle>,
     initial_heap_size: usize,
     min_heap_size: usize,
     heap_growth_percent: usize,
+    max_heap_size: Option<usize>,
+    max_call_depth: usize,
+    number_format: NumberFormat,
+    pure_mode: bool,
+    pure_modules: Vec<String>,
+    script_cache: Option<ScriptCache>,
+    verbose_traces: bool,
+    strict_library: bool,
+    expose_current_vm: bool,
 
     enable_relative_import: bool, // Uses @module, to mean [module] loaded relative to this one
+    name: Option<String>,
 }
 
 impl Default for VMConfig {
@@ -768,27 +2449,90 @@ impl Default for VMConfig {
 impl VMConfig {
     pub fn new() -> VMConfig {
         VMConfig {
+            thread_bound: None,
             printer: Box::new(PrintlnPrinter),
             script_loader: Box::new(NullLoader),
+            error_handler: None,
             library: None,
+            capabilities: CapabilityPolicy::new(),
+            string_policy: StringPolicy::Lossy,
+            require_send: false,
+            created_hooks: vec![],
+            drop_hooks: vec![],
+            call_observer: None,
+            error_rewriter: None,
+            definitions: vec![],
+            #[cfg(feature = "coverage")]
+            coverage: false,
+            #[cfg(feature = "debugger")]
+            debugger: None,
+            #[cfg(feature = "tokio")]
+            tokio_runtime: None,
             initial_heap_size: 1024 * 1024 * 10,
             min_heap_size: 1024 * 1024,
             heap_growth_percent: 50,
+            max_heap_size: None,
+            max_call_depth: 128,
+            number_format: NumberFormat::default(),
+            pure_mode: false,
+            pure_modules: vec![],
+            script_cache: None,
+            verbose_traces: false,
+            strict_library: false,
+            expose_current_vm: false,
             enable_relative_import: false,
+            name: None,
         }
     }
 
     pub fn printer<P: 'static + Printer>(mut self, p: P) -> Self {
+        self.printer = Box::new(p);
+        self.thread_bound.get_or_insert("printer");
+        self
+    }
+
+    /// Like [VMConfig::printer], but the VM can still be used as a [SyncVM]
+    pub fn send_printer<P: 'static + Printer + Send>(mut self, p: P) -> Self {
         self.printer = Box::new(p);
         self
     }
 
     pub fn script_loader<L: 'static + ModuleScriptLoader>(mut self, l: L) -> Self {
         self.script_loader = Box::new(l);
+        self.thread_bound.get_or_insert("script_loader");
+        self
+    }
+
+    /// Like [VMConfig::script_loader], but the VM can still be used as a [SyncVM]
+    pub fn send_script_loader<L: 'static + ModuleScriptLoader + Send>(mut self, l: L) -> Self {
+        self.script_loader = Box::new(l);
+        self
+    }
+
+    /// Passes every error Wren reports to [handler] as it happens (see [ErrorHandler])
+    ///
+    /// [VMWrapper::with_sinks] swaps it out for a while.
+    pub fn error_handler<H: 'static + ErrorHandler>(mut self, handler: H) -> Self {
+        self.error_handler = Some(Box::new(handler));
+        self.thread_bound.get_or_insert("error_handler");
+        self
+    }
+
+    /// Like [VMConfig::error_handler], but the VM can still be used as a [SyncVM]
+    pub fn send_error_handler<H: 'static + ErrorHandler + Send>(mut self, handler: H) -> Self {
+        self.error_handler = Some(Box::new(handler));
         self
     }
 
     pub fn library(mut self, l: &ModuleLibrary) -> Self {
+        self.library = Some(Arc::new(l.clone()));
+        self
+    }
+
+    /// Uses [l] without copying it, so any number of VMs can share one library
+    ///
+    /// Modules only one VM needs can be added to it with [VMWrapper::register_module].
+    pub fn shared_library(mut self, l: &Arc<ModuleLibrary>) -> Self {
         self.library = Some(l.clone());
         self
     }
@@ -798,6 +2542,97 @@ impl VMConfig {
         self
     }
 
+    /// Sets what scripts running in this VM may do outside of it (see [stdlib])
+    pub fn capabilities(mut self, policy: CapabilityPolicy) -> Self {
+        self.capabilities = policy;
+        self
+    }
+
+    pub fn string_policy(mut self, policy: StringPolicy) -> Self {
+        self.string_policy = policy;
+        self
+    }
+
+    /// Records which lines of each interpreted or loaded module run (see [VMWrapper::coverage_report])
+    ///
+    /// Sources are instrumented before they're compiled, so this slows scripts down a fair bit
+    #[cfg(feature = "coverage")]
+    pub fn coverage(mut self, enable: bool) -> Self {
+        self.coverage = enable;
+        self
+    }
+
+    /// Calls [handler] whenever a script hits a breakpoint, steps, or is asked to pause (experimental)
+    ///
+    /// The debugger relies on the coverage instrumentation, so this also turns coverage on.
+    #[cfg(feature = "debugger")]
+    pub fn on_pause<F: 'static + Fn(&PauseEvent, &VM) -> DebugAction>(mut self, handler: F) -> Self {
+        self.thread_bound.get_or_insert("on_pause");
+        self.coverage = true;
+        self.debugger = Some(debugger::Debugger::new(handler));
+        self
+    }
+
+    /// Runs futures from [VM::spawn_future] on [runtime], instead of the runtime the VM is used from
+    #[cfg(feature = "tokio")]
+    pub fn tokio_runtime(mut self, runtime: tokio::runtime::Handle) -> Self {
+        self.tokio_runtime = Some(runtime);
+        self
+    }
+
+    /// Defines [name] as a variable in [module] when the VM is built, so scripts can read host configuration
+    ///
+    /// The module is created if it doesn't exist yet. Scripts later interpreted into the same module
+    /// can't declare a variable with the same name.
+    pub fn define<M: Into<String>, N: Into<String>, V: Into<WrenValue>>(mut self, module: M, name: N, value: V) -> Self {
+        self.definitions.push((module.into(), name.into(), value.into()));
+        self
+    }
+
+    /// Requires every foreign class in the library to be Send, so the VM can safely be moved across threads
+    ///
+    /// Classes registered through [create_module] are checked automatically, but those registered
+    /// by hand must use [Module::send_class]. Checked by [VMConfig::try_build].
+    pub fn require_send(mut self, rs: bool) -> Self {
+        self.require_send = rs;
+        self
+    }
+
+    /// Adds a hook that is run on every VM built from this config, right after it's created
+    pub fn on_vm_created<F: 'static + Fn(&VMWrapper)>(mut self, hook: F) -> Self {
+        self.created_hooks.push(Box::new(hook));
+        self.thread_bound.get_or_insert("on_vm_created");
+        self
+    }
+
+    /// Sets a function that's told about every interpret, call and foreign method once it's done,
+    /// with how long it took and how it ended, so hosts can meter scripts however they like
+    pub fn call_observer<F: 'static + Fn(CallInfo)>(mut self, observer: F) -> Self {
+        self.call_observer = Some(Box::new(observer));
+        self.thread_bound.get_or_insert("call_observer");
+        self
+    }
+
+    /// Sets a function that every error from interpret and call (and everything built on them)
+    /// goes through before it's returned, so hosts can rename internal modules, redact paths or
+    /// translate messages in one place
+    ///
+    /// Call observers and tick reports see the rewritten errors. Error handlers (see
+    /// [VMConfig::error_handler]) don't, since they get errors as Wren reports them, before there's
+    /// a [VMError] to rewrite.
+    pub fn error_rewriter<F: 'static + Fn(VMError) -> VMError>(mut self, rewriter: F) -> Self {
+        self.error_rewriter = Some(Box::new(rewriter));
+        self.thread_bound.get_or_insert("error_rewriter");
+        self
+    }
+
+    /// Adds a hook that is run when a VM built from this config is freed
+    pub fn on_vm_dropped<F: 'static + Fn(VMReport)>(mut self, hook: F) -> Self {
+        self.drop_hooks.push(Box::new(hook));
+        self.thread_bound.get_or_insert("on_vm_dropped");
+        self
+    }
+
     pub fn initial_heap_size(mut self, ihs: usize) -> Self {
         self.initial_heap_size = ihs;
         self
@@ -813,12 +2648,129 @@ impl VMConfig {
         self
     }
 
+    /// Fails scripts that allocate more than [bytes], as far as Wren lets that be checked
+    ///
+    /// This is not a cap. Wren can't recover from a failed allocation, so the allocator lets it go over,
+    /// and the next foreign method aborts its fiber instead, and the interpret or call fails with
+    /// [VMError::HeapLimitExceeded] when it returns. A script that never calls a foreign method can
+    /// allocate as much as it likes, and only fails once it finishes. The next interpret or call
+    /// collects garbage first, and runs normally if that got the heap back under the limit.
+    pub fn max_heap_size(mut self, bytes: usize) -> Self {
+        self.max_heap_size = Some(bytes);
+        self
+    }
+
+    /// Limits how deeply interprets and calls can nest (through foreign methods calling back into Wren),
+    /// so runaway recursion fails with [VMError::CallDepthExceeded] instead of overflowing the native stack
+    ///
+    /// Defaults to 128.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// How numbers are written when this VM formats values (see [VM::format_value])
+    pub fn number_format(mut self, format: NumberFormat) -> Self {
+        self.number_format = format;
+        self
+    }
+
+    /// A name for the VM, to tell it apart from others in logs
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Shares module sources with other VMs using [cache], instead of loading them every time
+    pub fn script_cache(mut self, cache: &ScriptCache) -> Self {
+        self.script_cache = Some(cache.clone());
+        self
+    }
+
+    /// Keeps frames from the Wren code ruwren generates (like the callbacks module) in stack traces,
+    /// which are hidden by default so errors only show the scripts' own modules
+    pub fn verbose_traces(mut self, verbose: bool) -> Self {
+        self.verbose_traces = verbose;
+        self
+    }
+
+    /// Fails each interpret or call that declares foreign classes or methods nothing was registered
+    /// for with [VMError::UnboundForeign], listing them all, instead of whatever error they'd
+    /// lead to later (see [VMWrapper::validate_library])
+    pub fn strict_library(mut self, strict: bool) -> Self {
+        self.strict_library = strict;
+        self
+    }
+
+    /// Lets host code reach the VM with [current_vm] while one of its foreign methods is running,
+    /// instead of passing `&VM` all the way down
+    pub fn expose_current_vm(mut self, expose: bool) -> Self {
+        self.expose_current_vm = expose;
+        self
+    }
+
+    /// Locks the VM down for evaluating untrusted configuration or expressions
+    ///
+    /// A pure VM has no script loader or script cache, only the library modules allowed with [VMConfig::pure_module],
+    /// no capabilities or built-in modules, a call depth limit of at most 32, and a small starting heap.
+    /// Wren can't limit how long it runs or how much it allocates, so pure mode can't either
+    /// ([VMConfig::max_heap_size] is only checked between foreign methods).
+    /// This is applied when the VM is built, so it overrides the other settings either way round.
+    /// [VMConfig::sandboxed] starts from a pure config.
+    pub fn pure_mode(mut self, pure: bool) -> Self {
+        self.pure_mode = pure;
+        self
+    }
+
+    /// Allows a library module that has no side effects in a [pure](VMConfig::pure_mode) VM
+    pub fn pure_module<S: Into<String>>(mut self, module: S) -> Self {
+        self.pure_modules.push(module.into());
+        self
+    }
+
     pub fn enable_relative_import(mut self, eri: bool) -> Self {
         self.enable_relative_import = eri;
         self
     }
 
+    /// Builds the VM, panicking if the config is invalid (see [VMConfig::try_build])
     pub fn build(self) -> VMWrapper {
+        match self.try_build() {
+            Ok(vm) => vm,
+            Err(e) => panic!("Failed to build VM: {}", e),
+        }
+    }
+
+    pub fn try_build(mut self) -> Result<VMWrapper, BuildError> {
+        if self.pure_mode {
+            self.script_loader = Box::new(NullLoader);
+            self.script_cache = None;
+            if let Some(library) = self.library.as_mut() {
+                let allowed = &self.pure_modules;
+                // Only copy a shared library if there's something to take out of it
+                if library.modules.keys().any(|name| !allowed.contains(name)) {
+                    Arc::make_mut(library).modules.retain(|name, _| allowed.contains(name));
+                }
+            }
+            self.capabilities = CapabilityPolicy::new();
+            self.enable_relative_import = false;
+            self.max_call_depth = self.max_call_depth.min(32);
+            self.initial_heap_size = self.initial_heap_size.min(1024 * 1024);
+            self.min_heap_size = self.min_heap_size.min(256 * 1024);
+        }
+
+        if let Some(limit) = self.max_heap_size {
+            // Collect often enough that garbage alone doesn't take the heap over the limit
+            self.initial_heap_size = self.initial_heap_size.min(limit / 2);
+            self.min_heap_size = self.min_heap_size.min(limit / 4);
+        }
+
+        if self.require_send {
+            if let Some((module, class)) = self.library.as_ref().and_then(|lib| lib.non_send_classes().into_iter().next()) {
+                return Err(BuildError::NonSendClass { module, class });
+            }
+        }
+
         let (etx, erx) = channel();
 
         // Have an uninitialized VM...
@@ -829,10 +2781,62 @@ impl VMConfig {
 
         let vm_config = Box::into_raw(Box::new(UserData {
             error_channel: etx,
+            error_sink: self.error_handler,
             printer: self.printer,
             vm: Rc::downgrade(&wvm),
             loader: self.script_loader,
             library: self.library,
+            overlay: RefCell::new(ModuleLibrary::new()),
+            capabilities: self.capabilities,
+            #[cfg(feature = "process")]
+            process_imports: RefCell::new(stdlib::process::Imports::default()),
+            string_policy: self.string_policy,
+            utf8_violation: Cell::new(None),
+            callback_panic: RefCell::new(None),
+            foreign_abort: RefCell::new(None),
+            stats: Cell::new(VMStats::default()),
+            gc_guards: Cell::new(0),
+            gc_pending: Cell::new(false),
+            modules: RefCell::new(HashMap::new()),
+            callbacks: RefCell::new(callbacks::CallbackTable::default()),
+            #[cfg(feature = "scheduler")]
+            timers: RefCell::new(scheduler::Timers::default()),
+            events: RefCell::new(events::EventQueue::new()),
+            futures: RefCell::new(futures::FutureTable::default()),
+            call_handles: RefCell::new(HashMap::new()),
+            eval_declared: Cell::new(false),
+            return_declared: RefCell::new(HashSet::new()),
+            graph_declared: Cell::new(false),
+            fibers_declared: Cell::new(false),
+            call_depth: Cell::new(0),
+            scratch: scratch::Scratch::default(),
+            max_call_depth: self.max_call_depth,
+            heap_used: Cell::new(0),
+            max_heap_size: self.max_heap_size,
+            heap_exceeded: Cell::new(false),
+            number_format: self.number_format,
+            name: self.name,
+            relative_import: self.enable_relative_import,
+            pure: self.pure_mode,
+            script_cache: self.script_cache,
+            verbose_traces: self.verbose_traces,
+            unbound: RefCell::new(vec![]),
+            strict_library: self.strict_library,
+            unbound_reported: Cell::new(0),
+            expose_current: self.expose_current_vm,
+            foreign_method: Cell::new(None),
+            closure_methods: RefCell::new(vec![]),
+            profile: RefCell::new(None),
+            created: Instant::now(),
+            drop_hooks: self.drop_hooks,
+            call_observer: self.call_observer,
+            error_rewriter: self.error_rewriter,
+            #[cfg(feature = "coverage")]
+            coverage: if self.coverage { Some(RefCell::new(coverage::Coverage::default())) } else { None },
+            #[cfg(feature = "debugger")]
+            debugger: self.debugger,
+            #[cfg(feature = "tokio")]
+            tokio: tokio_bridge::TokioBridge::new(self.tokio_runtime),
         }));
 
         // Configure the Wren side of things
@@ -846,11 +2850,7 @@ impl VMConfig {
             config.bindForeignMethodFn = Some(wren_bind_foreign_method);
             config.bindForeignClassFn = Some(wren_bind_foreign_class);
             config.loadModuleFn = Some(wren_load_module);
-            config.resolveModuleFn = if self.enable_relative_import {
-                Some(wren_canonicalize)
-            } else {
-                None
-            };
+            config.resolveModuleFn = Some(wren_canonicalize);
             config.initialHeapSize = self.initial_heap_size as wren_sys::size_t;
             config.minHeapSize = self.min_heap_size as wren_sys::size_t;
             config.heapGrowthPercent = self.heap_growth_percent as raw::c_int;
@@ -860,33 +2860,208 @@ impl VMConfig {
 
         let vm = unsafe { wren_sys::wrenNewVM(&mut config) };
         wvm.borrow_mut().vm = vm;
-        VMWrapper(wvm)
+        let wrapper = VMWrapper(wvm);
+        for (module, name, value) in &self.definitions {
+            // Goes straight to Wren, so definitions don't show up in stats or coverage
+            let source = ffi::CString::new(format!("var {} = {}", name, value.to_wren_source()));
+            let module_cstr = ffi::CString::new(module.as_str());
+            let defined = match (module_cstr, source) {
+                (Ok(module_cstr), Ok(source)) => {
+                    let result = unsafe { wren_sys::wrenInterpret(vm, module_cstr.as_ptr(), source.as_ptr()) };
+                    result == wren_sys::WrenInterpretResult_WREN_RESULT_SUCCESS
+                },
+                _ => false,
+            };
+            if !defined {
+                let vm = wrapper.0.borrow();
+                let error = match vm.error_recv.try_recv() {
+                    Ok(WrenError::Compile(_, _, error)) | Ok(WrenError::Runtime(error)) => error,
+                    _ => "contains a NUL byte".to_string(),
+                };
+                while vm.error_recv.try_recv().is_ok() {}
+                return Err(BuildError::InvalidDefinition { module: module.clone(), name: name.clone(), error });
+            }
+        }
+        for hook in &self.created_hooks {
+            hook(&wrapper);
+        }
+        Ok(wrapper)
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ForeignSendError {
-    NoForeignClass,
-    NoWrenClass,
-    NoMemory,
-    ClassMismatch,
+impl VM {
+    fn user_data(&self) -> &UserData {
+        unsafe { &*(wren_sys::wrenGetUserData(self.vm) as *const UserData) }
+    }
 
-}
+    // A call handle for [signature] that lasts as long as the VM
+    fn cached_call_handle(&self, signature: FunctionSignature) -> *mut WrenHandle {
+        *self.user_data().call_handles.borrow_mut().entry(signature.as_wren_string()).or_insert_with_key(|signature| {
+            let signature = ffi::CString::new(signature.as_str()).expect("signature conversion failed");
+            unsafe { wren_sys::wrenMakeCallHandle(self.vm, signature.as_ptr()) }
+        })
+    }
 
-impl std::fmt::Display for ForeignSendError {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            ForeignSendError::NoForeignClass => write!(fmt, "no foreign class"),
-            ForeignSendError::NoWrenClass => write!(fmt, "no Wren class"),
-            ForeignSendError::NoMemory => write!(fmt, "unable to allocate memory"),
-            ForeignSendError::ClassMismatch => write!(fmt, "class mismatch"),
+    fn runtime_error(&self) -> VMError {
+        let mut error = "".to_string();
+        let mut frames = vec![];
+        while let Ok(err) = self.error_recv.try_recv() {
+            match err {
+                WrenError::Runtime(msg) => {error = msg; },
+                WrenError::StackTrace(module, line, msg) => {frames.push(VMStackFrameError::script(module, line, msg)); },
+                _ => unreachable!()
+            }
+        }
+        // Wren doesn't know about foreign frames, so add one if a foreign method caused this
+        if let Some((message, frame)) = self.user_data().foreign_abort.borrow_mut().take() {
+            if message == error {
+                frames.insert(0, frame);
+            }
+        }
+        let (verbose, pure) = (self.user_data().verbose_traces, self.user_data().pure);
+        frames.retain_mut(|frame| match glue_label(&frame.module, pure) {
+            Some((label, hidden)) => {
+                frame.module = label;
+                verbose || !hidden
+            },
+            None => true,
+        });
+        VMError::Runtime {
+            error,
+            frames
         }
     }
-}
 
-impl std::error::Error for ForeignSendError {}
+    /// Remembers which foreign method aborted the fiber, so the error's stack trace can include it
+    ///
+    /// [signature] is None for constructors. Returns the message to abort with, prefixed with the
+    /// method (like "Sprite.move(_,_): index out of bounds").
+    #[doc(hidden)]
+    pub fn record_foreign_abort(&self, message: &str, module: &str, class: &str, signature: Option<FunctionSignature>) -> String {
+        let signature = signature.map(|sgn| sgn.as_wren_string()).unwrap_or_default();
+        let function = if signature.is_empty() {
+            format!("{}.<constructor>", class)
+        } else {
+            format!("{}.{}", class, signature)
+        };
+        let message = format!("{}: {}", function, message);
+        *self.user_data().foreign_abort.borrow_mut() = Some((message.clone(), VMStackFrameError {
+            module: module.replace("_", "/"),
+            line: 0,
+            function,
+            kind: FrameKind::Foreign,
+            signature,
+        }));
+        message
+    }
+
+    // Fails a finished interpret or call if a callback panicked, the heap went over its limit, or
+    // invalid UTF-8 was printed while it ran
+    fn check_violations(&self, result: Result<(), VMError>) -> Result<(), VMError> {
+        let ud = self.user_data();
+        if let Some((callback, message)) = ud.callback_panic.take() {
+            // Whatever else went wrong likely followed from the panic
+            ud.utf8_violation.set(None);
+            return Err(VMError::CallbackPanic { callback: callback.to_string(), message });
+        }
+        if let (true, Some(limit)) = (ud.heap_exceeded.get(), ud.max_heap_size) {
+            ud.utf8_violation.set(None);
+            return Err(VMError::HeapLimitExceeded { limit });
+        }
+        let unbound = ud.unbound.borrow();
+        if ud.strict_library && unbound.len() > ud.unbound_reported.get() {
+            let new = unbound[ud.unbound_reported.replace(unbound.len())..].to_vec();
+            ud.utf8_violation.set(None);
+            return Err(VMError::UnboundForeign { unbound: new });
+        }
+        match (result, ud.utf8_violation.take()) {
+            (Ok(_), Some(context)) => Err(VMError::InvalidUtf8 { context: context.to_string() }),
+            (result, _) => result,
+        }
+    }
+
+    // Passes [error] through the config's error rewriter, if it has one
+    fn rewrite_error(&self, error: VMError) -> VMError {
+        match &self.user_data().error_rewriter {
+            Some(rewriter) => rewriter(error),
+            None => error,
+        }
+    }
+
+    /// The string policy this VM was built with
+    pub fn string_policy(&self) -> StringPolicy {
+        self.user_data().string_policy
+    }
+
+    // Gives a VM that went over its heap limit a chance to get back under it before running anything else
+    fn reset_heap_limit(&self) {
+        let ud = self.user_data();
+        if !ud.heap_exceeded.get() {
+            return;
+        }
+        // Nested calls keep failing, since the outermost one will anyway
+        if ud.call_depth.get() == 1 && ud.gc_guards.get() == 0 {
+            let _span = ffi_span!(DEBUG, "gc", reason = "heap limit");
+            unsafe { wren_sys::wrenCollectGarbage(self.vm) };
+        }
+        ud.heap_exceeded.set(ud.max_heap_size.is_some_and(|limit| ud.heap_used.get() > limit));
+    }
+
+    /// Aborts the running foreign method if the heap has gone over [VMConfig::max_heap_size]
+    ///
+    /// Called by [create_module!] and friends before each foreign method, which is where Wren can
+    /// be stopped safely.
+    ///
+    /// # Panics
+    /// If the heap is over the limit
+    #[doc(hidden)]
+    pub fn check_heap_limit(&self) {
+        let ud = self.user_data();
+        if let (true, Some(limit)) = (ud.heap_exceeded.get(), ud.max_heap_size) {
+            panic!("Heap limit of {} bytes exceeded", limit);
+        }
+    }
+
+    // Counts a nested interpret or call, failing if there are already too many
+    fn enter_call(&self) -> Result<CallDepth<'_>, VMError> {
+        let ud = self.user_data();
+        let depth = ud.call_depth.get();
+        if depth >= ud.max_call_depth {
+            return Err(self.rewrite_error(VMError::CallDepthExceeded { limit: ud.max_call_depth }));
+        }
+        ud.call_depth.set(depth + 1);
+        Ok(CallDepth(ud))
+    }
+
+    fn record_result(&self, interpret: bool, result: &Result<(), VMError>) {
+        let ud = self.user_data();
+        let mut stats = ud.stats.get();
+        if interpret {
+            stats.interprets += 1;
+        } else {
+            stats.calls += 1;
+        }
+        match result {
+            Err(VMError::Compile { .. }) => stats.compile_errors += 1,
+            Err(VMError::Runtime { .. }) | Err(VMError::InvalidUtf8 { .. }) | Err(VMError::CallDepthExceeded { .. })
+                | Err(VMError::CallbackPanic { .. }) | Err(VMError::HeapLimitExceeded { .. })
+                | Err(VMError::UnboundForeign { .. }) => stats.runtime_errors += 1,
+            // Only made once the call has been counted as a success
+            Err(VMError::InvalidReturn { .. }) | Ok(_) => {},
+        }
+        ud.stats.set(stats);
+    }
+
+    /// The capabilities granted to this VM
+    pub fn capabilities(&self) -> &CapabilityPolicy {
+        &self.user_data().capabilities
+    }
+
+    /// The name given to this VM (see [VMConfig::name])
+    pub fn name(&self) -> Option<&str> {
+        self.user_data().name.as_deref()
+    }
 
-impl VM {
     // Slot and Handle API
     pub fn ensure_slots(&self, count: usize) {
         unsafe {
@@ -952,24 +3127,32 @@ impl VM {
     }
 
     pub fn get_slot_bytes(&self, slot: SlotId) -> Option<Vec<u8>> {
-        if self.get_slot_type(slot) != SlotType::String {
-            None
-        } else {
-            let mut length = 0 as raw::c_int;
-            let ptr = unsafe {
-                wren_sys::wrenGetSlotBytes(self.vm, slot as raw::c_int, &mut length as *mut _)
-            };
-            let mut bytes = vec![];
+        self.slot_bytes(slot).map(<[u8]>::to_vec)
+    }
 
-            // Do some pointer maths to get the vector. Hurrah!
-            for offset in 0..length {
-                unsafe {
-                    bytes.push(*ptr.offset(offset as isize) as u8)
-                }
-            }
+    /// Borrows the bytes of the string in [slot] without copying them, or returns None if the
+    /// slot doesn't hold a string
+    ///
+    /// For reading large blobs (images, save data) that scripts hand over as strings. Wren's
+    /// strings never move or change, and the string is kept rooted while the guard is held.
+    pub fn get_slot_bytes_ref(&self, slot: SlotId) -> Option<SlotBytes<'_>> {
+        let bytes = self.slot_bytes(slot)?;
+        Some(SlotBytes { bytes, _root: self.root_slot(slot) })
+    }
 
-            Some(bytes)
+    // The bytes of the string in [slot], which are only valid until it's collected
+    fn slot_bytes(&self, slot: SlotId) -> Option<&[u8]> {
+        if self.get_slot_type(slot) != SlotType::String {
+            return None;
+        }
+        let mut length = 0 as raw::c_int;
+        let ptr = unsafe {
+            wren_sys::wrenGetSlotBytes(self.vm, slot as raw::c_int, &mut length as *mut _)
+        };
+        if ptr.is_null() || length <= 0 {
+            return Some(&[]);
         }
+        unsafe { Some(std::slice::from_raw_parts(ptr as *const u8, length as usize)) }
     }
 
     pub fn get_slot_string(&self, slot: SlotId) -> Option<String> {
@@ -982,7 +3165,31 @@ impl VM {
     
             let cstr = unsafe{ ffi::CStr::from_ptr(ptr) };
     
-            Some(cstr.to_string_lossy().to_string())
+            match (self.string_policy(), cstr.to_str()) {
+                (_, Ok(s)) => Some(s.to_string()),
+                (StringPolicy::Strict, Err(_)) => None,
+                (_, Err(_)) => {
+                    audit!("String in slot {} isn't valid UTF-8, so invalid bytes were replaced with U+FFFD", slot);
+                    Some(cstr.to_string_lossy().to_string())
+                },
+            }
+        }
+    }
+
+    /// Borrows the string in [slot], or returns None if the slot doesn't hold one
+    ///
+    /// Valid UTF-8 is borrowed without copying, for string arguments read on every call of a hot
+    /// foreign method. Anything else is handled by the string policy, as in [VM::get_slot_string]:
+    /// copied with invalid sequences replaced, or None under [StringPolicy::Strict].
+    pub fn get_slot_str(&self, slot: SlotId) -> Option<SlotStr<'_>> {
+        let bytes = self.slot_bytes(slot)?;
+        match (self.string_policy(), std::str::from_utf8(bytes)) {
+            (_, Ok(s)) => Some(SlotStr { string: s.into(), _root: Some(self.root_slot(slot)) }),
+            (StringPolicy::Strict, Err(_)) => None,
+            (_, Err(_)) => {
+                audit!("String in slot {} isn't valid UTF-8, so invalid bytes were replaced with U+FFFD", slot);
+                Some(SlotStr { string: String::from_utf8_lossy(bytes).into_owned().into(), _root: None })
+            },
         }
     }
 
@@ -991,6 +3198,7 @@ impl VM {
             wren_sys::WrenType_WREN_TYPE_NUM => SlotType::Num,
             wren_sys::WrenType_WREN_TYPE_BOOL => SlotType::Bool,
             wren_sys::WrenType_WREN_TYPE_LIST => SlotType::List,
+            wren_sys::WrenType_WREN_TYPE_MAP => SlotType::Map,
             wren_sys::WrenType_WREN_TYPE_NULL => SlotType::Null,
             wren_sys::WrenType_WREN_TYPE_STRING => SlotType::String,
             wren_sys::WrenType_WREN_TYPE_FOREIGN => SlotType::Foreign,
@@ -999,9 +3207,26 @@ impl VM {
         }
     }
 
-    pub fn get_variable<M: AsRef<str>, N: AsRef<str>>(&self, module: M, name: N, slot: SlotId) {
-        let module = ffi::CString::new(module.as_ref()).expect("module name conversion failed");
-        let name = ffi::CString::new(name.as_ref()).expect("variable name conversion failed");
+    /// Whether [module] has been loaded
+    pub fn has_module<M: ModuleName>(&self, module: M) -> bool {
+        let module = self.module_cstr(&module);
+        unsafe { wren_sys::wrenHasModule(self.vm, module.as_ptr()) }
+    }
+
+    /// Whether [module] has been loaded, and defines the variable [name]
+    pub fn has_variable<M: ModuleName, N: AsRef<str>>(&self, module: M, name: N) -> bool {
+        let module = self.module_cstr(&module);
+        // Wren asserts that the module exists
+        if !unsafe { wren_sys::wrenHasModule(self.vm, module.as_ptr()) } {
+            return false;
+        }
+        let name = self.scratch_cstr(name.as_ref(), "variable name");
+        unsafe { wren_sys::wrenHasVariable(self.vm, module.as_ptr(), name.as_ptr()) }
+    }
+
+    pub fn get_variable<M: ModuleName, N: AsRef<str>>(&self, module: M, name: N, slot: SlotId) {
+        let module = self.module_cstr(&module);
+        let name = self.scratch_cstr(name.as_ref(), "variable name");
         unsafe {
             wren_sys::wrenGetVariable(self.vm, module.as_ptr(), name.as_ptr(), slot as raw::c_int)
         }
@@ -1041,25 +3266,188 @@ impl VM {
         }
     }
 
-    pub fn get_slot_foreign<T: 'static + ClassObject>(&self, slot: SlotId) -> Option<&T> {
-        self.get_slot_foreign_mut(slot).map(|mr| &*mr)
+    pub fn set_slot_new_map(&self, slot: SlotId) {
+        unsafe {
+            wren_sys::wrenSetSlotNewMap(self.vm, slot as raw::c_int)
+        }
     }
 
-    pub fn get_slot_foreign_mut<T: 'static + ClassObject>(&self, slot: SlotId) -> Option<&mut T> {
+    pub fn get_map_count(&self, slot: SlotId) -> usize {
         unsafe {
-            let ptr = wren_sys::wrenGetSlotForeign(self.vm, slot as raw::c_int);
-            if !ptr.is_null() {
-                let fo = &mut *(ptr as *mut ForeignObject<T>);
-                if fo.type_id == any::TypeId::of::<T>() {
-                    // Safe to downcast
-                    fo.object.as_mut()
-                } else {
-                    // Incorrect type, unsafe to downcast
-                    None
-                }
+            wren_sys::wrenGetMapCount(self.vm, slot as raw::c_int) as usize
+        }
+    }
+
+    pub fn get_map_contains_key(&self, map_slot: SlotId, key_slot: SlotId) -> bool {
+        unsafe {
+            wren_sys::wrenGetMapContainsKey(self.vm, map_slot as raw::c_int, key_slot as raw::c_int)
+        }
+    }
+
+    /// Puts the value stored under the key in [key_slot] into [value_slot] (null if there isn't one)
+    pub fn get_map_value(&self, map_slot: SlotId, key_slot: SlotId, value_slot: SlotId) {
+        unsafe {
+            wren_sys::wrenGetMapValue(
+                self.vm,
+                map_slot as raw::c_int,
+                key_slot as raw::c_int,
+                value_slot as raw::c_int
+            )
+        }
+    }
+
+    pub fn set_map_value(&self, map_slot: SlotId, key_slot: SlotId, value_slot: SlotId) {
+        unsafe {
+            wren_sys::wrenSetMapValue(
+                self.vm,
+                map_slot as raw::c_int,
+                key_slot as raw::c_int,
+                value_slot as raw::c_int
+            )
+        }
+    }
+
+    /// Removes the key in [key_slot], putting the value it had into [removed_value_slot] (null if there wasn't one)
+    pub fn remove_map_value(&self, map_slot: SlotId, key_slot: SlotId, removed_value_slot: SlotId) {
+        unsafe {
+            wren_sys::wrenRemoveMapValue(
+                self.vm,
+                map_slot as raw::c_int,
+                key_slot as raw::c_int,
+                removed_value_slot as raw::c_int
+            )
+        }
+    }
+
+    fn root_slot(&self, slot: SlotId) -> Handle<'_> {
+        Handle {
+            handle: unsafe { wren_sys::wrenGetSlotHandle(self.vm, slot as raw::c_int) },
+            wvm: self.vm,
+            vm: marker::PhantomData
+        }
+    }
+
+    /// Saves every slot, so code that needs scratch slots (or makes calls) can put them back afterwards
+    pub fn snapshot_slots(&self) -> SlotSnapshot<'_> {
+        SlotSnapshot {
+            slots: (0..self.get_slot_count()).map(|slot| self.root_slot(slot)).collect(),
+        }
+    }
+
+    /// Puts the slots back the way they were when [snapshot] was taken
+    ///
+    /// Slots past the ones that were saved are left alone.
+    pub fn restore_slots(&self, snapshot: SlotSnapshot<'_>) {
+        self.ensure_slots(snapshot.slots.len());
+        for (slot, handle) in snapshot.slots.iter().enumerate() {
+            unsafe { wren_sys::wrenSetSlotHandle(self.vm, slot as raw::c_int, handle.handle) };
+        }
+    }
+
+    /// Borrows the foreign object in [slot]
+    ///
+    /// Returns None if the slot doesn't hold a T, or if the object is already mutably borrowed.
+    /// The object stays rooted for as long as the guard is held, so it's fine to overwrite the slot.
+    pub fn get_slot_foreign<T: 'static + ClassObject>(&self, slot: SlotId) -> Option<ForeignRef<'_, T>> {
+        let ptr = self.foreign_object_ptr::<T>(slot).ok()?;
+        unsafe {
+            if (*ptr).borrow < 0 {
+                return None;
+            }
+            (*ptr).borrow += 1;
+        }
+        Some(ForeignRef {
+            object: ptr,
+            _root: Some(self.root_slot(slot)),
+        })
+    }
+
+    /// Mutably borrows the foreign object in [slot]
+    ///
+    /// Returns None if the slot doesn't hold a T, or if the object is already borrowed.
+    /// The object stays rooted for as long as the guard is held, so it's fine to overwrite the slot.
+    pub fn get_slot_foreign_mut<T: 'static + ClassObject>(&self, slot: SlotId) -> Option<ForeignRefMut<'_, T>> {
+        let ptr = self.foreign_object_ptr::<T>(slot).ok()?;
+        unsafe {
+            if (*ptr).borrow != 0 {
+                return None;
+            }
+            (*ptr).borrow = -1;
+        }
+        Some(ForeignRefMut {
+            object: ptr,
+            _root: Some(self.root_slot(slot)),
+        })
+    }
+
+    /// Gets the foreign object in [slot] without tracking the borrow
+    ///
+    /// # Safety
+    /// The reference must not outlive the object (which is only guaranteed to live while it's in a slot),
+    /// and must not overlap a mutable borrow of the same object.
+    pub unsafe fn get_slot_foreign_unchecked<T: 'static + ClassObject>(&self, slot: SlotId) -> Option<&T> {
+        self.get_slot_foreign_mut_unchecked(slot).map(|mr| &*mr)
+    }
+
+    /// Mutably gets the foreign object in [slot] without tracking the borrow
+    ///
+    /// # Safety
+    /// The reference must not outlive the object (which is only guaranteed to live while it's in a slot),
+    /// and must not overlap any other borrow of the same object.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_slot_foreign_mut_unchecked<T: 'static + ClassObject>(&self, slot: SlotId) -> Option<&mut T> {
+        let ptr = self.foreign_object_ptr::<T>(slot).ok()?;
+        (*ptr).object.as_mut()
+    }
+
+    fn foreign_object_ptr<T: 'static>(&self, slot: SlotId) -> Result<*mut ForeignObject<T>, ForeignBorrowError> {
+        if self.get_slot_type(slot) != SlotType::Foreign {
+            return Err(ForeignBorrowError::NotForeign);
+        }
+        unsafe {
+            let data = wren_sys::wrenGetSlotForeign(self.vm, slot as raw::c_int);
+            // Checks the header is ruwren's before reading the rest of it, since Wren makes foreign objects too
+            let ptr = data as *mut ForeignObject<T>;
+            if data.is_null() || ForeignObject::type_of(data) != Some(any::TypeId::of::<T>()) || (*ptr).object.is_null() {
+                Err(ForeignBorrowError::TypeMismatch)
             } else {
-                None
+                Ok(ptr)
+            }
+        }
+    }
+
+    /// Runs [f] with a shared borrow of the foreign object in [slot]
+    ///
+    /// Fails if the object is mutably borrowed further up the stack.
+    pub fn try_borrow_foreign<T, R, F>(&self, slot: SlotId, f: F) -> Result<R, ForeignBorrowError>
+        where T: 'static, F: FnOnce(&T) -> R
+    {
+        let ptr = self.foreign_object_ptr::<T>(slot)?;
+        unsafe {
+            if (*ptr).borrow < 0 {
+                return Err(ForeignBorrowError::AlreadyBorrowed);
+            }
+            (*ptr).borrow += 1;
+            let _release = BorrowRelease(&mut (*ptr).borrow);
+            Ok(f(&*(*ptr).object))
+        }
+    }
+
+    /// Runs [f] with a mutable borrow of the foreign object in [slot]
+    ///
+    /// Fails if the object is borrowed at all further up the stack
+    /// (for instance, when a foreign method re-enters Wren, which calls back into the same object).
+    pub fn try_borrow_foreign_mut<T, R, F>(&self, slot: SlotId, f: F) -> Result<R, ForeignBorrowError>
+        where T: 'static, F: FnOnce(&mut T) -> R
+    {
+        let ptr = self.foreign_object_ptr::<T>(slot)?;
+        unsafe {
+            if (*ptr).borrow != 0 {
+                return Err(ForeignBorrowError::AlreadyBorrowed);
             }
+            (*ptr).borrow = -1;
+            let _release = BorrowRelease(&mut (*ptr).borrow);
+            Ok(f(&mut *(*ptr).object))
         }
     }
 
@@ -1068,44 +3456,64 @@ impl VM {
     ///  
     /// WARNING: This *will* overwrite slot 0, so be careful.
     pub fn set_slot_new_foreign<M: AsRef<str>, C: AsRef<str>, T: 'static + ClassObject>(&self, module: M, class: C, object: T, slot: SlotId) 
-        -> Result<&mut T, ForeignSendError> 
+        -> Result<ForeignRefMut<'_, T>, ForeignSendError> 
     {
-        let conf = unsafe { &mut *(wren_sys::wrenGetUserData(self.vm) as *mut UserData) };
-
-        self.ensure_slots((slot + 1) as usize);
+        self.ensure_slots(slot + 1);
         // Even if slot == 0, we can just load the class into slot 0, then use wrenSetSlotNewForeign to "create" a new object
-        match conf.library.as_ref().and_then(|lib| lib.get_foreign_class(module.as_ref(), class.as_ref())) {
+        self.load_foreign_class::<T, _, _>(module, class, 0)?;
+        unsafe {
+            let wptr = self.new_foreign_object(0, slot, object)?;
+            // Already mutably borrowed by the returned guard
+            (*wptr).borrow = -1;
+            Ok(ForeignRefMut {
+                object: wptr,
+                _root: Some(self.root_slot(slot)),
+            })
+        }
+    }
+
+    /// Sends [object] to Wren in [slot], as whichever registered class T is bound to
+    ///
+    /// Unlike [VM::set_slot_new_foreign], this doesn't overwrite slot 0 (it uses one slot past the
+    /// current slot count instead). If T is bound to more than one class, the first one registered
+    /// is used.
+    pub fn set_slot_foreign<T: 'static>(&self, slot: SlotId, object: T) -> Result<(), ForeignSendError> {
+        let ud = self.user_data();
+        let found = |lib: &ModuleLibrary| lib.find_foreign_class(any::TypeId::of::<T>())
+            .map(|(module, class)| (module.to_string(), class.to_string()));
+        // The VM's own modules take precedence over the shared library, as they do everywhere else
+        let (module, class) = found(&ud.overlay.borrow())
+            .or_else(|| ud.library.as_deref().and_then(found))
+            .ok_or(ForeignSendError::NoForeignClass)?;
+        let scratch = self.get_slot_count().max(slot + 1);
+        self.ensure_slots(scratch + 1);
+        self.load_foreign_class::<T, _, _>(&module, &class, scratch)?;
+        unsafe { self.new_foreign_object(scratch, slot, object)? };
+        Ok(())
+    }
+
+    // Loads the Wren class for T into [class_slot], checking that it's registered and declared
+    fn load_foreign_class<T: 'static, M: AsRef<str>, C: AsRef<str>>(&self, module: M, class: C, class_slot: SlotId) -> Result<(), ForeignSendError> {
+        let conf = self.user_data();
+        match conf.foreign_class(module.as_ref(), class.as_ref(), |rc| rc.type_id) {
             None => Err(ForeignSendError::NoForeignClass), // Couldn't find the corresponding class
-            Some(runtime_class) => {
-                if runtime_class.type_id == any::TypeId::of::<T>() {
+            Some(type_id) => {
+                if type_id == any::TypeId::of::<T>() {
                     // The Wren foreign class corresponds with this real object.
                     // We can coerce it and treat this object as that class, even if not instantiated by Wren.
 
-                    // Create the new ForeignObject
-                    let new_obj = ForeignObject {
-                        object: Box::into_raw(Box::new(object)),
-                        type_id: any::TypeId::of::<T>(),
-                    };
+                    // The module may not have been imported yet, and Wren only asserts that the variable exists
+                    if !self.has_variable(module.as_ref(), class.as_ref()) {
+                        return Err(ForeignSendError::NoWrenClass);
+                    }
 
-                    // Load the Wren class object into slot 0.
-                    self.get_variable(module, class, 0);
+                    // Load the Wren class object into the class slot.
+                    self.get_variable(module, class, class_slot);
 
                     // Make sure the class isn't null (undeclared in Wren code)
-                    match self.get_slot_type(0) {
+                    match self.get_slot_type(class_slot) {
                         SlotType::Null => Err(ForeignSendError::NoWrenClass), // You haven't declared the foreign class to Wren
-                        SlotType::Unknown => unsafe { // A Wren class
-                            // Create the Wren foreign pointer
-                            let wptr = wren_sys::wrenSetSlotNewForeign(self.vm, slot as raw::c_int, 0, mem::size_of::<ForeignObject<T>>() as wren_sys::size_t);
-
-                            // Move the ForeignObject into the pointer
-                            std::ptr::write(wptr as *mut _, new_obj);
-
-                            // Reinterpret the pointer as an object if we were successful
-                            match (wptr as *mut ForeignObject<T>).as_mut() {
-                                Some(ptr) => Ok(ptr.object.as_mut().unwrap()),
-                                None => Err(ForeignSendError::NoMemory)
-                            }
-                        },
+                        SlotType::Unknown => Ok(()), // A Wren class
                         _ => Err(ForeignSendError::NoWrenClass)
                     }
                 } else {
@@ -1116,15 +3524,29 @@ impl VM {
         }
     }
 
+    // Creates a new instance of the class in [class_slot] in [slot], wrapping [object]
+    unsafe fn new_foreign_object<T: 'static>(&self, class_slot: SlotId, slot: SlotId, object: T) -> Result<*mut ForeignObject<T>, ForeignSendError> {
+        let wptr = wren_sys::wrenSetSlotNewForeign(self.vm, slot as raw::c_int, class_slot as raw::c_int, mem::size_of::<ForeignObject<T>>() as wren_sys::size_t);
+
+        let wptr = wptr as *mut ForeignObject<T>;
+        if wptr.is_null() {
+            return Err(ForeignSendError::NoMemory);
+        }
+
+        std::ptr::write(wptr, ForeignObject::new(Box::into_raw(Box::new(object))));
+        Ok(wptr)
+    }
+
     fn make_call_handle<'b>(vm: *mut WrenVM, signature: FunctionSignature) -> Rc<FunctionHandle<'b>> {
-        let signature = ffi::CString::new(signature.as_wren_string()).expect("signature conversion failed");
+        let signature = signature.as_wren_string();
+        let cstr = ffi::CString::new(signature.as_str()).expect("signature conversion failed");
         Rc::new(FunctionHandle(Handle {
             handle: unsafe {
-                wren_sys::wrenMakeCallHandle(vm, signature.as_ptr())
+                wren_sys::wrenMakeCallHandle(vm, cstr.as_ptr())
             },
             wvm: vm,
             vm: marker::PhantomData
-        }))
+        }, signature))
     }
 
     pub fn abort_fiber(&self, slot: SlotId) {
@@ -1136,10 +3558,29 @@ impl VM {
 
 impl Drop for VM {
     fn drop(&mut self) {
-        unsafe {
+        let conf: Box<UserData> = unsafe {
             let conf = wren_sys::wrenGetUserData(self.vm);
-            let _: Box<UserData> = Box::from_raw(conf as *mut _); // Drop the userdata
+            let conf: Box<UserData> = Box::from_raw(conf as *mut _);
+            conf.callbacks.borrow_mut().release(self.vm);
+            conf.futures.borrow_mut().release(self.vm);
+            #[cfg(feature = "scheduler")]
+            conf.timers.borrow_mut().release(self.vm);
+            for (_, handle) in conf.call_handles.borrow_mut().drain() {
+                wren_sys::wrenReleaseHandle(self.vm, handle);
+            }
             wren_sys::wrenFreeVM(self.vm);
+            conf
+        };
+
+        let stats = conf.stats.get();
+        for hook in &conf.drop_hooks {
+            hook(VMReport {
+                interpret_count: stats.interprets,
+                call_count: stats.calls,
+                compile_errors: stats.compile_errors,
+                runtime_errors: stats.runtime_errors,
+                lifetime: conf.created.elapsed(),
+            });
         }
     }
 }
@@ -0,0 +1,106 @@
+//! Sending a VM's output and errors to the host's [log](https://docs.rs/log) logger, with the `log` feature
+//!
+//! ```ignore
+//! let vm = VMConfig::new().use_log().build();
+//! // or, to pick the level and target
+//! let vm = VMConfig::new().send_printer(LogPrinter::new().level(log::Level::Debug).target("game::scripts")).build();
+//! ```
+use crate::{Printer, ErrorHandler, WrenError, VMConfig};
+use log::Level;
+
+const TARGET: &str = "wren";
+
+/// A [Printer] that logs each line scripts print, at info level with a target of `wren` by default
+///
+/// Output is logged a line at a time, so `System.write` calls are collected until a newline (or
+/// until the printer is dropped).
+#[derive(Debug)]
+pub struct LogPrinter {
+    level: Level,
+    target: String,
+    // Output since the last newline
+    line: String,
+}
+
+impl LogPrinter {
+    pub fn new() -> LogPrinter {
+        LogPrinter { level: Level::Info, target: TARGET.to_string(), line: String::new() }
+    }
+
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn target<T: Into<String>>(mut self, target: T) -> Self {
+        self.target = target.into();
+        self
+    }
+}
+
+impl Default for LogPrinter {
+    fn default() -> Self {
+        LogPrinter::new()
+    }
+}
+
+impl Printer for LogPrinter {
+    fn print(&mut self, s: String) {
+        self.line.push_str(&s);
+        while let Some(end) = self.line.find('\n') {
+            let line: String = self.line.drain(..=end).collect();
+            log::log!(target: &self.target, self.level, "{}", &line[..end]);
+        }
+    }
+}
+
+impl Drop for LogPrinter {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            log::log!(target: &self.target, self.level, "{}", self.line);
+        }
+    }
+}
+
+/// An [ErrorHandler] that logs every error Wren reports (stack trace lines included), at error
+/// level with a target of `wren` by default
+#[derive(Debug)]
+pub struct LogErrorHandler {
+    level: Level,
+    target: String,
+}
+
+impl LogErrorHandler {
+    pub fn new() -> LogErrorHandler {
+        LogErrorHandler { level: Level::Error, target: TARGET.to_string() }
+    }
+
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn target<T: Into<String>>(mut self, target: T) -> Self {
+        self.target = target.into();
+        self
+    }
+}
+
+impl Default for LogErrorHandler {
+    fn default() -> Self {
+        LogErrorHandler::new()
+    }
+}
+
+impl ErrorHandler for LogErrorHandler {
+    fn handle(&mut self, error: &WrenError) {
+        log::log!(target: &self.target, self.level, "{}", error);
+    }
+}
+
+impl VMConfig {
+    /// Logs what scripts print with a [LogPrinter], and the errors Wren reports with a [LogErrorHandler]
+    pub fn use_log(self) -> Self {
+        self.send_printer(LogPrinter::new()).send_error_handler(LogErrorHandler::new())
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{VMWrapper, VMError, FunctionSignature};
+use std::borrow::Cow;
+use std::ffi::{CStr, CString};
+use std::sync::Arc;
+
+/// A module name that's already been converted for Wren, so using it doesn't have to convert it again
+///
+/// Get one from [VMWrapper::intern_module], or make one with [ModuleId::new] (for instance,
+/// to export from a binding crate).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModuleId {
+    name: Arc<str>,
+    cname: Arc<CStr>,
+}
+
+impl ModuleId {
+    /// # Panics
+    /// If [name] contains a NUL byte
+    pub fn new<N: AsRef<str>>(name: N) -> ModuleId {
+        let name = name.as_ref();
+        ModuleId {
+            name: name.into(),
+            cname: CString::new(name).expect("module name conversion failed").into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Display for ModuleId {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.name)
+    }
+}
+
+/// Anything that names a module: a string, or a [ModuleId]
+pub trait ModuleName {
+    fn module_name(&self) -> &str;
+    fn module_cstr(&self) -> Cow<'_, CStr>;
+    /// The converted name, if it's been converted ahead of time
+    fn interned_cstr(&self) -> Option<&CStr> {
+        None
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> ModuleName for T {
+    fn module_name(&self) -> &str {
+        self.as_ref()
+    }
+
+    fn module_cstr(&self) -> Cow<'_, CStr> {
+        Cow::Owned(CString::new(self.as_ref()).expect("module name conversion failed"))
+    }
+}
+
+impl ModuleName for ModuleId {
+    fn module_name(&self) -> &str {
+        &self.name
+    }
+
+    fn module_cstr(&self) -> Cow<'_, CStr> {
+        Cow::Borrowed(&self.cname)
+    }
+
+    fn interned_cstr(&self) -> Option<&CStr> {
+        Some(&self.cname)
+    }
+}
+
+impl ModuleName for &ModuleId {
+    fn module_name(&self) -> &str {
+        &self.name
+    }
+
+    fn module_cstr(&self) -> Cow<'_, CStr> {
+        Cow::Borrowed(&self.cname)
+    }
+
+    fn interned_cstr(&self) -> Option<&CStr> {
+        Some(&self.cname)
+    }
+}
+
+impl VMWrapper {
+    /// Gets the [ModuleId] for [name], which is the same every time it's asked for on this VM
+    pub fn intern_module<N: AsRef<str>>(&self, name: N) -> ModuleId {
+        self.execute(|vm| {
+            vm.user_data().modules.borrow_mut()
+                .entry(name.as_ref().to_string())
+                .or_insert_with(|| ModuleId::new(name.as_ref()))
+                .clone()
+        })
+    }
+
+    /// Calls static method [signature] of [class] in [module], with arguments already in slots 1 and up
+    ///
+    /// Overwrites slot 0 with the class.
+    pub fn call_static<M: ModuleName, C: AsRef<str>>(&self, module: M, class: C, signature: FunctionSignature) -> Result<(), VMError> {
+        self.execute(|vm| {
+            vm.ensure_slots(signature.arity() + 1);
+            vm.get_variable(module, class, 0);
+        });
+        self.call(signature)
+    }
+}
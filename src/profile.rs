@@ -0,0 +1,211 @@
+//! Breakdown of where a VM spends its time
+use crate::{VM, VMWrapper, VMError, FunctionSignature, ErrorKind};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Time spent in one foreign method
+#[derive(Debug, Clone, Default)]
+pub struct ForeignTiming {
+    pub calls: usize,
+    /// Includes any Wren code the method called back into
+    pub total: Duration,
+}
+
+/// What kind of code a [CallInfo] describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallKind {
+    Interpret,
+    /// A call from Rust into a Wren method
+    Call,
+    /// A call from Wren into a foreign method
+    Foreign,
+}
+
+/// One interpret, call or foreign method, handed to the [crate::VMConfig::call_observer] once it's done
+#[derive(Debug, Clone)]
+pub struct CallInfo {
+    pub kind: CallKind,
+    /// The module interpreted, the signature called, or "Class.signature" of a foreign method
+    pub name: String,
+    /// Includes any calls nested inside it
+    pub duration: Duration,
+    /// The kind of error the call failed with, if it did (a foreign method that panicked is a [ErrorKind::Runtime])
+    pub result: Result<(), ErrorKind>,
+}
+
+/// Where a VM spent its time over a [ProfilingSession]
+#[derive(Debug, Clone, Default)]
+pub struct ProfileReport {
+    /// Wall time since the session started
+    pub wall: Duration,
+    /// Time spent running Wren code
+    ///
+    /// Wren doesn't report the collections it starts on its own, so these count as script time.
+    pub script: Duration,
+    /// Time spent in foreign methods
+    pub foreign: Duration,
+    /// Time spent in collections started by [VMWrapper::collect_garbage]
+    pub gc: Duration,
+    /// Time spent in each foreign method, keyed (and sorted) by "Class.signature"
+    pub foreign_methods: BTreeMap<String, ForeignTiming>,
+}
+
+impl ProfileReport {
+    /// Time spent outside of the VM altogether
+    pub fn host(&self) -> Duration {
+        self.wall
+            .checked_sub(self.script + self.foreign + self.gc)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Profile {
+    started: Instant,
+    vm_depth: usize,
+    vm_time: Duration,
+    foreign_depth: usize,
+    foreign_time: Duration,
+    gc_time: Duration,
+    foreign_methods: BTreeMap<String, ForeignTiming>,
+}
+
+impl Profile {
+    fn new() -> Profile {
+        Profile {
+            started: Instant::now(),
+            vm_depth: 0,
+            vm_time: Duration::default(),
+            foreign_depth: 0,
+            foreign_time: Duration::default(),
+            gc_time: Duration::default(),
+            foreign_methods: BTreeMap::new(),
+        }
+    }
+
+    fn report(&self) -> ProfileReport {
+        ProfileReport {
+            wall: self.started.elapsed(),
+            script: self.vm_time.checked_sub(self.foreign_time).unwrap_or_default(),
+            foreign: self.foreign_time,
+            gc: self.gc_time,
+            foreign_methods: self.foreign_methods.clone(),
+        }
+    }
+}
+
+/// Records timings for a VM while held (see [VMWrapper::profiling_session])
+pub struct ProfilingSession<'a> {
+    vm: &'a VMWrapper,
+}
+
+impl<'a> ProfilingSession<'a> {
+    pub(crate) fn start(vm: &'a VMWrapper) -> ProfilingSession<'a> {
+        vm.execute(|vm| *vm.user_data().profile.borrow_mut() = Some(Profile::new()));
+        ProfilingSession { vm }
+    }
+
+    /// The timings so far
+    pub fn report(&self) -> ProfileReport {
+        self.vm.execute(|vm| vm.user_data().profile.borrow().as_ref().map(Profile::report).unwrap_or_default())
+    }
+
+    /// Stops recording, and returns the final timings
+    pub fn finish(self) -> ProfileReport {
+        self.report()
+    }
+}
+
+impl<'a> Drop for ProfilingSession<'a> {
+    fn drop(&mut self) {
+        self.vm.execute(|vm| *vm.user_data().profile.borrow_mut() = None);
+    }
+}
+
+impl VM {
+    // Only the outermost interpret/call is timed, as nested ones are already counted
+    pub(crate) fn profile_vm_start(&self) -> Option<Instant> {
+        let mut profile = self.user_data().profile.borrow_mut();
+        profile.as_mut().and_then(|profile| {
+            profile.vm_depth += 1;
+            if profile.vm_depth == 1 { Some(Instant::now()) } else { None }
+        })
+    }
+
+    pub(crate) fn profile_vm_end(&self, start: Option<Instant>) {
+        if let Some(profile) = self.user_data().profile.borrow_mut().as_mut() {
+            profile.vm_depth = profile.vm_depth.saturating_sub(1);
+            if let Some(start) = start {
+                profile.vm_time += start.elapsed();
+            }
+        }
+    }
+
+    pub(crate) fn profile_gc(&self, time: Duration) {
+        if let Some(profile) = self.user_data().profile.borrow_mut().as_mut() {
+            profile.gc_time += time;
+        }
+    }
+
+    #[doc(hidden)] // Used by create_module!
+    pub fn profile_foreign_start(&self) -> Option<Instant> {
+        let mut profile = self.user_data().profile.borrow_mut();
+        match profile.as_mut() {
+            Some(profile) => {
+                profile.foreign_depth += 1;
+                Some(Instant::now())
+            },
+            None => self.call_start(),
+        }
+    }
+
+    #[doc(hidden)] // Used by create_module!
+    pub fn profile_foreign_end<'a, F>(&self, start: Option<Instant>, aborted: bool, method: F) where F: FnOnce() -> (&'a str, FunctionSignature) {
+        let start = match start {
+            Some(start) => start,
+            None => return,
+        };
+        let elapsed = start.elapsed();
+        let observer = self.user_data().call_observer.as_ref();
+        let mut profile = self.user_data().profile.borrow_mut();
+        if profile.is_none() && observer.is_none() {
+            return;
+        }
+        let (class, signature) = method();
+        let name = format!("{}.{}", class, signature.as_wren_string());
+        if let Some(profile) = profile.as_mut() {
+            profile.foreign_depth = profile.foreign_depth.saturating_sub(1);
+            if profile.foreign_depth == 0 {
+                profile.foreign_time += elapsed;
+            }
+            let timing = profile.foreign_methods.entry(name.clone()).or_default();
+            timing.calls += 1;
+            timing.total += elapsed;
+        }
+        drop(profile);
+        if let Some(observer) = observer {
+            observer(CallInfo {
+                kind: CallKind::Foreign,
+                name,
+                duration: elapsed,
+                result: if aborted { Err(ErrorKind::Runtime) } else { Ok(()) },
+            });
+        }
+    }
+
+    // When an observed call started, or None if nothing's observing calls
+    pub(crate) fn call_start(&self) -> Option<Instant> {
+        self.user_data().call_observer.as_ref().map(|_| Instant::now())
+    }
+
+    pub(crate) fn observe_call(&self, start: Option<Instant>, kind: CallKind, name: &str, result: &Result<(), VMError>) {
+        if let (Some(start), Some(observer)) = (start, self.user_data().call_observer.as_ref()) {
+            observer(CallInfo {
+                kind,
+                name: name.to_string(),
+                duration: start.elapsed(),
+                result: result.as_ref().map(|_| ()).map_err(VMError::kind),
+            });
+        }
+    }
+}
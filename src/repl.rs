@@ -0,0 +1,115 @@
+//! Running Wren a line at a time, for consoles and debugging overlays
+//!
+//! ```ignore
+//! let mut repl = Repl::new(vm);
+//! for line in std::io::stdin().lines() {
+//!     match repl.feed(&line?) {
+//!         Ok(ReplOutput::Value(value)) => println!("{}", value),
+//!         Ok(ReplOutput::Incomplete) => print!("... "),
+//!         Ok(ReplOutput::Ran) => {},
+//!         Err(e) => eprintln!("{}", e),
+//!     }
+//! }
+//! ```
+use crate::{VMWrapper, VMError};
+use crate::lexer::{Token, Tokens};
+
+// Input starting with one of these is run as statements, and anything else is echoed as an expression
+const STATEMENT_KEYWORDS: &[&str] = &["break", "class", "continue", "for", "foreign", "if", "import", "return", "var", "while"];
+
+/// What a line given to a [Repl] did
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplOutput {
+    /// The input so far is unfinished (like an open block or string), so it's waiting for more lines
+    Incomplete,
+    /// The input was an expression, with this value (as its `toString`)
+    Value(String),
+    /// The input was statements, which ran
+    Ran,
+}
+
+/// Runs input a line at a time in one module of a VM, echoing the value of each expression
+#[derive(Debug)]
+pub struct Repl {
+    vm: VMWrapper,
+    module: String,
+    // Lines of unfinished input
+    buffer: String,
+    variables: Vec<String>,
+}
+
+impl Repl {
+    /// Runs input in the module "main" of [vm]
+    pub fn new(vm: VMWrapper) -> Repl {
+        Repl { vm, module: "main".to_string(), buffer: String::new(), variables: vec![] }
+    }
+
+    /// Runs input in [module] instead
+    pub fn module<M: Into<String>>(mut self, module: M) -> Self {
+        self.module = module.into();
+        self
+    }
+
+    pub fn vm(&self) -> &VMWrapper {
+        &self.vm
+    }
+
+    /// Adds [line] to the input, and runs it once it's complete
+    ///
+    /// Expressions are evaluated and their values returned; anything starting with a keyword
+    /// like `var` or `class` is run as statements. Errors drop the input they came from.
+    pub fn feed<L: AsRef<str>>(&mut self, line: L) -> Result<ReplOutput, VMError> {
+        self.buffer.push_str(line.as_ref());
+        self.buffer.push('\n');
+        if !is_complete(&self.buffer) {
+            return Ok(ReplOutput::Incomplete);
+        }
+        let input = std::mem::take(&mut self.buffer);
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(ReplOutput::Ran);
+        }
+
+        let first_word = input.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').next().unwrap_or_default();
+        if !STATEMENT_KEYWORDS.contains(&first_word) {
+            return self.vm.interpret_returning(&self.module, format!("({}).toString", input)).map(ReplOutput::Value);
+        }
+        self.vm.interpret(&self.module, input)?;
+        for name in crate::hot_reload::top_level_names(input) {
+            if !self.variables.contains(&name) && self.vm.execute(|vm| vm.has_variable(&self.module, &name)) {
+                self.variables.push(name);
+            }
+        }
+        Ok(ReplOutput::Ran)
+    }
+
+    /// Whether earlier lines are waiting for the rest of their input
+    pub fn is_incomplete(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// Drops unfinished input
+    pub fn cancel(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Every top-level variable (and class) defined through the REPL, in the order they were defined
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+}
+
+// Whether [source] closes every bracket, string and comment it opens (extra closing brackets are
+// left for Wren to complain about)
+fn is_complete(source: &str) -> bool {
+    let mut tokens = Tokens::new(source);
+    let mut depth = 0isize;
+    for token in tokens.by_ref() {
+        match token {
+            Token::Open(_) => depth += 1,
+            Token::Close(_) => depth -= 1,
+            _ => {},
+        }
+    }
+    depth <= 0 && !tokens.unterminated()
+}
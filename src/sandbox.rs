@@ -0,0 +1,94 @@
+//! A vetted preset for running untrusted scripts, and a report of what a VM enforces
+//!
+//! ```ignore
+//! let vm = VMConfig::sandboxed().library(&lib).pure_module("math").build();
+//! println!("{}", vm.sandbox_report());
+//! ```
+use crate::{VMConfig, VMWrapper, StringPolicy, Capability};
+use std::fmt;
+
+/// Limits Wren gives an embedder no way to enforce
+///
+/// Wren can't take a failed allocation, so [VMConfig::max_heap_size] is only checked when a
+/// foreign method is called, and a loop of pure Wren can still use as much memory as it likes.
+const UNENFORCEABLE: &[&str] = &["total memory", "execution time"];
+
+/// What a VM restricts scripts to, as it was built (see [VMWrapper::sandbox_report])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxReport {
+    /// Whether the VM is [pure](VMConfig::pure_mode)
+    pub pure: bool,
+    /// Library modules scripts can import, in the order they were registered (this VM's own last)
+    pub library_modules: Vec<String>,
+    /// Whether imports of anything else go to the script loader and script cache
+    pub loads_scripts: bool,
+    /// Capabilities granted to the VM or any module in it
+    pub capabilities: Vec<Capability>,
+    pub string_policy: StringPolicy,
+    pub max_call_depth: usize,
+    /// The [heap limit](VMConfig::max_heap_size) in bytes, if there is one
+    pub max_heap_size: Option<usize>,
+    /// Limits that aren't enforced, because Wren can't enforce them
+    pub unenforced: Vec<&'static str>,
+}
+
+impl SandboxReport {
+    /// Whether the VM is as locked down as [VMConfig::sandboxed] makes it
+    pub fn is_sandboxed(&self) -> bool {
+        self.pure && !self.loads_scripts && self.capabilities.is_empty() && self.string_policy == StringPolicy::Strict
+    }
+}
+
+impl fmt::Display for SandboxReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "pure: {}", self.pure)?;
+        writeln!(fmt, "library modules: {}", self.library_modules.join(", "))?;
+        writeln!(fmt, "loads scripts: {}", self.loads_scripts)?;
+        writeln!(fmt, "capabilities: {:?}", self.capabilities)?;
+        writeln!(fmt, "string policy: {:?}", self.string_policy)?;
+        writeln!(fmt, "max call depth: {}", self.max_call_depth)?;
+        match self.max_heap_size {
+            Some(bytes) => writeln!(fmt, "max heap size: {} bytes", bytes)?,
+            None => writeln!(fmt, "max heap size: unlimited")?,
+        }
+        writeln!(fmt, "foreign method panics: abort the calling fiber")?;
+        writeln!(fmt, "not enforced: {}", self.unenforced.join(", "))
+    }
+}
+
+impl VMConfig {
+    /// A [pure](VMConfig::pure_mode) VM with the [Strict](StringPolicy::Strict) string policy, for
+    /// scripts that aren't trusted at all
+    ///
+    /// Library modules still have to be allowed one by one with [VMConfig::pure_module], which
+    /// makes that the import allowlist. Foreign method panics abort the calling fiber, as they
+    /// do in every VM. Memory and execution time can't be limited, since Wren has no way to stop a
+    /// script that's allocating or looping without calling out to Rust. Check
+    /// [VMWrapper::sandbox_report] for what the built VM enforces.
+    pub fn sandboxed() -> VMConfig {
+        VMConfig::new()
+            .pure_mode(true)
+            .string_policy(StringPolicy::Strict)
+    }
+}
+
+impl VMWrapper {
+    /// What this VM restricts scripts to
+    pub fn sandbox_report(&self) -> SandboxReport {
+        self.execute(|vm| {
+            let ud = vm.user_data();
+            SandboxReport {
+                pure: ud.pure,
+                library_modules: ud.library.iter().map(|lib| &**lib).chain(Some(&*ud.overlay.borrow()))
+                    .flat_map(|lib| lib.modules.keys().cloned())
+                    .collect(),
+                loads_scripts: !ud.pure,
+                capabilities: Capability::ALL.iter().copied().filter(|&cap| ud.capabilities.is_granted_anywhere(cap)).collect(),
+                string_policy: ud.string_policy,
+                max_call_depth: ud.max_call_depth,
+                max_heap_size: ud.max_heap_size,
+                unenforced: UNENFORCEABLE.to_vec(),
+            }
+        })
+    }
+}
@@ -0,0 +1,184 @@
+//! wren-cli's `scheduler` and `timer` modules, run on game time by a [SchedulerPump]
+//!
+//! Scripts written for wren-cli work as they are:
+//!
+//! ```wren
+//! import "scheduler" for Scheduler
+//! import "timer" for Timer
+//! Scheduler.add { System.print("while the main fiber sleeps") }
+//! Timer.sleep(500)
+//! System.print("half a second (of game time) later")
+//! ```
+//!
+//! A sleeping fiber is suspended, so the interpret (or call) that started it returns, and the
+//! fiber carries on from whichever [SchedulerPump::tick] its timer runs out in.
+use crate::{VMWrapper, UserData, FunctionSignature, TickReport};
+use wren_sys::{WrenVM, WrenHandle};
+use std::time::{Duration, Instant};
+use std::{ffi, os::raw};
+
+pub(crate) const SCHEDULER_MODULE: &str = "scheduler";
+pub(crate) const TIMER_MODULE: &str = "timer";
+
+pub(crate) const SCHEDULER_SOURCE: &str = "class Scheduler {
+    static add(callable) {
+        if (__scheduled == null) __scheduled = []
+        __scheduled.add(Fiber.new {
+            callable.call()
+            runNextScheduled_()
+        })
+    }
+
+    static resume_(fiber) { fiber.transfer() }
+    static resume_(fiber, arg) { fiber.transfer(arg) }
+    static resumeError_(fiber, error) { fiber.transferError(error) }
+
+    static await_(fn) {
+        fn.call()
+        return Scheduler.runNextScheduled_()
+    }
+
+    static runNextScheduled_() {
+        if (__scheduled == null || __scheduled.isEmpty) {
+            return Fiber.suspend()
+        } else {
+            return __scheduled.removeAt(0).transfer()
+        }
+    }
+
+    // Starts the fibers added since the last tick, from SchedulerPump::tick
+    static pump_() {
+        if (__scheduled != null && !__scheduled.isEmpty) __scheduled.removeAt(0).transfer()
+    }
+}
+";
+
+pub(crate) const TIMER_SOURCE: &str = "import \"scheduler\" for Scheduler
+
+class Timer {
+    static sleep(milliseconds) {
+        if (!(milliseconds is Num)) Fiber.abort(\"Milliseconds must be a number.\")
+        if (milliseconds < 0) Fiber.abort(\"Milliseconds cannot be negative.\")
+        return Scheduler.await_ { startTimer_(milliseconds, Fiber.current) }
+    }
+
+    foreign static startTimer_(milliseconds, fiber)
+}
+";
+
+/// Fibers sleeping in `Timer.sleep`, and the game time they're measured against
+#[derive(Debug, Default)]
+pub(crate) struct Timers {
+    now: Duration,
+    // Sleeping fibers, kept sorted by when they wake (ties in the order they went to sleep)
+    sleeping: Vec<(Duration, *mut WrenHandle)>,
+}
+
+impl Timers {
+    /// Releases every handle, before the VM is freed
+    pub(crate) fn release(&mut self, vm: *mut WrenVM) {
+        for (_, handle) in self.sleeping.drain(..) {
+            unsafe { wren_sys::wrenReleaseHandle(vm, handle) };
+        }
+    }
+
+    // Every fiber due to wake by now
+    fn take_due(&mut self) -> Vec<*mut WrenHandle> {
+        let due = self.sleeping.partition_point(|(wake, _)| *wake <= self.now);
+        self.sleeping.drain(..due).map(|(_, handle)| handle).collect()
+    }
+}
+
+// When a fiber sleeping for [milliseconds] from [now] wakes, or why it can't sleep that long
+fn wake_time(now: Duration, milliseconds: f64) -> Result<Duration, &'static str> {
+    // Timer.sleep has already checked it's a number that isn't negative, but NaN gets past that
+    if !(milliseconds.is_finite() && milliseconds >= 0.0) {
+        return Err("Milliseconds must be a finite, non-negative number.");
+    }
+    Duration::try_from_secs_f64(milliseconds / 1000.0).ok()
+        .and_then(|delay| now.checked_add(delay))
+        .ok_or("Milliseconds is too large.")
+}
+
+pub(crate) extern "C" fn start_timer(vm: *mut WrenVM) {
+    unsafe {
+        let conf = &*(wren_sys::wrenGetUserData(vm) as *const UserData);
+        let mut timers = conf.timers.borrow_mut();
+        let wake = match wake_time(timers.now, wren_sys::wrenGetSlotDouble(vm, 1)) {
+            Ok(wake) => wake,
+            Err(message) => {
+                let message = ffi::CString::new(message).unwrap();
+                wren_sys::wrenSetSlotString(vm, 0, message.as_ptr());
+                wren_sys::wrenAbortFiber(vm, 0);
+                return;
+            },
+        };
+        let handle = wren_sys::wrenGetSlotHandle(vm, 2 as raw::c_int);
+        let index = timers.sleeping.partition_point(|(other, _)| *other <= wake);
+        timers.sleeping.insert(index, (wake, handle));
+    }
+}
+
+/// Drives `Timer.sleep` and `Scheduler.add` for a VM, from the embedding's game loop
+///
+/// Time only passes when [SchedulerPump::tick] says it does, so sleeping scripts pause along with the game.
+#[derive(Debug, Clone)]
+pub struct SchedulerPump {
+    vm: VMWrapper,
+}
+
+impl SchedulerPump {
+    pub fn new(vm: &VMWrapper) -> SchedulerPump {
+        SchedulerPump { vm: vm.clone() }
+    }
+
+    /// Moves game time on by [dt], waking every fiber whose sleep has run out (in the order they
+    /// were due), then starts the fibers scripts have added to the `Scheduler`
+    ///
+    /// Fibers that go to sleep while ticking wait for the next tick, even with `Timer.sleep(0)`.
+    pub fn tick(&self, dt: Duration) -> TickReport {
+        let start = Instant::now();
+        let mut report = TickReport::default();
+        let due = self.vm.execute(|vm| {
+            let mut timers = vm.user_data().timers.borrow_mut();
+            timers.now += dt;
+            timers.take_due()
+        });
+        let loaded = self.vm.execute(|vm| vm.has_module(SCHEDULER_MODULE));
+        for fiber in due {
+            let resume = self.vm.execute(|vm| unsafe {
+                vm.ensure_slots(2);
+                vm.get_variable(SCHEDULER_MODULE, "Scheduler", 0);
+                wren_sys::wrenSetSlotHandle(vm.vm, 1, fiber);
+                wren_sys::wrenReleaseHandle(vm.vm, fiber);
+                vm.cached_call_handle(FunctionSignature::new_function("resume_", 1))
+            });
+            if let Err(err) = self.vm.call_raw(resume, "resume_(_)") {
+                report.errors.push(err);
+            }
+            report.ran += 1;
+        }
+        if loaded {
+            let pump = self.vm.execute(|vm| {
+                vm.ensure_slots(1);
+                vm.get_variable(SCHEDULER_MODULE, "Scheduler", 0);
+                vm.cached_call_handle(FunctionSignature::new_function("pump_", 0))
+            });
+            if let Err(err) = self.vm.call_raw(pump, "pump_()") {
+                report.errors.push(err);
+            }
+        }
+        report.elapsed = start.elapsed();
+        report
+    }
+
+    /// How much game time has been ticked through
+    pub fn now(&self) -> Duration {
+        self.vm.execute(|vm| vm.user_data().timers.borrow().now)
+    }
+
+    /// How many fibers are sleeping
+    pub fn sleeping(&self) -> usize {
+        self.vm.execute(|vm| vm.user_data().timers.borrow().sleeping.len())
+    }
+}
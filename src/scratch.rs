@@ -0,0 +1,191 @@
+//! A bump allocator for the short-lived C strings ruwren passes to Wren
+//!
+//! Every variable lookup or interpret needs its strings NUL-terminated, which used to mean a
+//! fresh CString each time. While a call is running, those go into reusable chunks instead,
+//! which are all freed at once when the outermost call returns. Strings too big for a chunk
+//! (like a whole script) are staged in buffers of their own, which are kept for the next call
+//! to reuse. Outside any call there's no point to reset at, so strings are heap-allocated as before.
+use crate::{VM, VMWrapper, ModuleName};
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+const CHUNK_SIZE: usize = 4096;
+// Chunks kept across resets; any more are freed
+const RETAINED_CHUNKS: usize = 4;
+// Staging buffers kept across resets, as long as they're no bigger than this
+const RETAINED_BUFFERS: usize = 4;
+const RETAINED_BUFFER_SIZE: usize = 1 << 20;
+
+/// How much marshalling the scratch allocator has saved (see [VMWrapper::scratch_stats])
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScratchStats {
+    /// Strings that went into scratch memory that was already allocated, instead of their own heap allocation
+    pub allocations_avoided: u64,
+    /// Bytes of those strings, including their NULs
+    pub bytes_saved: u64,
+    /// Times the scratch allocator had to allocate memory itself, for a new chunk or staging buffer
+    pub scratch_allocations: u64,
+    /// Strings made outside a call, which went to the heap
+    pub heap_fallbacks: u64,
+    /// How many times the scratch space has been freed, once per outermost call
+    pub resets: u64,
+}
+
+type Buffer = Box<[Cell<u8>]>;
+
+fn buffer(len: usize) -> Buffer {
+    (0..len).map(|_| Cell::new(0)).collect()
+}
+
+// Copies [bytes] and a NUL into [buffer], which is at least one byte longer than them
+fn fill(buffer: &[Cell<u8>], bytes: &[u8]) -> *const c_char {
+    for (cell, &byte) in buffer.iter().zip(bytes.iter().chain(&[0])) {
+        cell.set(byte);
+    }
+    buffer.as_ptr() as *const c_char
+}
+
+#[derive(Default)]
+pub(crate) struct Scratch {
+    // Boxed so that handing out a pointer into one stays valid when more are added, and Cells so
+    // that writing to a chunk never needs a mutable borrow of memory Wren might be reading
+    chunks: RefCell<Vec<Buffer>>,
+    chunk: Cell<usize>,
+    used: Cell<usize>,
+    // One string each, for strings too big for a chunk. The first [staged] are in use.
+    buffers: RefCell<Vec<Buffer>>,
+    staged: Cell<usize>,
+    stats: Cell<ScratchStats>,
+}
+
+/// A NUL-terminated string, in the scratch space, on the heap, or already converted
+pub(crate) enum ScratchCStr<'a> {
+    Scratch(*const c_char),
+    Heap(CString),
+    Borrowed(&'a CStr),
+}
+
+impl ScratchCStr<'_> {
+    pub(crate) fn as_ptr(&self) -> *const c_char {
+        match self {
+            ScratchCStr::Scratch(ptr) => *ptr,
+            ScratchCStr::Heap(cstr) => cstr.as_ptr(),
+            ScratchCStr::Borrowed(cstr) => cstr.as_ptr(),
+        }
+    }
+}
+
+impl Scratch {
+    // Copies [bytes] and a NUL into the current chunk (moving on to the next if it's full), or
+    // into a staging buffer if they don't fit in one. Also returns whether that took a new allocation.
+    fn alloc(&self, bytes: &[u8]) -> (*const c_char, bool) {
+        let len = bytes.len() + 1;
+        if len > CHUNK_SIZE {
+            return self.stage(bytes);
+        }
+        let mut chunks = self.chunks.borrow_mut();
+        let mut allocated = false;
+        if chunks.is_empty() || self.used.get() + len > CHUNK_SIZE {
+            if !chunks.is_empty() {
+                self.chunk.set(self.chunk.get() + 1);
+            }
+            if self.chunk.get() == chunks.len() {
+                chunks.push(buffer(CHUNK_SIZE));
+                allocated = true;
+            }
+            self.used.set(0);
+        }
+        let start = self.used.get();
+        self.used.set(start + len);
+        (fill(&chunks[self.chunk.get()][start..start + len], bytes), allocated)
+    }
+
+    // Copies [bytes] and a NUL into the next staging buffer, replacing it if it's too small
+    fn stage(&self, bytes: &[u8]) -> (*const c_char, bool) {
+        let len = bytes.len() + 1;
+        let mut buffers = self.buffers.borrow_mut();
+        let index = self.staged.get();
+        self.staged.set(index + 1);
+        let allocated = match buffers.get(index) {
+            Some(old) if old.len() >= len => false,
+            // Buffers past the staged ones aren't in use, so it's fine to replace one
+            Some(_) => {
+                buffers[index] = buffer(len);
+                true
+            },
+            None => {
+                buffers.push(buffer(len));
+                true
+            },
+        };
+        (fill(&buffers[index], bytes), allocated)
+    }
+
+    // Frees everything at once. Only safe when nothing handed out is still in use.
+    pub(crate) fn reset(&self) {
+        if self.chunk.get() == 0 && self.used.get() == 0 && self.staged.get() == 0 {
+            return;
+        }
+        self.chunks.borrow_mut().truncate(RETAINED_CHUNKS);
+        self.chunk.set(0);
+        self.used.set(0);
+        let mut buffers = self.buffers.borrow_mut();
+        buffers.retain(|buffer| buffer.len() <= RETAINED_BUFFER_SIZE);
+        buffers.truncate(RETAINED_BUFFERS);
+        self.staged.set(0);
+        let mut stats = self.stats.get();
+        stats.resets += 1;
+        self.stats.set(stats);
+    }
+}
+
+impl VM {
+    /// Converts [string] for Wren, in the scratch space if a call is running
+    ///
+    /// # Panics
+    /// If [string] contains a NUL byte (with [what] in the message)
+    pub(crate) fn scratch_cstr(&self, string: &str, what: &str) -> ScratchCStr<'static> {
+        if string.contains('\0') {
+            panic!("{} conversion failed", what);
+        }
+        let ud = self.user_data();
+        let scratch = &ud.scratch;
+        let mut stats = scratch.stats.get();
+        let cstr = match ud.call_depth.get() {
+            0 => {
+                stats.heap_fallbacks += 1;
+                ScratchCStr::Heap(CString::new(string).unwrap())
+            },
+            _ => {
+                let (ptr, allocated) = scratch.alloc(string.as_bytes());
+                if allocated {
+                    stats.scratch_allocations += 1;
+                } else {
+                    stats.allocations_avoided += 1;
+                    stats.bytes_saved += string.len() as u64 + 1;
+                }
+                ScratchCStr::Scratch(ptr)
+            },
+        };
+        scratch.stats.set(stats);
+        cstr
+    }
+}
+
+impl VM {
+    /// Converts the name of [module] for Wren, unless it's a [crate::ModuleId] that already has been
+    pub(crate) fn module_cstr<'a, M: ModuleName>(&self, module: &'a M) -> ScratchCStr<'a> {
+        match module.interned_cstr() {
+            Some(cstr) => ScratchCStr::Borrowed(cstr),
+            None => self.scratch_cstr(module.module_name(), "module name"),
+        }
+    }
+}
+
+impl VMWrapper {
+    /// How many heap allocations, and bytes, the scratch allocator has saved string marshalling
+    pub fn scratch_stats(&self) -> ScratchStats {
+        self.execute(|vm| vm.user_data().scratch.stats.get())
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::CString;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// Module sources shared between VMs, so hosts that build many short-lived VMs from the same
+/// scripts only load and check each one once
+///
+/// Wren can't save compiled modules, so each VM still compiles what it imports; the cache only
+/// skips the script loader (and converting its output for Wren). Sources are stored by hash, so
+/// modules with identical source share one copy. Clones share the same cache.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptCache {
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    names: HashMap<String, Arc<CString>>,
+    // For finding identical sources
+    sources: HashMap<u64, Shared>,
+    hits: usize,
+    misses: usize,
+}
+
+// A source, and how many module names it's cached under. VMs that are loading it hold clones
+// too, so the Arc's own count can't say whether any names are left.
+#[derive(Debug)]
+struct Shared {
+    source: Arc<CString>,
+    names: usize,
+}
+
+impl CacheInner {
+    // Drops one name's claim on [source], forgetting it once no names are left
+    fn release(&mut self, source: &Arc<CString>) {
+        let key = hash(source);
+        if let Some(shared) = self.sources.get_mut(&key).filter(|shared| Arc::ptr_eq(&shared.source, source)) {
+            shared.names -= 1;
+            if shared.names == 0 {
+                self.sources.remove(&key);
+            }
+        }
+    }
+}
+
+impl ScriptCache {
+    pub fn new() -> ScriptCache {
+        ScriptCache::default()
+    }
+
+    /// Forgets [module], so the next VM to import it loads it again
+    pub fn invalidate<M: AsRef<str>>(&self, module: M) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(source) = inner.names.remove(module.as_ref()) {
+            inner.release(&source);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.names.clear();
+        inner.sources.clear();
+    }
+
+    /// How many distinct sources are cached
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many imports were served from the cache
+    pub fn hits(&self) -> usize {
+        self.inner.lock().unwrap().hits
+    }
+
+    /// How many imports had to be loaded
+    pub fn misses(&self) -> usize {
+        self.inner.lock().unwrap().misses
+    }
+
+    pub(crate) fn get(&self, module: &str) -> Option<Arc<CString>> {
+        let mut inner = self.inner.lock().unwrap();
+        let source = inner.names.get(module).cloned();
+        match source {
+            Some(_) => inner.hits += 1,
+            None => inner.misses += 1,
+        }
+        source
+    }
+
+    pub(crate) fn insert(&self, module: &str, source: CString) -> Arc<CString> {
+        let mut inner = self.inner.lock().unwrap();
+        let key = hash(&source);
+        let source = match inner.sources.get_mut(&key) {
+            Some(shared) if *shared.source == source => {
+                shared.names += 1;
+                shared.source.clone()
+            },
+            // A different source with the same hash, which is cached without being shared
+            Some(_) => Arc::new(source),
+            None => {
+                let source = Arc::new(source);
+                inner.sources.insert(key, Shared { source: source.clone(), names: 1 });
+                source
+            },
+        };
+        if let Some(replaced) = inner.names.insert(module.to_string(), source.clone()) {
+            inner.release(&replaced);
+        }
+        source
+    }
+}
+
+fn hash(source: &CString) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,679 @@
+//! Writing any `Serialize` value into a slot, and reading any `Deserialize` one out, with the `serde` feature
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Config { title: String, players: Vec<String> }
+//!
+//! vm.set_slot_serialize(1, &config)?;
+//! let config: Config = vm.get_slot_deserialize(1)?;
+//! ```
+//!
+//! Values come out the way serde_json would write them: structs and maps become maps, sequences
+//! and tuples become lists, `None` and `()` become null, and bytes become a list of numbers. Enum
+//! variants are strings when they hold nothing, and otherwise a map of their name to their contents.
+//!
+//! Reading goes the other way, except that Wren can't list a map's keys from Rust, so a Wren map
+//! can only be read into a struct or enum, whose field and variant names are looked up in it.
+use crate::{VM, SlotId, SlotType, WrenValue, WrenMap, ConversionError};
+use ::serde::ser::{self, Serialize};
+use ::serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use std::fmt::Display;
+
+impl ser::Error for ConversionError {
+    fn custom<T: Display>(msg: T) -> Self {
+        ConversionError::Serialize { message: msg.to_string() }
+    }
+}
+
+impl de::Error for ConversionError {
+    fn custom<T: Display>(msg: T) -> Self {
+        ConversionError::Deserialize { path: String::new(), message: msg.to_string() }
+    }
+}
+
+impl ConversionError {
+    // Says where an error happened, unless something further in already has
+    fn at(self, path: &str) -> Self {
+        match self {
+            ConversionError::Deserialize { path: inner, message } if inner.is_empty() => {
+                ConversionError::Deserialize { path: path.to_string(), message }
+            },
+            error => error,
+        }
+    }
+}
+
+/// Converts [value] into a [WrenValue]
+///
+/// Fails if [value]'s own Serialize impl does, or if it has a map key Wren can't use (a list or map).
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<WrenValue, ConversionError> {
+    value.serialize(Serializer)
+}
+
+impl VM {
+    /// Writes [value] into [slot], as nested Wren lists, maps, strings and numbers (see [to_value])
+    pub fn set_slot_serialize<T: Serialize + ?Sized>(&self, slot: SlotId, value: &T) -> Result<(), ConversionError> {
+        self.set_slot_value(slot, &to_value(value)?);
+        Ok(())
+    }
+
+    /// Reads the value in [slot] into a T, walking any lists and maps in it
+    ///
+    /// Uses slots past the current slot count as scratch space, one more for each level of nesting.
+    /// Errors say where in the value they happened, like `players[1].name`.
+    pub fn get_slot_deserialize<T: DeserializeOwned>(&self, slot: SlotId) -> Result<T, ConversionError> {
+        let scratch = self.get_slot_count().max(slot + 1);
+        T::deserialize(Deserializer { vm: self, slot, scratch, path: String::new() })
+    }
+}
+
+/// A serde Serializer that produces [WrenValue]s
+pub struct Serializer;
+
+// An enum variant wraps its contents in a map of its name to them
+fn variant(name: &'static str, value: WrenValue) -> WrenValue {
+    let mut map = WrenMap::new();
+    map.insert(name, value);
+    WrenValue::Map(map)
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<WrenValue, ConversionError> {
+        if v.unsigned_abs() as f64 > crate::audit::MAX_SAFE_INTEGER {
+            audit!("Serialized {} is too big for a Wren number, so it became {}", v, v as f64);
+        }
+        Ok(WrenValue::Num(v as f64))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<WrenValue, ConversionError> {
+        if v as f64 > crate::audit::MAX_SAFE_INTEGER {
+            audit!("Serialized {} is too big for a Wren number, so it became {}", v, v as f64);
+        }
+        Ok(WrenValue::Num(v as f64))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Num(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<WrenValue, ConversionError> {
+        Ok(v.iter().map(|&byte| WrenValue::Num(byte.into())).collect::<Vec<_>>().into())
+    }
+
+    fn serialize_none(self) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<WrenValue, ConversionError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<WrenValue, ConversionError> {
+        Ok(WrenValue::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<WrenValue, ConversionError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, name: &'static str, value: &T) -> Result<WrenValue, ConversionError> {
+        Ok(variant(name, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, ConversionError> {
+        Ok(SerializeList { items: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, ConversionError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeList, ConversionError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, name: &'static str, len: usize) -> Result<SerializeList, ConversionError> {
+        Ok(SerializeList { items: Vec::with_capacity(len), variant: Some(name) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, ConversionError> {
+        Ok(SerializeMap { map: WrenMap::new(), key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap, ConversionError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, name: &'static str, _len: usize) -> Result<SerializeMap, ConversionError> {
+        Ok(SerializeMap { map: WrenMap::new(), key: None, variant: Some(name) })
+    }
+}
+
+/// Builds a list for [Serializer]
+pub struct SerializeList {
+    items: Vec<WrenValue>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeList {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<WrenValue, ConversionError> {
+        let list = WrenValue::from(self.items);
+        Ok(match self.variant {
+            Some(name) => variant(name, list),
+            None => list,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<WrenValue, ConversionError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<WrenValue, ConversionError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<WrenValue, ConversionError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<WrenValue, ConversionError> {
+        self.finish()
+    }
+}
+
+/// Builds a map for [Serializer]
+pub struct SerializeMap {
+    map: WrenMap,
+    key: Option<WrenValue>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeMap {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: WrenValue, value: &T) -> Result<(), ConversionError> {
+        if let WrenValue::List(_) | WrenValue::Map(_) = key {
+            return Err(ConversionError::Unrepresentable { format: "Wren", value: format!("{} as a map key", key) });
+        }
+        self.map.insert(key, value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<WrenValue, ConversionError> {
+        let map = WrenValue::Map(self.map);
+        Ok(match self.variant {
+            Some(name) => variant(name, map),
+            None => map,
+        })
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ConversionError> {
+        self.key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<WrenValue, ConversionError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), ConversionError> {
+        self.insert(WrenValue::from(key), value)
+    }
+
+    fn end(self) -> Result<WrenValue, ConversionError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = WrenValue;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), ConversionError> {
+        self.insert(WrenValue::from(key), value)
+    }
+
+    fn end(self) -> Result<WrenValue, ConversionError> {
+        self.finish()
+    }
+}
+
+/// A serde Deserializer that reads the value in a slot (see [VM::get_slot_deserialize])
+pub struct Deserializer<'a> {
+    vm: &'a VM,
+    slot: SlotId,
+    // The first slot free for nested values
+    scratch: SlotId,
+    path: String,
+}
+
+impl<'a> Deserializer<'a> {
+    // A deserializer for a value nested in this one, which has to be loaded into the returned slot
+    fn child(&self, path: String) -> Deserializer<'a> {
+        self.vm.ensure_slots(self.scratch + 2);
+        Deserializer { vm: self.vm, slot: self.scratch, scratch: self.scratch + 1, path }
+    }
+
+    fn field_path(&self, field: &str) -> String {
+        match self.path.as_str() {
+            "" => field.to_string(),
+            parent => format!("{}.{}", parent, field),
+        }
+    }
+
+    fn slot_type(&self) -> SlotType {
+        self.vm.get_slot_type(self.slot)
+    }
+
+    fn unexpected(&self) -> Unexpected<'static> {
+        match self.slot_type() {
+            SlotType::Null => Unexpected::Unit,
+            SlotType::Bool => Unexpected::Bool(self.vm.get_slot_bool(self.slot).unwrap()),
+            SlotType::Num => Unexpected::Float(self.vm.get_slot_double(self.slot).unwrap()),
+            SlotType::String => Unexpected::Other("string"),
+            SlotType::List => Unexpected::Seq,
+            SlotType::Map => Unexpected::Map,
+            SlotType::Foreign => Unexpected::Other("foreign object"),
+            SlotType::Unknown => Unexpected::Other("Wren object"),
+        }
+    }
+
+    fn invalid_type<V: Visitor<'a>>(&self, visitor: &V) -> ConversionError {
+        de::Error::invalid_type(self.unexpected(), visitor)
+    }
+
+    fn string(&self) -> Result<String, ConversionError> {
+        self.vm.get_slot_string(self.slot).ok_or_else(|| de::Error::custom("string isn't valid UTF-8"))
+    }
+
+    // Looks [key] up in the map in this slot, loading its value into [value]'s slot
+    fn map_lookup(&self, key: &str, value: &Deserializer) -> bool {
+        let key_slot = value.slot + 1;
+        self.vm.set_slot_string(key_slot, key);
+        if !self.vm.get_map_contains_key(self.slot, key_slot) {
+            return false;
+        }
+        self.vm.get_map_value(self.slot, key_slot, value.slot);
+        true
+    }
+
+    fn integer<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.vm.get_slot_double(self.slot) {
+            Some(n) if n.fract() == 0.0 && n >= 0.0 && n <= u64::MAX as f64 => visitor.visit_u64(n as u64),
+            Some(n) if n.fract() == 0.0 && n >= i64::MIN as f64 => visitor.visit_i64(n as i64),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = ConversionError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::Null => visitor.visit_unit(),
+            SlotType::Bool => visitor.visit_bool(self.vm.get_slot_bool(self.slot).unwrap()),
+            SlotType::Num => match self.vm.get_slot_double(self.slot).unwrap() {
+                n if n.fract() == 0.0 && n.abs() <= crate::audit::MAX_SAFE_INTEGER => visitor.visit_i64(n as i64),
+                n => visitor.visit_f64(n),
+            },
+            SlotType::String => visitor.visit_string(self.string()?),
+            SlotType::List => self.deserialize_seq(visitor),
+            SlotType::Map => Err(de::Error::custom("Wren can't list a map's keys, so maps can only be read into structs and enums")),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.vm.get_slot_bool(self.slot) {
+            Some(b) => visitor.visit_bool(b),
+            None => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.integer(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.vm.get_slot_double(self.slot) {
+            Some(n) => visitor.visit_f64(n),
+            None => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::String => visitor.visit_string(self.string()?),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    /// A string's raw bytes, or a list of numbers
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::String => visitor.visit_byte_buf(self.vm.get_slot_bytes(self.slot).unwrap()),
+            _ => self.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::Null => visitor.visit_unit(),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::List => {
+                let len = self.vm.get_list_count(self.slot);
+                visitor.visit_seq(ListAccess { list: self, index: 0, len })
+            },
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::Map => visitor.visit_map(FieldAccess { map: self, fields: fields.iter(), value: None }),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, ConversionError> {
+        match self.slot_type() {
+            SlotType::String => visitor.visit_enum(self.string()?.into_deserializer()),
+            SlotType::Map if self.vm.get_map_count(self.slot) == 1 => {
+                for &name in variants {
+                    let contents = self.child(self.field_path(name));
+                    if self.map_lookup(name, &contents) {
+                        return visitor.visit_enum(VariantAccess { name, contents });
+                    }
+                }
+                Err(de::Error::custom(format_args!("no variant of {} found", variants.join(", "))))
+            },
+            _ => Err(self.invalid_type(&visitor)),
+        }.map_err(|error| error.at(&self.path))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConversionError> {
+        visitor.visit_unit()
+    }
+}
+
+struct ListAccess<'a> {
+    list: Deserializer<'a>,
+    index: usize,
+    len: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for ListAccess<'de> {
+    type Error = ConversionError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, ConversionError> {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        let path = format!("{}[{}]", self.list.path, self.index);
+        let element = self.list.child(path.clone());
+        self.list.vm.get_list_element(self.list.slot, self.index as i32, element.slot);
+        self.index += 1;
+        seed.deserialize(element).map(Some).map_err(|error| error.at(&path))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
+
+// A struct's fields, looked up by name since the map's own keys can't be listed
+struct FieldAccess<'a> {
+    map: Deserializer<'a>,
+    fields: std::slice::Iter<'static, &'static str>,
+    value: Option<Deserializer<'a>>,
+}
+
+impl<'de> de::MapAccess<'de> for FieldAccess<'de> {
+    type Error = ConversionError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, ConversionError> {
+        // Missing fields are skipped, for serde to default or complain about
+        for &field in &mut self.fields {
+            let value = self.map.child(self.map.field_path(field));
+            if self.map.map_lookup(field, &value) {
+                self.value = Some(value);
+                return seed.deserialize(field.into_deserializer()).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ConversionError> {
+        let value = self.value.take().expect("next_value_seed called before next_key_seed");
+        let path = value.path.clone();
+        seed.deserialize(value).map_err(|error| error.at(&path))
+    }
+}
+
+struct VariantAccess<'a> {
+    name: &'static str,
+    contents: Deserializer<'a>,
+}
+
+impl<'de> de::EnumAccess<'de> for VariantAccess<'de> {
+    type Error = ConversionError;
+    type Variant = Deserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Deserializer<'de>), ConversionError> {
+        let variant = seed.deserialize(self.name.into_deserializer())?;
+        Ok((variant, self.contents))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer<'de> {
+    type Error = ConversionError;
+
+    fn unit_variant(self) -> Result<(), ConversionError> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, ConversionError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ConversionError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, ConversionError> {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
+    }
+}
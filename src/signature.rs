@@ -0,0 +1,212 @@
+use crate::SignatureError;
+use std::fmt;
+use std::str::FromStr;
+
+/// The most parameters a Wren method can have
+const MAX_PARAMETERS: usize = 16;
+
+const PREFIX_OPERATORS: &[&str] = &["-", "!", "~"];
+const INFIX_OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "%", "<", ">", "<=", ">=", "==", "!=", "&", "|", "^", "<<", ">>", "..", "...", "is",
+];
+const KEYWORDS: &[&str] = &[
+    "break", "class", "construct", "continue", "else", "false", "for", "foreign", "if", "import",
+    "in", "is", "null", "return", "static", "super", "this", "true", "var", "while",
+];
+
+/// The signature Wren looks a method up by, like `move(_,_)`, `name=(_)` or `[_]`
+///
+/// The constructors don't check anything; use [FunctionSignature::parse] or [FunctionSignature::validate]
+/// to make sure Wren could actually bind a signature.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FunctionSignature {
+    /// A method with parameters (including none, like `reset()`), or an infix operator like `+(_)`
+    Function {
+        name: String,
+        arity: usize
+    },
+    /// A getter, or a prefix operator like `-`
+    Getter(String),
+    Setter(String),
+    /// `[_]`, with [arity] parameters between the brackets
+    Subscript {
+        arity: usize
+    },
+    /// `[_]=(_)`, with [arity] parameters between the brackets
+    SubscriptSetter {
+        arity: usize
+    },
+}
+
+impl FunctionSignature {
+    pub fn new_function<N: Into<String>>(name: N, arity: usize) -> FunctionSignature {
+        FunctionSignature::Function {
+            name: name.into(),
+            arity
+        }
+    }
+
+    pub fn new_getter<N: Into<String>>(name: N) -> FunctionSignature {
+        FunctionSignature::Getter(name.into())
+    }
+
+    pub fn new_setter<N: Into<String>>(name: N) -> FunctionSignature {
+        FunctionSignature::Setter(name.into())
+    }
+
+    /// An infix operator like `+(_)`
+    pub fn new_operator<N: Into<String>>(operator: N) -> FunctionSignature {
+        FunctionSignature::new_function(operator, 1)
+    }
+
+    /// A prefix operator like `-`
+    pub fn new_prefix_operator<N: Into<String>>(operator: N) -> FunctionSignature {
+        FunctionSignature::new_getter(operator)
+    }
+
+    pub fn new_subscript(arity: usize) -> FunctionSignature {
+        FunctionSignature::Subscript { arity }
+    }
+
+    pub fn new_subscript_setter(arity: usize) -> FunctionSignature {
+        FunctionSignature::SubscriptSetter { arity }
+    }
+
+    /// Parses a signature the way Wren writes them (`move(_,_)`, `x=(_)`, `[_,_]=(_)`, `+(_)`), and validates it
+    pub fn parse(signature: &str) -> Result<FunctionSignature, SignatureError> {
+        let malformed = || SignatureError::Malformed(signature.to_string());
+        let parsed = if let Some(rest) = signature.strip_prefix('[') {
+            let (params, rest) = rest.split_once(']').ok_or_else(malformed)?;
+            let arity = parameters(params).ok_or_else(malformed)?;
+            match rest {
+                "" => FunctionSignature::Subscript { arity },
+                "=(_)" => FunctionSignature::SubscriptSetter { arity },
+                _ => return Err(malformed()),
+            }
+        } else {
+            let is_identifier = signature.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_');
+            let end = signature.find(|c: char| if is_identifier {
+                !(c.is_ascii_alphanumeric() || c == '_')
+            } else {
+                !"-!~+*/%<>=&|^.".contains(c)
+            }).unwrap_or(signature.len());
+            let (name, rest) = signature.split_at(end);
+            if name.is_empty() {
+                return Err(malformed());
+            }
+            if rest.is_empty() {
+                FunctionSignature::new_getter(name)
+            } else if rest == "=(_)" {
+                FunctionSignature::new_setter(name)
+            } else {
+                let params = rest.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')).ok_or_else(malformed)?;
+                FunctionSignature::new_function(name, parameters(params).ok_or_else(malformed)?)
+            }
+        };
+        parsed.validate()?;
+        Ok(parsed)
+    }
+
+    /// Checks that Wren could declare a method with this signature
+    pub fn validate(&self) -> Result<(), SignatureError> {
+        let invalid_operator = |name: &str| Err(SignatureError::InvalidOperator {
+            name: name.to_string(),
+            signature: self.as_wren_string(),
+        });
+        match self {
+            FunctionSignature::Function { name, arity } if is_operator(name) => {
+                if *arity == 1 && INFIX_OPERATORS.contains(&name.as_str()) { Ok(()) } else { invalid_operator(name) }
+            },
+            FunctionSignature::Getter(name) if is_operator(name) => {
+                if PREFIX_OPERATORS.contains(&name.as_str()) { Ok(()) } else { invalid_operator(name) }
+            },
+            FunctionSignature::Setter(name) if is_operator(name) => invalid_operator(name),
+            FunctionSignature::Function { name, .. } | FunctionSignature::Getter(name) | FunctionSignature::Setter(name) => {
+                if !is_identifier(name) {
+                    return Err(SignatureError::InvalidName(name.clone()));
+                }
+                self.check_arity(name)
+            },
+            FunctionSignature::Subscript { arity } | FunctionSignature::SubscriptSetter { arity } => {
+                if *arity == 0 {
+                    return Err(SignatureError::Malformed(self.as_wren_string()));
+                }
+                self.check_arity("[]")
+            },
+        }
+    }
+
+    fn check_arity(&self, name: &str) -> Result<(), SignatureError> {
+        if self.arity() > MAX_PARAMETERS {
+            Err(SignatureError::TooManyParameters { name: name.to_string(), arity: self.arity() })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn as_wren_string(&self) -> String {
+        match self {
+            FunctionSignature::Function { name, arity } => format!("{}({})", name, vec!["_".to_string(); *arity].join(",")),
+            FunctionSignature::Getter(name) => name.clone(),
+            FunctionSignature::Setter(name) => format!("{}=(_)", name),
+            FunctionSignature::Subscript { arity } => format!("[{}]", vec!["_".to_string(); *arity].join(",")),
+            FunctionSignature::SubscriptSetter { arity } => format!("[{}]=(_)", vec!["_".to_string(); *arity].join(",")),
+        }
+    }
+
+    pub(crate) fn as_wren_declaration(&self) -> String {
+        let args = |arity| (0..arity).map(|i| format!("arg{}", i)).collect::<Vec<_>>().join(", ");
+        match self {
+            FunctionSignature::Function { name, arity } => format!("{}({})", name, args(*arity)),
+            FunctionSignature::Getter(name) => name.clone(),
+            FunctionSignature::Setter(name) => format!("{}=(value)", name),
+            FunctionSignature::Subscript { arity } => format!("[{}]", args(*arity)),
+            FunctionSignature::SubscriptSetter { arity } => format!("[{}]=(value)", args(*arity)),
+        }
+    }
+
+    /// How many arguments the method takes
+    pub fn arity(&self) -> usize {
+        match self {
+            FunctionSignature::Function { arity, .. } | FunctionSignature::Subscript { arity } => *arity,
+            FunctionSignature::Getter(_) => 0,
+            FunctionSignature::Setter(_) => 1,
+            FunctionSignature::SubscriptSetter { arity } => arity + 1,
+        }
+    }
+}
+
+impl fmt::Display for FunctionSignature {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.as_wren_string())
+    }
+}
+
+impl FromStr for FunctionSignature {
+    type Err = SignatureError;
+
+    fn from_str(signature: &str) -> Result<Self, Self::Err> {
+        FunctionSignature::parse(signature)
+    }
+}
+
+// How many parameters a list like "_,_" has
+fn parameters(params: &str) -> Option<usize> {
+    if params.is_empty() {
+        Some(0)
+    } else if params.split(',').all(|param| param == "_") {
+        Some(params.split(',').count())
+    } else {
+        None
+    }
+}
+
+fn is_operator(name: &str) -> bool {
+    PREFIX_OPERATORS.contains(&name) || INFIX_OPERATORS.contains(&name)
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
@@ -0,0 +1,91 @@
+//! Printing what's in a VM's slots, for tracking down "wrong slot" errors in foreign methods
+//!
+//! ```ignore
+//! fn heal(&mut self, vm: &VM) {
+//!     eprintln!("{:#?}", vm.dump_slots());
+//!     // [
+//!     //     0: <Foreign> Player (from "game"),
+//!     //     1: <String> "lots",
+//!     // ]
+//! }
+//! ```
+use crate::{VM, SlotId, SlotType, ForeignObject, ModuleLibrary};
+use std::{any, fmt};
+
+// How many characters of a string are shown
+const STRING_PREVIEW: usize = 40;
+
+/// What a slot held when [VM::dump_slots] was called
+#[derive(Clone, PartialEq)]
+pub struct SlotDump {
+    pub slot: SlotId,
+    pub slot_type: SlotType,
+    /// The value, or as much as can be told about it: numbers and bools as they are, strings
+    /// quoted (and cut short if they're long), how many elements lists and maps have, and which
+    /// registered class a foreign object is
+    pub summary: String,
+}
+
+impl fmt::Debug for SlotDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: <{:?}> {}", self.slot, self.slot_type, self.summary)
+    }
+}
+
+impl fmt::Display for SlotDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl VM {
+    /// Describes every slot in use, in order
+    ///
+    /// Nothing in the VM is changed, so this is safe to call at any point in a foreign method.
+    pub fn dump_slots(&self) -> Vec<SlotDump> {
+        (0..self.get_slot_count()).map(|slot| self.dump_slot(slot)).collect()
+    }
+
+    /// Describes the slot [slot] (see [VM::dump_slots])
+    pub fn dump_slot(&self, slot: SlotId) -> SlotDump {
+        let slot_type = self.get_slot_type(slot);
+        let summary = match slot_type {
+            SlotType::Num => self.get_slot_double(slot).map(|n| n.to_string()).unwrap_or_default(),
+            SlotType::Bool => self.get_slot_bool(slot).map(|b| b.to_string()).unwrap_or_default(),
+            SlotType::Null => "null".to_string(),
+            SlotType::String => {
+                let bytes = self.get_slot_bytes(slot).unwrap_or_default();
+                let string = String::from_utf8_lossy(&bytes);
+                match string.char_indices().nth(STRING_PREVIEW) {
+                    Some((end, _)) => format!("{:?}... ({} bytes)", &string[..end], bytes.len()),
+                    None => format!("{:?}", string),
+                }
+            },
+            SlotType::List => count(self.get_list_count(slot), "element", "elements"),
+            SlotType::Map => count(self.get_map_count(slot), "entry", "entries"),
+            SlotType::Foreign => self.foreign_class_name(slot)
+                .map(|(module, class)| format!("{} (from {:?})", class, module))
+                .unwrap_or_else(|| "unregistered foreign object".to_string()),
+            // Instances of Wren classes, classes, functions and fibers, which the slot API can't tell apart
+            SlotType::Unknown => "object".to_string(),
+        };
+        SlotDump { slot, slot_type, summary }
+    }
+
+    // The registered class of the foreign object in [slot]
+    fn foreign_class_name(&self, slot: SlotId) -> Option<(String, String)> {
+        // Every object ruwren makes starts with its ForeignObject header, whatever it holds, but
+        // Wren's own (like Random) don't
+        let type_id: any::TypeId = unsafe {
+            ForeignObject::type_of(wren_sys::wrenGetSlotForeign(self.vm, slot as std::os::raw::c_int))?
+        };
+        let ud = self.user_data();
+        let found = |lib: &ModuleLibrary| lib.find_foreign_class(type_id)
+            .map(|(module, class)| (module.to_string(), class.to_string()));
+        found(&ud.overlay.borrow()).or_else(|| ud.library.as_deref().and_then(found))
+    }
+}
+
+fn count(count: usize, one: &str, many: &str) -> String {
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
@@ -0,0 +1,53 @@
+use crate::ModuleScriptLoader;
+use std::borrow::Cow;
+
+/// Module sources compiled into the binary, so shipped games don't need loose script files
+///
+/// Usually made by `embed_modules!` (with the `derive` feature), which names each module after
+/// its path in the directory, without the extension:
+///
+/// ```ignore
+/// // scripts/main.wren is "main", and scripts/enemies/slime.wren is "enemies/slime"
+/// let vm = VMConfig::new().script_loader(ruwren::embed_modules!("scripts/**/*.wren")).build();
+/// ```
+///
+/// Sources that end in a nul byte (as embedded ones do) are handed to Wren without being copied.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticLoader {
+    modules: &'static [(&'static str, &'static str)],
+}
+
+impl StaticLoader {
+    /// Serves each (name, source) in [modules]
+    pub const fn new(modules: &'static [(&'static str, &'static str)]) -> StaticLoader {
+        StaticLoader { modules }
+    }
+
+    /// The source of [module], without its nul terminator
+    pub fn get<M: AsRef<str>>(&self, module: M) -> Option<&'static str> {
+        self.modules.iter()
+            .find(|(name, _)| *name == module.as_ref())
+            .map(|(_, source)| source.strip_suffix('\0').unwrap_or(source))
+    }
+
+    /// The name of every module, in the order they were given
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        self.modules.iter().map(|(name, _)| *name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+impl ModuleScriptLoader for StaticLoader {
+    fn load_script(&mut self, name: String) -> Option<Cow<'static, str>> {
+        self.modules.iter()
+            .find(|(module, _)| *module == name)
+            .map(|(_, source)| Cow::Borrowed(*source))
+    }
+}
@@ -0,0 +1,190 @@
+//! The `encoding` module, with base64, hex, UTF-8 and UTF-16 helpers for scripts
+//!
+//! Wren strings are byte strings, so everything here works on the raw bytes of a string.
+use crate::{Class, VM, SlotType, create_module, get_slot_checked};
+
+pub use self::encoding::{publish_module, source};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes [bytes] as padded, standard base64
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard base64, with or without padding
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut decoded = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+        acc = acc << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    // A single dangling character can't encode a whole byte
+    if bits >= 6 {
+        None
+    } else {
+        Some(decoded)
+    }
+}
+
+/// Encodes [bytes] as lowercase hex
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hex of either case
+pub fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.as_bytes().chunks(2).map(|pair| {
+        let hi = (pair[0] as char).to_digit(16)?;
+        let lo = (pair[1] as char).to_digit(16)?;
+        Some((hi << 4 | lo) as u8)
+    }).collect()
+}
+
+pub struct Base64;
+
+impl Class for Base64 {
+    fn initialize(_: &VM) -> Self {
+        panic!("Base64 is a purely static class");
+    }
+}
+
+impl Base64 {
+    fn encode(vm: &VM) {
+        let bytes = get_slot_checked!(vm => bytes 1);
+        vm.set_slot_string(0, base64_encode(&bytes));
+    }
+
+    fn decode(vm: &VM) {
+        let text = get_slot_checked!(vm => string 1);
+        match base64_decode(&text) {
+            Some(bytes) => vm.set_slot_bytes(0, &bytes),
+            None => panic!("Invalid base64 string"),
+        }
+    }
+}
+
+pub struct Hex;
+
+impl Class for Hex {
+    fn initialize(_: &VM) -> Self {
+        panic!("Hex is a purely static class");
+    }
+}
+
+impl Hex {
+    fn encode(vm: &VM) {
+        let bytes = get_slot_checked!(vm => bytes 1);
+        vm.set_slot_string(0, hex_encode(&bytes));
+    }
+
+    fn decode(vm: &VM) {
+        let text = get_slot_checked!(vm => string 1);
+        match hex_decode(&text) {
+            Some(bytes) => vm.set_slot_bytes(0, &bytes),
+            None => panic!("Invalid hex string"),
+        }
+    }
+}
+
+pub struct Utf8;
+
+impl Class for Utf8 {
+    fn initialize(_: &VM) -> Self {
+        panic!("Utf8 is a purely static class");
+    }
+}
+
+impl Utf8 {
+    fn is_valid(vm: &VM) {
+        let bytes = get_slot_checked!(vm => bytes 1);
+        vm.set_slot_bool(0, std::str::from_utf8(&bytes).is_ok());
+    }
+}
+
+pub struct Utf16;
+
+impl Class for Utf16 {
+    fn initialize(_: &VM) -> Self {
+        panic!("Utf16 is a purely static class");
+    }
+}
+
+impl Utf16 {
+    fn encode(vm: &VM) {
+        let string = get_slot_checked!(vm => string 1);
+        vm.ensure_slots(2);
+        vm.set_slot_new_list(0);
+        for unit in string.encode_utf16() {
+            vm.set_slot_double(1, unit as f64);
+            vm.insert_in_list(0, -1, 1);
+        }
+    }
+
+    fn decode(vm: &VM) {
+        vm.ensure_slots(3);
+        if vm.get_slot_type(1) != SlotType::List {
+            panic!("Utf16.decode expects a list of code units");
+        }
+        let mut units = vec![];
+        for i in 0..vm.get_list_count(1) {
+            vm.get_list_element(1, i as i32, 2);
+            let unit = get_slot_checked!(vm => num 2);
+            if unit.fract() != 0.0 || unit < 0.0 || unit > u16::MAX as f64 {
+                panic!("{} is not a UTF-16 code unit", unit);
+            }
+            units.push(unit as u16);
+        }
+        match String::from_utf16(&units) {
+            Ok(string) => vm.set_slot_string(0, string),
+            Err(e) => panic!("Invalid UTF-16: {}", e),
+        }
+    }
+}
+
+create_module! {
+    class("Base64") crate::stdlib::encoding::Base64 => base64 {
+        static(fn "encode", 1) encode,
+        static(fn "decode", 1) decode
+    }
+
+    class("Hex") crate::stdlib::encoding::Hex => hex {
+        static(fn "encode", 1) encode,
+        static(fn "decode", 1) decode
+    }
+
+    class("Utf8") crate::stdlib::encoding::Utf8 => utf8 {
+        static(fn "isValid", 1) is_valid
+    }
+
+    class("Utf16") crate::stdlib::encoding::Utf16 => utf16 {
+        static(fn "encode", 1) encode,
+        static(fn "decode", 1) decode
+    }
+
+    module => encoding
+}
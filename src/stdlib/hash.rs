@@ -0,0 +1,69 @@
+//! The `hash` module, with checksums and digests for scripts
+//!
+//! Every function accepts either a string, or a list of byte values.
+use crate::{Class, VM, SlotType, create_module, get_slot_checked};
+use super::encoding::hex_encode;
+use sha2::Digest;
+
+pub use self::hash::{publish_module, source};
+
+/// 64-bit FNV-1a, a fast non-cryptographic hash
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn get_slot_data(vm: &VM, slot: usize) -> Vec<u8> {
+    match vm.get_slot_type(slot) {
+        SlotType::String => get_slot_checked!(vm => bytes slot),
+        SlotType::List => {
+            vm.ensure_slots(slot + 2);
+            (0..vm.get_list_count(slot)).map(|i| {
+                vm.get_list_element(slot, i as i32, slot + 1);
+                let byte = get_slot_checked!(vm => num slot + 1);
+                if byte.fract() != 0.0 || !(0.0..=255.0).contains(&byte) {
+                    panic!("{} is not a byte", byte);
+                }
+                byte as u8
+            }).collect()
+        },
+        _ => panic!("Expected a string or a list of bytes"),
+    }
+}
+
+pub struct Hash;
+
+impl Class for Hash {
+    fn initialize(_: &VM) -> Self {
+        panic!("Hash is a purely static class");
+    }
+}
+
+impl Hash {
+    fn crc32(vm: &VM) {
+        let data = get_slot_data(vm, 1);
+        vm.set_slot_double(0, crc32fast::hash(&data) as f64);
+    }
+
+    fn sha256(vm: &VM) {
+        let data = get_slot_data(vm, 1);
+        vm.set_slot_string(0, hex_encode(&sha2::Sha256::digest(&data)));
+    }
+
+    // Wren numbers can't hold all 64 bits, so this is returned as hex
+    fn fnv1a(vm: &VM) {
+        let data = get_slot_data(vm, 1);
+        vm.set_slot_string(0, format!("{:016x}", fnv1a(&data)));
+    }
+}
+
+create_module! {
+    class("Hash") crate::stdlib::hash::Hash => hash_class {
+        static(fn "crc32", 1) crc32,
+        static(fn "sha256", 1) sha256,
+        static(fn "fnv1a", 1) fnv1a
+    }
+
+    module => hash
+}
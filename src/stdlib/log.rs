@@ -0,0 +1,66 @@
+//! The `log` module, which sends script messages to the host's [log](https://docs.rs/log) logger
+//!
+//! ```wren
+//! import "log" for Log
+//! Log.warn("low on memory")
+//! ```
+//!
+//! Each message is logged with a target of `wren::<module>`, where module is the one that
+//! imported `Log`, and prefixed with the VM's [name](crate::VMConfig::name) if it has one.
+use crate::{Class, VM, WrenValue, create_module, get_slot_checked};
+
+pub use self::log::{publish_module, source};
+
+pub(crate) const MODULE: &str = "log";
+
+// What a module's own copy of the log module is called
+pub(crate) const PREFIX: &str = "log@";
+
+/// The `Log` class a module gets when it imports "log"
+pub fn logger_source(module: &str) -> String {
+    let module = WrenValue::from(module).to_wren_source();
+    let mut source = String::from("import \"log\" for LogCore\nclass Log {\n");
+    for (level, name) in ["error", "warn", "info", "debug", "trace"].iter().enumerate() {
+        source.push_str(&format!("    static {}(message) {{ LogCore.write({}, {}, \"%(message)\") }}\n", name, level + 1, module));
+    }
+    source.push_str("}\n");
+    source
+}
+
+pub struct LogCore;
+
+impl Class for LogCore {
+    fn initialize(_: &VM) -> Self {
+        panic!("LogCore is a purely static class");
+    }
+}
+
+impl LogCore {
+    fn write(vm: &VM) {
+        let level = get_slot_checked!(vm => num 1);
+        let module = get_slot_checked!(vm => string 2);
+        let message = get_slot_checked!(vm => string 3);
+        let level = match level as usize {
+            1 => ::log::Level::Error,
+            2 => ::log::Level::Warn,
+            3 => ::log::Level::Info,
+            4 => ::log::Level::Debug,
+            _ => ::log::Level::Trace,
+        };
+        let target = format!("wren::{}", module);
+        match vm.name() {
+            Some(name) => ::log::log!(target: &target, level, "[{}] {}", name, message),
+            None => ::log::log!(target: &target, level, "{}", message),
+        }
+    }
+}
+
+// Scripts don't use LogCore directly: importing `Log` from "log" gets them a copy of logger_source
+// bound to their module instead.
+create_module! {
+    class("LogCore") crate::stdlib::log::LogCore => log_core {
+        static(fn "write", 3) write
+    }
+
+    module => log
+}
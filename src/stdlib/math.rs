@@ -0,0 +1,428 @@
+//! The `math` module, with the vector, matrix and color types most game scripts need
+//!
+//! Each class wraps the matching [glam] type (Color wraps a [glam::Vec4] of RGBA components from 0 to 1),
+//! and converts to and from it with `From`. Wren numbers are doubles, so components lose precision
+//! on their way into these f32 types.
+//!
+//! Binary operators are written in Wren and call private static methods, so that an object can
+//! be used on both sides of one (like `v * v`) without borrowing it twice.
+use crate::{Class, FromWren, ToWren, VM, create_module};
+
+pub use self::math::{publish_module, source};
+
+fn arg<T: FromWren>(vm: &VM, slot: usize) -> T {
+    vm.get_slot(slot).unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn ret<T: ToWren>(vm: &VM, value: T) {
+    vm.set_slot(0, value).unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn binary<A: FromWren, B: FromWren, R: ToWren>(vm: &VM, f: impl FnOnce(A, B) -> R) {
+    let result = f(arg(vm, 1), arg(vm, 2));
+    ret(vm, result);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vec2(pub glam::Vec2);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vec3(pub glam::Vec3);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4(pub glam::Mat4);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color(pub glam::Vec4);
+
+macro_rules! glam_conversions {
+    ($($wrapper:ident($glam:ty)),+) => {
+        $(
+            impl From<$glam> for $wrapper {
+                fn from(value: $glam) -> Self {
+                    $wrapper(value)
+                }
+            }
+
+            impl From<$wrapper> for $glam {
+                fn from(value: $wrapper) -> Self {
+                    value.0
+                }
+            }
+        )+
+    };
+}
+
+glam_conversions!(Vec2(glam::Vec2), Vec3(glam::Vec3), Mat4(glam::Mat4), Color(glam::Vec4));
+
+impl Class for Vec2 {
+    fn initialize(vm: &VM) -> Self {
+        Vec2(glam::vec2(arg(vm, 1), arg(vm, 2)))
+    }
+}
+
+impl Vec2 {
+    fn x(&mut self, vm: &VM, value: Option<f32>) {
+        match value {
+            Some(x) => self.0.x = x,
+            None => ret(vm, self.0.x),
+        }
+    }
+
+    fn y(&mut self, vm: &VM, value: Option<f32>) {
+        match value {
+            Some(y) => self.0.y = y,
+            None => ret(vm, self.0.y),
+        }
+    }
+
+    fn length(&self, vm: &VM) {
+        ret(vm, self.0.length());
+    }
+
+    fn normalized(&self, vm: &VM) {
+        ret(vm, Vec2(self.0.normalize_or_zero()));
+    }
+
+    fn dot_(vm: &VM) {
+        binary(vm, |a: Vec2, b: Vec2| a.0.dot(b.0));
+    }
+
+    fn lerp_(vm: &VM) {
+        let (a, b): (Vec2, Vec2) = (arg(vm, 1), arg(vm, 2));
+        ret(vm, Vec2(a.0.lerp(b.0, arg(vm, 3))));
+    }
+
+    fn add_(vm: &VM) {
+        binary(vm, |a: Vec2, b: Vec2| Vec2(a.0 + b.0));
+    }
+
+    fn sub_(vm: &VM) {
+        binary(vm, |a: Vec2, b: Vec2| Vec2(a.0 - b.0));
+    }
+
+    fn mul_(vm: &VM) {
+        binary(vm, |a: Vec2, b: Vec2| Vec2(a.0 * b.0));
+    }
+
+    fn scale_(vm: &VM) {
+        binary(vm, |a: Vec2, n: f32| Vec2(a.0 * n));
+    }
+
+    fn eq_(vm: &VM) {
+        binary(vm, |a: Vec2, b: Vec2| a == b);
+    }
+}
+
+impl Class for Vec3 {
+    fn initialize(vm: &VM) -> Self {
+        Vec3(glam::vec3(arg(vm, 1), arg(vm, 2), arg(vm, 3)))
+    }
+}
+
+impl Vec3 {
+    fn x(&mut self, vm: &VM, value: Option<f32>) {
+        match value {
+            Some(x) => self.0.x = x,
+            None => ret(vm, self.0.x),
+        }
+    }
+
+    fn y(&mut self, vm: &VM, value: Option<f32>) {
+        match value {
+            Some(y) => self.0.y = y,
+            None => ret(vm, self.0.y),
+        }
+    }
+
+    fn z(&mut self, vm: &VM, value: Option<f32>) {
+        match value {
+            Some(z) => self.0.z = z,
+            None => ret(vm, self.0.z),
+        }
+    }
+
+    fn length(&self, vm: &VM) {
+        ret(vm, self.0.length());
+    }
+
+    fn normalized(&self, vm: &VM) {
+        ret(vm, Vec3(self.0.normalize_or_zero()));
+    }
+
+    fn dot_(vm: &VM) {
+        binary(vm, |a: Vec3, b: Vec3| a.0.dot(b.0));
+    }
+
+    fn cross_(vm: &VM) {
+        binary(vm, |a: Vec3, b: Vec3| Vec3(a.0.cross(b.0)));
+    }
+
+    fn lerp_(vm: &VM) {
+        let (a, b): (Vec3, Vec3) = (arg(vm, 1), arg(vm, 2));
+        ret(vm, Vec3(a.0.lerp(b.0, arg(vm, 3))));
+    }
+
+    fn add_(vm: &VM) {
+        binary(vm, |a: Vec3, b: Vec3| Vec3(a.0 + b.0));
+    }
+
+    fn sub_(vm: &VM) {
+        binary(vm, |a: Vec3, b: Vec3| Vec3(a.0 - b.0));
+    }
+
+    fn mul_(vm: &VM) {
+        binary(vm, |a: Vec3, b: Vec3| Vec3(a.0 * b.0));
+    }
+
+    fn scale_(vm: &VM) {
+        binary(vm, |a: Vec3, n: f32| Vec3(a.0 * n));
+    }
+
+    fn eq_(vm: &VM) {
+        binary(vm, |a: Vec3, b: Vec3| a == b);
+    }
+}
+
+impl Class for Mat4 {
+    fn initialize(_: &VM) -> Self {
+        Mat4(glam::Mat4::IDENTITY)
+    }
+}
+
+impl Mat4 {
+    fn translation(vm: &VM) {
+        ret(vm, Mat4(glam::Mat4::from_translation(glam::vec3(arg(vm, 1), arg(vm, 2), arg(vm, 3)))));
+    }
+
+    fn scale(vm: &VM) {
+        ret(vm, Mat4(glam::Mat4::from_scale(glam::vec3(arg(vm, 1), arg(vm, 2), arg(vm, 3)))));
+    }
+
+    fn rotation_x(vm: &VM) {
+        ret(vm, Mat4(glam::Mat4::from_rotation_x(arg(vm, 1))));
+    }
+
+    fn rotation_y(vm: &VM) {
+        ret(vm, Mat4(glam::Mat4::from_rotation_y(arg(vm, 1))));
+    }
+
+    fn rotation_z(vm: &VM) {
+        ret(vm, Mat4(glam::Mat4::from_rotation_z(arg(vm, 1))));
+    }
+
+    /// Right-handed, with a depth range of -1 to 1 (like OpenGL)
+    fn perspective(vm: &VM) {
+        ret(vm, Mat4(glam::Mat4::perspective_rh_gl(arg(vm, 1), arg(vm, 2), arg(vm, 3), arg(vm, 4))));
+    }
+
+    fn look_at(vm: &VM) {
+        let (eye, target, up): (Vec3, Vec3, Vec3) = (arg(vm, 1), arg(vm, 2), arg(vm, 3));
+        ret(vm, Mat4(glam::Mat4::look_at_rh(eye.0, target.0, up.0)));
+    }
+
+    fn element(&self, vm: &VM) {
+        let (row, col): (usize, usize) = (arg(vm, 1), arg(vm, 2));
+        if row > 3 || col > 3 {
+            panic!("[{}, {}] is outside a 4x4 matrix", row, col);
+        }
+        ret(vm, self.0.col(col)[row]);
+    }
+
+    fn determinant(&self, vm: &VM) {
+        ret(vm, self.0.determinant());
+    }
+
+    fn transposed(&self, vm: &VM) {
+        ret(vm, Mat4(self.0.transpose()));
+    }
+
+    fn inverse(&self, vm: &VM) {
+        ret(vm, Mat4(self.0.inverse()));
+    }
+
+    fn describe(&self, vm: &VM) {
+        let rows: Vec<_> = (0..4).map(|row| {
+            let row = self.0.row(row);
+            format!("[{}, {}, {}, {}]", row.x, row.y, row.z, row.w)
+        }).collect();
+        ret(vm, format!("[{}]", rows.join(", ")));
+    }
+
+    fn mul_(vm: &VM) {
+        binary(vm, |a: Mat4, b: Mat4| Mat4(a.0 * b.0));
+    }
+
+    /// Transforms a point (so translations apply)
+    fn transform_(vm: &VM) {
+        binary(vm, |m: Mat4, v: Vec3| Vec3(m.0.transform_point3(v.0)));
+    }
+
+    fn eq_(vm: &VM) {
+        binary(vm, |a: Mat4, b: Mat4| a == b);
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa` (the `#` is optional)
+pub fn parse_hex_color(text: &str) -> Option<Color> {
+    let digits = text.strip_prefix('#').unwrap_or(text);
+    if !(digits.len() == 6 || digits.len() == 8) || !digits.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| digits.get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()).map(|c| c as f32 / 255.0);
+    let alpha = if digits.len() == 8 { channel(6)? } else { 1.0 };
+    Some(Color(glam::vec4(channel(0)?, channel(2)?, channel(4)?, alpha)))
+}
+
+impl Class for Color {
+    fn initialize(vm: &VM) -> Self {
+        Color(glam::vec4(arg(vm, 1), arg(vm, 2), arg(vm, 3), arg(vm, 4)))
+    }
+}
+
+impl Color {
+    /// `#rrggbbaa`, with each channel clamped to 0..=1
+    pub fn to_hex(self) -> String {
+        let [r, g, b, a] = self.0.clamp(glam::Vec4::ZERO, glam::Vec4::ONE).to_array().map(|c| (c * 255.0).round() as u8);
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+
+    fn hex(vm: &VM) {
+        let text: String = arg(vm, 1);
+        match parse_hex_color(&text) {
+            Some(color) => ret(vm, color),
+            None => panic!("{:?} is not a hex color", text),
+        }
+    }
+
+    fn r(&self, vm: &VM) {
+        ret(vm, self.0.x);
+    }
+
+    fn g(&self, vm: &VM) {
+        ret(vm, self.0.y);
+    }
+
+    fn b(&self, vm: &VM) {
+        ret(vm, self.0.z);
+    }
+
+    fn a(&self, vm: &VM) {
+        ret(vm, self.0.w);
+    }
+
+    fn hex_string(&self, vm: &VM) {
+        ret(vm, self.to_hex());
+    }
+
+    fn lerp_(vm: &VM) {
+        let (a, b): (Color, Color) = (arg(vm, 1), arg(vm, 2));
+        ret(vm, Color(a.0.lerp(b.0, arg(vm, 3))));
+    }
+
+    fn eq_(vm: &VM) {
+        binary(vm, |a: Color, b: Color| a == b);
+    }
+}
+
+create_module! {
+    class("Vec2") crate::stdlib::math::Vec2 => vec2 {
+        instance(property "x") x,
+        instance(property "y") y,
+        instance(getter "length") length,
+        instance(getter "normalized") normalized,
+        static(fn "dot_", 2) dot_,
+        static(fn "lerp_", 3) lerp_,
+        static(fn "add_", 2) add_,
+        static(fn "sub_", 2) sub_,
+        static(fn "mul_", 2) mul_,
+        static(fn "scale_", 2) scale_,
+        static(fn "eq_", 2) eq_
+    } wren(r##"
+    construct new(x, y) {}
+    static zero { Vec2.new(0, 0) }
+    dot(other) { Vec2.dot_(this, other) }
+    lerp(other, t) { Vec2.lerp_(this, other, t) }
+    +(other) { Vec2.add_(this, other) }
+    -(other) { Vec2.sub_(this, other) }
+    *(other) { other is Num ? Vec2.scale_(this, other) : Vec2.mul_(this, other) }
+    /(other) { Vec2.scale_(this, 1 / other) }
+    - { Vec2.scale_(this, -1) }
+    ==(other) { other is Vec2 && Vec2.eq_(this, other) }
+    !=(other) { !(this == other) }
+    toString { "(%(x), %(y))" }
+"##)
+
+    class("Vec3") crate::stdlib::math::Vec3 => vec3 {
+        instance(property "x") x,
+        instance(property "y") y,
+        instance(property "z") z,
+        instance(getter "length") length,
+        instance(getter "normalized") normalized,
+        static(fn "dot_", 2) dot_,
+        static(fn "cross_", 2) cross_,
+        static(fn "lerp_", 3) lerp_,
+        static(fn "add_", 2) add_,
+        static(fn "sub_", 2) sub_,
+        static(fn "mul_", 2) mul_,
+        static(fn "scale_", 2) scale_,
+        static(fn "eq_", 2) eq_
+    } wren(r##"
+    construct new(x, y, z) {}
+    static zero { Vec3.new(0, 0, 0) }
+    dot(other) { Vec3.dot_(this, other) }
+    cross(other) { Vec3.cross_(this, other) }
+    lerp(other, t) { Vec3.lerp_(this, other, t) }
+    +(other) { Vec3.add_(this, other) }
+    -(other) { Vec3.sub_(this, other) }
+    *(other) { other is Num ? Vec3.scale_(this, other) : Vec3.mul_(this, other) }
+    /(other) { Vec3.scale_(this, 1 / other) }
+    - { Vec3.scale_(this, -1) }
+    ==(other) { other is Vec3 && Vec3.eq_(this, other) }
+    !=(other) { !(this == other) }
+    toString { "(%(x), %(y), %(z))" }
+"##)
+
+    class("Mat4") crate::stdlib::math::Mat4 => mat4 {
+        static(fn "translation", 3) translation,
+        static(fn "scale", 3) scale,
+        static(fn "rotationX", 1) rotation_x,
+        static(fn "rotationY", 1) rotation_y,
+        static(fn "rotationZ", 1) rotation_z,
+        static(fn "perspective", 4) perspective,
+        static(fn "lookAt", 3) look_at,
+        instance(subscript 2) element,
+        instance(getter "determinant") determinant,
+        instance(getter "transposed") transposed,
+        instance(getter "inverse") inverse,
+        instance(getter "toString") describe,
+        static(fn "mul_", 2) mul_,
+        static(fn "transform_", 2) transform_,
+        static(fn "eq_", 2) eq_
+    } wren(r##"
+    construct identity() {}
+    *(other) { other is Mat4 ? Mat4.mul_(this, other) : Mat4.transform_(this, other) }
+    ==(other) { other is Mat4 && Mat4.eq_(this, other) }
+    !=(other) { !(this == other) }
+"##)
+
+    class("Color") crate::stdlib::math::Color => color {
+        static(fn "hex", 1) hex,
+        instance(getter "r") r,
+        instance(getter "g") g,
+        instance(getter "b") b,
+        instance(getter "a") a,
+        instance(getter "toHex") hex_string,
+        static(fn "lerp_", 3) lerp_,
+        static(fn "eq_", 2) eq_
+    } wren(r##"
+    construct new(r, g, b, a) {}
+    static rgb(r, g, b) { Color.new(r, g, b, 1) }
+    lerp(other, t) { Color.lerp_(this, other, t) }
+    ==(other) { other is Color && Color.eq_(this, other) }
+    !=(other) { !(this == other) }
+    toString { toHex }
+"##)
+
+    module => math
+}
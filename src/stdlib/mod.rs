@@ -0,0 +1,16 @@
+//! Optional foreign modules that ship with ruwren
+//!
+//! Each module provides a `publish_module` function to register its foreign classes, along with
+//! their Wren declarations, with a [ModuleLibrary](crate::ModuleLibrary), so scripts can import
+//! it straight away. Its `source` function returns those declarations.
+
+#[cfg(feature = "process")]
+pub mod process;
+pub mod path;
+pub mod encoding;
+#[cfg(feature = "hash")]
+pub mod hash;
+#[cfg(feature = "log")]
+pub mod log;
+#[cfg(feature = "math")]
+pub mod math;
@@ -0,0 +1,81 @@
+//! The `path` module, exposing `std::path` manipulation to scripts
+use crate::{Class, VM, create_module, get_slot_checked};
+use std::path::{Component, Path as StdPath, PathBuf};
+
+pub use self::path::{publish_module, source};
+
+pub struct Path;
+
+impl Class for Path {
+    fn initialize(_: &VM) -> Self {
+        panic!("Path is a purely static class");
+    }
+}
+
+fn set_slot_path(vm: &VM, path: Option<&std::ffi::OsStr>) {
+    match path {
+        Some(path) => vm.set_slot_string(0, path.to_string_lossy()),
+        None => vm.set_slot_null(0),
+    }
+}
+
+/// Lexically resolves `.` and `..` components, without touching the filesystem
+pub fn normalize(path: &StdPath) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => { normal.pop(); },
+                // There's nothing above the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {},
+                _ => normal.push(".."),
+            },
+            component => normal.push(component.as_os_str()),
+        }
+    }
+    if normal.as_os_str().is_empty() {
+        normal.push(".");
+    }
+    normal
+}
+
+impl Path {
+    fn join(vm: &VM) {
+        let base = get_slot_checked!(vm => string 1);
+        let path = get_slot_checked!(vm => string 2);
+        set_slot_path(vm, Some(StdPath::new(&base).join(path).as_os_str()));
+    }
+
+    fn dirname(vm: &VM) {
+        let path = get_slot_checked!(vm => string 1);
+        set_slot_path(vm, StdPath::new(&path).parent().map(|p| p.as_os_str()));
+    }
+
+    fn basename(vm: &VM) {
+        let path = get_slot_checked!(vm => string 1);
+        set_slot_path(vm, StdPath::new(&path).file_name());
+    }
+
+    fn extension(vm: &VM) {
+        let path = get_slot_checked!(vm => string 1);
+        set_slot_path(vm, StdPath::new(&path).extension());
+    }
+
+    fn normalize(vm: &VM) {
+        let path = get_slot_checked!(vm => string 1);
+        set_slot_path(vm, Some(normalize(StdPath::new(&path)).as_os_str()));
+    }
+}
+
+create_module! {
+    class("Path") crate::stdlib::path::Path => path_class {
+        static(fn "join", 2) join,
+        static(fn "dirname", 1) dirname,
+        static(fn "basename", 1) basename,
+        static(fn "extension", 1) extension,
+        static(fn "normalize", 1) normalize
+    }
+
+    module => path
+}
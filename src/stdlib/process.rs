@@ -0,0 +1,175 @@
+//! The `process` module, which lets scripts spawn host commands
+//!
+//! Governed by [Capability::Process](crate::Capability::Process), and the command allowlist
+//! of the VM's [CapabilityPolicy](crate::CapabilityPolicy).
+//!
+//! Each module granted the capability that imports `Process` gets a copy of [importer_source]
+//! bound to its name, so commands are only run for modules the capability was granted to.
+//! Modules that weren't granted it can't import a module holding a copy, since they'd get its
+//! `Process` along with it.
+use crate::{Class, VM, WrenValue, SlotType, Capability, CapabilityPolicy, create_module, get_slot_checked, send_foreign};
+use std::collections::{HashMap, HashSet};
+
+pub use self::process::{publish_module, source};
+
+pub(crate) const MODULE: &str = "process";
+
+// What a module's own copy of the process module is called
+pub(crate) const PREFIX: &str = "process@";
+
+/// The `Process` class a module gets when it imports "process"
+pub fn importer_source(module: &str) -> String {
+    let module = WrenValue::from(module).to_wren_source();
+    format!("import \"process\" for Process as ProcessCore, ProcessOutput
+class Process {{
+    static run(command, args) {{ ProcessCore.run_({}, command, args) }}
+    static run(command) {{ run(command, []) }}
+}}
+", module)
+}
+
+/// Which modules imported which, so copies of `Process` aren't passed on to modules that weren't granted it
+#[derive(Debug, Default)]
+pub(crate) struct Imports {
+    // What each module has imported, in order
+    imported: HashMap<String, Vec<String>>,
+    // The module each module was first loaded for, if it was loaded by an import
+    loaded_for: HashMap<String, String>,
+}
+
+impl Imports {
+    pub(crate) fn record(&mut self, importer: &str, module: &str, loaded: bool) {
+        if !loaded {
+            self.loaded_for.entry(module.to_string()).or_insert_with(|| importer.to_string());
+        }
+        let imported = self.imported.entry(importer.to_string()).or_default();
+        if !imported.iter().any(|other| other == module) {
+            imported.push(module.to_string());
+        }
+    }
+
+    /// Whether [module] was granted the capability, and so was every module it was loaded for,
+    /// since anything it imports ends up in them too
+    pub(crate) fn is_granted(&self, policy: &CapabilityPolicy, module: &str) -> bool {
+        let mut seen = HashSet::new();
+        let mut module = module;
+        loop {
+            if !policy.is_granted_to(module, Capability::Process) {
+                return false;
+            }
+            match self.loaded_for.get(module) {
+                Some(importer) if seen.insert(importer.as_str()) => module = importer,
+                _ => return true,
+            }
+        }
+    }
+
+    /// Whether [module] holds a copy of `Process`, or imported a module that does, however indirectly
+    pub(crate) fn reaches_process(&self, module: &str) -> bool {
+        let mut seen = HashSet::new();
+        let mut pending = vec![module];
+        while let Some(module) = pending.pop() {
+            if module.starts_with(PREFIX) {
+                return true;
+            }
+            if seen.insert(module) {
+                pending.extend(self.imported.get(module).into_iter().flatten().map(String::as_str));
+            }
+        }
+        false
+    }
+}
+
+pub struct Process;
+
+impl Class for Process {
+    fn initialize(_: &VM) -> Self {
+        panic!("Process is a purely static class");
+    }
+}
+
+impl Process {
+    fn run(vm: &VM) {
+        vm.ensure_slots(5);
+        let module = get_slot_checked!(vm => string 1);
+        let command = get_slot_checked!(vm => string 2);
+        let mut args = vec![];
+        match vm.get_slot_type(3) {
+            SlotType::List => {
+                for i in 0..vm.get_list_count(3) {
+                    vm.get_list_element(3, i as i32, 4);
+                    args.push(get_slot_checked!(vm => string 4));
+                }
+            },
+            SlotType::Null => {},
+            _ => panic!("Process.run expects a list of arguments"),
+        }
+
+        if !vm.capabilities().is_granted_to(&module, Capability::Process) {
+            panic!("Process capability has not been granted to module {:?}", module);
+        }
+        if !vm.capabilities().is_command_allowed(&command) {
+            panic!("Command {:?} is not allowed", command);
+        }
+
+        match std::process::Command::new(&command).args(&args).output() {
+            Ok(output) => {
+                send_foreign!(vm, "process", "ProcessOutput", ProcessOutput {
+                    code: output.status.code(),
+                    stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                } => 0);
+            },
+            Err(e) => panic!("Failed to run {:?}: {}", command, e),
+        }
+    }
+}
+
+/// The captured result of a finished command
+pub struct ProcessOutput {
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+impl Class for ProcessOutput {
+    fn initialize(_: &VM) -> Self {
+        panic!("ProcessOutput cannot be constructed from Wren");
+    }
+}
+
+impl ProcessOutput {
+    fn code(&self, vm: &VM) {
+        match self.code {
+            Some(code) => vm.set_slot_double(0, code as f64),
+            None => vm.set_slot_null(0), // Terminated by a signal
+        }
+    }
+
+    fn stdout(&self, vm: &VM) {
+        vm.set_slot_string(0, &self.stdout);
+    }
+
+    fn stderr(&self, vm: &VM) {
+        vm.set_slot_string(0, &self.stderr);
+    }
+}
+
+create_module! {
+    class("Process") crate::stdlib::process::Process => process_class {
+        static(fn "run_", 3) run
+    } wren(r##"
+    static run(command, args) { run_("process", command, args) }
+    static run(command) { run(command, []) }
+"##)
+
+    class("ProcessOutput") crate::stdlib::process::ProcessOutput => process_output {
+        instance(getter "code") code,
+        instance(getter "stdout") stdout,
+        instance(getter "stderr") stderr
+    } wren(r##"
+    success { code == 0 }
+"##)
+
+    module => process
+}
@@ -0,0 +1,150 @@
+//! A VM that can be shared between threads, at the cost of locking on every operation
+use crate::{VMWrapper, VMConfig, VM, VMError, BuildError, FunctionSignature, ModuleName, SlotId, FromWren, WrenValue};
+use wren_sys::WrenHandle;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::os::raw;
+
+struct SendVM(VMWrapper);
+
+// Safety: SyncVM only builds VMs whose foreign classes and callbacks are all Send,
+// and never lets the VMWrapper (or anything else sharing its Rc) escape the mutex.
+unsafe impl Send for SendVM {}
+
+/// A VM behind a mutex, which can be cloned and used from any thread
+///
+/// Foreign methods still receive a plain `&VM`, and must not try to use the SyncVM themselves
+/// (the lock is already held, so they would deadlock).
+#[derive(Clone)]
+pub struct SyncVM(Arc<Mutex<SendVM>>);
+
+impl SyncVM {
+    /// Builds a shareable VM from [config]
+    ///
+    /// Every foreign class must be Send (see [VMConfig::require_send]), and any printer, script
+    /// loader or error handler must have been given with [VMConfig::send_printer],
+    /// [VMConfig::send_script_loader] or [VMConfig::send_error_handler].
+    /// Lifecycle hooks can't be used, as they may hold on to the VM.
+    pub fn new(config: VMConfig) -> Result<SyncVM, BuildError> {
+        if let Some(callback) = config.thread_bound {
+            return Err(BuildError::NonSendCallback { callback: callback.to_string() });
+        }
+        let vm = config.require_send(true).try_build()?;
+        Ok(SyncVM(Arc::new(Mutex::new(SendVM(vm)))))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SendVM> {
+        // A foreign method panicking leaves the VM no worse off than it does without the lock
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn interpret<M: ModuleName, C: AsRef<str>>(&self, module: M, code: C) -> Result<(), VMError> {
+        self.lock().0.interpret(module, code)
+    }
+
+    pub fn call(&self, signature: FunctionSignature) -> Result<(), VMError> {
+        self.lock().0.call(signature)
+    }
+
+    pub fn call_handle(&self, handle: &SyncFunctionHandle) -> Result<(), VMError> {
+        self.lock().0.call_raw(handle.0.handle, &handle.1)
+    }
+
+    /// Like [SyncVM::call], but calls the method on [receiver] with [args], and converts what it returned
+    /// (see [VMWrapper::call_returning])
+    ///
+    /// The receiver and arguments are put in their slots, and the result read back, under the same lock
+    /// as the call, so another thread can't overwrite them in between.
+    pub fn call_returning<T: FromWren>(&self, receiver: &SyncHandle, signature: FunctionSignature, args: &[WrenValue]) -> Result<T, VMError> {
+        let vm = self.lock();
+        set_call_slots(&vm.0, receiver, args);
+        vm.0.call_returning(signature)
+    }
+
+    /// Like [SyncVM::call_handle], but calls the method on [receiver] with [args], and converts what it returned
+    ///
+    /// As with [SyncVM::call_returning], all of it happens under one lock.
+    pub fn call_handle_returning<T: FromWren>(&self, receiver: &SyncHandle, handle: &SyncFunctionHandle, args: &[WrenValue]) -> Result<T, VMError> {
+        let vm = self.lock();
+        set_call_slots(&vm.0, receiver, args);
+        vm.0.call_raw(handle.0.handle, &handle.1)?;
+        vm.0.execute(|vm| T::from_wren(vm, 0).map_err(|error| vm.rewrite_error(VMError::InvalidReturn { error })))
+    }
+
+    /// Evaluates [expr] in [module] and converts its value (see [VMWrapper::interpret_returning])
+    pub fn interpret_returning<T: FromWren, M: ModuleName, E: AsRef<str>>(&self, module: M, expr: E) -> Result<T, VMError> {
+        self.lock().0.interpret_returning(module, expr)
+    }
+
+    /// Runs [f] with the VM locked
+    pub fn execute<T, F>(&self, f: F) -> T where F: FnOnce(&VM) -> T {
+        self.lock().0.execute(f)
+    }
+
+    pub fn get_slot_handle(&self, slot: SlotId) -> SyncHandle {
+        let handle = self.execute(|vm| unsafe {
+            wren_sys::wrenGetSlotHandle(vm.vm, slot as raw::c_int)
+        });
+        SyncHandle {
+            handle,
+            vm: self.clone(),
+        }
+    }
+
+    pub fn set_slot_handle(&self, slot: SlotId, handle: &SyncHandle) {
+        self.execute(|vm| unsafe {
+            wren_sys::wrenSetSlotHandle(vm.vm, slot as raw::c_int, handle.handle)
+        })
+    }
+
+    pub fn make_call_handle(&self, signature: FunctionSignature) -> SyncFunctionHandle {
+        let signature = signature.as_wren_string();
+        let cstr = std::ffi::CString::new(signature.as_str()).expect("signature conversion failed");
+        let handle = self.execute(|vm| unsafe {
+            wren_sys::wrenMakeCallHandle(vm.vm, cstr.as_ptr())
+        });
+        SyncFunctionHandle(SyncHandle {
+            handle,
+            vm: self.clone(),
+        }, signature)
+    }
+
+    /// Instruct Wren to start a garbage collection cycle
+    pub fn collect_garbage(&self) {
+        self.lock().0.collect_garbage()
+    }
+}
+
+// Puts [receiver] and [args] where a call reads them from, for a caller already holding the lock
+fn set_call_slots(vm: &VMWrapper, receiver: &SyncHandle, args: &[WrenValue]) {
+    vm.execute(|vm| {
+        vm.ensure_slots(args.len() + 1);
+        unsafe { wren_sys::wrenSetSlotHandle(vm.vm, 0, receiver.handle) };
+        for (slot, arg) in args.iter().enumerate() {
+            vm.set_slot_value(slot + 1, arg);
+        }
+    });
+}
+
+/// A handle to a Wren object in a [SyncVM]
+///
+/// Keeps the VM alive, and locks it to release the handle on drop.
+pub struct SyncHandle {
+    handle: *mut WrenHandle,
+    vm: SyncVM,
+}
+
+// Safety: the handle is only ever used with the VM locked
+unsafe impl Send for SyncHandle {}
+unsafe impl Sync for SyncHandle {}
+
+impl Drop for SyncHandle {
+    fn drop(&mut self) {
+        let handle = self.handle;
+        self.vm.execute(|vm| unsafe {
+            wren_sys::wrenReleaseHandle(vm.vm, handle)
+        })
+    }
+}
+
+/// A handle to a Wren method call in a [SyncVM]
+pub struct SyncFunctionHandle(SyncHandle, String);
@@ -0,0 +1,310 @@
+//! Runs a directory of Wren test scripts, each in a VM of its own
+//!
+//! Test scripts are files ending in `_test.wren`. They can group assertions into named cases
+//! with the built-in `ruwren/test` module:
+//!
+//! ```wren
+//! import "ruwren/test" for Test, Assert
+//!
+//! Test.case("addition") {
+//!     Assert.equal(1 + 1, 2)
+//! }
+//! ```
+//!
+//! Cases run after the rest of the file, one at a time, so a failing case's stack trace shows
+//! where it failed. A script that doesn't declare any cases passes as long as it runs without
+//! error.
+use crate::{VMConfig, VMWrapper, VMError, VMStackFrameError, Error, NullLoader};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io, mem};
+
+pub(crate) const MODULE: &str = "ruwren/test";
+
+pub(crate) const SOURCE: &str = r##"
+class Test {
+    static case(name, fn) {
+        if (__cases == null) __cases = []
+        __cases.add([name, fn])
+    }
+
+    // Taken by the runner once the file has run, which then runs them one at a time
+    static take_() {
+        __running = __cases == null ? [] : __cases
+        __cases = null
+        return __running.count
+    }
+
+    static name_(i) { __running[i][0].toString }
+    static run_(i) { __running[i][1].call() }
+}
+
+class Assert {
+    static equal(actual, expected) {
+        if (actual != expected) Fiber.abort("Expected %(expected), got %(actual)")
+    }
+
+    static notEqual(actual, unexpected) {
+        if (actual == unexpected) Fiber.abort("Expected anything but %(unexpected)")
+    }
+
+    static isTrue(value) {
+        if (!value) Fiber.abort("Expected a true value, got %(value)")
+    }
+
+    static isFalse(value) {
+        if (value) Fiber.abort("Expected a false value, got %(value)")
+    }
+
+    static isNull(value) {
+        if (value != null) Fiber.abort("Expected null, got %(value)")
+    }
+
+    static aborts(fn) {
+        var fiber = Fiber.new(fn)
+        fiber.try()
+        if (fiber.error == null) Fiber.abort("Expected the function to abort")
+    }
+
+    static fail(message) { Fiber.abort(message) }
+}
+"##;
+
+const SUFFIX: &str = "_test.wren";
+
+/// Runs every Wren test file under a directory as one Rust test
+///
+/// ```ignore
+/// ruwren::wren_tests!(script_tests, "tests/wren");
+/// ruwren::wren_tests!(game_tests, "tests/game", || VMConfig::new().library(&game_library()));
+/// ```
+///
+/// The directory is relative to the crate's manifest.
+#[macro_export]
+macro_rules! wren_tests {
+    ($name:ident, $dir:expr) => {
+        $crate::wren_tests!($name, $dir, $crate::VMConfig::new);
+    };
+    ($name:ident, $dir:expr, $config:expr) => {
+        #[test]
+        fn $name() {
+            $crate::TestRunner::new(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join($dir))
+                .config($config)
+                .run()
+                .unwrap()
+                .assert_passed();
+        }
+    };
+}
+
+/// Finds and runs `*_test.wren` files under a directory
+pub struct TestRunner {
+    dir: PathBuf,
+    config: Box<dyn Fn() -> VMConfig>,
+    shared: bool,
+}
+
+impl TestRunner {
+    pub fn new<P: AsRef<Path>>(dir: P) -> TestRunner {
+        TestRunner {
+            dir: dir.as_ref().to_path_buf(),
+            config: Box::new(VMConfig::new),
+            shared: false,
+        }
+    }
+
+    /// Builds the config for each test file's VM (by default, [VMConfig::new])
+    ///
+    /// Tests can import the files next to them, falling back to the config's own script loader.
+    pub fn config<F: 'static + Fn() -> VMConfig>(mut self, config: F) -> Self {
+        self.config = Box::new(config);
+        self
+    }
+
+    /// Runs every file in one VM instead of a fresh one each, for suites that are slow to set up
+    ///
+    /// Files then share modules they import, which are looked for from the runner's directory
+    /// rather than next to each file.
+    pub fn shared_vm(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Every test file, sorted by path
+    pub fn discover(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.to_string_lossy().ends_with(SUFFIX) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn run(&self) -> io::Result<TestReport> {
+        let shared = match self.shared {
+            true => Some(self.build(self.dir.clone())),
+            false => None,
+        };
+        let mut files = vec![];
+        for path in self.discover()? {
+            let source = fs::read_to_string(&path)?;
+            let module = path.strip_prefix(&self.dir).unwrap_or(&path).with_extension("")
+                .to_string_lossy().replace('\\', "/");
+            let report = match &shared {
+                Some(vm) => run_file(vm, path, &module, &source),
+                None => {
+                    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                    run_file(&self.build(dir), path, &module, &source)
+                },
+            };
+            files.push(report);
+        }
+        Ok(TestReport { files })
+    }
+
+    // A VM from the config, which can import the files in [dir], falling back to its own script loader
+    fn build(&self, dir: PathBuf) -> VMWrapper {
+        let mut config = (self.config)();
+        let mut fallback = mem::replace(&mut config.script_loader, Box::new(NullLoader));
+        config.script_loader = Box::new(move |name: String| {
+            fs::read_to_string(dir.join(format!("{}.wren", name))).ok().map(Cow::Owned).or_else(|| fallback.load_script(name))
+        });
+        config.build()
+    }
+}
+
+fn run_file(vm: &VMWrapper, path: PathBuf, module: &str, source: &str) -> FileReport {
+    let mut report = FileReport { path, error: None, cases: vec![] };
+    if let Err(err) = vm.interpret(module, source) {
+        report.error = Some(err);
+    }
+    // Only loaded if the file used it
+    if !vm.execute(|vm| vm.has_variable(MODULE, "Test")) {
+        return report;
+    }
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable(MODULE, "Test", 0);
+    });
+    let test = vm.get_slot_handle(0);
+    let test = &*test;
+    // Taken even from a file that failed, so a shared VM doesn't run them with the next file
+    let count = match vm.call_on(test, "take_()").invoke::<f64>() {
+        Ok(count) => count as usize,
+        Err(err) => {
+            report.error.get_or_insert(into_vm_error(err));
+            return report;
+        },
+    };
+    // Cases a file declared before failing still don't run
+    if report.error.is_some() {
+        return report;
+    }
+    for i in 0..count {
+        let name = vm.call_on(test, "name_(_)").arg(i as f64).invoke::<String>().unwrap_or_default();
+        let (error, frames) = match vm.call_on(test, "run_(_)").arg(i as f64).invoke::<()>().map_err(into_vm_error) {
+            Ok(()) => (None, vec![]),
+            Err(VMError::Runtime { error, frames }) => (Some(error), frames),
+            Err(err) => (Some(err.to_string()), vec![]),
+        };
+        report.cases.push(CaseResult { name, error, frames });
+    }
+    report
+}
+
+fn into_vm_error(err: Error) -> VMError {
+    match err {
+        Error::VM(err) => err,
+        err => VMError::Runtime { error: err.to_string(), frames: vec![] },
+    }
+}
+
+/// What happened to every test file
+#[derive(Debug)]
+pub struct TestReport {
+    pub files: Vec<FileReport>,
+}
+
+/// What happened to one test file
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// Set if the file didn't compile, or aborted outside of a case
+    pub error: Option<VMError>,
+    pub cases: Vec<CaseResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub name: String,
+    /// Why the case failed, if it did
+    pub error: Option<String>,
+    /// Where it failed, innermost first
+    pub frames: Vec<VMStackFrameError>,
+}
+
+impl FileReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.cases.iter().all(|case| case.error.is_none())
+    }
+}
+
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.files.iter().all(FileReport::passed)
+    }
+
+    /// The number of (passed, failed) cases, counting files without cases as one each
+    pub fn counts(&self) -> (usize, usize) {
+        self.files.iter().fold((0, 0), |(passed, failed), file| {
+            let (p, f) = match (&file.error, file.cases.len()) {
+                (Some(_), _) => (0, file.cases.len().max(1)),
+                (None, 0) => (1, 0),
+                (None, _) => {
+                    let f = file.cases.iter().filter(|case| case.error.is_some()).count();
+                    (file.cases.len() - f, f)
+                },
+            };
+            (passed + p, failed + f)
+        })
+    }
+
+    /// Panics with the report if anything failed, for running a suite from `cargo test`
+    pub fn assert_passed(&self) {
+        if !self.passed() {
+            panic!("Wren tests failed:\n{}", self);
+        }
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for file in &self.files {
+            writeln!(fmt, "{} {}", if file.passed() { "ok  " } else { "FAIL" }, file.path.display())?;
+            if let Some(error) = &file.error {
+                writeln!(fmt, "    {}", error.to_string().trim_end().replace('\n', "\n    "))?;
+            }
+            for case in &file.cases {
+                match &case.error {
+                    Some(error) => {
+                        writeln!(fmt, "    FAIL {}: {}", case.name, error)?;
+                        for frame in &case.frames {
+                            writeln!(fmt, "        {}", frame)?;
+                        }
+                    },
+                    None => writeln!(fmt, "    ok   {}", case.name)?,
+                }
+            }
+        }
+        let (passed, failed) = self.counts();
+        write!(fmt, "{} passed, {} failed", passed, failed)
+    }
+}
@@ -62,6 +62,21 @@ create_module! {
     module => main
 }
 
+// A VM with the modules [publish] registers in its library
+fn library_vm(publish: fn(&mut super::ModuleLibrary)) -> super::VMWrapper {
+    let mut lib = super::ModuleLibrary::new();
+    publish(&mut lib);
+    VMConfig::new().library(&lib).build()
+}
+
+// The message of the runtime error [result] failed with
+fn runtime_error<T: std::fmt::Debug>(result: Result<T, super::VMError>) -> String {
+    match result {
+        Err(super::VMError::Runtime { error, .. }) => error,
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
 #[test]
 fn init_vm() {
     let _ = VMConfig::new().build();
@@ -104,9 +119,7 @@ fn test_small_wren_program_call() {
 
 #[test]
 fn test_external_module() {
-    let mut lib = super::ModuleLibrary::new();
-    main::publish_module(&mut lib);
-    let vm = VMConfig::new().library(&lib).build();
+    let vm = library_vm(main::publish_module);
     let source = vm.interpret("main", "
     class Math {
         foreign static add5(a)
@@ -164,17 +177,18 @@ fn test_script_module() {
     struct TestLoader;
 
     impl super::ModuleScriptLoader for TestLoader {
-        fn load_script(&mut self, name: String) -> Option<String> {
-            if name == "math" {
-                Some("
+        fn load_script(&mut self, name: String) -> Option<std::borrow::Cow<'static, str>> {
+            match name.as_str() {
+                "math" => Some("
                 class Math {
                     static add5(val) {
                         return val + 5
                     }
                 }
-                ".into())
-            } else {
-                None
+                ".into()),
+                // Handed to Wren as is
+                "constants" => Some("var Five = 5\0".into()),
+                _ => None,
             }
         }
     }
@@ -182,6 +196,7 @@ fn test_script_module() {
     let vm = VMConfig::new().script_loader(TestLoader).build();
     let source = vm.interpret("main", "
     import \"math\" for Math
+    import \"constants\" for Five
 
     class GameEngine {
         static update(elapsedTime) {
@@ -209,9 +224,7 @@ fn test_script_module() {
 
 #[test]
 fn foreign_instance() {
-    let mut lib = super::ModuleLibrary::new();
-    main::publish_module(&mut lib);
-    let vm = VMConfig::new().library(&lib).build();
+    let vm = library_vm(main::publish_module);
     let source = vm.interpret("main", "
     class Math {
         foreign static add5(a)
@@ -253,4 +266,3210 @@ fn foreign_instance() {
         assert_eq!(vm.get_slot_type(0), super::SlotType::Num);
         assert_eq!(vm.get_slot_double(0), Some(21.45));
     });
-}
\ No newline at end of file
+}
+
+#[test]
+fn foreign_borrow_tracking() {
+    use super::ForeignBorrowError;
+
+    let vm = library_vm(main::publish_module);
+    vm.interpret("main", "
+    foreign class RawPoint {
+        construct new(x) {}
+    }
+    var Point = RawPoint.new(1)
+    ").unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Point", 0);
+        // Both slots hold the same object, as happens when a method is passed its own receiver
+        vm.get_variable("main", "Point", 1);
+        let nested = vm.try_borrow_foreign_mut(0, |p: &mut Point| {
+            p.x = 2.0;
+            (vm.try_borrow_foreign(1, |p: &Point| p.x), vm.try_borrow_foreign_mut(1, |p: &mut Point| p.x))
+        });
+        assert_eq!(nested, Ok((Err(ForeignBorrowError::AlreadyBorrowed), Err(ForeignBorrowError::AlreadyBorrowed))));
+        let shared = vm.try_borrow_foreign(0, |_: &Point| {
+            (vm.try_borrow_foreign(1, |p: &Point| p.x), vm.try_borrow_foreign_mut(1, |p: &mut Point| p.x))
+        });
+        assert_eq!(shared, Ok((Ok(2.0), Err(ForeignBorrowError::AlreadyBorrowed))));
+        // Released once the closures return
+        assert_eq!(vm.try_borrow_foreign_mut(1, |p: &mut Point| p.x), Ok(2.0));
+
+        assert_eq!(vm.try_borrow_foreign(0, |_: &Math| ()), Err(ForeignBorrowError::TypeMismatch));
+        vm.set_slot_double(1, 1.0);
+        assert_eq!(vm.try_borrow_foreign(1, |_: &Point| ()), Err(ForeignBorrowError::NotForeign));
+    });
+}
+
+#[test]
+fn foreign_guards() {
+    let vm = library_vm(main::publish_module);
+    vm.interpret("main", "
+    foreign class RawPoint {
+        construct new(x) {}
+    }
+    ").unwrap();
+
+    vm.execute(|inner| {
+        inner.ensure_slots(2);
+        let mut point = inner.set_slot_new_foreign("main", "RawPoint", Point { x: 3.0 }, 1).unwrap();
+        assert!(inner.get_slot_foreign::<Point>(1).is_none());
+        // Nothing else refers to the object, but the guard keeps it alive
+        inner.set_slot_null(0);
+        inner.set_slot_null(1);
+        vm.collect_garbage();
+        point.x += 1.0;
+        assert_eq!(point.x, 4.0);
+    });
+
+    vm.execute(|inner| {
+        inner.ensure_slots(2);
+        drop(inner.set_slot_new_foreign("main", "RawPoint", Point { x: 5.0 }, 1).unwrap());
+        {
+            let first = inner.get_slot_foreign::<Point>(1).unwrap();
+            let second = inner.get_slot_foreign::<Point>(1).unwrap();
+            assert_eq!(first.x + second.x, 10.0);
+            assert!(inner.get_slot_foreign_mut::<Point>(1).is_none());
+        }
+        let mut point = inner.get_slot_foreign_mut::<Point>(1).unwrap();
+        point.x = 6.0;
+        assert!(inner.get_slot_foreign::<Point>(1).is_none());
+        drop(point);
+        assert_eq!(inner.get_slot_foreign::<Point>(1).map(|p| p.x), Some(6.0));
+    });
+
+    // Pins borrow through the same flags
+    let mut pin = vm.pin(1).unwrap();
+    vm.execute(|inner| {
+        let point = inner.get_slot_foreign_mut::<Point>(1).unwrap();
+        assert!(pin.get::<Point>().is_none());
+        drop(point);
+        let shared = pin.get::<Point>().unwrap();
+        assert!(inner.get_slot_foreign_mut::<Point>(1).is_none());
+        assert_eq!(shared.x, 6.0);
+    });
+    pin.get_mut::<Point>().unwrap().x = 7.0;
+    assert!(pin.get::<Math>().is_none());
+    vm.execute(|inner| assert_eq!(inner.get_slot_foreign::<Point>(1).map(|p| p.x), Some(7.0)));
+}
+
+#[test]
+fn capability_command_allowlist() {
+    use super::{Capability, CapabilityPolicy};
+
+    let policy = CapabilityPolicy::new();
+    assert!(!policy.is_command_allowed("echo"));
+
+    let policy = policy.grant(Capability::Process);
+    assert!(policy.is_command_allowed("echo"));
+
+    let policy = policy.allow_command("echo");
+    assert!(policy.is_command_allowed("echo"));
+    assert!(!policy.is_command_allowed("rm"));
+}
+
+#[test]
+fn path_normalize() {
+    use std::path::Path;
+    use super::stdlib::path::normalize;
+
+    assert_eq!(normalize(Path::new("a/./b/../c")), Path::new("a/c"));
+    assert_eq!(normalize(Path::new("../a/..")), Path::new(".."));
+    assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    assert_eq!(normalize(Path::new("a/..")), Path::new("."));
+}
+
+#[test]
+fn encoding_roundtrip() {
+    use super::stdlib::encoding::{base64_encode, base64_decode, hex_encode, hex_decode};
+
+    assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+    assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    assert_eq!(base64_decode("Zm9vYg==").as_deref(), Some(&b"foob"[..]));
+    assert_eq!(base64_decode("Zm9vYg").as_deref(), Some(&b"foob"[..]));
+    assert_eq!(base64_decode("Z"), None);
+    assert_eq!(hex_encode(&[0xde, 0xad, 0x01]), "dead01");
+    assert_eq!(hex_decode("DEAD01").as_deref(), Some(&[0xde, 0xad, 0x01][..]));
+    assert_eq!(hex_decode("abc"), None);
+}
+
+#[cfg(feature = "hash")]
+#[test]
+fn hash_fnv1a() {
+    use super::stdlib::hash::fnv1a;
+
+    assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+    assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+}
+
+#[test]
+fn string_policies() {
+    use super::{Printer, StringPolicy, VMError};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Bytes(Rc<RefCell<Vec<u8>>>);
+    impl Printer for Bytes {
+        fn print(&mut self, s: String) {
+            self.0.borrow_mut().extend(s.bytes());
+        }
+        fn print_bytes(&mut self, bytes: &[u8]) {
+            self.0.borrow_mut().extend(bytes);
+        }
+    }
+    let source = "System.print(\"bad \" + String.fromByte(255))";
+
+    let out = Bytes::default();
+    let vm = VMConfig::new().printer(out.clone()).build();
+    vm.interpret("main", source).unwrap();
+    assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "bad \u{fffd}\n");
+
+    let out = Bytes::default();
+    let vm = VMConfig::new().string_policy(StringPolicy::Bytes).printer(out.clone()).build();
+    vm.interpret("main", source).unwrap();
+    assert_eq!(*out.0.borrow(), b"bad \xff\n");
+
+    let out = Bytes::default();
+    let vm = VMConfig::new().string_policy(StringPolicy::Strict).printer(out.clone()).build();
+    assert!(matches!(vm.interpret("main", source), Err(VMError::InvalidUtf8 { .. })));
+    assert_eq!(*out.0.borrow(), b"\n");
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.set_slot_bytes(0, b"\xff");
+        vm.set_slot_string(1, "fine");
+        assert_eq!(vm.get_slot_string(0), None);
+        assert_eq!(vm.get_slot_string(1).as_deref(), Some("fine"));
+    });
+    // The violation doesn't stick to the next run
+    vm.interpret("main", "System.print(\"ok\")").unwrap();
+}
+
+#[test]
+fn lifecycle_hooks() {
+    use std::rc::Rc;
+    use std::cell::Cell;
+
+    let created = Rc::new(Cell::new(false));
+    let interprets = Rc::new(Cell::new(0));
+    let (c, i) = (created.clone(), interprets.clone());
+    let vm = VMConfig::new()
+        .on_vm_created(move |_| c.set(true))
+        .on_vm_dropped(move |report| i.set(report.interpret_count))
+        .build();
+    assert!(created.get());
+
+    vm.interpret("main", "var x = 1").unwrap();
+    vm.interpret("main", "var y = 2").unwrap();
+    drop(vm);
+    assert_eq!(interprets.get(), 2);
+}
+
+#[test]
+fn pinned_foreign_survives_gc() {
+    let vm = library_vm(main::publish_module);
+    vm.interpret("main", "
+    foreign class RawPoint {
+        construct new(x) {}
+    }
+
+    var Points = [RawPoint.new(1), RawPoint.new(2)]
+    ").unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Points", 0);
+    });
+    let pins = vm.pin_all(0).unwrap();
+    vm.interpret("main", "Points = null").unwrap();
+    vm.collect_garbage();
+
+    let xs: Vec<_> = pins.iter().map(|p| p.get::<Point>().unwrap().x).collect();
+    assert_eq!(xs, vec![1.0, 2.0]);
+}
+
+#[test]
+fn require_send_classes() {
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    assert!(lib.non_send_classes().is_empty());
+    assert!(VMConfig::new().library(&lib).require_send(true).try_build().is_ok());
+
+    let mut module = super::Module::new();
+    module.class::<Point, _>("RawPoint");
+    lib.module("unchecked", module);
+    match VMConfig::new().library(&lib).require_send(true).try_build() {
+        Err(super::BuildError::NonSendClass { module, class }) => {
+            assert_eq!(module, "unchecked");
+            assert_eq!(class, "RawPoint");
+        },
+        _ => panic!("expected a NonSendClass error"),
+    }
+}
+
+#[test]
+fn sync_vm_across_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<super::SyncVM>();
+    assert_send_sync::<super::SyncHandle>();
+
+    assert!(super::SyncVM::new(VMConfig::new().printer(|_: String| {})).is_err());
+
+    let vm = super::SyncVM::new(VMConfig::new().send_printer(|_: String| {})).unwrap();
+    let remote = vm.clone();
+    std::thread::spawn(move || {
+        remote.interpret("main", "var Answer = 42").unwrap();
+    }).join().unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Answer", 0);
+        assert_eq!(vm.get_slot_double(0), Some(42.0));
+    });
+}
+
+#[test]
+fn profiling_session_counts_foreign_calls() {
+    let vm = library_vm(main::publish_module);
+    vm.interpret("main", "
+    class Math {
+        foreign static add5(a)
+    }
+    ").unwrap();
+
+    let session = vm.profiling_session();
+    vm.interpret("main", "
+    for (i in 0...10) Math.add5(i)
+    ").unwrap();
+    vm.collect_garbage();
+    let report = session.finish();
+
+    assert_eq!(report.foreign_methods["Math.add5(_)"].calls, 10);
+    assert!(report.script + report.foreign + report.gc <= report.wall);
+}
+
+#[cfg(feature = "coverage")]
+#[test]
+fn coverage_tracks_executed_lines() {
+    let vm = VMConfig::new().coverage(true).build();
+    vm.interpret("main", "var x = 1
+if (x > 1) {
+    x = 2
+}
+System.print(x)").unwrap();
+
+    let report = vm.coverage_report().unwrap();
+    let main = &report.modules["main"];
+    assert_eq!(main.hits.get(&1), Some(&1));
+    assert_eq!(main.missed(), vec![3]);
+
+    match vm.interpret("main", "var y = 1\nFiber.abort(\"oops\")") {
+        Err(super::VMError::Runtime { frames, .. }) => assert_eq!(frames[0].line, 2),
+        _ => panic!("expected a runtime error"),
+    }
+}
+
+#[cfg(feature = "debugger")]
+#[test]
+fn debugger_pauses_on_breakpoint() {
+    use super::{DebugAction, DebugValue, PauseReason};
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    let pauses = Rc::new(RefCell::new(vec![]));
+    let p = pauses.clone();
+    let vm = VMConfig::new()
+        .on_pause(move |event, _| {
+            p.borrow_mut().push(event.clone());
+            DebugAction::Step
+        })
+        .build();
+    vm.set_breakpoint("main", 2);
+    vm.interpret("main", "var x = 1
+x = x + 1
+var y = x").unwrap();
+
+    let pauses = pauses.borrow();
+    assert_eq!(pauses.len(), 2);
+    assert_eq!(pauses[0].reason, PauseReason::Breakpoint);
+    assert_eq!(pauses[0].variables, vec![("x".to_string(), DebugValue::Num(1.0))]);
+    assert_eq!((pauses[1].line, pauses[1].reason), (3, PauseReason::Step));
+}
+
+fn clamp(vm: &super::VM) {
+    let x = get_slot_checked!(vm => num 1);
+    let lo = get_slot_checked!(vm => num 2);
+    let hi = get_slot_checked!(vm => num 3);
+    vm.set_slot_double(0, x.max(lo).min(hi));
+}
+
+mod namespaced {
+    use super::super::create_namespace;
+    use super::clamp;
+
+    create_namespace! {
+        namespace("Math2") Math2 => math2 {
+            (fn "clamp", 3) clamp
+        }
+
+        module => utils
+    }
+
+    pub use self::utils::publish_module;
+}
+
+#[test]
+fn namespace_free_functions() {
+    let vm = library_vm(namespaced::publish_module);
+    vm.interpret("utils", "
+    class Math2 {
+        foreign static clamp(x, lo, hi)
+    }
+    var Clamped = Math2.clamp(12, 0, 10)
+    ").unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("utils", "Clamped", 0);
+        assert_eq!(vm.get_slot_double(0), Some(10.0));
+    });
+}
+
+struct Counter {
+    count: f64,
+}
+
+impl super::Class for Counter {
+    fn initialize(vm: &super::VM) -> Counter {
+        Counter { count: get_slot_checked!(vm => num 1) }
+    }
+}
+
+impl Counter {
+    fn count(&self, vm: &super::VM) {
+        vm.set_slot_double(0, self.count);
+    }
+
+    fn set_count(&mut self, vm: &super::VM) {
+        self.count = get_slot_checked!(vm => num 1);
+    }
+}
+
+mod inline_wren {
+    create_module! {
+        class("Counter") Counter => counter {
+            instance(getter "count") count,
+            instance(setter "count") set_count
+        } wren(r#"
+    construct new(start) {}
+    increment() { count = count + 1 }
+"#)
+
+        module => counters
+    }
+
+    use super::{create_module, Counter};
+    pub use self::counters::{publish_module, source};
+}
+
+#[test]
+fn inline_wren_source() {
+    let source = inline_wren::source();
+    assert!(source.starts_with("foreign class Counter {\n    construct new(start) {}\n"));
+    assert!(source.contains("    foreign count=(value)\n"));
+
+    let vm = library_vm(inline_wren::publish_module);
+    vm.interpret("main", "
+    import \"counters\" for Counter
+    var c = Counter.new(1)
+    c.increment()
+    var Count = c.count
+    ").unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Count", 0);
+        assert_eq!(vm.get_slot_double(0), Some(2.0));
+    });
+}
+
+#[test]
+fn static_only_classes_are_plain() {
+    let source = main::source();
+    assert!(source.contains("foreign class RawPoint {\n"));
+    assert!(source.contains("\nclass Math {\n    foreign static add5(arg0)\n"));
+
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).strict_library(true).build();
+    vm.interpret("game", "import \"main\" for Math\nvar Six = Math.add5(1)").unwrap();
+    assert_eq!(vm.interpret_returning::<f64, _, _>("game", "Six").unwrap(), 6.0);
+    assert!(vm.validate_library().is_ok());
+}
+
+#[test]
+fn define_module_constants() {
+    use super::WrenValue;
+
+    let vm = VMConfig::new()
+        .define("host", "Version", "1.2 \"beta\" 100%")
+        .define("host", "Features", vec!["process", "hash"])
+        .define("main", "Debug", true)
+        .build();
+    vm.interpret("main", "
+    import \"host\" for Version, Features
+    var Summary = Debug ? \"%(Version) %(Features.count)\" : null
+    ").unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Summary", 0);
+        assert_eq!(vm.get_slot_string(0).as_deref(), Some("1.2 \"beta\" 100% 2"));
+    });
+
+    let bad = VMConfig::new().define("main", "not a name", WrenValue::Null).try_build();
+    assert!(matches!(bad, Err(super::BuildError::InvalidDefinition { .. })));
+}
+
+#[test]
+fn foreign_frames_in_stack_trace() {
+    use super::{FrameKind, VMError};
+
+    let vm = library_vm(main::publish_module);
+    vm.interpret("main", "
+    class Math {
+        foreign static add5(a)
+    }
+    class Caller {
+        construct new() {}
+        call() { Math.add5(\"five\") }
+    }
+    ").unwrap();
+
+    match vm.interpret("main", "Caller.new().call()") {
+        Err(VMError::Runtime { error, frames }) => {
+            assert!(error.starts_with("Math.add5(_): "), "{}", error);
+            assert!(frames[0].is_foreign());
+            assert_eq!(frames[0].function, "Math.add5(_)");
+            assert_eq!((frames[1].kind, frames[1].signature.as_str()), (FrameKind::Method, "call()"));
+            assert_eq!(frames.last().unwrap().kind, FrameKind::Module);
+        },
+        _ => panic!("expected a runtime error"),
+    }
+}
+
+#[test]
+fn error_kinds() {
+    use super::{Error, ErrorKind};
+
+    let vm = VMConfig::new().build();
+    let err: Error = vm.interpret("main", "var = 1").unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Compile);
+    let err: Error = vm.interpret("main", "Fiber.abort(\"no\")").unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Runtime);
+    assert!(err.to_string().starts_with("Runtime Error: no\n"));
+}
+
+#[test]
+fn call_depth_limit() {
+    use super::VMError;
+
+    let vm = VMConfig::new().max_call_depth(0).build();
+    match vm.interpret("main", "var x = 1") {
+        Err(VMError::CallDepthExceeded { limit }) => assert_eq!(limit, 0),
+        other => panic!("expected the call depth to be exceeded, got {:?}", other),
+    }
+
+    // The depth is released once a call finishes
+    let vm = VMConfig::new().max_call_depth(1).build();
+    vm.interpret("main", "var x = 1").unwrap();
+    vm.interpret("main", "var y = 2").unwrap();
+}
+
+#[test]
+fn wren_value_formatting() {
+    use super::{WrenValue, WrenList, WrenMap};
+
+    let mut map = WrenMap::new();
+    map.insert("name", "say \"hi\"");
+    map.insert("sizes", vec![1.0, 2.5]);
+    map.insert("empty", WrenList::new());
+    let value = WrenValue::Map(map);
+    assert_eq!(value.to_string(), r#"{"name": "say \"hi\"", "sizes": [1, 2.5], "empty": []}"#);
+    assert_eq!(value.format_compact(), value.to_string());
+    assert_eq!(value.format_indented(2), "{\n  \"name\": \"say \\\"hi\\\"\",\n  \"sizes\": [\n    1,\n    2.5\n  ],\n  \"empty\": []\n}");
+}
+
+#[test]
+fn list_and_map_views() {
+    use super::{WrenValue, WrenMap};
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var Items = [1, \"two\", [true, null]]").unwrap();
+    let list = vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Items", 0);
+        vm.get_slot::<super::WrenList>(0).unwrap()
+    });
+    assert_eq!(list[1], WrenValue::from("two"));
+    assert_eq!(list.iter().count(), 3);
+    let mut nums = 0;
+    for item in &list {
+        if let WrenValue::Num(_) = item {
+            nums += 1;
+        }
+    }
+    assert_eq!(nums, 1);
+
+    let map: WrenMap = vec![("a", 1), ("b", 2), ("a", 3)].into_iter().collect();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["a"], WrenValue::Num(3.0));
+    let total: f64 = (&map).into_iter().map(|(_, v)| match v { WrenValue::Num(n) => *n, _ => 0.0 }).sum();
+    assert_eq!(total, 5.0);
+}
+
+#[test]
+fn spawn_foreigns_in_bulk() {
+    let vm = library_vm(main::publish_module);
+    vm.interpret("main", "
+    foreign class RawPoint {
+        construct new(x) {}
+
+        foreign x()
+        foreign set_x(val)
+    }
+    ").unwrap();
+
+    let points = vm.spawn_foreigns("main", "RawPoint", (0..100).map(|i| Point { x: i as f64 }).collect()).unwrap();
+    vm.collect_garbage();
+    assert_eq!(points.len(), 100);
+    assert_eq!(points[42].with(|p| p.x), Ok(42.0));
+    points[42].with_mut(|p| p.x = -1.0).unwrap();
+
+    vm.execute(|vm| vm.ensure_slots(1));
+    vm.set_slot_handle(0, points[42].handle());
+    vm.execute(|vm| {
+        assert_eq!(vm.get_slot_foreign::<Point>(0).map(|p| p.x), Some(-1.0));
+    });
+
+    assert!(vm.spawn_foreigns("main", "Missing", vec![Point { x: 0.0 }]).is_err());
+}
+
+#[test]
+fn with_sinks_routes_output() {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    let outer = Rc::new(RefCell::new(String::new()));
+    let o = outer.clone();
+    let vm = VMConfig::new().printer(move |s: String| o.borrow_mut().push_str(&s)).build();
+
+    let inner = Rc::new(RefCell::new(String::new()));
+    let errors = Rc::new(RefCell::new(vec![]));
+    let (i, e) = (inner.clone(), errors.clone());
+    vm.with_sinks(move |s: String| i.borrow_mut().push_str(&s), move |err: &super::WrenError| e.borrow_mut().push(err.kind()), |vm| {
+        vm.interpret("main", "System.print(\"inside\")").unwrap();
+        assert!(vm.interpret("main", "Fiber.abort(\"oops\")").is_err());
+    });
+    vm.interpret("main", "System.print(\"outside\")").unwrap();
+
+    assert_eq!(*inner.borrow(), "inside\n");
+    assert_eq!(*outer.borrow(), "outside\n");
+    assert_eq!(errors.borrow()[0], super::ErrorKind::Runtime);
+}
+
+#[test]
+fn write_printers() {
+    use super::{WritePrinter, FlushPolicy};
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    // Records each write, and each flush as "|"
+    #[derive(Clone, Default)]
+    struct Console(Rc<RefCell<Vec<String>>>);
+    impl io::Write for Console {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().push(String::from_utf8_lossy(buf).into_owned());
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.borrow_mut().push("|".to_string());
+            Ok(())
+        }
+    }
+    let source = "System.write(\"a\")\nSystem.print(\"b\")\nSystem.write(\"c\")";
+
+    let console = Console::default();
+    let vm = VMConfig::new().printer(WritePrinter::new(console.clone())).build();
+    vm.interpret("main", source).unwrap();
+    assert_eq!(*console.0.borrow(), ["ab\n", "|"]);
+    // What's left of the last line is written when the printer is dropped
+    drop(vm);
+    assert_eq!(*console.0.borrow(), ["ab\n", "|", "c", "|"]);
+
+    let console = Console::default();
+    let printer = WritePrinter::new(console.clone()).line_buffered(false).flush(FlushPolicy::Never);
+    let vm = VMConfig::new().printer(printer).build();
+    vm.interpret("main", source).unwrap();
+    assert_eq!(*console.0.borrow(), ["a", "b", "\n", "c"]);
+}
+
+#[test]
+fn error_handler_streams_errors() {
+    use super::WrenError;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    let seen = Rc::new(RefCell::new(vec![]));
+    let s = seen.clone();
+    let vm = VMConfig::new().error_handler(move |err: &WrenError| s.borrow_mut().push(err.clone())).build();
+
+    assert!(vm.interpret("main", "var = 1").is_err());
+    assert!(vm.interpret("main", "Fiber.abort(\"oops\")").is_err());
+    assert!(matches!(&seen.borrow()[0], WrenError::Compile(module, 1, _) if module == "main"));
+    // The compiler can report more than one error for a line
+    let runtime = seen.borrow().iter().position(|err| matches!(err, WrenError::Runtime(_))).unwrap();
+    assert!(matches!(&seen.borrow()[runtime], WrenError::Runtime(message) if message == "oops"));
+    assert!(matches!(&seen.borrow()[runtime + 1], WrenError::StackTrace(module, 1, _) if module == "main"));
+
+    // Put back after being swapped out
+    let count = seen.borrow().len();
+    vm.with_sinks(|_: String| {}, |_: &WrenError| {}, |vm| {
+        assert!(vm.interpret("main", "Fiber.abort(\"hidden\")").is_err());
+    });
+    assert_eq!(seen.borrow().len(), count);
+    assert!(vm.interpret("main", "Fiber.abort(\"again\")").is_err());
+    assert!(matches!(&seen.borrow()[count], WrenError::Runtime(message) if message == "again"));
+}
+
+#[test]
+fn interned_modules() {
+    use super::FunctionSignature;
+
+    let vm = library_vm(main::publish_module);
+    let main = vm.intern_module("main");
+    assert_eq!(main, vm.intern_module("main"));
+
+    vm.interpret(&main, "
+    class Math {
+        foreign static add5(a)
+    }
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.set_slot_double(1, 10.0);
+    });
+    vm.call_static(&main, "Math", FunctionSignature::new_function("add5", 1)).unwrap();
+    vm.execute(|vm| assert_eq!(vm.get_slot_double(0), Some(15.0)));
+}
+
+#[test]
+fn registered_callbacks() {
+    use super::WrenValue;
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    import \"ruwren/callbacks\" for Callbacks
+    Callbacks.register(\"add\", Fn.new {|a, b| a + b })
+    Callbacks.register(\"drop\", Fn.new { null })
+    ").unwrap();
+    assert_eq!(vm.callback_keys(), vec!["add", "drop"]);
+
+    // The function has to survive a GC with nothing else referencing it
+    vm.collect_garbage();
+    vm.invoke_callback("add", &[WrenValue::Num(2.0), WrenValue::Num(3.0)]).unwrap().unwrap();
+    vm.execute(|vm| assert_eq!(vm.get_slot_double(0), Some(5.0)));
+
+    assert!(vm.unregister_callback("drop"));
+    assert!(!vm.has_callback("drop"));
+    assert!(vm.invoke_callback("drop", &[]).is_none());
+
+    vm.interpret("main", "Callbacks.unregister(\"add\")").unwrap();
+    assert!(vm.callback_keys().is_empty());
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn derived_records() {
+    use super::{WrenRecord, WrenValue, FromWrenValue};
+
+    #[derive(Debug, Clone, PartialEq, WrenRecord)]
+    struct Spawn {
+        name: String,
+        #[wren(rename = "hitPoints")]
+        hit_points: i32,
+        tags: Vec<String>,
+        boss: Option<bool>,
+    }
+
+    let spawn = Spawn { name: "slime".into(), hit_points: 12, tags: vec!["small".into()], boss: None };
+    let map = spawn.to_wren_map();
+    assert_eq!(map["hitPoints"], WrenValue::Num(12.0));
+    assert_eq!(Spawn::from_wren_map(&map), Some(spawn.clone()));
+    assert_eq!(Spawn::from_wren_value(&WrenValue::Null), None);
+    // An Option field can be left out, but not given the wrong kind of value
+    let without = |key: &str| map.iter().filter(|(k, _)| *k != WrenValue::from(key)).cloned().collect::<super::WrenMap>();
+    assert_eq!(Spawn::from_wren_map(&without("boss")), Some(spawn.clone()));
+    assert_eq!(Spawn::from_wren_map(&without("name")), None);
+    let mut wrong = map.clone();
+    wrong.insert("boss", "yes");
+    assert_eq!(Spawn::from_wren_map(&wrong), None);
+
+    let vm = VMConfig::new().define("main", "Event", spawn).build();
+    vm.interpret("main", "var Hp = Event[\"hitPoints\"] + Event[\"tags\"].count").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Hp", 0);
+        assert_eq!(vm.get_slot_double(0), Some(13.0));
+    });
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn derived_enums() {
+    use super::{WrenEnum, WrenValue, FromWrenValue};
+
+    #[derive(Debug, Clone, Copy, PartialEq, WrenEnum)]
+    enum Direction {
+        #[wren(rename = "north")]
+        North,
+        #[wren(rename = "east")]
+        East,
+        #[wren(rename = "south")]
+        South,
+        #[wren(rename = "west")]
+        West,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, WrenEnum)]
+    #[wren(numbers)]
+    enum Layer {
+        Ground = 1,
+        Sky = 4,
+    }
+
+    assert_eq!(WrenValue::from(Direction::South), WrenValue::String("south".into()));
+    assert_eq!(WrenValue::from(Layer::Sky), WrenValue::Num(4.0));
+    assert_eq!(Layer::from_wren_value(&WrenValue::String("Ground".into())), Some(Layer::Ground));
+
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.set_slot_string(0, "west");
+        assert_eq!(vm.get_slot_enum::<Direction>(0), Ok(Direction::West));
+        vm.set_slot(1, Layer::Sky).unwrap();
+        assert_eq!(vm.get_slot_double(1), Some(4.0));
+        assert_eq!(vm.get_slot::<Layer>(1), Ok(Layer::Sky));
+
+        vm.set_slot_string(0, "up");
+        let err = vm.get_slot_enum::<Direction>(0).unwrap_err();
+        assert_eq!(err.to_string(), "slot 0 holds \"up\", which is not a Direction (expected one of \"north\", \"east\", \"south\", \"west\")");
+        vm.set_slot_bool(1, true);
+        let err = vm.get_slot::<Layer>(1).unwrap_err();
+        assert_eq!(err.to_string(), "slot 1 holds true, which is not a Layer (expected one of 1 (\"Ground\"), 4 (\"Sky\"))");
+    });
+}
+
+#[test]
+fn eval_expressions() {
+    use super::{WrenValue, VMError};
+
+    let vm = VMConfig::new().build();
+    assert_eq!(vm.eval("1 + 2").unwrap(), WrenValue::Num(3.0));
+    assert_eq!(vm.eval("[1, \"two\"].count").unwrap(), WrenValue::Num(2.0));
+    assert_eq!(vm.eval("\"a\" * 3").unwrap(), WrenValue::String("aaa".into()));
+    assert_eq!(vm.eval("Fn.new {}").unwrap(), WrenValue::Null);
+    assert!(matches!(vm.eval("1 +"), Err(VMError::Compile { .. })));
+    assert!(matches!(vm.eval("Fiber.abort(\"no\")"), Err(VMError::Runtime { .. })));
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn c_api_round_trip() {
+    use super::capi::*;
+    use std::ffi::{CStr, CString};
+
+    let c = |s: &str| CString::new(s).unwrap();
+    unsafe {
+        let lib = ruwren_library_new();
+        assert!(ruwren_library_add_stdlib(lib, c("path").as_ptr()));
+        let vm = ruwren_vm_new(lib);
+        ruwren_library_free(lib);
+
+        assert_eq!(ruwren_vm_interpret(vm, c("main").as_ptr(), c("
+        import \"path\" for Path
+        class Api {
+            static join(a, b) { Path.join(a, b) }
+        }
+        ").as_ptr()), RuwrenResult::Success);
+
+        let args = [ruwren_value_string(c("a").as_ptr()), ruwren_value_string(c("b").as_ptr())];
+        let mut out = std::ptr::null_mut();
+        let result = ruwren_vm_call(vm, c("main").as_ptr(), c("Api").as_ptr(), c("join").as_ptr(), args.as_ptr() as *const *const _, 2, &mut out);
+        assert_eq!(result, RuwrenResult::Success);
+        assert_eq!(ruwren_value_type(out), RuwrenValueType::String);
+        let joined = ruwren_value_get_string(out);
+        assert_eq!(CStr::from_ptr(joined).to_str(), Ok("a/b"));
+        ruwren_string_free(joined);
+        ruwren_value_free(out);
+        for arg in args.iter() {
+            ruwren_value_free(*arg);
+        }
+
+        assert_eq!(ruwren_vm_eval(vm, c("1 +").as_ptr(), std::ptr::null_mut()), RuwrenResult::CompileError);
+        assert!(!ruwren_vm_error(vm).is_null());
+        ruwren_vm_free(vm);
+    }
+}
+
+#[test]
+fn number_formats() {
+    use super::{NumberFormat, WrenValue};
+
+    let wren = NumberFormat::wren();
+    assert_eq!(wren.format(1.0 / 3.0), "0.33333333333333");
+    assert_eq!(wren.format(1e20), "1e+20");
+    assert_eq!(wren.format(0.00001), "1e-05");
+    assert_eq!(wren.format(-42.0), "-42");
+    assert_eq!(NumberFormat::default().format(0.1 + 0.2), "0.30000000000000004");
+    assert_eq!(NumberFormat::default().precision(3).format(1234.5678), "1230");
+
+    let vm = VMConfig::new().number_format(NumberFormat::default().precision(2)).build();
+    let value = WrenValue::from(vec![1.0 / 3.0, 2.0]);
+    assert_eq!(vm.execute(|vm| vm.format_value(&value, None)), "[0.33, 2]");
+}
+
+// One logger for the whole test binary, which tests filter by target
+#[cfg(feature = "log")]
+mod captured_log {
+    use std::sync::{Mutex, Once};
+
+    static LOGGED: Mutex<Vec<(log::Level, String, String)>> = Mutex::new(Vec::new());
+    static INSTALL: Once = Once::new();
+
+    struct Capture;
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool { true }
+        fn log(&self, record: &log::Record) {
+            LOGGED.lock().unwrap().push((record.level(), record.target().to_string(), record.args().to_string()));
+        }
+        fn flush(&self) {}
+    }
+
+    pub fn install() {
+        INSTALL.call_once(|| {
+            log::set_logger(&Capture).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+    }
+
+    pub fn logged(target: &str) -> Vec<(log::Level, String, String)> {
+        LOGGED.lock().unwrap().iter().filter(|(_, t, _)| t.starts_with(target)).cloned().collect()
+    }
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn script_logging() {
+    captured_log::install();
+
+    let mut lib = super::ModuleLibrary::new();
+    super::stdlib::log::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).name("game").build();
+    vm.interpret("main", "
+    import \"log\" for Log
+    Log.warn(\"low on %(\"memory\")\")
+    ").unwrap();
+    vm.interpret("ui", "import \"log\" for Log\nLog.debug(3)").unwrap();
+
+    let logged = captured_log::logged("wren::");
+    assert_eq!(logged[0], (log::Level::Warn, "wren::main".to_string(), "[game] low on memory".to_string()));
+    assert_eq!(logged[1], (log::Level::Debug, "wren::ui".to_string(), "[game] 3".to_string()));
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_sinks() {
+    use super::{LogPrinter, LogErrorHandler};
+    use log::Level;
+
+    captured_log::install();
+    let vm = VMConfig::new()
+        .send_printer(LogPrinter::new().target("log_sinks"))
+        .send_error_handler(LogErrorHandler::new().level(Level::Warn).target("log_sinks"))
+        .build();
+    vm.interpret("main", "
+    System.write(\"half \")
+    System.print(\"a line\")
+    System.print(\"another\")
+    ").unwrap();
+    assert!(vm.interpret("main", "Fiber.abort(\"oops\")").is_err());
+
+    let logged = captured_log::logged("log_sinks");
+    let line = |level, message: &str| (level, "log_sinks".to_string(), message.to_string());
+    assert_eq!(logged[..3], [line(Level::Info, "half a line"), line(Level::Info, "another"), line(Level::Warn, "runtime error: oops")]);
+    // Followed by the stack trace
+    assert!(logged[3..].iter().all(|(level, _, message)| *level == Level::Warn && message.starts_with("in main:")));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_spans() {
+    use std::sync::{Arc, Mutex};
+    use tracing::{span, Event, Metadata, Subscriber};
+    use tracing::field::{Field, Visit};
+
+    // Records each span as its name and fields
+    struct Spans(Arc<Mutex<Vec<String>>>);
+    struct Fields(String);
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+    impl Subscriber for Spans {
+        fn enabled(&self, _: &Metadata) -> bool { true }
+        fn new_span(&self, span: &span::Attributes) -> span::Id {
+            let mut fields = Fields(span.metadata().name().to_string());
+            span.record(&mut fields);
+            let mut spans = self.0.lock().unwrap();
+            spans.push(fields.0);
+            span::Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &Event) {}
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let spans = Arc::new(Mutex::new(vec![]));
+    tracing::subscriber::with_default(Spans(spans.clone()), || {
+        let vm = library_vm(main::publish_module);
+        vm.interpret("main", "
+        class Math {
+            foreign static add5(a)
+        }
+        var Seven = Math.add5(2)
+        ").unwrap();
+        vm.collect_garbage();
+    });
+    assert_eq!(spans.lock().unwrap()[..], [
+        "interpret module=\"main\"",
+        "foreign class=Math signature=\"add5(_)\"",
+        "gc",
+    ]);
+}
+
+#[test]
+fn budgeted_tick() {
+    use std::time::Duration;
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    import \"ruwren/callbacks\" for Callbacks
+    var Ran = []
+    for (i in 0...3) Callbacks.schedule(0, Fn.new { Ran.add(i) })
+    Callbacks.schedule(0, Fn.new { Fiber.abort(\"late\") })
+    Callbacks.schedule(3600, Fn.new { Ran.add(\"never\") })
+    ").unwrap();
+
+    // Only one function fits in an empty budget
+    let report = vm.tick(Duration::from_secs(0));
+    assert_eq!((report.ran, report.deferred), (1, 3));
+
+    let report = vm.tick(Duration::from_secs(60));
+    assert_eq!((report.ran, report.deferred, report.errors.len()), (3, 0, 1));
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Ran", 0);
+        assert_eq!(vm.get_slot_list::<f64>(0).unwrap(), vec![0.0, 1.0, 2.0]);
+    });
+
+    assert_eq!(runtime_error(vm.interpret("main", "Callbacks.schedule(1e300, Fn.new {})")), "Delay is too long");
+}
+
+#[cfg(feature = "scheduler")]
+#[test]
+fn scheduler_pump() {
+    use std::time::Duration;
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    import \"scheduler\" for Scheduler
+    import \"timer\" for Timer
+    var Ran = []
+    Scheduler.add {
+        Timer.sleep(50)
+        Ran.add(\"added\")
+    }
+    Timer.sleep(100)
+    Ran.add(\"main\")
+    ").unwrap();
+
+    // Going to sleep runs the added fiber, until it goes to sleep too
+    let pump = super::SchedulerPump::new(&vm);
+    assert_eq!(pump.sleeping(), 2);
+    assert_eq!(pump.tick(Duration::from_millis(60)).ran, 1);
+    assert_eq!(pump.tick(Duration::from_millis(60)).ran, 1);
+    assert_eq!(pump.tick(Duration::from_millis(60)).ran, 0);
+    assert_eq!(pump.now(), Duration::from_millis(180));
+    assert_eq!(pump.sleeping(), 0);
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Ran", 0);
+        assert_eq!(vm.get_slot_list::<String>(0).unwrap(), vec!["added", "main"]);
+    });
+
+    for delay in ["-1", "0/0", "1/0", "1e300"] {
+        let err = vm.interpret("main", format!("Timer.sleep({})", delay));
+        assert!(matches!(err, Err(super::VMError::Runtime { .. })), "{}: {:?}", delay, err);
+    }
+    assert_eq!(pump.sleeping(), 0);
+    // The scheduler and timer modules are glue, so they're left out of stack traces
+    match vm.interpret("main", "Timer.sleep(\"soon\")") {
+        Err(super::VMError::Runtime { frames, .. }) => assert!(frames.iter().all(|frame| frame.module == "main"), "{:?}", frames),
+        other => panic!("expected the sleep to abort, got {:?}", other),
+    }
+}
+
+#[test]
+fn mounted_libraries() {
+    let mut engine = super::ModuleLibrary::new();
+    main::publish_module(&mut engine);
+    let mut util = super::Module::new();
+    util.source("import \"main\" for Math\nclass Util {\n    static seven { Math.add5(2) }\n}\n");
+    engine.module("util", util);
+
+    let mut lib = super::ModuleLibrary::new();
+    lib.mount("engine", engine);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("game", "
+    import \"engine/util\" for Util
+    var Seven = Util.seven
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("game", "Seven", 0);
+        assert_eq!(vm.get_slot_double(0), Some(7.0));
+    });
+    assert!(vm.interpret("game2", "import \"util\" for Util").is_err());
+}
+
+#[test]
+fn pure_mode_config() {
+    use super::WrenValue;
+
+    let mut lib = super::ModuleLibrary::new();
+    super::stdlib::encoding::publish_module(&mut lib);
+    super::stdlib::path::publish_module(&mut lib);
+    let vm = VMConfig::new()
+        .library(&lib)
+        .pure_mode(true)
+        .pure_module("encoding")
+        .build();
+
+    vm.interpret("config", "import \"encoding\" for Hex\nvar Color = Hex.encode(\"AB\")").unwrap();
+    assert!(vm.interpret("config", "import \"path\" for Path").is_err());
+    assert!(vm.interpret("config", "import \"ruwren/callbacks\" for Callbacks").is_err());
+    assert_eq!(vm.eval("[1, 2].count").unwrap(), WrenValue::Num(2.0));
+    // Wren can't be stopped mid-allocation, so there's no limit to pretend to enforce
+    assert_eq!(vm.sandbox_report().max_heap_size, None);
+
+    let roomy = VMConfig::new().pure_mode(true).max_heap_size(128 * 1024 * 1024).build();
+    assert_eq!(roomy.sandbox_report().max_heap_size, Some(128 * 1024 * 1024));
+}
+
+#[test]
+fn shared_script_cache() {
+    use std::rc::Rc;
+    use std::cell::Cell;
+
+    let cache = super::ScriptCache::new();
+    let loads = Rc::new(Cell::new(0));
+    for _ in 0..3 {
+        let l = loads.clone();
+        let vm = VMConfig::new()
+            .script_cache(&cache)
+            .script_loader(move |name: String| {
+                l.set(l.get() + 1);
+                match name.as_str() {
+                    "a" | "b" => Some("var Answer = 42".to_string()),
+                    _ => None,
+                }
+            })
+            .build();
+        vm.interpret("main", "import \"a\" for Answer\nimport \"b\"").unwrap();
+    }
+    assert_eq!(loads.get(), 2);
+    assert_eq!((cache.len(), cache.hits(), cache.misses()), (1, 4, 2));
+
+    cache.invalidate("a");
+    assert_eq!(cache.len(), 1);
+    cache.invalidate("b");
+    assert!(cache.is_empty());
+
+    // A VM still loading a source doesn't keep it cached
+    cache.insert("c", std::ffi::CString::new("var C = 1").unwrap());
+    let loading = cache.get("c");
+    cache.invalidate("c");
+    assert!(cache.is_empty());
+    assert!(loading.is_some());
+}
+
+#[test]
+fn slot_coercion() {
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    class Named {
+        construct new() {}
+        toString { \"a Named\" }
+    }
+    var Values = [null, false, 0, 1 / 3, \"text\", [1, \"a\"], Named.new()]
+    ").unwrap();
+
+    let coerce = |index: i32| vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Values", 1);
+        vm.get_list_element(1, index, 0);
+        (vm.coerce_slot_to_bool(0), vm.coerce_slot_to_string(0).unwrap())
+    });
+    assert_eq!(coerce(0), (false, "null".to_string()));
+    assert_eq!(coerce(1), (false, "false".to_string()));
+    assert_eq!(coerce(2), (true, "0".to_string()));
+    assert_eq!(coerce(3), (true, "0.33333333333333".to_string()));
+    assert_eq!(coerce(4), (true, "text".to_string()));
+    assert_eq!(coerce(5), (true, "[1, a]".to_string()));
+    assert_eq!(coerce(6), (true, "a Named".to_string()));
+}
+
+#[test]
+fn glue_frames_relabelled() {
+    use super::VMError;
+
+    let vm = VMConfig::new().build();
+    match vm.eval("Fiber.abort(\"bad\")") {
+        Err(VMError::Runtime { frames, .. }) => assert_eq!(frames[0].module, "<eval>"),
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn slot_snapshots() {
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var Name = \"kept\"").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(3);
+        vm.set_slot_double(0, 1.0);
+        vm.get_variable("main", "Name", 1);
+        vm.set_slot_bool(2, true);
+
+        let snapshot = vm.snapshot_slots();
+        assert_eq!(snapshot.len(), 3);
+        vm.set_slot_null(0);
+        vm.set_slot_new_list(1);
+        vm.set_slot_double(2, 5.0);
+        vm.restore_slots(snapshot);
+
+        assert_eq!(vm.get_slot_double(0), Some(1.0));
+        assert_eq!(vm.get_slot_string(1).as_deref(), Some("kept"));
+        assert_eq!(vm.get_slot_bool(2), Some(true));
+    });
+}
+
+#[test]
+fn optional_slots() {
+    use super::{SlotError, SlotType, get_slot_checked};
+
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        vm.ensure_slots(3);
+        vm.set_slot_null(0);
+        vm.set_slot_double(1, 2.5);
+        vm.set_slot_string(2, "name");
+
+        assert!(vm.slot_is_null(0));
+        assert_eq!(vm.get_slot_option::<f64>(0), Ok(None));
+        assert_eq!(vm.get_slot_option::<f64>(1), Ok(Some(2.5)));
+        assert_eq!(vm.get_slot_option::<bool>(2), Err(SlotError::TypeMismatch { slot: 2, expected: SlotType::Bool, found: SlotType::String }));
+
+        assert_eq!(get_slot_checked!(vm => num? 0 => 1.0), 1.0);
+        assert_eq!(get_slot_checked!(vm => num? 1 => 1.0), 2.5);
+        assert_eq!(get_slot_checked!(vm => string? 0 => "default"), "default");
+        assert_eq!(get_slot_checked!(vm => string? 2), Some("name".to_string()));
+    });
+}
+
+#[test]
+fn script_test_runner() {
+    use super::{TestRunner, VMError};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("ruwren-test-runner-{}", std::process::id()));
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    fs::write(dir.join("helper.wren"), "var Double = Fn.new {|x| x * 2 }").unwrap();
+    fs::write(dir.join("math_test.wren"), r#"
+import "ruwren/test" for Test, Assert
+import "helper" for Double
+
+Test.case("doubles") { Assert.equal(Double.call(2), 4) }
+Test.case("aborts") { Assert.aborts { Fiber.abort("no") } }
+Test.case("broken") { Assert.equal(1, 2) }
+"#).unwrap();
+    fs::write(dir.join("nested/plain_test.wren"), "System.print(\"no cases\")").unwrap();
+    fs::write(dir.join("nested/bad_test.wren"), "var x = ").unwrap();
+    fs::write(dir.join("nested/not_a_test.wren"), "var x = ").unwrap();
+
+    let report = TestRunner::new(&dir).config(VMConfig::new).run().unwrap();
+    let shared = TestRunner::new(&dir).shared_vm(true).run().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    for report in [&report, &shared] {
+        assert_eq!(report.files.len(), 3);
+        assert!(!report.passed());
+        assert_eq!(report.counts(), (3, 2));
+
+        let math = report.files.iter().find(|file| file.path.ends_with("math_test.wren")).unwrap();
+        let cases: Vec<_> = math.cases.iter().map(|case| (case.name.as_str(), case.error.as_deref())).collect();
+        assert_eq!(cases, vec![("doubles", None), ("aborts", None), ("broken", Some("Expected 2, got 1"))]);
+        // Traces point at the failing line
+        assert_eq!(math.cases[2].frames.first().map(|frame| (frame.module.as_str(), frame.line)), Some(("math_test", 7)));
+
+        let bad = report.files.iter().find(|file| file.path.ends_with("bad_test.wren")).unwrap();
+        assert!(matches!(bad.error, Some(VMError::Compile { .. })));
+        assert!(report.to_string().ends_with("3 passed, 2 failed"));
+    }
+}
+
+super::wren_tests!(bundled_wren_tests, "tests/wren");
+
+#[cfg(feature = "process")]
+#[test]
+fn module_capability_grants() {
+    use super::{Capability, CapabilityPolicy};
+
+    let policy = CapabilityPolicy::new().grant_to("engine/*", Capability::Process).allow_command("true");
+    assert!(policy.is_granted_to("engine/boot", Capability::Process));
+    assert!(!policy.is_granted_to("mods/evil", Capability::Process));
+    assert!(!policy.is_granted(Capability::Process));
+    assert!(policy.is_command_allowed("true"));
+    assert!(!policy.clone().revoke(Capability::Process).is_granted_anywhere(Capability::Process));
+
+    // A module that happens to be called "process" isn't guarded
+    let vm = VMConfig::new()
+        .capabilities(policy.clone())
+        .script_loader(|name: String| if name == "process" { Some("class Process {}".to_string()) } else { None })
+        .build();
+    assert!(vm.interpret("mods/evil", "import \"process\" for Process").is_ok());
+
+    let mut lib = super::ModuleLibrary::new();
+    super::stdlib::process::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).capabilities(policy).build();
+    vm.interpret("engine/boot", "import \"process\" for Process\nvar Ok = Process.run(\"true\").success").unwrap();
+    for source in ["import \"process\" for Process", "import \"process@engine/boot\" for Process"] {
+        let error = runtime_error(vm.interpret("mods/evil", source));
+        assert!(error.starts_with("Module \"mods/evil\" may not import \"process"), "{}", error);
+    }
+    // Calls are checked against the module making them, not the whole VM
+    let error = runtime_error(vm.interpret("process", "Process.run(\"true\")"));
+    assert!(error.contains("not been granted to module \"process\""), "{}", error);
+
+    // Nor can a granted module pass its copy on, however indirectly
+    vm.interpret("engine/api", "import \"engine/boot\" for Process").unwrap();
+    for module in ["engine/boot", "engine/api"] {
+        assert!(vm.interpret("mods/evil", format!("import \"{}\" for Process", module)).is_err(), "{}", module);
+    }
+    // Even when it's only loaded for the import
+    let engine = || VMConfig::new()
+        .library(&lib)
+        .capabilities(CapabilityPolicy::new().grant_to("engine/*", Capability::Process))
+        .script_loader(|name: String| match name.as_str() {
+            "engine/tools" => Some("import \"process\" for Process".to_string()),
+            "engine/api" => Some("import \"engine/tools\" for Process".to_string()),
+            _ => None,
+        })
+        .build();
+    assert!(engine().interpret("mods/evil", "import \"engine/api\" for Process").is_err());
+    let vm = engine();
+    vm.interpret("engine/main", "import \"engine/api\" for Process").unwrap();
+    assert!(vm.interpret("mods/evil", "import \"engine/api\" for Process").is_err());
+}
+
+#[test]
+fn object_graph_export() {
+    use super::{WrenValue, WrenMap};
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", r#"
+class Thing {
+    construct new() {}
+    toString { "a thing" }
+}
+var Items = [1, "two"]
+Items.add(Items)
+var Root = [Items, Thing.new(), Items, {"k": 2}]
+"#).unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Root", 0);
+    });
+    let root = vm.get_slot_handle(0);
+    let node = |fields: Vec<(&str, WrenValue)>| WrenValue::Map(fields.into_iter().collect::<WrenMap>());
+    let reference = || node(vec![("ref", 1.0.into())]);
+
+    let items = node(vec![("type", "List".into()), ("id", 1.0.into()), ("items", vec![1.0.into(), "two".into(), reference()].into())]);
+    let thing = node(vec![("type", "Thing".into()), ("id", 2.0.into()), ("string", "a thing".into())]);
+    let map = node(vec![("type", "Map".into()), ("id", 3.0.into()), ("entries", vec![WrenValue::from(vec![WrenValue::from("k"), 2.0.into()])].into())]);
+    let expected = node(vec![("type", "List".into()), ("id", 0.0.into()), ("items", vec![items, thing, reference(), map].into())]);
+    assert_eq!(vm.export_object_graph(std::slice::from_ref(&*root)), WrenValue::from(vec![expected]));
+
+    let truncated = |name: &str| node(vec![("type", name.into()), ("truncated", true.into())]);
+    let shallow = vm.export_object_graph_to_depth(std::slice::from_ref(&*root), 1);
+    let items = match &shallow {
+        WrenValue::List(trees) => match &trees[0] {
+            WrenValue::Map(tree) => tree.get("items").cloned(),
+            _ => None,
+        },
+        _ => None,
+    };
+    assert_eq!(items, Some(vec![truncated("List"), truncated("Thing"), truncated("List"), truncated("Map")].into()));
+}
+
+struct Sprite {
+    label: String,
+}
+
+impl super::Class for Sprite {
+    fn initialize(_: &super::VM) -> Sprite {
+        Sprite { label: String::new() }
+    }
+}
+
+impl Sprite {
+    fn label(&mut self, vm: &super::VM, value: Option<String>) {
+        match value {
+            Some(label) => self.label = label,
+            None => vm.set_slot_string(0, &self.label),
+        }
+    }
+}
+
+mod properties {
+    create_module! {
+        class("Sprite") Sprite => sprite {
+            instance(property "label") label
+        } wren("construct new() {}")
+
+        module => sprites
+    }
+
+    use super::{create_module, Sprite};
+    pub use self::sprites::{publish_module, source};
+}
+
+#[test]
+fn property_accessors() {
+    let source = properties::source();
+    assert!(source.contains("    foreign label\n    foreign label=(value)\n"));
+
+    let vm = library_vm(properties::publish_module);
+    vm.interpret("main", "
+    import \"sprites\" for Sprite
+    var s = Sprite.new()
+    s.label = \"hero\"
+    var Label = s.label
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Label", 0);
+        assert_eq!(vm.get_slot_string(0).as_deref(), Some("hero"));
+    });
+
+    assert!(runtime_error(vm.interpret("main", "s.label = 5")).starts_with("Sprite.label=(_): "));
+}
+
+struct Grid {
+    cells: Vec<f64>,
+}
+
+impl super::Class for Grid {
+    fn initialize(_: &super::VM) -> Grid {
+        Grid { cells: vec![0.0; 4] }
+    }
+}
+
+impl Grid {
+    fn cell(&self, vm: &super::VM) {
+        let index: usize = vm.get_slot(1).unwrap();
+        vm.set_slot(0, self.cells[index]).unwrap();
+    }
+
+    fn set_cell(&mut self, vm: &super::VM) {
+        let index: usize = vm.get_slot(1).unwrap();
+        self.cells[index] = vm.get_slot(2).unwrap();
+        vm.set_slot(0, self.cells[index]).unwrap();
+    }
+}
+
+mod grids {
+    create_module! {
+        class("Grid") Grid => grid {
+            instance(subscript 1) cell,
+            instance(subscript_setter 1) set_cell
+        } wren("construct new() {}")
+
+        module => grids
+    }
+
+    use super::{create_module, Grid};
+    pub use self::grids::{publish_module, source};
+}
+
+#[test]
+fn subscript_operators() {
+    assert!(grids::source().contains("    foreign [arg0]\n    foreign [arg0]=(value)\n"));
+
+    let vm = library_vm(grids::publish_module);
+    vm.interpret("main", "
+    import \"grids\" for Grid
+    var g = Grid.new()
+    g[2] = 1.5
+    var Cells = [g[0], g[2]]
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Cells", 0);
+        assert_eq!(vm.get_slot::<Vec<f64>>(0), Ok(vec![0.0, 1.5]));
+    });
+}
+
+#[test]
+fn signature_parsing() {
+    use super::{FunctionSignature, SignatureError};
+
+    assert_eq!(FunctionSignature::parse("move(_,_)"), Ok(FunctionSignature::new_function("move", 2)));
+    assert_eq!(FunctionSignature::parse("reset()"), Ok(FunctionSignature::new_function("reset", 0)));
+    assert_eq!(FunctionSignature::parse("count"), Ok(FunctionSignature::new_getter("count")));
+    assert_eq!(FunctionSignature::parse("count=(_)"), Ok(FunctionSignature::new_setter("count")));
+    assert_eq!(FunctionSignature::parse("[_,_]=(_)"), Ok(FunctionSignature::new_subscript_setter(2)));
+    assert_eq!(FunctionSignature::parse("<=(_)"), Ok(FunctionSignature::new_function("<=", 1)));
+    assert_eq!("-".parse(), Ok(FunctionSignature::new_getter("-")));
+
+    assert_eq!(FunctionSignature::parse("move(_, _)"), Err(SignatureError::Malformed("move(_, _)".to_string())));
+    assert_eq!(FunctionSignature::parse("[]"), Err(SignatureError::Malformed("[]".to_string())));
+    assert_eq!(FunctionSignature::parse("class(_)"), Err(SignatureError::InvalidName("class".to_string())));
+    assert_eq!(FunctionSignature::parse("2d"), Err(SignatureError::InvalidName("2d".to_string())));
+    assert!(matches!(FunctionSignature::parse("*"), Err(SignatureError::InvalidOperator { .. })));
+    assert!(matches!(FunctionSignature::new_function("f", 17).validate(), Err(SignatureError::TooManyParameters { arity: 17, .. })));
+
+    assert_eq!(FunctionSignature::new_subscript(1).to_string(), "[_]");
+}
+
+#[test]
+fn gc_guards_defer_collection() {
+    let vm = VMConfig::new().build();
+    {
+        let _outer = vm.gc_guard();
+        {
+            let _inner = vm.gc_guard();
+            vm.collect_garbage();
+            assert!(vm.gc_pending());
+        }
+        assert!(vm.gc_pending());
+    }
+    assert!(!vm.gc_pending());
+
+    let guard = vm.gc_guard().collect_on_drop();
+    assert!(!vm.gc_pending());
+    drop(guard);
+    assert!(!vm.gc_pending());
+}
+
+#[test]
+fn call_observer() {
+    use super::{CallKind, ErrorKind, FunctionSignature};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let calls = Rc::new(RefCell::new(vec![]));
+    let seen = calls.clone();
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    let vm = VMConfig::new()
+        .library(&lib)
+        .call_observer(move |info| seen.borrow_mut().push((info.kind, info.name, info.result)))
+        .build();
+    vm.interpret("main", "
+    class Math {
+        foreign static add5(a)
+    }
+    class Game {
+        static update(dt) { Math.add5(dt) }
+    }
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Game", 0);
+        vm.set_slot_double(1, 1.0);
+    });
+    vm.call(FunctionSignature::new_function("update", 1)).unwrap();
+    assert!(vm.interpret("main", "Math.add5(\"x\")").is_err());
+
+    assert_eq!(*calls.borrow(), vec![
+        (CallKind::Interpret, "main".to_string(), Ok(())),
+        (CallKind::Foreign, "Math.add5(_)".to_string(), Ok(())),
+        (CallKind::Call, "update(_)".to_string(), Ok(())),
+        (CallKind::Foreign, "Math.add5(_)".to_string(), Err(ErrorKind::Runtime)),
+        (CallKind::Interpret, "main".to_string(), Err(ErrorKind::Runtime)),
+    ]);
+}
+
+#[test]
+fn map_slots() {
+    use super::{SlotType, WrenMap, WrenValue};
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    class Inventory {
+        static describe(items) { \"%(items.count) %(items[\"sword\"])\" }
+    }
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(4);
+        vm.set_slot_new_map(0);
+        assert_eq!(vm.get_slot_type(0), SlotType::Map);
+        vm.set_slot_string(1, "sword");
+        vm.set_slot_double(2, 1.0);
+        vm.set_map_value(0, 1, 2);
+        assert_eq!(vm.get_map_count(0), 1);
+        assert!(vm.get_map_contains_key(0, 1));
+        vm.get_map_value(0, 1, 3);
+        assert_eq!(vm.get_slot_double(3), Some(1.0));
+        vm.remove_map_value(0, 1, 3);
+        assert_eq!(vm.get_slot_double(3), Some(1.0));
+        assert_eq!(vm.get_map_count(0), 0);
+
+        let items: WrenMap = vec![("sword", 2.0), ("shield", 1.0)].into_iter().collect();
+        vm.get_variable("main", "Inventory", 0);
+        vm.set_slot_value(1, &WrenValue::Map(items));
+    });
+    vm.call(super::FunctionSignature::new_function("describe", 1)).unwrap();
+    vm.execute(|vm| assert_eq!(vm.get_slot_string(0).as_deref(), Some("2 2")));
+}
+
+#[cfg(all(feature = "toml", feature = "ron"))]
+#[test]
+fn config_value_bridges() {
+    use super::{ConversionError, WrenValue};
+    use std::convert::TryInto;
+
+    let config: toml::Value = toml::from_str("
+    title = \"Lobby\"
+    gravity = 9.5
+    [players]
+    max = 4
+    ").unwrap();
+    let value = WrenValue::from(config.clone());
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    class Config {
+        static describe(config) { \"%(config[\"title\"]) %(config[\"players\"][\"max\"]) %(config[\"gravity\"])\" }
+    }
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Config", 0);
+        vm.set_slot_value(1, &value);
+    });
+    vm.call(super::FunctionSignature::new_function("describe", 1)).unwrap();
+    vm.execute(|vm| assert_eq!(vm.get_slot_string(0).as_deref(), Some("Lobby 4 9.5")));
+    assert_eq!(value.try_into(), Ok(config));
+    let null: Result<toml::Value, _> = WrenValue::from(vec![WrenValue::Null]).try_into();
+    assert_eq!(
+        null,
+        Err(ConversionError::Unrepresentable { format: "TOML", value: "null".into() })
+    );
+
+    let level: ron::Value = ron::from_str("(name: \"Cave\", boss: None)").unwrap();
+    match WrenValue::from(level) {
+        WrenValue::Map(map) => {
+            assert_eq!(map.get("name"), Some(&WrenValue::from("Cave")));
+            assert_eq!(map.get("boss"), Some(&WrenValue::Null));
+        },
+        value => panic!("expected a map, got {}", value),
+    }
+    assert_eq!(ron::Value::from(WrenValue::Num(1.0)), ron::Value::Number(1.into()));
+    assert_eq!(ron::Value::from(WrenValue::Null), ron::Value::Unit);
+}
+
+#[derive(Clone)]
+struct Tile {
+    x: f64,
+}
+
+impl super::Class for Tile {
+    fn initialize(vm: &super::VM) -> Tile {
+        Tile { x: vm.get_slot(1).unwrap() }
+    }
+}
+
+impl Tile {
+    fn x(&self, vm: &super::VM) {
+        vm.set_slot(0, self.x).unwrap();
+    }
+
+    fn shift(vm: &super::VM) {
+        let tiles: Vec<Tile> = vm.get_slot(1).unwrap();
+        let by: Option<f64> = vm.get_slot(2).unwrap();
+        let shifted: Vec<Tile> = tiles.into_iter().map(|tile| Tile { x: tile.x + by.unwrap_or(1.0) }).collect();
+        vm.set_slot(0, shifted).unwrap();
+    }
+}
+
+mod tiles {
+    create_module! {
+        class("Tile") Tile => tile {
+            instance(getter "x") x,
+            static(fn "shift", 2) shift
+        } wren("construct new(x) {}")
+
+        module => tiles
+    }
+
+    use super::{create_module, Tile};
+    pub use self::tiles::publish_module;
+}
+
+#[test]
+fn foreign_objects_before_import() {
+    use super::ForeignSendError;
+
+    let vm = library_vm(tiles::publish_module);
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        assert_eq!(vm.set_slot_foreign(0, Tile { x: 1.0 }), Err(ForeignSendError::NoWrenClass));
+    });
+    vm.interpret("main", "import \"tiles\" for Tile").unwrap();
+    vm.execute(|vm| assert!(vm.set_slot_foreign(0, Tile { x: 1.0 }).is_ok()));
+}
+
+#[test]
+fn closure_methods() {
+    use super::{Module, FunctionSignature};
+    use std::sync::{Arc, Mutex};
+
+    let spent = Arc::new(Mutex::new(0.0));
+    let source = "
+    foreign class Tile {
+        construct new(x) {}
+        foreign doubled
+    }
+    class Wallet {
+        foreign static spend(amount)
+    }
+    ";
+    let mut shop = Module::new();
+    shop.source(source);
+    shop.class::<Tile, _>("Tile")
+        .method("Tile", FunctionSignature::new_getter("doubled"), |vm, tile: &mut Tile| vm.set_slot(0, tile.x * 2.0).unwrap());
+    let s = spent.clone();
+    shop.static_method("Wallet", FunctionSignature::new_function("spend", 1), move |vm| {
+        let amount = vm.try_get_slot_double(1).unwrap_or_else(|err| panic!("{}", err));
+        *s.lock().unwrap() += amount;
+    });
+    let mut lib = super::ModuleLibrary::new();
+    lib.module("shop", shop);
+
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("main", "import \"shop\" for Tile, Wallet").unwrap();
+    assert_eq!(vm.interpret_returning::<f64, _, _>("main", "Tile.new(4).doubled").unwrap(), 8.0);
+    vm.interpret("main", "Wallet.spend(3)\nWallet.spend(1.5)").unwrap();
+    assert_eq!(*spent.lock().unwrap(), 4.5);
+
+    assert!(runtime_error(vm.interpret("main", "Wallet.spend(\"lots\")")).contains("Wallet.spend(_): slot 1 is a <String>"));
+
+    // Rebinding the same closures reuses their trampolines
+    let bound = vm.execute(|vm| vm.user_data().closure_methods.borrow().len());
+    let mut hot = super::HotReload::new();
+    for _ in 0..3 {
+        assert!(hot.reload(&vm, "shop", source).result.is_ok());
+    }
+    assert_eq!(vm.execute(|vm| vm.user_data().closure_methods.borrow().len()), bound);
+}
+
+#[test]
+fn async_foreign_methods() {
+    use super::{Module, FunctionSignature};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+
+    // Loads that the test finishes by hand
+    #[derive(Default)]
+    struct Loading {
+        result: Option<Result<String, String>>,
+        waker: Option<Waker>,
+    }
+    struct Load(Arc<Mutex<Loading>>);
+    impl Future for Load {
+        type Output = Result<String, String>;
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            let mut loading = self.0.lock().unwrap();
+            match loading.result.take() {
+                Some(result) => Poll::Ready(result),
+                None => {
+                    loading.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                },
+            }
+        }
+    }
+    let finish = |loading: &Arc<Mutex<Loading>>, result: Result<&str, &str>| {
+        let mut loading = loading.lock().unwrap();
+        loading.result = Some(result.map(str::to_string).map_err(str::to_string));
+        loading.waker.take().unwrap().wake();
+    };
+
+    let loading = Arc::new(Mutex::new(Loading::default()));
+    let mut assets = Module::new();
+    assets.source("
+    import \"ruwren/async\" for Future
+    class Assets {
+        foreign static loadAsync_(path)
+        static load(path) { loadAsync_(path).await }
+    }
+    ");
+    let l = loading.clone();
+    assets.static_method("Assets", FunctionSignature::new_function("loadAsync_", 1), move |vm| {
+        vm.set_slot_future(0, Load(l.clone())).unwrap();
+    });
+    let mut lib = super::ModuleLibrary::new();
+    lib.module("assets", assets);
+
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("main", "
+    import \"assets\" for Assets
+    var Text = Assets.load(\"intro.txt\")
+    ").unwrap();
+    // The script is suspended until the load finishes
+    let report = vm.poll_async();
+    assert_eq!((report.completed, report.resumed, report.pending), (0, 0, 1));
+    assert!(vm.interpret_returning::<bool, _, _>("main", "Text == null").unwrap());
+
+    finish(&loading, Ok("hello"));
+    let report = vm.poll_async();
+    assert_eq!((report.completed, report.resumed, report.pending), (1, 1, 0));
+    assert_eq!(vm.interpret_returning::<String, _, _>("main", "Text").unwrap(), "hello");
+
+    vm.interpret("main", "Assets.load(\"missing.txt\")").unwrap();
+    finish(&loading, Err("missing.txt not found"));
+    let report = vm.poll_async();
+    assert!(matches!(&report.errors[..], [super::VMError::Runtime { error, .. }] if error.contains("missing.txt not found")));
+    assert_eq!(vm.pending_futures(), 0);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_futures() {
+    use super::{Module, FunctionSignature};
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut net = Module::new();
+    net.source("
+    import \"ruwren/async\" for Future
+    class Net {
+        foreign static fetchAsync_(url)
+        static fetch(url) { fetchAsync_(url).await }
+    }
+    ");
+    net.static_method("Net", FunctionSignature::new_function("fetchAsync_", 1), |vm| {
+        let url = vm.get_slot::<String>(1).unwrap();
+        vm.spawn_future(0, async move {
+            tokio::task::yield_now().await;
+            Ok::<_, String>(format!("fetched {}", url))
+        }).unwrap();
+    });
+    let mut lib = super::ModuleLibrary::new();
+    lib.module("net", net);
+
+    let vm = VMConfig::new().library(&lib).tokio_runtime(runtime.handle().clone()).build();
+    vm.interpret("main", "
+    import \"net\" for Net
+    var Page = Net.fetch(\"example.com\")
+    ").unwrap();
+    // Nothing has run on the runtime yet
+    assert_eq!(vm.pump_events().pending, 1);
+
+    let mut resumed = 0;
+    for _ in 0..10 {
+        runtime.block_on(tokio::task::yield_now());
+        resumed += vm.pump_events().resumed;
+        if resumed > 0 {
+            break;
+        }
+    }
+    assert_eq!(resumed, 1);
+    assert_eq!(vm.interpret_returning::<String, _, _>("main", "Page").unwrap(), "fetched example.com");
+    assert_eq!(vm.pending_futures(), 0);
+}
+
+#[test]
+fn fiber_handles() {
+    let vm = VMConfig::new().build();
+    vm.interpret("ai", "
+    var Patrol = Fiber.new {|steps|
+        while (steps < 10) steps = Fiber.yield(steps * 2)
+        return \"done\"
+    }
+    var Walk = Fn.new {
+        Fiber.yield(1)
+        Fiber.abort(\"tripped\")
+    }
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("ai", "Patrol", 0);
+    });
+    let patrol = vm.get_slot_fiber(0).unwrap();
+    assert!(!patrol.is_done());
+    assert_eq!(patrol.resume_with::<_, f64>(3.0).unwrap(), 6.0);
+    assert_eq!(patrol.resume_with::<_, f64>(4.0).unwrap(), 8.0);
+    assert_eq!(patrol.resume_with::<_, String>(10.0).unwrap(), "done");
+    assert!(patrol.is_done());
+    assert!(patrol.resume::<()>().is_err());
+
+    vm.execute(|vm| vm.get_variable("ai", "Walk", 0));
+    assert!(vm.get_slot_fiber(0).is_none());
+    vm.execute(|vm| vm.get_variable("ai", "Walk", 0));
+    let walk = vm.new_fiber(&vm.get_slot_handle(0)).unwrap();
+    assert_eq!(walk.resume::<f64>().unwrap(), 1.0);
+    assert!(walk.resume::<()>().is_err());
+    assert!(walk.is_done());
+    assert_eq!(walk.error().as_deref(), Some("tripped"));
+}
+
+#[test]
+fn slot_dumps() {
+    let vm = library_vm(main::publish_module);
+    vm.interpret("main", "
+    foreign class RawPoint {
+        construct new(x) {}
+    }
+    var Point = RawPoint.new(1)
+    import \"random\" for Random
+    var Dice = Random.new(1)
+    ").unwrap();
+    let dump = vm.execute(|vm| {
+        vm.ensure_slots(7);
+        vm.get_variable("main", "Point", 0);
+        vm.set_slot_string(1, "a string long enough that it won't all be shown");
+        vm.set_slot_double(2, 2.5);
+        vm.set_slot_new_list(3);
+        vm.insert_in_list(3, -1, 2);
+        vm.set_slot_new_map(4);
+        vm.set_slot_null(5);
+        // Wren's own foreign objects don't carry ruwren's header
+        vm.get_variable("main", "Dice", 6);
+        assert!(vm.get_slot_foreign::<Point>(6).is_none());
+        assert_eq!(vm.try_borrow_foreign(6, |_: &Point| ()), Err(super::ForeignBorrowError::TypeMismatch));
+        vm.dump_slots()
+    });
+    let summaries: Vec<_> = dump.iter().map(|slot| slot.summary.as_str()).collect();
+    assert_eq!(summaries, vec![
+        "RawPoint (from \"main\")",
+        "\"a string long enough that it won't all b\"... (47 bytes)",
+        "2.5",
+        "1 element",
+        "0 entries",
+        "null",
+        "unregistered foreign object",
+    ]);
+    assert_eq!(format!("{:?}", dump[2]), "2: <Num> 2.5");
+}
+
+#[test]
+fn slot_bytes_refs() {
+    let vm = VMConfig::new().build();
+    let blob: Vec<u8> = (0..=255).chain(0..=255).collect();
+    vm.execute(|inner| {
+        inner.ensure_slots(2);
+        inner.set_slot_bytes(0, &blob);
+        inner.set_slot_double(1, 1.0);
+        let bytes = inner.get_slot_bytes_ref(0).unwrap();
+        // Still readable once nothing else holds the string
+        inner.set_slot_null(0);
+        vm.collect_garbage();
+        assert_eq!(&*bytes, &blob[..]);
+        assert!(inner.get_slot_bytes_ref(1).is_none());
+        inner.set_slot_bytes(1, &[]);
+        assert_eq!(inner.get_slot_bytes_ref(1).unwrap().len(), 0);
+        assert_eq!(inner.get_slot_bytes(1), Some(vec![]));
+    });
+}
+
+#[test]
+fn slot_strs() {
+    use super::StringPolicy;
+
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.set_slot_string(0, "héllo");
+        vm.set_slot_bytes(1, b"bad \xff byte");
+        let hello = vm.get_slot_str(0).unwrap();
+        assert_eq!(&*hello, "héllo");
+        assert!(!hello.is_copied());
+        let bad = vm.get_slot_str(1).unwrap();
+        assert_eq!(&*bad, "bad \u{fffd} byte");
+        assert!(bad.is_copied());
+        vm.set_slot_null(1);
+        assert!(vm.get_slot_str(1).is_none());
+    });
+
+    let strict = VMConfig::new().string_policy(StringPolicy::Strict).build();
+    strict.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.set_slot_bytes(0, b"\xff");
+        assert!(vm.get_slot_str(0).is_none());
+    });
+}
+
+#[test]
+fn checked_integer_slots() {
+    use super::SlotError;
+
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.set_slot_i32(0, -7);
+        assert_eq!(vm.get_slot_i32(0), Ok(-7));
+        assert_eq!(vm.get_slot_i64(0), Ok(-7));
+        assert_eq!(vm.get_slot_u32(0), Err(SlotError::OutOfRange { slot: 0, value: "-7".into(), ty: "u32" }));
+
+        vm.set_slot_double(0, 2.5);
+        let err = vm.get_slot_usize(0).unwrap_err();
+        assert_eq!(err.to_string(), "slot 0 holds 2.5, which is not an integer");
+        vm.set_slot_double(0, f64::NAN);
+        assert!(matches!(vm.get_slot_i32(0), Err(SlotError::NotAnInteger { .. })));
+
+        vm.set_slot_double(0, 2147483648.0);
+        assert!(matches!(vm.get_slot_i32(0), Err(SlotError::OutOfRange { ty: "i32", .. })));
+        assert_eq!(vm.get_slot_u32(0), Ok(2147483648));
+        vm.set_slot_double(0, 9223372036854775808.0);
+        assert!(matches!(vm.get_slot_i64(0), Err(SlotError::OutOfRange { ty: "i64", .. })));
+
+        vm.set_slot_usize(0, 1 << 40);
+        assert_eq!(vm.get_slot_usize(0), Ok(1 << 40));
+        vm.set_slot_string(0, "12");
+        assert!(matches!(vm.get_slot_i32(0), Err(SlotError::TypeMismatch { .. })));
+    });
+}
+
+#[test]
+fn class_builder() {
+    use super::{Module, SignatureError};
+
+    // Not a ClassObject, so only known to Wren through the builder
+    #[derive(Clone)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    let mut geometry = Module::new();
+    geometry.source("
+    foreign class Point {
+        construct new(x, y) {}
+        foreign dot(other)
+        foreign static origin
+    }
+    ");
+    geometry.class_builder::<Point, _>("Point")
+        .constructor(|c| Point { x: c.arg(0).unwrap(), y: c.arg(1).unwrap() })
+        .method("dot(_)", |vm, p| {
+            let other = vm.try_borrow_foreign(1, Point::clone).unwrap();
+            vm.set_slot_double(0, p.x * other.x + p.y * other.y);
+        }).unwrap()
+        .static_method("origin", |vm| vm.set_slot_foreign(0, Point { x: 0.0, y: 0.0 }).unwrap()).unwrap();
+    assert!(matches!(geometry.class_builder::<Point, _>("Other").method("dot(_, _)", |_, _| {}), Err(SignatureError::Malformed(_))));
+    let mut lib = super::ModuleLibrary::new();
+    lib.module("geometry", geometry);
+
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("main", "import \"geometry\" for Point").unwrap();
+    assert_eq!(vm.interpret_returning::<f64, _, _>("main", "Point.new(1, 2).dot(Point.new(3, 4))").unwrap(), 11.0);
+    assert_eq!(vm.interpret_returning::<f64, _, _>("main", "Point.origin.dot(Point.new(3, 4))").unwrap(), 0.0);
+}
+
+#[test]
+fn generated_stubs() {
+    use super::{FunctionSignature, Module};
+
+    #[derive(Clone)]
+    struct Meter(f64);
+
+    let mut units = Module::new();
+    units.class_builder::<Meter, _>("Meter")
+        .constructor(|c| Meter(c.arg(0).unwrap()))
+        .wren("    construct new(length) {}")
+        .method("plus(_)", |vm, m| {
+            let other = vm.try_borrow_foreign(1, Meter::clone).unwrap();
+            vm.set_slot_double(0, m.0 + other.0);
+        }).unwrap()
+        .static_method("zero", |vm| vm.set_slot_foreign(0, Meter(0.0)).unwrap()).unwrap();
+    units.static_method("Units", FunctionSignature::new_getter("count"), |vm| vm.set_slot_double(0, 1.0));
+    let mut lib = super::ModuleLibrary::new();
+    lib.module("units", units);
+    vectors::publish_module(&mut lib);
+
+    assert_eq!(lib.generate_stub("units"), "\
+foreign class Meter {
+    construct new(length) {}
+    foreign plus(arg0)
+    foreign static zero
+}
+class Units {
+    foreign static count
+}
+");
+    assert_eq!(lib.generate_stub("nowhere"), "");
+    // Inline Wren from create_module! carries over too
+    assert!(lib.generate_stub("vectors").starts_with("foreign class Vec2 {\n"));
+
+    // No source was registered, so importing the module loads its stub
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("main", "import \"units\" for Meter, Units").unwrap();
+    assert_eq!(vm.interpret_returning::<f64, _, _>("main", "Meter.new(2).plus(Meter.zero) + Units.count").unwrap(), 3.0);
+}
+
+#[test]
+fn typed_slot_conversions() {
+    use super::{ForeignBorrowError, SlotError, SlotType};
+
+    let vm = library_vm(tiles::publish_module);
+    vm.interpret("main", "
+    import \"tiles\" for Tile
+    var Moved = Tile.shift([Tile.new(1), Tile.new(2)], 3).map { |t| t.x }.toList
+    var Nudged = Tile.shift([Tile.new(1)], null)[0].x
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Moved", 0);
+        assert_eq!(vm.get_slot::<Vec<i32>>(0), Ok(vec![4, 5]));
+        vm.get_variable("main", "Nudged", 0);
+        assert_eq!(vm.get_slot::<Option<f64>>(0), Ok(Some(2.0)));
+        assert_eq!(vm.get_slot::<String>(0), Err(SlotError::TypeMismatch { slot: 0, expected: SlotType::String, found: SlotType::Num }));
+
+        vm.set_slot(1, Tile { x: 7.0 }).unwrap();
+        assert_eq!(vm.get_slot::<Tile>(1).map(|tile| tile.x), Ok(7.0));
+        assert!(matches!(vm.get_slot::<Tile>(0), Err(SlotError::Foreign { slot: 0, error: ForeignBorrowError::NotForeign })));
+        vm.set_slot(1, vec![Some("a"), None]).unwrap();
+        assert_eq!(vm.get_slot::<Vec<Option<String>>>(1), Ok(vec![Some("a".to_string()), None]));
+    });
+}
+
+struct Span {
+    start: f64,
+    end: f64,
+}
+
+impl super::Class for Span {
+    fn construct(constructor: &super::Constructor) -> Span {
+        assert_eq!(constructor.arity(), 2);
+        // Nothing past the declared arguments
+        assert!(constructor.arg::<f64>(2).is_err());
+        let arg = |index| constructor.arg(index).unwrap_or_else(|err| panic!("{}", err));
+        Span { start: arg(0), end: arg(1) }
+    }
+}
+
+impl Span {
+    fn length(&self, vm: &super::VM) {
+        vm.set_slot(0, self.end - self.start).unwrap();
+    }
+}
+
+mod spans {
+    create_module! {
+        class("Span") Span => span {
+            instance(getter "length") length
+        } wren("construct new(start, end) {}")
+
+        module => spans
+    }
+
+    use super::{create_module, Span};
+    pub use self::spans::publish_module;
+}
+
+#[test]
+fn constructor_arguments() {
+    let vm = library_vm(spans::publish_module);
+    vm.interpret("main", "import \"spans\" for Span").unwrap();
+    assert_eq!(vm.interpret_returning::<f64, _, _>("main", "Span.new(2, 5).length").unwrap(), 3.0);
+
+    assert!(runtime_error(vm.interpret("main", "Span.new(\"two\", 5)")).contains("Span: slot 1 is a <String>, not a <Num>"));
+}
+
+struct Tally {
+    count: f64,
+}
+
+impl super::Class for Tally {
+    fn construct(constructor: &super::Constructor) -> Tally {
+        use super::SlotType;
+
+        constructor.dispatch(&[
+            (&[], |_| Tally { count: 0.0 }),
+            (&[SlotType::Num], |c| Tally { count: c.arg(0).unwrap() }),
+            (&[SlotType::String], |c| Tally { count: c.arg::<String>(0).unwrap().len() as f64 }),
+        ])
+    }
+}
+
+impl Tally {
+    fn count(&self, vm: &super::VM) {
+        vm.set_slot(0, self.count).unwrap();
+    }
+}
+
+mod tallies {
+    create_module! {
+        class("Tally") Tally => tally {
+            instance(getter "count") count
+        } wren("construct new() {}\n    construct new(start) {}\n    construct fromText(text) {}")
+
+        module => tallies
+    }
+
+    use super::{create_module, Tally};
+    pub use self::tallies::publish_module;
+}
+
+#[test]
+fn multiple_constructors() {
+    let vm = library_vm(tallies::publish_module);
+    vm.interpret("main", "import \"tallies\" for Tally").unwrap();
+    let count = |source: &str| vm.interpret_returning::<f64, _, _>("main", source).unwrap();
+    assert_eq!(count("Tally.new().count"), 0.0);
+    assert_eq!(count("Tally.new(4).count"), 4.0);
+    assert_eq!(count("Tally.fromText(\"abc\").count"), 3.0);
+
+    assert!(runtime_error(vm.interpret("main", "Tally.new(true)")).contains("no constructor takes (Bool)"));
+}
+
+#[derive(Clone, PartialEq)]
+struct Vec2 {
+    x: f64,
+    y: f64,
+}
+
+impl super::Class for Vec2 {
+    fn initialize(vm: &super::VM) -> Vec2 {
+        Vec2 { x: vm.get_slot(1).unwrap(), y: vm.get_slot(2).unwrap() }
+    }
+}
+
+impl Vec2 {
+    fn xy(&self, vm: &super::VM) {
+        vm.set_slot(0, vec![self.x, self.y]).unwrap();
+    }
+
+    fn add(&self, vm: &super::VM) {
+        let other: Vec2 = vm.get_slot(1).unwrap();
+        vm.set_slot(0, Vec2 { x: self.x + other.x, y: self.y + other.y }).unwrap();
+    }
+
+    fn negate(&self, vm: &super::VM) {
+        vm.set_slot(0, Vec2 { x: -self.x, y: -self.y }).unwrap();
+    }
+
+    fn equals(&self, vm: &super::VM) {
+        let other: Option<Vec2> = vm.get_slot::<Vec2>(1).ok();
+        vm.set_slot(0, other.as_ref() == Some(self)).unwrap();
+    }
+}
+
+mod vectors {
+    create_module! {
+        class("Vec2") Vec2 => vec2 {
+            instance(getter "xy") xy,
+            instance(operator "+") add,
+            instance(prefix "-") negate,
+            instance(operator "==") equals
+        } wren("construct new(x, y) {}")
+
+        module => vectors
+    }
+
+    use super::{create_module, Vec2};
+    pub use self::vectors::{publish_module, source};
+}
+
+#[test]
+fn operator_methods() {
+    assert!(vectors::source().contains("    foreign +(arg0)\n    foreign -\n    foreign ==(arg0)\n"));
+
+    let vm = library_vm(vectors::publish_module);
+    vm.interpret("main", "
+    import \"vectors\" for Vec2
+    var Sum = (-Vec2.new(1, 2) + Vec2.new(4, 4)).xy
+    var Same = Vec2.new(1, 2) == Vec2.new(1, 2)
+    var Different = Vec2.new(1, 2) == 3
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Sum", 0);
+        assert_eq!(vm.get_slot::<Vec<f64>>(0), Ok(vec![3.0, 2.0]));
+        vm.get_variable("main", "Same", 0);
+        assert_eq!(vm.get_slot::<bool>(0), Ok(true));
+        vm.get_variable("main", "Different", 0);
+        assert_eq!(vm.get_slot::<bool>(0), Ok(false));
+    });
+}
+
+#[cfg(feature = "math")]
+#[test]
+fn math_module() {
+    use super::stdlib::math::{self, Color, Vec3};
+
+    assert_eq!(math::parse_hex_color("#ff000080").map(Color::to_hex).as_deref(), Some("#ff000080"));
+    assert_eq!(math::parse_hex_color("#fff"), None);
+
+    let vm = library_vm(math::publish_module);
+    vm.interpret("main", "
+    import \"math\" for Vec2, Vec3, Mat4, Color
+    var v = Vec2.new(3, 4)
+    var Length = v.length
+    var Sum = (v + v * 2 - Vec2.new(1, 1)).toString
+    var Moved = Mat4.translation(1, 2, 3) * Vec3.new(1, 1, 1)
+    var Cross = Vec3.new(1, 0, 0).cross(Vec3.new(0, 1, 0)) == Vec3.new(0, 0, 1)
+    var Corner = Mat4.scale(2, 2, 2)[3, 3]
+    var Red = Color.hex(\"#ff0000\").lerp(Color.rgb(0, 0, 1), 0.5).toHex
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        let get = |name: &str| {
+            vm.get_variable("main", name, 0);
+            vm.get_slot_value(0)
+        };
+        assert_eq!(get("Length"), Some(5.0.into()));
+        assert_eq!(get("Sum"), Some("(8, 11)".into()));
+        assert_eq!(get("Cross"), Some(true.into()));
+        assert_eq!(get("Corner"), Some(1.0.into()));
+        assert_eq!(get("Red"), Some("#800080ff".into()));
+        vm.get_variable("main", "Moved", 0);
+        assert_eq!(vm.get_slot::<Vec3>(0).map(glam::Vec3::from), Ok(glam::vec3(2.0, 3.0, 4.0)));
+    });
+}
+
+#[test]
+fn scratch_allocator() {
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var Answer = 42").unwrap();
+    vm.interpret("main", "Answer = Answer + 1").unwrap();
+    let stats = vm.scratch_stats();
+    // Only the first string needed a chunk, which was kept for the second call
+    assert_eq!(stats.scratch_allocations, 1);
+    assert_eq!(stats.allocations_avoided, 3);
+    assert_eq!(stats.bytes_saved, "var Answer = 42".len() as u64 + ("main".len() as u64 + 1) + "Answer = Answer + 1".len() as u64 + 2);
+    assert_eq!(stats.resets, 2);
+    assert_eq!(stats.heap_fallbacks, 0);
+
+    // Outside of a call, names go on the heap
+    vm.execute(|vm| assert!(!vm.has_variable("main", "Missing")));
+    assert_eq!(vm.scratch_stats().heap_fallbacks, 2);
+
+    // Strings too big for a chunk are staged in a buffer of their own, which the next call reuses
+    let big = format!("var Big = \"{}\"", "x".repeat(8192));
+    vm.interpret("main", &big).unwrap();
+    assert_eq!(vm.scratch_stats().scratch_allocations, 2);
+    vm.interpret("main", big.replace("var Big", "Big")).unwrap();
+    let stats = vm.scratch_stats();
+    assert_eq!(stats.scratch_allocations, 2);
+    assert_eq!(stats.allocations_avoided, 6);
+    assert_eq!(stats.heap_fallbacks, 2);
+}
+
+#[cfg(feature = "derive")]
+#[super::wren_class(name = "Player", module = "game")]
+struct Hero {
+    health: f64,
+    name: String,
+}
+
+#[cfg(feature = "derive")]
+#[super::wren_methods]
+impl Hero {
+    #[wren(constructor)]
+    fn new(name: String) -> Hero {
+        Hero { health: 10.0, name }
+    }
+
+    fn health(&self) -> f64 {
+        self.health
+    }
+
+    fn set_health(&mut self, health: f64) {
+        self.health = health;
+    }
+
+    fn take_damage(&mut self, amount: f64, vm: &super::VM) -> bool {
+        vm.ensure_slots(1);
+        self.health -= amount;
+        self.health <= 0.0
+    }
+
+    #[wren(name = "named")]
+    fn with_name(name: String) -> Hero {
+        Hero { health: 1.0, name }
+    }
+
+    #[wren(getter)]
+    fn label(&self) -> String {
+        format!("{} ({})", self.name, self.health)
+    }
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn attribute_classes() {
+    use super::WrenClass;
+
+    let source = Hero::source();
+    assert!(source.starts_with("foreign class Player {\nconstruct new(name) {}\n"));
+    assert!(source.contains("    foreign takeDamage(arg0)\n"));
+    assert!(source.contains("    foreign static named(arg0)\n"));
+
+    let mut lib = super::ModuleLibrary::new();
+    let mut module = super::Module::new();
+    module.wren_class::<Hero>();
+    lib.module("game", module);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("main", "
+    import \"game\" for Player
+    var p = Player.new(\"Ada\")
+    p.health = 3
+    var Dead = [p.takeDamage(2), p.takeDamage(2)]
+    var Label = Player.named(\"Bo\").label
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Dead", 0);
+        assert_eq!(vm.get_slot::<Vec<bool>>(0), Ok(vec![false, true]));
+        vm.get_variable("main", "Label", 0);
+        assert_eq!(vm.get_slot::<String>(0).as_deref(), Ok("Bo (1)"));
+    });
+
+    assert!(runtime_error(vm.interpret("main", "p.takeDamage(\"lots\")")).starts_with("Player.takeDamage(_): "));
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn embedded_modules() {
+    let loader = super::embed_modules!("examples/relative_imports/*.wren");
+    assert_eq!(loader.names().collect::<Vec<_>>(), vec!["a", "main", "test"]);
+    assert!(loader.get("a").unwrap().starts_with("class Alpha {"));
+    assert!(!loader.get("a").unwrap().ends_with('\0'));
+    assert_eq!(super::embed_modules!("examples/**/*.wren").get("basic_integration/maths"), super::embed_modules!("examples/basic_integration/*.wren").get("maths"));
+
+    let vm = VMConfig::new().script_loader(loader).build();
+    vm.interpret("main", "import \"a\" for Alpha").unwrap();
+    assert_eq!(vm.interpret_returning::<String, _, _>("main", "Alpha.testString()").unwrap(), "haha!");
+}
+
+#[test]
+fn error_rewriter() {
+    use super::VMError;
+
+    let vm = VMConfig::new()
+        .error_rewriter(|err| match err {
+            VMError::Runtime { error, frames } => VMError::Runtime {
+                error: error.replace("/home/dev/", ""),
+                frames: frames.into_iter().map(|mut frame| {
+                    frame.module = frame.module.replace("ruwren/", "engine/");
+                    frame
+                }).collect(),
+            },
+            VMError::Compile { line, error, .. } => VMError::Compile { module: "script".into(), line, error },
+            err => err,
+        })
+        .build();
+
+    match vm.interpret("ruwren/main", "Fiber.abort(\"missing /home/dev/save.dat\")") {
+        Err(VMError::Runtime { error, frames }) => {
+            assert_eq!(error, "missing save.dat");
+            assert_eq!(frames[0].module, "engine/main");
+        },
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+    assert!(matches!(vm.interpret("secret/module", "var"), Err(VMError::Compile { module, .. }) if module == "script"));
+}
+
+#[test]
+fn typed_call_builder() {
+    use super::{ErrorKind, WrenValue};
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", r##"
+    class Shapes {
+        static transform(scale, name) { "%(name):%(scale * 2)" }
+        static area(width, height) { width * height }
+        static describe(sides) { sides.count }
+        static reset() { null }
+    }
+    "##).unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Shapes", 0);
+    });
+    let shapes = vm.get_slot_handle(0);
+
+    let label: String = vm.call_on(&shapes, "transform(_,_)").arg(1.5).arg("square").invoke().unwrap();
+    assert_eq!(label, "square:3");
+    assert_eq!(vm.call_on(&shapes, "area(_,_)").arg(3).arg(4.5).invoke::<f64>().unwrap(), 13.5);
+    assert_eq!(vm.call_on(&shapes, "describe(_)").arg(vec![WrenValue::Null; 3]).invoke::<u32>().unwrap(), 3);
+    vm.call_on(&shapes, "reset()").invoke::<()>().unwrap();
+
+    let mismatch = vm.call_on(&shapes, "area(_,_)").arg(1).invoke::<f64>().unwrap_err();
+    assert_eq!(mismatch.kind(), ErrorKind::InvalidSignature);
+    let wrong_type = vm.call_on(&shapes, "transform(_,_)").arg(1).arg("x").invoke::<f64>().unwrap_err();
+    assert_eq!(wrong_type.kind(), ErrorKind::TypeMismatch);
+    let missing = vm.call_on(&shapes, "missing()").invoke::<()>().unwrap_err();
+    assert_eq!(missing.kind(), ErrorKind::Runtime);
+}
+
+#[test]
+fn registration_order() {
+    let mut lib = super::ModuleLibrary::new();
+    for name in ["zeta", "alpha"] {
+        let mut modl = super::Module::new();
+        modl.source("foreign class Tile {\n    construct new(x) {}\n}\n").class::<Tile, _>("Tile");
+        lib.module(name, modl);
+    }
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("main", "
+    import \"alpha\" for Tile as AlphaTile
+    import \"zeta\" for Tile as ZetaTile
+    class Check {
+        static isZeta(tile) { tile is ZetaTile }
+    }
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Check", 0);
+    });
+    let check = vm.get_slot_handle(0);
+    // zeta was registered first, even though alpha sorts first
+    assert!(vm.call_on(&check, "isZeta(_)").arg(Tile { x: 1.0 }).invoke::<bool>().unwrap());
+}
+
+#[test]
+fn returning_values() {
+    use super::{ErrorKind, VMError};
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    class Game {
+        static score { 42 }
+        static name(prefix) { prefix + \"hero\" }
+    }
+    var Lives = 3
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Game", 0);
+        vm.set_slot_string(1, "super");
+    });
+    let name: String = vm.call_returning(super::FunctionSignature::new_function("name", 1)).unwrap();
+    assert_eq!(name, "superhero");
+
+    assert_eq!(vm.interpret_returning::<u32, _, _>("main", "Game.score + Lives").unwrap(), 45);
+    assert_eq!(vm.interpret_returning::<Option<f64>, _, _>("main", "null").unwrap(), None);
+    match vm.interpret_returning::<bool, _, _>("main", "Lives") {
+        Err(err @ VMError::InvalidReturn { .. }) => assert_eq!(err.kind(), ErrorKind::TypeMismatch),
+        other => panic!("expected a conversion error, got {:?}", other),
+    }
+}
+
+#[test]
+fn sandbox_preset() {
+    use super::{Capability, CapabilityPolicy, StringPolicy};
+
+    let mut lib = super::ModuleLibrary::new();
+    super::stdlib::encoding::publish_module(&mut lib);
+    super::stdlib::path::publish_module(&mut lib);
+    let vm = VMConfig::sandboxed()
+        .library(&lib)
+        .capabilities(CapabilityPolicy::allow_all())
+        .pure_module("encoding")
+        .build();
+
+    let report = vm.sandbox_report();
+    assert!(report.is_sandboxed());
+    assert_eq!(report.library_modules, vec!["encoding".to_string()]);
+    assert!(report.capabilities.is_empty());
+    assert_eq!(report.string_policy, StringPolicy::Strict);
+    assert!(report.max_call_depth <= 32);
+    assert_eq!(report.max_heap_size, None);
+    assert!(report.unenforced.contains(&"total memory"));
+    assert!(vm.interpret("mod", "import \"path\" for Path").is_err());
+
+    let open = VMConfig::new().capabilities(CapabilityPolicy::new().grant(Capability::Process)).build().sandbox_report();
+    assert!(!open.is_sandboxed());
+    assert_eq!(open.capabilities, vec![Capability::Process]);
+}
+
+#[test]
+fn shared_library_worlds() {
+    use std::sync::Arc;
+
+    let mut lib = super::ModuleLibrary::new();
+    super::stdlib::encoding::publish_module(&mut lib);
+    let lib = Arc::new(lib);
+    let worlds: Vec<_> = (0..3).map(|_| VMConfig::new().shared_library(&lib).build()).collect();
+    assert_eq!(Arc::strong_count(&lib), 4);
+
+    let mut modl = super::Module::new();
+    modl.source("foreign class Tile {\n    construct new(x) {}\n    foreign x\n}\n").class::<Tile, _>("Tile");
+    worlds[0].register_module("tiles", modl);
+
+    for world in &worlds {
+        world.interpret("main", "import \"encoding\" for Hex").unwrap();
+    }
+    worlds[0].interpret("main", "import \"tiles\" for Tile\nvar X = Tile.new(4).x").unwrap();
+    assert_eq!(worlds[0].interpret_returning::<f64, _, _>("main", "X").unwrap(), 4.0);
+    assert!(worlds[1].interpret("main", "import \"tiles\" for Tile").is_err());
+
+    // A VM's own registration of a class wins over the library's
+    let vm = library_vm(tiles::publish_module);
+    let mut modl = super::Module::new();
+    modl.source("foreign class Tile {\n    construct new(x) {}\n}\n").class::<Tile, _>("Tile");
+    vm.register_module("local", modl);
+    vm.interpret("main", "import \"local\" for Tile").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        assert!(vm.set_slot_foreign(0, Tile { x: 2.0 }).is_ok());
+    });
+}
+
+#[test]
+fn owned_handles() {
+    struct Component {
+        script: super::OwnedHandle,
+    }
+
+    let component = {
+        let vm = VMConfig::new().build();
+        vm.interpret("main", "
+        class Counter {
+            construct new() { _n = 0 }
+            bump() { _n = _n + 1 }
+        }
+        var C = Counter.new()
+        ").unwrap();
+        vm.execute(|vm| {
+            vm.ensure_slots(1);
+            vm.get_variable("main", "C", 0);
+        });
+        Component { script: vm.get_slot_owned_handle(0) }
+    };
+    // The VMWrapper is gone, but the handle kept the VM alive
+    let vm = component.script.vm();
+    vm.call_on(component.script.as_handle(), "bump()").invoke::<f64>().unwrap();
+    assert_eq!(vm.call_on(component.script.as_handle(), "bump()").invoke::<f64>().unwrap(), 2.0);
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn script_events() {
+    use super::{WrenEvent, WrenRecord, WrenValue};
+
+    #[derive(Debug, PartialEq, WrenRecord)]
+    struct SpawnEvent {
+        kind: String,
+        x: f64,
+    }
+
+    impl WrenEvent for SpawnEvent {
+        const NAME: &'static str = "spawn";
+    }
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    import \"ruwren/host\" for Host
+    Host.emit(\"spawn\", {\"kind\": \"goblin\", \"x\": 3})
+    Host.emit(\"quit\")
+    Host.emit(\"spawn\", {\"kind\": \"orc\", \"x\": 5})
+    Host.emit(\"spawn\", {\"kind\": 7})
+    Host.emit(\"wave\", [[1, 2], {\"boss\": [true]}])
+    ").unwrap();
+    assert!(vm.interpret("main", "Host.emit(\"bad\", Fn.new {})").is_err());
+
+    assert_eq!(vm.events::<SpawnEvent>(), vec![
+        SpawnEvent { kind: "goblin".into(), x: 3.0 },
+        SpawnEvent { kind: "orc".into(), x: 5.0 },
+    ]);
+    assert_eq!(vm.pending_events(), 3);
+    let rest = vm.drain_events();
+    assert_eq!(rest[0], ("quit".to_string(), WrenValue::Null));
+    assert_eq!(rest[1].0, "spawn");
+    let mut boss = super::WrenMap::new();
+    boss.insert("boss", vec![WrenValue::Bool(true)]);
+    assert_eq!(rest[2].1, WrenValue::from(vec![WrenValue::from(vec![1.0, 2.0]), WrenValue::Map(boss)]));
+    assert_eq!(vm.pending_events(), 0);
+
+    // A list or map that contains itself can't be sent, but one that's in a payload twice can
+    assert_eq!(runtime_error(vm.interpret("main", "var l = []\nl.add(l)\nHost.emit(\"loop\", l)")), "Event payload can't contain itself");
+    assert_eq!(runtime_error(vm.interpret("main", "var m = {}\nm[\"self\"] = [m]\nHost.emit(\"loop\", m)")), "Event payload can't contain itself");
+    vm.interpret("main", "var shared = [1]\nHost.emit(\"pair\", [shared, shared])").unwrap();
+    assert_eq!(vm.drain_events(), vec![("pair".to_string(), WrenValue::from(vec![WrenValue::from(vec![1.0]); 2]))]);
+}
+
+#[test]
+fn callback_panics() {
+    use super::{ErrorKind, VMError};
+
+    let vm = VMConfig::new()
+        .printer(|line: String| if line == "boom" { panic!("printer exploded") })
+        .script_loader(|name: String| match name.as_str() {
+            "good" => Some("var Ok = true".to_string()),
+            _ => panic!("no such disk"),
+        })
+        .build();
+
+    match vm.interpret("main", "System.print(\"boom\")") {
+        Err(VMError::CallbackPanic { callback, message }) => {
+            assert_eq!(callback, "printer");
+            assert_eq!(message, "printer exploded");
+        },
+        other => panic!("expected a callback panic, got {:?}", other),
+    }
+    let err = vm.interpret("main", "import \"bad\"").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::CallbackPanic);
+    assert!(matches!(err, VMError::CallbackPanic { callback, .. } if callback == "script loader"));
+
+    // The VM is still fine afterwards
+    vm.interpret("main", "import \"good\" for Ok\nSystem.print(\"fine\")").unwrap();
+}
+
+#[test]
+fn typed_handles() {
+    use super::ForeignBorrowError;
+
+    let vm = library_vm(tiles::publish_module);
+    vm.interpret("main", "
+    import \"tiles\" for Tile
+    var T = Tile.new(2)
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "T", 0);
+    });
+    let tile = vm.get_slot_typed_handle::<Tile>(0).unwrap();
+    assert_eq!(tile.borrow().unwrap().x, 2.0);
+    {
+        let mut edit = tile.borrow_mut().unwrap();
+        edit.x = 9.0;
+        assert!(matches!(tile.borrow(), Err(ForeignBorrowError::AlreadyBorrowed)));
+    }
+    assert_eq!(vm.interpret_returning::<f64, _, _>("main", "T.x").unwrap(), 9.0);
+
+    // Borrowing lends slot 0 rather than adding a slot each time
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.set_slot_double(0, 4.0);
+    });
+    let slots = vm.execute(|vm| vm.get_slot_count());
+    for _ in 0..100 {
+        assert_eq!(tile.borrow().unwrap().x, 9.0);
+    }
+    vm.execute(|vm| {
+        assert_eq!(vm.get_slot_count(), slots);
+        assert_eq!(vm.get_slot_double(0), Some(4.0));
+    });
+
+    vm.execute(|vm| vm.set_slot_double(0, 1.0));
+    assert!(matches!(vm.get_slot_typed_handle::<Tile>(0), Err(ForeignBorrowError::NotForeign)));
+}
+
+#[test]
+fn foreign_method_index() {
+    let mut lib = super::ModuleLibrary::new();
+    tiles::publish_module(&mut lib);
+    for name in ["static_x", "instance_shift"] {
+        let mut modl = super::Module::new();
+        modl.class::<Tile, _>("Tile");
+        lib.module(name, modl);
+    }
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("tiles", "
+    foreign class Tile {
+        construct new(x) {}
+        foreign x
+        foreign static shift(tiles, by)
+    }
+    ").unwrap();
+    assert_eq!(vm.interpret_returning::<f64, _, _>("tiles", "Tile.shift([Tile.new(1)], 1)[0].x").unwrap(), 2.0);
+    // Static and instance methods are bound separately, even with the same signature
+    assert!(vm.interpret("static_x", "foreign class Tile {\n    foreign static x\n}").is_err());
+    assert!(vm.interpret("instance_shift", "foreign class Tile {\n    foreign shift(tiles, by)\n}").is_err());
+}
+
+#[test]
+fn sync_vm_return_values() {
+    let vm = super::SyncVM::new(VMConfig::new().send_printer(|_: String| {})).unwrap();
+    vm.interpret("main", "
+    class Counter {
+        static next { __n = (__n || 0) + 1 }
+        static add(n) { __n = __n + n }
+    }
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Counter", 0);
+    });
+    let counter = std::sync::Arc::new(vm.get_slot_handle(0));
+    let next = vm.make_call_handle(super::FunctionSignature::new_getter("next"));
+    let workers: Vec<_> = (0..4).map(|_| {
+        let vm = vm.clone();
+        let counter = counter.clone();
+        let handle = vm.make_call_handle(super::FunctionSignature::new_getter("next"));
+        std::thread::spawn(move || vm.call_handle_returning::<f64>(&counter, &handle, &[]).unwrap())
+    }).collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(vm.call_handle_returning::<f64>(&counter, &next, &[]).unwrap(), 5.0);
+    let add = super::FunctionSignature::new_function("add", 1);
+    assert_eq!(vm.call_returning::<f64>(&counter, add, &[super::WrenValue::Num(10.0)]).unwrap(), 16.0);
+    assert_eq!(vm.interpret_returning::<String, _, _>("main", "\"%(1 + 1)\"").unwrap(), "2");
+}
+
+#[test]
+fn heap_limit() {
+    use super::{ErrorKind, VMError};
+
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).max_heap_size(4 * 1024 * 1024).build();
+    vm.interpret("main", "
+    class Math {
+        foreign static add5(i)
+    }
+    var hoard = []
+    ").unwrap();
+    assert!(vm.heap_size() > 0);
+
+    // Stopped by the first foreign method called after going over
+    let err = vm.interpret("main", "
+    while (true) {
+        hoard.add(\"x\" * 1024)
+        Math.add5(1)
+    }
+    ").unwrap_err();
+    assert!(matches!(err, VMError::HeapLimitExceeded { limit } if limit == 4 * 1024 * 1024));
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+
+    // Still over the limit, since the hoard can't be collected
+    assert!(vm.interpret("main", "System.print(1)").is_err());
+    vm.interpret("main", "hoard = null").unwrap_err();
+    vm.interpret("main", "System.print(1)").unwrap();
+    assert!(vm.heap_size() <= 4 * 1024 * 1024);
+}
+
+struct Probe;
+
+impl super::Class for Probe {
+    fn initialize(_: &super::VM) -> Probe { Probe }
+}
+
+// Far from any foreign method signature
+fn current_vm_name() -> Option<String> {
+    super::current_vm(|vm| vm.name().unwrap_or("").to_string())
+}
+
+impl Probe {
+    fn name(vm: &super::VM) {
+        vm.set_slot_string(0, current_vm_name().unwrap_or_default());
+    }
+
+    fn fail(_: &super::VM) {
+        assert!(current_vm_name().is_some());
+        panic!("probe failed");
+    }
+
+    fn heal(vm: &super::VM) {
+        match vm.try_get_slot_double(1) {
+            Ok(amount) => vm.set_slot_double(0, amount),
+            Err(err) => vm.set_slot_string(0, err.to_string()),
+        }
+    }
+}
+
+create_module! {
+    class("Probe") crate::tests::Probe => probe {
+        static(fn "name", 0) name,
+        static(fn "fail", 0) fail,
+        static(fn "heal", 1) heal
+    }
+
+    module => current
+}
+
+#[test]
+fn current_vm_scope() {
+    let mut lib = super::ModuleLibrary::new();
+    current::publish_module(&mut lib);
+    let source = "class Probe {\n    foreign static name\n    foreign static fail\n}";
+    let vm = VMConfig::new().library(&lib).name("exposed").expose_current_vm(true).build();
+    vm.interpret("current", source).unwrap();
+    assert_eq!(vm.interpret_returning::<String, _, _>("current", "Probe.name").unwrap(), "exposed");
+    assert!(vm.interpret("current", "Probe.fail").is_err());
+    // Gone again once the method returns, even if it panicked
+    assert!(current_vm_name().is_none());
+
+    let hidden = VMConfig::new().library(&lib).name("hidden").build();
+    hidden.interpret("current", source).unwrap();
+    assert_eq!(hidden.interpret_returning::<String, _, _>("current", "Probe.name").unwrap(), "");
+}
+
+#[test]
+fn fallible_slot_getters() {
+    use super::{SlotError, SlotType};
+
+    let vm = library_vm(current::publish_module);
+    vm.interpret("current", "class Probe {\n    foreign static heal(amount)\n}").unwrap();
+    assert_eq!(vm.interpret_returning::<f64, _, _>("current", "Probe.heal(3)").unwrap(), 3.0);
+    assert_eq!(
+        vm.interpret_returning::<String, _, _>("current", "Probe.heal(\"lots\")").unwrap(),
+        "Probe.heal(_): slot 1 is a <String>, not a <Num>"
+    );
+
+    vm.execute(|vm| {
+        // Outside a foreign method there's no method to name
+        assert_eq!(vm.foreign_method(), None);
+        vm.ensure_slots(1);
+        vm.set_slot_bool(0, true);
+        assert_eq!(vm.try_get_slot_string(0), Err(SlotError::TypeMismatch { slot: 0, expected: SlotType::String, found: SlotType::Bool }));
+        assert_eq!(vm.try_get_slot_bool(0), Ok(true));
+    });
+}
+
+#[cfg(feature = "audit")]
+#[test]
+fn conversion_audit() {
+    captured_log::install();
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var id = 2.5\nvar big = 2.pow(60)\nvar ids = [1, \"two\"]").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "id", 0);
+        assert_eq!(vm.get_slot::<i32>(0).unwrap(), 2);
+        vm.get_variable("main", "big", 0);
+        vm.get_slot::<u64>(0).unwrap();
+        vm.get_variable("main", "ids", 0);
+        assert!(vm.get_slot::<Vec<f64>>(0).is_err());
+        vm.set_slot(0, u64::MAX).unwrap();
+        // Exact conversions stay quiet
+        vm.set_slot(0, 7i64).unwrap();
+        assert_eq!(vm.get_slot::<i64>(0).unwrap(), 7);
+    });
+
+    let logged: Vec<_> = captured_log::logged("ruwren::audit").into_iter().map(|(_, _, message)| message).collect();
+    assert!(logged.iter().any(|m| m.contains("held 2.5, which was read as the i32 2")));
+    assert!(logged.iter().any(|m| m.contains("too big for Wren to have stored exactly")));
+    assert!(logged.iter().any(|m| m.contains("Element 1 of the list")));
+    assert!(logged.iter().any(|m| m.contains(&format!("The u64 {} is too big", u64::MAX))));
+    assert!(!logged.iter().any(|m| m.contains(" 7")));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_into_slots() {
+    use super::WrenValue;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    enum Mode {
+        Coop,
+        Versus { teams: u8 },
+    }
+
+    #[derive(Serialize)]
+    struct Config {
+        title: String,
+        players: Vec<&'static str>,
+        seed: Option<u32>,
+        modes: (Mode, Mode),
+    }
+
+    let config = Config { title: "Lobby".into(), players: vec!["ann", "bo"], seed: None, modes: (Mode::Coop, Mode::Versus { teams: 2 }) };
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var config = null\nclass Store {\n    static set(value) { config = value }\n}").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Store", 0);
+        vm.set_slot_serialize(1, &config).unwrap();
+    });
+    vm.call(super::FunctionSignature::new_function("set", 1)).unwrap();
+    assert_eq!(vm.interpret_returning::<String, _, _>("main", "config[\"players\"][1]").unwrap(), "bo");
+    assert!(vm.interpret_returning::<bool, _, _>("main", "config[\"seed\"] == null").unwrap());
+    assert_eq!(vm.interpret_returning::<String, _, _>("main", "config[\"modes\"][0]").unwrap(), "Coop");
+    assert_eq!(vm.interpret_returning::<f64, _, _>("main", "config[\"modes\"][1][\"Versus\"][\"teams\"]").unwrap(), 2.0);
+
+    let mut bad = BTreeMap::new();
+    bad.insert(vec![1], "list key");
+    assert!(super::serde::to_value(&bad).is_err());
+    assert_eq!(super::serde::to_value(&'x').unwrap(), WrenValue::from("x"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserialize_from_slots() {
+    use super::ConversionError;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Mode {
+        Coop,
+        Versus { teams: u8 },
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Player {
+        name: String,
+        #[serde(default)]
+        score: u32,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        title: String,
+        players: Vec<Player>,
+        seed: Option<f64>,
+        modes: (Mode, Mode),
+    }
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    var config = {
+        \"title\": \"Lobby\",
+        \"players\": [{\"name\": \"ann\", \"score\": 3}, {\"name\": \"bo\"}],
+        \"seed\": null,
+        \"modes\": [\"Coop\", {\"Versus\": {\"teams\": 2}}]
+    }
+    var broken = {\"title\": \"Lobby\", \"players\": [{\"name\": \"ann\"}, {\"name\": 4}], \"modes\": [\"Coop\", \"Coop\"]}
+    ").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "config", 0);
+        assert_eq!(vm.get_slot_deserialize::<Config>(0).unwrap(), Config {
+            title: "Lobby".into(),
+            players: vec![Player { name: "ann".into(), score: 3 }, Player { name: "bo".into(), score: 0 }],
+            seed: None,
+            modes: (Mode::Coop, Mode::Versus { teams: 2 }),
+        });
+
+        vm.get_variable("main", "broken", 0);
+        match vm.get_slot_deserialize::<Config>(0) {
+            Err(ConversionError::Deserialize { path, message }) => {
+                assert_eq!(path, "players[1].name");
+                assert!(message.contains("expected a string"), "{}", message);
+            },
+            other => panic!("expected a deserialize error, got {:?}", other),
+        }
+        // Wren can't list the keys, so there's nothing to build a HashMap from
+        assert!(vm.get_slot_deserialize::<std::collections::HashMap<String, f64>>(0).is_err());
+    });
+}
+
+#[test]
+fn typed_list_reads() {
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var Names = [\"ann\", \"bo\"]\nvar Mixed = [1, \"two\"]\nvar Grid = [[1, 2], [3]]").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Names", 0);
+        assert_eq!(vm.get_slot_list::<String>(0), Some(vec!["ann".to_string(), "bo".to_string()]));
+        vm.get_variable("main", "Mixed", 0);
+        assert_eq!(vm.get_slot_list::<f64>(0), None);
+        vm.get_variable("main", "Grid", 0);
+        assert_eq!(vm.get_slot_list::<Vec<u32>>(0), Some(vec![vec![1, 2], vec![3]]));
+        // Scratch slots are past the slot count, so the list itself is left alone
+        assert_eq!(vm.get_slot_list::<Vec<u32>>(0).map(|grid| grid.len()), Some(2));
+    });
+}
+
+#[test]
+fn list_writes_from_iterators() {
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "class Lists {\n    static describe(a, b) { \"%(a) %(b)\" }\n}").unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(3);
+        vm.get_variable("main", "Lists", 0);
+        vm.set_slot_string(2, "kept");
+        vm.set_slot_list(1, (1..=3).map(|n| n * n)).unwrap();
+        assert_eq!(vm.get_slot::<String>(2).unwrap(), "kept");
+        vm.set_slot_list(2, ["a", "b"].iter().copied()).unwrap();
+    });
+    let described: String = vm.call_returning(super::FunctionSignature::new_function("describe", 2)).unwrap();
+    assert_eq!(described, "[1, 4, 9] [a, b]");
+}
+
+#[test]
+fn map_conversions() {
+    use std::collections::{BTreeMap, HashMap};
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "
+    var Props = {\"hp\": 10, \"speed\": 2.5}
+    class Tables {
+        static describe(table) { table.keys.toList.sort {|a, b| a.bytes[0] < b.bytes[0] }.map {|key| \"%(key)=%(table[key])\" }.join(\",\") }
+    }
+    ").unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Props", 0);
+    });
+    let props: HashMap<String, f64> = vm.get_slot_map(0).unwrap();
+    assert_eq!(props.len(), 2);
+    assert_eq!(props["speed"], 2.5);
+    vm.execute(|vm| vm.get_variable("main", "Props", 0));
+    assert!(vm.get_slot_map::<String, bool>(0).is_err());
+
+    let mut table = BTreeMap::new();
+    table.insert("a".to_string(), vec![1, 2]);
+    table.insert("b".to_string(), vec![]);
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Tables", 0);
+        vm.set_slot(1, table.clone()).unwrap();
+    });
+    let described: String = vm.call_returning(super::FunctionSignature::new_function("describe", 1)).unwrap();
+    assert_eq!(described, "a=[1, 2],b=[]");
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.set_slot_map(0, table.clone()).unwrap();
+    });
+    assert_eq!(vm.get_slot_btree_map::<String, Vec<i32>>(0).unwrap(), table);
+}
+
+#[test]
+fn hot_reload() {
+    use super::HotReload;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let vm = VMConfig::new().build();
+    vm.interpret("game", "
+    // class Commented {}
+    class Game {
+        static speed { 1 }
+        static label { \"class Quoted {}\" }
+    }
+    ").unwrap();
+    let mut hot = HotReload::new();
+    let game = hot.track(&vm, "game", "Game");
+    let lives = hot.track(&vm, "game", "Lives");
+    assert!(hot.handle(lives).is_none());
+    let reloads = Rc::new(Cell::new(0));
+    let counter = reloads.clone();
+    hot.on_reload(move |_| counter.set(counter.get() + 1));
+
+    hot.changed("game", "class Game {\n    static speed { Lives }\n}\nvar Lives = 2");
+    let events = hot.poll(&vm);
+    assert_eq!((events[0].module.as_str(), events[0].generation), ("game", 1));
+    assert!(events[0].result.is_ok());
+    assert_eq!(vm.interpret_returning::<f64, _, _>("game", "Game.speed").unwrap(), 2.0);
+    assert_eq!(vm.call_on(hot.handle(game).unwrap().as_handle(), "speed").invoke::<f64>().unwrap(), 2.0);
+    assert!(hot.handle(lives).is_some());
+    vm.interpret("main", "import \"game\" for Lives").unwrap();
+
+    // A broken source leaves the module as it was
+    let mut next = Some("class Game {".to_string());
+    hot.watch("game", move || next.take());
+    assert!(hot.poll(&vm)[0].result.is_err());
+    assert!(hot.poll(&vm).is_empty());
+    assert_eq!(vm.interpret_returning::<f64, _, _>("game", "Game.speed").unwrap(), 2.0);
+    assert_eq!(reloads.get(), 2);
+
+    // Modules that weren't loaded yet are just interpreted
+    assert!(hot.reload(&vm, "menu", "var Title = \"Start\"").result.is_ok());
+    assert_eq!(vm.interpret_returning::<String, _, _>("menu", "Title").unwrap(), "Start");
+
+    // Brackets in interpolated strings and nested comments don't end a block early
+    let source = "class A {\n    static b {\n        var t = \"%(\"}}\")\"\n        var Hidden = t /* /* } */ } */\n    }\n}\nvar C = 1";
+    assert_eq!(super::hot_reload::top_level_names(source), ["A", "C"]);
+}
+
+#[test]
+fn unbound_foreign_declarations() {
+    use super::{UnboundForeign, VMError};
+
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    let source = "
+    foreign class Ghost {
+        construct new() {}
+    }
+    class Math {
+        foreign static add5(a)
+        foreign static add6(a)
+    }
+    ";
+    let ghost = UnboundForeign::Class { module: "main".into(), class: "Ghost".into() };
+    let add6 = UnboundForeign::Method { module: "main".into(), class: "Math".into(), is_static: true, signature: "add6(_)".into() };
+    assert_eq!(add6.to_string(), "foreign static method Math.add6(_) in module main");
+
+    let vm = VMConfig::new().library(&lib).build();
+    assert!(vm.validate_library().is_ok());
+    assert!(matches!(vm.interpret("main", source), Err(VMError::Runtime { .. })));
+    assert_eq!(vm.validate_library(), Err(vec![ghost.clone(), add6.clone()]));
+
+    let vm = VMConfig::new().library(&lib).strict_library(true).build();
+    match vm.interpret("main", source) {
+        Err(VMError::UnboundForeign { unbound }) => assert_eq!(unbound, vec![ghost, add6]),
+        other => panic!("expected unbound declarations, got {:?}", other),
+    }
+    // Each one is only reported once
+    assert!(vm.interpret("main", "var Fine = 1").is_ok());
+
+    // Wren's optional modules bind their own foreigns
+    let vm = VMConfig::new().strict_library(true).build();
+    vm.interpret("main", "import \"random\" for Random\nimport \"meta\" for Meta\nvar N = Random.new(1).int(10)").unwrap();
+    assert!(vm.validate_library().is_ok());
+}
+
+#[test]
+fn repl_lines() {
+    use super::{Repl, ReplOutput, VMError};
+
+    let mut repl = Repl::new(VMConfig::new().build()).module("console");
+    assert_eq!(repl.feed("1 + 2").unwrap(), ReplOutput::Value("3".to_string()));
+    assert_eq!(repl.feed("var answer = 42").unwrap(), ReplOutput::Ran);
+    assert_eq!(repl.feed("class Greeter {").unwrap(), ReplOutput::Incomplete);
+    assert!(repl.is_incomplete());
+    assert_eq!(repl.feed("    static hi { \"}\" + /* } */ \"hi\" }").unwrap(), ReplOutput::Incomplete);
+    assert_eq!(repl.feed("}").unwrap(), ReplOutput::Ran);
+    assert_eq!(repl.feed("Greeter.hi").unwrap(), ReplOutput::Value("}hi".to_string()));
+    assert_eq!(repl.feed("answer = answer + 1").unwrap(), ReplOutput::Value("43".to_string()));
+    assert_eq!(repl.feed("").unwrap(), ReplOutput::Ran);
+    assert_eq!(repl.feed("\"%(\"{\")\"").unwrap(), ReplOutput::Value("{".to_string()));
+    assert_eq!(repl.variables(), ["answer", "Greeter"]);
+
+    assert!(matches!(repl.feed("Fiber.abort(\"oops\")"), Err(VMError::Runtime { .. })));
+    assert!(matches!(repl.feed("var = 3"), Err(VMError::Compile { .. })));
+    assert!(!repl.is_incomplete());
+    assert_eq!(repl.feed("[1,").unwrap(), ReplOutput::Incomplete);
+    repl.cancel();
+    assert_eq!(repl.feed("answer").unwrap(), ReplOutput::Value("43".to_string()));
+}
@@ -0,0 +1,94 @@
+//! Running async foreign methods' futures on a tokio runtime
+//!
+//! Futures given to [VM::spawn_future] run on the runtime (so they can use tokio's I/O and timers,
+//! and run on its threads), and send their results back over a channel. [VMWrapper::pump_events]
+//! takes them off the channel, and resumes the fibers that were waiting on them.
+use crate::{VM, VMWrapper, WrenValue, SlotId, ForeignSendError, AsyncReport};
+use crate::futures::State;
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::sync::mpsc;
+use tokio::runtime::Handle;
+use tokio::task::AbortHandle;
+
+type Completion = (u64, Result<WrenValue, String>);
+
+/// The runtime a VM spawns futures on, and where they send their results
+#[derive(Debug)]
+pub(crate) struct TokioBridge {
+    runtime: Option<Handle>,
+    sender: mpsc::Sender<Completion>,
+    receiver: mpsc::Receiver<Completion>,
+    next_id: Cell<u64>,
+}
+
+impl TokioBridge {
+    pub(crate) fn new(runtime: Option<Handle>) -> TokioBridge {
+        let (sender, receiver) = mpsc::channel();
+        TokioBridge { runtime, sender, receiver, next_id: Cell::new(0) }
+    }
+}
+
+/// A future running on the runtime, which is aborted if nothing wants its result anymore
+pub(crate) struct Spawned {
+    pub(crate) id: u64,
+    abort: AbortHandle,
+}
+
+impl fmt::Debug for Spawned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Spawned").field("id", &self.id).finish()
+    }
+}
+
+impl Drop for Spawned {
+    fn drop(&mut self) {
+        // Does nothing if it's already finished
+        self.abort.abort();
+    }
+}
+
+impl VM {
+    /// Like [VM::set_slot_future], but runs [future] on a tokio runtime, and the fibers waiting on
+    /// it are resumed by [VMWrapper::pump_events]
+    ///
+    /// Uses the runtime given to [VMConfig::tokio_runtime](crate::VMConfig::tokio_runtime), or else
+    /// the one the VM is being run from. Panics if there's neither, like `tokio::spawn`.
+    pub fn spawn_future<F, T, E>(&self, slot: SlotId, future: F) -> Result<(), ForeignSendError>
+    where
+        F: 'static + Future<Output = Result<T, E>> + Send,
+        T: Into<WrenValue>,
+        E: fmt::Display,
+    {
+        let bridge = &self.user_data().tokio;
+        let id = bridge.next_id.get();
+        bridge.next_id.set(id + 1);
+        let sender = bridge.sender.clone();
+        let task = async move {
+            let result = future.await.map(Into::into).map_err(|e| e.to_string());
+            // The VM may be gone, in which case no one's waiting
+            let _ = sender.send((id, result));
+        };
+        let join = match &bridge.runtime {
+            Some(runtime) => runtime.spawn(task),
+            None => tokio::spawn(task),
+        };
+        self.set_slot_task(slot, State::Spawned(Spawned { id, abort: join.abort_handle() }))
+    }
+}
+
+impl VMWrapper {
+    /// Takes the results of every future spawned with [VM::spawn_future] that has finished, and
+    /// resumes the fibers waiting on them (along with those [VMWrapper::poll_async] would)
+    pub fn pump_events(&self) -> AsyncReport {
+        self.execute(|vm| {
+            let ud = vm.user_data();
+            let futures = ud.futures.borrow();
+            for (id, result) in ud.tokio.receiver.try_iter() {
+                futures.complete(id, result);
+            }
+        });
+        self.poll_async()
+    }
+}
@@ -0,0 +1,29 @@
+//! Spans around everything that crosses the FFI boundary, with the `tracing` feature
+//!
+//! Interprets (`interpret`, with its `module`), calls (`call`, with the `signature`), foreign
+//! methods (`foreign`, with the `class` and `signature`), module loads (`load_module`, with the
+//! `module`) and garbage collections (`gc`) each get a span with a target of `ruwren`, so scripts
+//! show up interleaved with the host's own traces. Without the feature the spans compile away.
+
+// Enters a span at [level], which is exited when the returned guard is dropped. Fields are only
+// evaluated if the span is enabled.
+#[cfg(feature = "tracing")]
+macro_rules! ffi_span {
+    ($level:ident, $name:literal $(, $($field:tt)+)?) => {
+        tracing::span!(target: "ruwren", tracing::Level::$level, $name $(, $($field)+)?).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! ffi_span {
+    ($($arg:tt)+) => {
+        $crate::trace::FfiSpan
+    };
+}
+
+/// What [ffi_span] returns
+#[cfg(feature = "tracing")]
+pub(crate) type FfiSpan = tracing::span::EnteredSpan;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct FfiSpan;
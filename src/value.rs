@@ -0,0 +1,1254 @@
+//! Plain Wren data, detached from any VM
+use crate::{VM, VMWrapper, VMError, SlotError, ForeignSendError, ClassObject, SlotId, SlotType, FunctionSignature, Error};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::iter::FromIterator;
+use std::os::raw;
+use std::fmt::Write;
+use std::ops::Index;
+
+/// A Wren value that can be copied in and out of a VM
+#[derive(Debug, Clone, PartialEq)]
+pub enum WrenValue {
+    Null,
+    Bool(bool),
+    Num(f64),
+    String(String),
+    List(WrenList),
+    Map(WrenMap),
+}
+
+/// The elements of a Wren list
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WrenList(Vec<WrenValue>);
+
+impl WrenList {
+    pub fn new() -> WrenList {
+        WrenList(vec![])
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&WrenValue> {
+        self.0.get(index)
+    }
+
+    pub fn push<V: Into<WrenValue>>(&mut self, value: V) {
+        self.0.push(value.into())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, WrenValue> {
+        self.0.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<WrenValue> {
+        self.0
+    }
+}
+
+impl From<Vec<WrenValue>> for WrenList {
+    fn from(items: Vec<WrenValue>) -> Self {
+        WrenList(items)
+    }
+}
+
+impl<V: Into<WrenValue>> std::iter::FromIterator<V> for WrenList {
+    fn from_iter<I: IntoIterator<Item = V>>(iter: I) -> Self {
+        WrenList(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+impl Index<usize> for WrenList {
+    type Output = WrenValue;
+
+    fn index(&self, index: usize) -> &WrenValue {
+        &self.0[index]
+    }
+}
+
+impl IntoIterator for WrenList {
+    type Item = WrenValue;
+    type IntoIter = std::vec::IntoIter<WrenValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a WrenList {
+    type Item = &'a WrenValue;
+    type IntoIter = std::slice::Iter<'a, WrenValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// The entries of a Wren map, in insertion order (Wren maps themselves are unordered)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WrenMap(Vec<(WrenValue, WrenValue)>);
+
+impl WrenMap {
+    pub fn new() -> WrenMap {
+        WrenMap(vec![])
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get<K: Into<WrenValue>>(&self, key: K) -> Option<&WrenValue> {
+        let key = key.into();
+        self.0.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key<K: Into<WrenValue>>(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets [key] to [value], returning the value it replaced
+    pub fn insert<K: Into<WrenValue>, V: Into<WrenValue>>(&mut self, key: K, value: V) -> Option<WrenValue> {
+        let (key, value) = (key.into(), value.into());
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old)) => Some(std::mem::replace(old, value)),
+            None => {
+                self.0.push((key, value));
+                None
+            }
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, (WrenValue, WrenValue)> {
+        self.0.iter()
+    }
+}
+
+impl<K: Into<WrenValue>, V: Into<WrenValue>> std::iter::FromIterator<(K, V)> for WrenMap {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = WrenMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl Index<&str> for WrenMap {
+    type Output = WrenValue;
+
+    fn index(&self, key: &str) -> &WrenValue {
+        self.get(key).unwrap_or_else(|| panic!("no key {:?} in map", key))
+    }
+}
+
+impl IntoIterator for WrenMap {
+    type Item = (WrenValue, WrenValue);
+    type IntoIter = std::vec::IntoIter<(WrenValue, WrenValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a WrenMap {
+    type Item = &'a (WrenValue, WrenValue);
+    type IntoIter = std::slice::Iter<'a, (WrenValue, WrenValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl WrenValue {
+    /// Wren source that evaluates to this value
+    pub(crate) fn to_wren_source(&self) -> String {
+        let mut source = String::new();
+        self.write_source(&mut source);
+        source
+    }
+
+    fn write_source(&self, out: &mut String) {
+        match self {
+            WrenValue::Null => out.push_str("null"),
+            WrenValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            WrenValue::Num(n) if n.is_nan() => out.push_str("(0/0)"),
+            WrenValue::Num(n) if n.is_infinite() => out.push_str(if *n > 0.0 { "(1/0)" } else { "(-1/0)" }),
+            WrenValue::Num(n) => write!(out, "({:?})", n).unwrap(),
+            WrenValue::String(s) => write_quoted(out, s, true),
+            WrenValue::List(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_source(out);
+                }
+                out.push(']');
+            },
+            WrenValue::Map(entries) => {
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    key.write_source(out);
+                    out.push_str(": ");
+                    value.write_source(out);
+                }
+                out.push('}');
+            },
+        }
+    }
+
+    /// Formats the value on one line, the same as [Display](std::fmt::Display)
+    pub fn format_compact(&self) -> String {
+        self.to_string()
+    }
+
+    /// Formats the value on one line, writing numbers with [format]
+    pub fn format_compact_with(&self, format: &NumberFormat) -> String {
+        let mut out = String::new();
+        self.write_compact(&mut out, format);
+        out
+    }
+
+    /// Formats the value with each element of a (non-empty) list or map on its own line,
+    /// nested [indent] spaces deeper than its parent
+    pub fn format_indented(&self, indent: usize) -> String {
+        self.format_indented_with(indent, &NumberFormat::default())
+    }
+
+    /// Like [WrenValue::format_indented], writing numbers with [format]
+    pub fn format_indented_with(&self, indent: usize, format: &NumberFormat) -> String {
+        let mut out = String::new();
+        self.write_indented(&mut out, indent, 0, format);
+        out
+    }
+
+    fn write_compact(&self, out: &mut String, format: &NumberFormat) {
+        match self {
+            WrenValue::Null => out.push_str("null"),
+            WrenValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            WrenValue::Num(n) => out.push_str(&format.format(*n)),
+            WrenValue::String(s) => write_quoted(out, s, false),
+            WrenValue::List(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_compact(out, format);
+                }
+                out.push(']');
+            },
+            WrenValue::Map(entries) => {
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    key.write_compact(out, format);
+                    out.push_str(": ");
+                    value.write_compact(out, format);
+                }
+                out.push('}');
+            },
+        }
+    }
+
+    fn write_indented(&self, out: &mut String, indent: usize, depth: usize, format: &NumberFormat) {
+        let pad = |out: &mut String, depth: usize| out.push_str(&" ".repeat(indent * depth));
+        match self {
+            WrenValue::List(items) if !items.is_empty() => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    pad(out, depth + 1);
+                    item.write_indented(out, indent, depth + 1, format);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                pad(out, depth);
+                out.push(']');
+            },
+            WrenValue::Map(entries) if !entries.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    pad(out, depth + 1);
+                    key.write_indented(out, indent, depth + 1, format);
+                    out.push_str(": ");
+                    value.write_indented(out, indent, depth + 1, format);
+                    out.push_str(if i + 1 < entries.len() { ",\n" } else { "\n" });
+                }
+                pad(out, depth);
+                out.push('}');
+            },
+            other => other.write_compact(out, format),
+        }
+    }
+}
+
+// Writes [s] as a Wren string literal. Interpolation only needs escaping in actual source.
+fn write_quoted(out: &mut String, s: &str, escape_interpolation: bool) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '%' if escape_interpolation => out.push_str("\\%"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            c if (c as u32) < 0x20 => write!(out, "\\x{:02x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// How numbers are written when formatting values
+///
+/// The default writes every number exactly, never with an exponent. For output that has to be
+/// stable (like golden tests), set a [precision](NumberFormat::precision).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberFormat {
+    /// Significant digits to round to (None to write the shortest exact form)
+    pub precision: Option<usize>,
+    /// Numbers at least this large (ignoring sign) are written with an exponent
+    pub exponent_above: f64,
+    /// Nonzero numbers smaller than this (ignoring sign) are written with an exponent
+    pub exponent_below: f64,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            precision: None,
+            exponent_above: f64::INFINITY,
+            exponent_below: 0.0,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Wren's own format (as used by System.print): 14 significant digits, with an exponent
+    /// outside 1e-4 to 1e14
+    pub fn wren() -> NumberFormat {
+        NumberFormat {
+            precision: Some(14),
+            exponent_above: 1e14,
+            exponent_below: 1e-4,
+        }
+    }
+
+    pub fn precision(mut self, digits: usize) -> Self {
+        self.precision = Some(digits.max(1));
+        self
+    }
+
+    pub fn exponent_above(mut self, limit: f64) -> Self {
+        self.exponent_above = limit;
+        self
+    }
+
+    pub fn exponent_below(mut self, limit: f64) -> Self {
+        self.exponent_below = limit;
+        self
+    }
+
+    pub fn format(&self, n: f64) -> String {
+        if n.is_nan() {
+            return "nan".to_string();
+        } else if n.is_infinite() {
+            return if n > 0.0 { "infinity" } else { "-infinity" }.to_string();
+        }
+
+        // Rounding through the exponent form also drops trailing zeros, like %g
+        let n = match self.precision {
+            Some(digits) => format!("{:.*e}", digits.max(1) - 1, n).parse().unwrap_or(n),
+            None => n,
+        };
+        if n != 0.0 && (n.abs() >= self.exponent_above || n.abs() < self.exponent_below) {
+            let exp = format!("{:e}", n);
+            let (mantissa, exponent) = exp.split_at(exp.find('e').unwrap());
+            let exponent: i32 = exponent[1..].parse().unwrap();
+            format!("{}e{}{:02}", mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+        } else if n.fract() == 0.0 && n.abs() < 1e15 {
+            format!("{:.0}", n)
+        } else {
+            format!("{}", n)
+        }
+    }
+}
+
+impl std::fmt::Display for WrenValue {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.format_compact_with(&NumberFormat::default()))
+    }
+}
+
+impl From<bool> for WrenValue {
+    fn from(b: bool) -> Self {
+        WrenValue::Bool(b)
+    }
+}
+
+impl From<f64> for WrenValue {
+    fn from(n: f64) -> Self {
+        WrenValue::Num(n)
+    }
+}
+
+impl From<i32> for WrenValue {
+    fn from(n: i32) -> Self {
+        WrenValue::Num(n as f64)
+    }
+}
+
+impl From<&str> for WrenValue {
+    fn from(s: &str) -> Self {
+        WrenValue::String(s.to_string())
+    }
+}
+
+impl From<String> for WrenValue {
+    fn from(s: String) -> Self {
+        WrenValue::String(s)
+    }
+}
+
+impl<T: Into<WrenValue>> From<Option<T>> for WrenValue {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(WrenValue::Null)
+    }
+}
+
+impl<T: Into<WrenValue>> From<Vec<T>> for WrenValue {
+    fn from(items: Vec<T>) -> Self {
+        WrenValue::List(items.into_iter().collect())
+    }
+}
+
+impl From<WrenList> for WrenValue {
+    fn from(list: WrenList) -> Self {
+        WrenValue::List(list)
+    }
+}
+
+impl From<WrenMap> for WrenValue {
+    fn from(map: WrenMap) -> Self {
+        WrenValue::Map(map)
+    }
+}
+
+/// Converts a [WrenValue] back into a Rust value, if it holds the right kind of data
+pub trait FromWrenValue: Sized {
+    fn from_wren_value(value: &WrenValue) -> Option<Self>;
+
+    /// The value of a [WrenRecord] field whose key is missing from the map, if it can be left out
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+impl FromWrenValue for WrenValue {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromWrenValue for bool {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromWrenValue for f64 {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// Only whole numbers in range convert
+impl FromWrenValue for i32 {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::Num(n) if n.fract() == 0.0 && *n >= i32::MIN as f64 && *n <= i32::MAX as f64 => Some(*n as i32),
+            _ => None,
+        }
+    }
+}
+
+impl FromWrenValue for String {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// null converts to None, and so does a missing record field (but not one of the wrong kind)
+impl<T: FromWrenValue> FromWrenValue for Option<T> {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::Null => Some(None),
+            value => T::from_wren_value(value).map(Some),
+        }
+    }
+
+    fn from_missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<T: FromWrenValue> FromWrenValue for Vec<T> {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::List(list) => list.iter().enumerate().map(|(index, item)| {
+                let converted = T::from_wren_value(item);
+                if converted.is_none() {
+                    audit!("List element {} ({}) isn't a {}, so the whole list was rejected", index, item, std::any::type_name::<T>());
+                }
+                converted
+            }).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl FromWrenValue for WrenList {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::List(list) => Some(list.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromWrenValue for WrenMap {
+    fn from_wren_value(value: &WrenValue) -> Option<Self> {
+        match value {
+            WrenValue::Map(map) => Some(map.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// A Rust value that can be read straight out of a slot
+///
+/// Foreign methods can use this (through [VM::get_slot]) to pull typed arguments out of their
+/// slots. Numbers are converted with `as`, so reading 2.5 as an i32 gives 2 (which the `audit`
+/// feature logs, along with any other conversion that loses information).
+pub trait FromWren: Sized {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError>;
+}
+
+/// A Rust value that can be put into a slot, the counterpart of [FromWren]
+///
+/// Only foreign objects can fail to send (see [VM::set_slot_foreign]).
+pub trait ToWren {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError>;
+}
+
+fn expect_slot(vm: &VM, slot: SlotId, expected: SlotType) -> Result<(), SlotError> {
+    match vm.get_slot_type(slot) {
+        found if found == expected => Ok(()),
+        found => Err(SlotError::TypeMismatch { slot, expected, found }),
+    }
+}
+
+/// Null, so a method with nothing to return leaves null in the slot like Wren's would
+impl ToWren for () {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_null(slot);
+        Ok(())
+    }
+}
+
+/// Ignores the slot, for calls whose result doesn't matter
+impl FromWren for () {
+    fn from_wren(_vm: &VM, _slot: SlotId) -> Result<Self, SlotError> {
+        Ok(())
+    }
+}
+
+impl FromWren for bool {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        expect_slot(vm, slot, SlotType::Bool)?;
+        Ok(vm.get_slot_bool(slot).unwrap())
+    }
+}
+
+impl ToWren for bool {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_bool(slot, self);
+        Ok(())
+    }
+}
+
+macro_rules! wren_num {
+    ($($ty:ty),+; integer: $integer:expr) => {
+        $(
+            impl FromWren for $ty {
+                fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+                    expect_slot(vm, slot, SlotType::Num)?;
+                    let num = vm.get_slot_double(slot).unwrap();
+                    let value = num as $ty;
+                    // Floats only lose precision, which is expected, unless they overflow
+                    let lossy = if $integer { value as f64 != num } else { (value as f64).is_infinite() && num.is_finite() };
+                    if lossy {
+                        audit!("Slot {} held {}, which was read as the {} {}", slot, num, stringify!($ty), value);
+                    } else if $integer && num.abs() > crate::audit::MAX_SAFE_INTEGER {
+                        audit!("Slot {} held {}, which is too big for Wren to have stored exactly", slot, num);
+                    }
+                    Ok(value)
+                }
+            }
+
+            impl ToWren for $ty {
+                fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+                    let num = self as f64;
+                    if $integer && num.abs() > crate::audit::MAX_SAFE_INTEGER {
+                        audit!("The {} {} is too big for a Wren number, so slot {} holds {}", stringify!($ty), self, slot, num);
+                    }
+                    vm.set_slot_double(slot, num);
+                    Ok(())
+                }
+            }
+        )+
+    };
+}
+
+wren_num!(f64, f32; integer: false);
+wren_num!(i32, i64, u32, u64, usize; integer: true);
+
+impl FromWren for String {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        expect_slot(vm, slot, SlotType::String)?;
+        vm.get_slot_string(slot).ok_or(SlotError::InvalidUtf8 { slot })
+    }
+}
+
+impl ToWren for String {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_string(slot, self);
+        Ok(())
+    }
+}
+
+impl ToWren for &str {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_string(slot, self);
+        Ok(())
+    }
+}
+
+/// Raw bytes, as a Wren string
+impl FromWren for Vec<u8> {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        expect_slot(vm, slot, SlotType::String)?;
+        Ok(vm.get_slot_bytes(slot).unwrap())
+    }
+}
+
+impl ToWren for Vec<u8> {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_bytes(slot, &self);
+        Ok(())
+    }
+}
+
+/// Null is None
+impl<T: FromWren> FromWren for Option<T> {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        vm.get_slot_option(slot)
+    }
+}
+
+impl<T: ToWren> ToWren for Option<T> {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        match self {
+            Some(value) => value.to_wren(vm, slot),
+            None => {
+                vm.set_slot_null(slot);
+                Ok(())
+            },
+        }
+    }
+}
+
+/// The elements of a list (using one slot past the current slot count as scratch space)
+impl<T: FromWren> FromWren for Vec<T> {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        expect_slot(vm, slot, SlotType::List)?;
+        let scratch = vm.get_slot_count().max(slot + 1);
+        vm.ensure_slots(scratch + 1);
+        (0..vm.get_list_count(slot)).map(|index| {
+            vm.get_list_element(slot, index as i32, scratch);
+            T::from_wren(vm, scratch).inspect_err(|error| {
+                audit!("Element {} of the list in slot {} couldn't be read, so the whole list was rejected: {}", index, slot, error);
+            })
+        }).collect()
+    }
+}
+
+impl<T: ToWren> ToWren for Vec<T> {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_list(slot, self)
+    }
+}
+
+/// A new map, with keys and values converted like anything else (see [VM::set_slot_map])
+impl<K: ToWren, V: ToWren> ToWren for HashMap<K, V> {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_map(slot, self)
+    }
+}
+
+impl<K: ToWren, V: ToWren> ToWren for BTreeMap<K, V> {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_map(slot, self)
+    }
+}
+
+impl FromWren for WrenList {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        expect_slot(vm, slot, SlotType::List)?;
+        // A list holding something that can't be copied is as good as the wrong type
+        vm.copy_slot_list(slot).ok_or(SlotError::TypeMismatch { slot, expected: SlotType::List, found: SlotType::Unknown })
+    }
+}
+
+/// Fails on anything that can't be copied (see [VM::get_slot_value])
+impl FromWren for WrenValue {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        vm.get_slot_value(slot).ok_or_else(|| {
+            SlotError::TypeMismatch { slot, expected: SlotType::Unknown, found: vm.get_slot_type(slot) }
+        })
+    }
+}
+
+impl ToWren for WrenValue {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_value(slot, &self);
+        Ok(())
+    }
+}
+
+/// A copy of the foreign object, which has to be free to borrow
+impl<T: 'static + ClassObject + Clone> FromWren for T {
+    fn from_wren(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        vm.try_borrow_foreign(slot, T::clone).map_err(|error| SlotError::Foreign { slot, error })
+    }
+}
+
+/// A new foreign object, as whichever class T is bound to (see [VM::set_slot_foreign])
+impl<T: 'static + ClassObject> ToWren for T {
+    fn to_wren(self, vm: &VM, slot: SlotId) -> Result<(), ForeignSendError> {
+        vm.set_slot_foreign(slot, self)
+    }
+}
+
+/// A plain struct that's passed to Wren as a map, with its field names as keys
+///
+/// Usually derived (with the `derive` feature), which also implements `From<Self> for WrenValue`
+/// and [FromWrenValue], so records can be nested in each other, or in lists.
+pub trait WrenRecord: Sized {
+    fn to_wren_map(&self) -> WrenMap;
+    /// Returns None if a field holds the wrong kind of value, or is missing (unless it's an Option)
+    fn from_wren_map(map: &WrenMap) -> Option<Self>;
+}
+
+/// A fieldless enum that scripts pass as strings (or numbers), like `"North"` for `Direction::North`
+///
+/// Usually derived (with the `derive` feature), which also implements [FromWren], [ToWren],
+/// `From<Self> for WrenValue` and [FromWrenValue] for the enum. Read one with [VM::get_slot_enum],
+/// which fails with every name the script could have used if it passed something else.
+pub trait WrenEnum: Sized + Copy + PartialEq + 'static {
+    /// The enum's name, for errors
+    const NAME: &'static str;
+    /// Every variant, with the name and number scripts can give for it
+    const VARIANTS: &'static [(&'static str, f64, Self)];
+    /// Whether variants are written to Wren as their numbers, rather than their names (either
+    /// is read back)
+    const NUMBERED: bool = false;
+
+    /// The variant with the name or number in [value]
+    fn from_value(value: &WrenValue) -> Option<Self> {
+        Self::VARIANTS.iter().find(|(name, number, _)| match value {
+            WrenValue::String(s) => s == name,
+            WrenValue::Num(n) => n == number,
+            _ => false,
+        }).map(|(_, _, variant)| *variant)
+    }
+
+    /// The name or number the variant is written to Wren as
+    fn to_value(self) -> WrenValue {
+        let (name, number, _) = Self::VARIANTS.iter().find(|(_, _, variant)| *variant == self)
+            .expect("WrenEnum::VARIANTS is missing a variant");
+        if Self::NUMBERED { WrenValue::Num(*number) } else { WrenValue::String(name.to_string()) }
+    }
+
+    /// Reads the variant in [slot], for [FromWren] (see [VM::get_slot_enum])
+    fn from_slot(vm: &VM, slot: SlotId) -> Result<Self, SlotError> {
+        let found = match vm.get_slot_type(slot) {
+            SlotType::String => vm.get_slot_str(slot)
+                .and_then(|s| Self::VARIANTS.iter().find(|(name, _, _)| *name == &*s)),
+            SlotType::Num => vm.get_slot_double(slot)
+                .and_then(|n| Self::VARIANTS.iter().find(|(_, number, _)| *number == n)),
+            _ => None,
+        };
+        found.map(|(_, _, variant)| *variant).ok_or_else(|| {
+            let expected = Self::VARIANTS.iter()
+                .map(|(name, number, _)| if Self::NUMBERED { format!("{} ({:?})", number, name) } else { format!("{:?}", name) })
+                .collect::<Vec<_>>()
+                .join(", ");
+            SlotError::NotAVariant { slot, value: vm.dump_slot(slot).summary, ty: Self::NAME, expected }
+        })
+    }
+}
+
+/// TOML tables become maps, and datetimes become strings in TOML's own format
+#[cfg(feature = "toml")]
+impl From<toml::Value> for WrenValue {
+    fn from(value: toml::Value) -> Self {
+        match value {
+            toml::Value::String(s) => WrenValue::String(s),
+            toml::Value::Integer(n) => WrenValue::Num(n as f64),
+            toml::Value::Float(n) => WrenValue::Num(n),
+            toml::Value::Boolean(b) => WrenValue::Bool(b),
+            toml::Value::Datetime(dt) => WrenValue::String(dt.to_string()),
+            toml::Value::Array(items) => items.into(),
+            toml::Value::Table(table) => WrenValue::Map(table.into_iter().collect()),
+        }
+    }
+}
+
+/// Whole numbers become TOML integers. Fails on null, which TOML can't write, and on maps
+/// with keys that aren't strings
+///
+/// `toml::Value::try_from` is toml's own serde conversion, so use `value.try_into()` instead.
+#[cfg(feature = "toml")]
+impl std::convert::TryFrom<WrenValue> for toml::Value {
+    type Error = crate::ConversionError;
+
+    fn try_from(value: WrenValue) -> Result<Self, crate::ConversionError> {
+        use crate::ConversionError;
+        use std::convert::TryInto;
+
+        Ok(match value {
+            WrenValue::Null => return Err(ConversionError::Unrepresentable { format: "TOML", value: "null".into() }),
+            WrenValue::Bool(b) => toml::Value::Boolean(b),
+            WrenValue::Num(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => toml::Value::Integer(n as i64),
+            WrenValue::Num(n) => toml::Value::Float(n),
+            WrenValue::String(s) => toml::Value::String(s),
+            WrenValue::List(list) => toml::Value::Array(list.into_iter().map(TryInto::try_into).collect::<Result<_, _>>()?),
+            WrenValue::Map(map) => {
+                let mut table = toml::map::Map::new();
+                for (key, value) in map {
+                    let key = match key {
+                        WrenValue::String(key) => key,
+                        key => return Err(ConversionError::NonStringKey { format: "TOML", key: key.to_string() }),
+                    };
+                    table.insert(key, value.try_into()?);
+                }
+                toml::Value::Table(table)
+            },
+        })
+    }
+}
+
+/// Unit and None become null, and chars become strings
+#[cfg(feature = "ron")]
+impl From<ron::Value> for WrenValue {
+    fn from(value: ron::Value) -> Self {
+        match value {
+            ron::Value::Bool(b) => WrenValue::Bool(b),
+            ron::Value::Char(c) => WrenValue::String(c.to_string()),
+            ron::Value::Map(map) => WrenValue::Map(map.into_iter().map(|(k, v)| (WrenValue::from(k), WrenValue::from(v))).collect()),
+            ron::Value::Number(n) => WrenValue::Num(n.into_f64()),
+            ron::Value::Option(value) => value.map(|value| WrenValue::from(*value)).unwrap_or(WrenValue::Null),
+            ron::Value::String(s) => WrenValue::String(s),
+            ron::Value::Seq(items) => items.into(),
+            ron::Value::Unit => WrenValue::Null,
+        }
+    }
+}
+
+/// Null becomes unit, and whole numbers become RON integers
+#[cfg(feature = "ron")]
+impl From<WrenValue> for ron::Value {
+    fn from(value: WrenValue) -> Self {
+        match value {
+            WrenValue::Null => ron::Value::Unit,
+            WrenValue::Bool(b) => ron::Value::Bool(b),
+            WrenValue::Num(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => ron::Value::Number((n as i64).into()),
+            WrenValue::Num(n) => ron::Value::Number(n.into()),
+            WrenValue::String(s) => ron::Value::String(s),
+            WrenValue::List(list) => ron::Value::Seq(list.into_iter().map(ron::Value::from).collect()),
+            WrenValue::Map(map) => ron::Value::Map(map.into_iter().map(|(k, v)| (ron::Value::from(k), ron::Value::from(v))).collect()),
+        }
+    }
+}
+
+impl VM {
+    /// How this VM formats numbers (see [crate::VMConfig::number_format])
+    pub fn number_format(&self) -> NumberFormat {
+        self.user_data().number_format
+    }
+
+    /// Formats [value] on one line with this VM's number format, or indented (see
+    /// [WrenValue::format_indented]) if [indent] is given
+    pub fn format_value(&self, value: &WrenValue, indent: Option<usize>) -> String {
+        match indent {
+            Some(indent) => value.format_indented_with(indent, &self.user_data().number_format),
+            None => value.format_compact_with(&self.user_data().number_format),
+        }
+    }
+
+    pub fn slot_is_null(&self, slot: SlotId) -> bool {
+        self.get_slot_type(slot) == SlotType::Null
+    }
+
+    /// Reads [slot] as a T, or None if it's null
+    pub fn get_slot_option<T: FromWren>(&self, slot: SlotId) -> Result<Option<T>, SlotError> {
+        if self.slot_is_null(slot) {
+            Ok(None)
+        } else {
+            T::from_wren(self, slot).map(Some)
+        }
+    }
+
+    /// Reads [slot] as a T
+    pub fn get_slot<T: FromWren>(&self, slot: SlotId) -> Result<T, SlotError> {
+        T::from_wren(self, slot)
+    }
+
+    /// Reads [slot] as a T, like [VM::get_slot], but naming the foreign method that's reading it
+    ///
+    /// Inside a foreign method, errors come back as [SlotError::InMethod], so
+    /// `vm.try_get_slot::<f64>(1)?` in `Player.heal(_)` reports
+    /// `Player.heal(_): slot 1 is a <String>, not a <Num>` rather than just the slot.
+    pub fn try_get_slot<T: FromWren>(&self, slot: SlotId) -> Result<T, SlotError> {
+        T::from_wren(self, slot).map_err(|error| self.in_foreign_method(error))
+    }
+
+    /// Reads [slot] as a T, or None if it's null, naming the foreign method like [VM::try_get_slot]
+    pub fn try_get_slot_option<T: FromWren>(&self, slot: SlotId) -> Result<Option<T>, SlotError> {
+        self.get_slot_option(slot).map_err(|error| self.in_foreign_method(error))
+    }
+
+    pub fn try_get_slot_double(&self, slot: SlotId) -> Result<f64, SlotError> {
+        self.try_get_slot(slot)
+    }
+
+    pub fn try_get_slot_bool(&self, slot: SlotId) -> Result<bool, SlotError> {
+        self.try_get_slot(slot)
+    }
+
+    pub fn try_get_slot_string(&self, slot: SlotId) -> Result<String, SlotError> {
+        self.try_get_slot(slot)
+    }
+
+    pub fn try_get_slot_bytes(&self, slot: SlotId) -> Result<Vec<u8>, SlotError> {
+        self.try_get_slot(slot)
+    }
+
+    /// Reads [slot] as an i32, failing (rather than truncating, like [VM::get_slot] does) if it
+    /// holds anything but a whole number in range
+    ///
+    /// Errors name the foreign method doing the reading, like [VM::try_get_slot].
+    pub fn get_slot_i32(&self, slot: SlotId) -> Result<i32, SlotError> {
+        self.get_slot_integer(slot, "i32", i32::MIN as f64, i32::MAX as f64).map(|num| num as i32)
+    }
+
+    /// Reads [slot] as a u32, like [VM::get_slot_i32]
+    pub fn get_slot_u32(&self, slot: SlotId) -> Result<u32, SlotError> {
+        self.get_slot_integer(slot, "u32", u32::MIN as f64, u32::MAX as f64).map(|num| num as u32)
+    }
+
+    /// Reads [slot] as an i64, like [VM::get_slot_i32]
+    pub fn get_slot_i64(&self, slot: SlotId) -> Result<i64, SlotError> {
+        self.get_slot_integer(slot, "i64", i64::MIN as f64, i64::MAX as f64).map(|num| num as i64)
+    }
+
+    /// Reads [slot] as a usize, like [VM::get_slot_i32]
+    pub fn get_slot_usize(&self, slot: SlotId) -> Result<usize, SlotError> {
+        self.get_slot_integer(slot, "usize", usize::MIN as f64, usize::MAX as f64).map(|num| num as usize)
+    }
+
+    // The whole number in [slot], if it's between [min] and [max] (the bounds of [ty], as floats)
+    fn get_slot_integer(&self, slot: SlotId, ty: &'static str, min: f64, max: f64) -> Result<f64, SlotError> {
+        let num = self.try_get_slot_double(slot)?;
+        let error = if num.fract() != 0.0 || !num.is_finite() {
+            SlotError::NotAnInteger { slot, value: num.to_string() }
+        // The largest values round up to the next power of two as floats, which is out of range
+        } else if num < min || num >= max + 1.0 {
+            SlotError::OutOfRange { slot, value: num.to_string(), ty }
+        } else {
+            return Ok(num);
+        };
+        Err(self.in_foreign_method(error))
+    }
+
+    pub fn set_slot_i32(&self, slot: SlotId, value: i32) {
+        self.set_slot_double(slot, value as f64);
+    }
+
+    pub fn set_slot_u32(&self, slot: SlotId, value: u32) {
+        self.set_slot_double(slot, value as f64);
+    }
+
+    /// Puts [value] into [slot], which Wren only stores exactly up to 2^53 (the `audit` feature
+    /// logs a warning for anything bigger)
+    pub fn set_slot_i64(&self, slot: SlotId, value: i64) {
+        let _ = value.to_wren(self, slot);
+    }
+
+    /// Puts [value] into [slot], like [VM::set_slot_i64]
+    pub fn set_slot_usize(&self, slot: SlotId, value: usize) {
+        let _ = value.to_wren(self, slot);
+    }
+
+    /// Reads [slot] as the enum T, from the name or number of one of its variants
+    ///
+    /// Errors name the foreign method doing the reading, like [VM::try_get_slot], and list the
+    /// variants: `Player.walk(_): slot 1 holds "up", which is not a Direction (expected one of
+    /// "North", "East", "South", "West")`.
+    pub fn get_slot_enum<T: WrenEnum>(&self, slot: SlotId) -> Result<T, SlotError> {
+        T::from_slot(self, slot).map_err(|error| self.in_foreign_method(error))
+    }
+
+    /// Puts the name (or number, if T is [WrenEnum::NUMBERED]) of [value] into [slot]
+    pub fn set_slot_enum<T: WrenEnum>(&self, slot: SlotId, value: T) {
+        self.set_slot_value(slot, &value.to_value());
+    }
+
+    // Wraps a slot error with the foreign method that hit it, if there's one running
+    pub(crate) fn in_foreign_method(&self, error: SlotError) -> SlotError {
+        match self.foreign_method() {
+            Some(method) => SlotError::InMethod { method, error: Box::new(error) },
+            None => error,
+        }
+    }
+
+    /// Puts [value] into [slot]
+    pub fn set_slot<T: ToWren>(&self, slot: SlotId, value: T) -> Result<(), ForeignSendError> {
+        value.to_wren(self, slot)
+    }
+
+    /// Whether the value in [slot] is truthy, the way Wren's `if` sees it (only false and null aren't)
+    pub fn coerce_slot_to_bool(&self, slot: SlotId) -> bool {
+        match self.get_slot_type(slot) {
+            SlotType::Bool => self.get_slot_bool(slot).unwrap_or(true),
+            SlotType::Null => false,
+            _ => true,
+        }
+    }
+
+    /// The value in [slot] as a string, the same as its `toString` in Wren
+    ///
+    /// Nulls, bools, numbers and strings are converted directly. Anything else has its toString
+    /// called, which can fail, leaves the result in slot 0, and (as with any call) doesn't keep the other
+    /// slots. Wren also can't call back into Wren from a foreign method, so only do that from outside one.
+    pub fn coerce_slot_to_string(&self, slot: SlotId) -> Result<String, VMError> {
+        match self.get_slot_type(slot) {
+            SlotType::Null => Ok("null".to_string()),
+            SlotType::Bool => Ok(self.coerce_slot_to_bool(slot).to_string()),
+            SlotType::Num => Ok(NumberFormat::wren().format(self.get_slot_double(slot).unwrap_or_default())),
+            SlotType::String => Ok(self.get_slot_string(slot).unwrap_or_default()),
+            _ => {
+                let _depth = self.enter_call()?;
+                let to_string = self.cached_call_handle(FunctionSignature::new_getter("toString"));
+                self.user_data().foreign_abort.replace(None);
+                unsafe {
+                    if slot != 0 {
+                        let receiver = wren_sys::wrenGetSlotHandle(self.vm, slot as raw::c_int);
+                        wren_sys::wrenSetSlotHandle(self.vm, 0, receiver);
+                        wren_sys::wrenReleaseHandle(self.vm, receiver);
+                    }
+                    match wren_sys::wrenCall(self.vm, to_string) {
+                        wren_sys::WrenInterpretResult_WREN_RESULT_SUCCESS => Ok(self.get_slot_string(0).unwrap_or_default()),
+                        _ => Err(self.rewrite_error(self.runtime_error())),
+                    }
+                }
+            },
+        }
+    }
+
+    /// Copies the value in [slot] out of the VM
+    ///
+    /// Returns None if the slot holds (or a list in it contains) something that can't be copied,
+    /// like a foreign object, a class instance, or a map (since Wren can't list a map's keys).
+    pub fn get_slot_value(&self, slot: SlotId) -> Option<WrenValue> {
+        match self.get_slot_type(slot) {
+            SlotType::Null => Some(WrenValue::Null),
+            SlotType::Bool => self.get_slot_bool(slot).map(WrenValue::Bool),
+            SlotType::Num => self.get_slot_double(slot).map(WrenValue::Num),
+            SlotType::String => self.get_slot_string(slot).map(WrenValue::String),
+            SlotType::List => self.copy_slot_list(slot).map(WrenValue::List),
+            _ => None,
+        }
+    }
+
+    /// Puts a new list of [items] in [slot]
+    ///
+    /// Each item is converted in the slot past the current slot count, so no other slots are touched.
+    /// Stops at the first item that fails to convert, leaving the list with the items before it.
+    pub fn set_slot_list<T: ToWren, I: IntoIterator<Item = T>>(&self, slot: SlotId, items: I) -> Result<(), ForeignSendError> {
+        self.set_slot_new_list(slot);
+        let scratch = self.get_slot_count().max(slot + 1);
+        self.ensure_slots(scratch + 1);
+        for item in items {
+            item.to_wren(self, scratch)?;
+            self.insert_in_list(slot, -1, scratch);
+        }
+        Ok(())
+    }
+
+    /// Puts a new map of [entries] in [slot]
+    ///
+    /// Keys and values are converted in the two slots past the current slot count, so no other
+    /// slots are touched. Keys should be numbers, strings, bools or null, since Wren can't hash
+    /// anything else.
+    pub fn set_slot_map<K: ToWren, V: ToWren, I: IntoIterator<Item = (K, V)>>(&self, slot: SlotId, entries: I) -> Result<(), ForeignSendError> {
+        self.set_slot_new_map(slot);
+        let scratch = self.get_slot_count().max(slot + 1);
+        self.ensure_slots(scratch + 2);
+        for (key, value) in entries {
+            key.to_wren(self, scratch)?;
+            value.to_wren(self, scratch + 1)?;
+            self.set_map_value(slot, scratch, scratch + 1);
+        }
+        Ok(())
+    }
+
+    /// The elements of the list in [slot], converted to T
+    ///
+    /// Returns None if [slot] isn't a list, or any element doesn't convert. Uses the slot past the
+    /// current slot count as scratch space, like `get_slot::<Vec<T>>` (which says what went wrong).
+    pub fn get_slot_list<T: FromWren>(&self, slot: SlotId) -> Option<Vec<T>> {
+        self.get_slot(slot).ok()
+    }
+
+    // Copies the list in [slot] out of the VM (see [VM::get_slot_value])
+    pub(crate) fn copy_slot_list(&self, slot: SlotId) -> Option<WrenList> {
+        if self.get_slot_type(slot) != SlotType::List {
+            return None;
+        }
+        let scratch = self.get_slot_count();
+        self.ensure_slots(scratch + 1);
+        (0..self.get_list_count(slot)).map(|index| {
+            self.get_list_element(slot, index as i32, scratch);
+            let value = self.get_slot_value(scratch);
+            if value.is_none() {
+                audit!("Element {} of the list in slot {} is a {:?}, which can't be copied, so the whole list was rejected",
+                    index, slot, self.get_slot_type(scratch));
+            }
+            value
+        }).collect::<Option<Vec<_>>>().map(WrenList)
+    }
+
+    /// Copies [value] into [slot]
+    pub fn set_slot_value(&self, slot: SlotId, value: &WrenValue) {
+        match value {
+            WrenValue::Null => self.set_slot_null(slot),
+            WrenValue::Bool(b) => self.set_slot_bool(slot, *b),
+            WrenValue::Num(n) => self.set_slot_double(slot, *n),
+            WrenValue::String(s) => self.set_slot_string(slot, s),
+            WrenValue::List(list) => {
+                self.set_slot_new_list(slot);
+                let scratch = self.get_slot_count().max(slot + 1);
+                self.ensure_slots(scratch + 1);
+                for item in list {
+                    self.set_slot_value(scratch, item);
+                    self.insert_in_list(slot, -1, scratch);
+                }
+            },
+            WrenValue::Map(map) => {
+                self.set_slot_new_map(slot);
+                let scratch = self.get_slot_count().max(slot + 1);
+                self.ensure_slots(scratch + 2);
+                for (key, value) in map {
+                    self.set_slot_value(scratch, key);
+                    self.set_slot_value(scratch + 1, value);
+                    self.set_map_value(slot, scratch, scratch + 1);
+                }
+            },
+        }
+    }
+}
+
+pub(crate) const EVAL_MODULE: &str = "ruwren/eval";
+
+impl VMWrapper {
+    /// The entries of the map in [slot], converted to K and V
+    ///
+    /// Wren's C API has no way to list a map's keys, so this asks the map for them by calling into
+    /// Wren, which leaves only slot 0 in place. That means it can't be used from a foreign method
+    /// (whose [VM] has [VM::get_slot_deserialize] and [VM::get_map_value] for maps with known keys).
+    pub fn get_slot_map<K: FromWren + Eq + Hash, V: FromWren>(&self, slot: SlotId) -> Result<HashMap<K, V>, Error> {
+        self.slot_map_entries(slot)
+    }
+
+    /// Like [VMWrapper::get_slot_map], but sorted by key
+    pub fn get_slot_btree_map<K: FromWren + Ord, V: FromWren>(&self, slot: SlotId) -> Result<BTreeMap<K, V>, Error> {
+        self.slot_map_entries(slot)
+    }
+
+    fn slot_map_entries<K: FromWren, V: FromWren, M: FromIterator<(K, V)>>(&self, slot: SlotId) -> Result<M, Error> {
+        let (keys, to_list) = self.execute(|vm| -> Result<_, SlotError> {
+            expect_slot(vm, slot, SlotType::Map)?;
+            Ok((vm.cached_call_handle(FunctionSignature::new_getter("keys")), vm.cached_call_handle(FunctionSignature::new_getter("toList"))))
+        })?;
+        let map = self.get_slot_handle(slot);
+        self.set_slot_handle(0, &map);
+        self.call_raw(keys, "keys")?;
+        self.call_raw(to_list, "toList")?;
+        self.execute(|vm| {
+            vm.ensure_slots(4);
+            unsafe { wren_sys::wrenSetSlotHandle(vm.vm, 1, map.handle) };
+            (0..vm.get_list_count(0)).map(|index| {
+                vm.get_list_element(0, index as i32, 2);
+                vm.get_map_value(1, 2, 3);
+                Ok((K::from_wren(vm, 2)?, V::from_wren(vm, 3)?))
+            }).collect()
+        })
+    }
+
+    /// Evaluates the Wren expression [expr], and copies its value out
+    ///
+    /// The expression runs in a module of its own, so it only sees the core library and anything
+    /// it imports. Values that can't be copied (see [VM::get_slot_value]) come back as Null.
+    pub fn eval<E: AsRef<str>>(&self, expr: E) -> Result<WrenValue, VMError> {
+        if !self.execute(|vm| vm.user_data().eval_declared.get()) {
+            self.interpret(EVAL_MODULE, "var Result = null")?;
+            self.execute(|vm| vm.user_data().eval_declared.set(true));
+        }
+        self.interpret(EVAL_MODULE, format!("Result = ({})", expr.as_ref()))?;
+        let value = self.execute(|vm| {
+            vm.ensure_slots(1);
+            vm.get_variable(EVAL_MODULE, "Result", 0);
+            vm.get_slot_value(0).unwrap_or(WrenValue::Null)
+        });
+        // Don't keep the result alive for the garbage collector
+        self.interpret(EVAL_MODULE, "Result = null")?;
+        Ok(value)
+    }
+}
@@ -0,0 +1,83 @@
+//! Sending script output to any [io::Write], like a file, a socket or an in-game console
+//!
+//! ```ignore
+//! let log = std::fs::File::create("script_output.txt")?;
+//! let vm = VMConfig::new().send_printer(WritePrinter::new(log).flush(FlushPolicy::Never)).build();
+//! ```
+use crate::Printer;
+use std::io;
+
+/// When a [WritePrinter] flushes its writer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// After every write
+    Always,
+    /// After each write that ends a line
+    Line,
+    /// Only when the printer is dropped (writers still flush themselves when their own buffer fills)
+    Never,
+}
+
+/// A [Printer] that writes script output to [W]
+///
+/// By default output is line buffered, so only whole lines are written (and output from
+/// `System.write` waits for the rest of its line), and the writer is flushed after each line.
+/// Write errors are dropped, since scripts couldn't do anything about them.
+#[derive(Debug)]
+pub struct WritePrinter<W: io::Write> {
+    writer: W,
+    line_buffered: bool,
+    flush: FlushPolicy,
+    // Output since the last newline, when line buffered
+    line: Vec<u8>,
+}
+
+impl<W: io::Write> WritePrinter<W> {
+    pub fn new(writer: W) -> WritePrinter<W> {
+        WritePrinter { writer, line_buffered: true, flush: FlushPolicy::Line, line: vec![] }
+    }
+
+    /// Whether output is held back until it ends a line, or written as soon as it's printed
+    pub fn line_buffered(mut self, line_buffered: bool) -> Self {
+        self.line_buffered = line_buffered;
+        self
+    }
+
+    pub fn flush(mut self, flush: FlushPolicy) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let _ = self.writer.write_all(bytes);
+        let ends_line = bytes.last() == Some(&b'\n');
+        if self.flush == FlushPolicy::Always || (self.flush == FlushPolicy::Line && ends_line) {
+            let _ = self.writer.flush();
+        }
+    }
+}
+
+impl<W: io::Write> Printer for WritePrinter<W> {
+    fn print(&mut self, s: String) {
+        self.print_bytes(s.as_bytes())
+    }
+
+    fn print_bytes(&mut self, bytes: &[u8]) {
+        if !self.line_buffered {
+            return self.write(bytes);
+        }
+        self.line.extend_from_slice(bytes);
+        if let Some(end) = self.line.iter().rposition(|&b| b == b'\n') {
+            let lines: Vec<u8> = self.line.drain(..=end).collect();
+            self.write(&lines);
+        }
+    }
+}
+
+impl<W: io::Write> Drop for WritePrinter<W> {
+    fn drop(&mut self) {
+        let rest = std::mem::take(&mut self.line);
+        let _ = self.writer.write_all(&rest);
+        let _ = self.writer.flush();
+    }
+}